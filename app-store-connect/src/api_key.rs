@@ -10,10 +10,50 @@ use {
     crate::{ConnectTokenEncoder, Error, Result},
     anyhow::Context,
     base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine},
+    ring::{
+        aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+        pbkdf2,
+        rand::{SecureRandom, SystemRandom},
+    },
     serde::{Deserialize, Serialize},
-    std::{fs::Permissions, io::Write, path::Path},
+    std::{
+        fs::Permissions,
+        io::Write,
+        num::NonZeroU32,
+        path::{Path, PathBuf},
+        process::Command,
+    },
 };
 
+/// Environment variable holding the issuer ID of an API key.
+pub const ISSUER_ID_ENV: &str = "APP_STORE_CONNECT_API_ISSUER_ID";
+
+/// Environment variable holding the key ID of an API key.
+pub const KEY_ID_ENV: &str = "APP_STORE_CONNECT_API_KEY_ID";
+
+/// Environment variable holding the private key of an API key.
+///
+/// The value is the base64 encoded content of the `.p8` file downloaded from
+/// App Store Connect. The PEM content itself is accepted too.
+pub const PRIVATE_KEY_ENV: &str = "APP_STORE_CONNECT_API_PRIVATE_KEY";
+
+/// Environment variable holding the passphrase of an encrypted API key file.
+pub const PASSPHRASE_ENV: &str = "APP_STORE_CONNECT_API_KEY_PASSPHRASE";
+
+/// Identifies the encryption scheme of [EncryptedUnifiedApiKey].
+const ENCRYPTION_SCHEME: &str = "pbkdf2-sha256-aes-256-gcm";
+
+/// PBKDF2 iterations used when encrypting API keys.
+const ENCRYPTION_ITERATIONS: u32 = 600_000;
+
+/// Maximum PBKDF2 iterations accepted when decrypting API keys.
+///
+/// The iteration count is read from the file. This prevents a crafted file from
+/// stalling key derivation.
+const MAX_ENCRYPTION_ITERATIONS: u32 = 10_000_000;
+
+const SALT_LEN: usize = 16;
+
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
@@ -36,15 +76,15 @@ pub struct UnifiedApiKey {
     /// Who issued the key.
     ///
     /// Likely a UUID.
-    issuer_id: String,
+    pub(crate) issuer_id: String,
 
     /// Key identifier.
     ///
     /// An alphanumeric string like `DEADBEEF42`.
-    key_id: String,
+    pub(crate) key_id: String,
 
     /// Base64 encoded DER of ECDSA private key material.
-    private_key: String,
+    pub(crate) private_key: String,
 }
 
 impl UnifiedApiKey {
@@ -59,6 +99,15 @@ impl UnifiedApiKey {
     ) -> Result<Self> {
         let pem_data = std::fs::read(path.as_ref())?;
 
+        Self::from_ecdsa_pem(issuer_id, key_id, &pem_data)
+    }
+
+    /// Construct an instance from constitute parts and PEM encoded ECDSA private key data.
+    pub fn from_ecdsa_pem(
+        issuer_id: impl ToString,
+        key_id: impl ToString,
+        pem_data: &[u8],
+    ) -> Result<Self> {
         let parsed = pem::parse(pem_data).map_err(|_| InvalidPemPrivateKey)?;
 
         if parsed.tag() != "PRIVATE KEY" {
@@ -75,8 +124,19 @@ impl UnifiedApiKey {
     }
 
     /// Construct an instance from serialized JSON.
+    ///
+    /// Errors with [PassphraseRequired] if the data is an encrypted key.
     pub fn from_json(data: impl AsRef<[u8]>) -> Result<Self> {
-        Ok(serde_json::from_slice(data.as_ref())?)
+        match serde_json::from_slice(data.as_ref()) {
+            Ok(key) => Ok(key),
+            Err(e) => {
+                if is_encrypted_json(data.as_ref()) {
+                    Err(PassphraseRequired.into())
+                } else {
+                    Err(e.into())
+                }
+            }
+        }
     }
 
     /// Construct an instance from a JSON file.
@@ -86,6 +146,155 @@ impl UnifiedApiKey {
         Self::from_json(data)
     }
 
+    /// Construct an instance from JSON which may be encrypted.
+    ///
+    /// Whether the data is encrypted is determined from its content. The passphrase
+    /// is only used for encrypted data, which errors with [PassphraseRequired] if
+    /// none is given.
+    pub fn from_json_with_passphrase(
+        data: impl AsRef<[u8]>,
+        passphrase: Option<&str>,
+    ) -> Result<Self> {
+        match passphrase {
+            Some(passphrase) if is_encrypted_json(data.as_ref()) => {
+                Self::from_encrypted_json(data, passphrase)
+            }
+            _ => Self::from_json(data),
+        }
+    }
+
+    /// Construct an instance from environment variables.
+    ///
+    /// [ISSUER_ID_ENV], [KEY_ID_ENV], and [PRIVATE_KEY_ENV] must be set. This allows
+    /// CI systems to provide the key as secrets without writing it to disk.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Construct an instance from variables named like the environment variables
+    /// read by [Self::from_env()].
+    fn from_vars(lookup: impl Fn(&'static str) -> Option<String>) -> Result<Self> {
+        let var = |name: &'static str| {
+            lookup(name).ok_or_else(|| anyhow::Error::from(MissingEnvironmentVariable(name)))
+        };
+
+        let issuer_id = var(ISSUER_ID_ENV)?;
+        let key_id = var(KEY_ID_ENV)?;
+        let private_key = var(PRIVATE_KEY_ENV)?;
+        let private_key = private_key.trim();
+
+        let pem_data = if private_key.starts_with("-----BEGIN") {
+            private_key.as_bytes().to_vec()
+        } else {
+            STANDARD_ENGINE
+                .decode(private_key)
+                .with_context(|| format!("{PRIVATE_KEY_ENV} is not base64"))?
+        };
+
+        Self::from_ecdsa_pem(issuer_id, key_id, &pem_data)
+    }
+
+    /// Construct an instance from the output of a command.
+    ///
+    /// The command is run by the system shell and must print the key's JSON
+    /// serialization to stdout. This allows keys to be kept in secret stores. e.g.
+    /// `security find-generic-password -s app-store-connect -w` reads a key stored
+    /// as a generic password in the macOS keychain.
+    pub fn from_command(command: &str) -> Result<Self> {
+        let output = if cfg!(windows) {
+            Command::new("cmd").args(["/C", command]).output()
+        } else {
+            Command::new("sh").args(["-c", command]).output()
+        }
+        .with_context(|| format!("running {command}"))?;
+
+        if !output.status.success() {
+            return Err(ApiKeyCommandFailed {
+                command: command.to_string(),
+                status: output.status.to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }
+            .into());
+        }
+
+        Self::from_json(output.stdout)
+    }
+
+    /// Construct an instance from JSON encrypted by [Self::to_encrypted_json_string()].
+    ///
+    /// Errors with [InvalidPassphrase] if the passphrase is wrong.
+    pub fn from_encrypted_json(data: impl AsRef<[u8]>, passphrase: &str) -> Result<Self> {
+        let encrypted = serde_json::from_slice::<EncryptedUnifiedApiKey>(data.as_ref())?;
+
+        if encrypted.encryption != ENCRYPTION_SCHEME {
+            return Err(anyhow::anyhow!(
+                "unsupported API key encryption: {}",
+                encrypted.encryption
+            ));
+        }
+
+        if encrypted.iterations > MAX_ENCRYPTION_ITERATIONS {
+            return Err(anyhow::anyhow!(
+                "PBKDF2 iteration count {} exceeds maximum of {}",
+                encrypted.iterations,
+                MAX_ENCRYPTION_ITERATIONS
+            ));
+        }
+
+        let salt = STANDARD_ENGINE.decode(&encrypted.salt)?;
+        let nonce = Nonce::try_assume_unique_for_key(&STANDARD_ENGINE.decode(&encrypted.nonce)?)
+            .map_err(|_| anyhow::anyhow!("invalid nonce in encrypted API key"))?;
+        let mut data = STANDARD_ENGINE.decode(&encrypted.ciphertext)?;
+
+        let key = encryption_key(passphrase, &salt, encrypted.iterations)?;
+        let plaintext = key
+            .open_in_place(nonce, encryption_aad(encrypted.iterations), &mut data)
+            .map_err(|_| InvalidPassphrase)?;
+
+        Self::from_json(plaintext)
+    }
+
+    /// Construct an instance from a file written by [Self::write_encrypted_json_file()].
+    pub fn from_encrypted_json_path(path: impl AsRef<Path>, passphrase: &str) -> Result<Self> {
+        let data = std::fs::read(path.as_ref())?;
+
+        Self::from_encrypted_json(data, passphrase)
+    }
+
+    /// Serialize this instance to JSON encrypted with a passphrase.
+    ///
+    /// The key is encrypted with AES-256-GCM using a key derived from the
+    /// passphrase with PBKDF2-HMAC-SHA256.
+    pub fn to_encrypted_json_string(&self, passphrase: &str) -> Result<String> {
+        self.encrypt(passphrase, ENCRYPTION_ITERATIONS)
+    }
+
+    fn encrypt(&self, passphrase: &str, iterations: u32) -> Result<String> {
+        let rng = SystemRandom::new();
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut salt)
+            .and_then(|_| rng.fill(&mut nonce))
+            .map_err(|_| anyhow::anyhow!("failed to generate random data"))?;
+
+        let mut data = serde_json::to_vec(&self)?;
+        encryption_key(passphrase, &salt, iterations)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                encryption_aad(iterations),
+                &mut data,
+            )
+            .map_err(|_| anyhow::anyhow!("failed to encrypt API key"))?;
+
+        Ok(serde_json::to_string_pretty(&EncryptedUnifiedApiKey {
+            encryption: ENCRYPTION_SCHEME.to_string(),
+            iterations,
+            salt: STANDARD_ENGINE.encode(salt),
+            nonce: STANDARD_ENGINE.encode(nonce),
+            ciphertext: STANDARD_ENGINE.encode(data),
+        })?)
+    }
+
     /// Serialize this instance to a JSON object.
     pub fn to_json_string(&self) -> Result<String> {
         Ok(serde_json::to_string_pretty(&self)?)
@@ -100,21 +309,130 @@ impl UnifiedApiKey {
     /// Permissions on the resulting file may not be as restrictive as desired. It is up
     /// to callers to additionally harden as desired.
     pub fn write_json_file(&self, path: impl AsRef<Path>) -> Result<()> {
-        let path = path.as_ref();
+        write_private_file(path.as_ref(), &self.to_json_string()?)
+    }
 
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
+    /// Write this instance to a JSON file encrypted with a passphrase.
+    ///
+    /// See [Self::to_encrypted_json_string()] and [Self::write_json_file()].
+    pub fn write_encrypted_json_file(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: &str,
+    ) -> Result<()> {
+        write_private_file(path.as_ref(), &self.to_encrypted_json_string(passphrase)?)
+    }
+}
+
+/// Write a file only readable by its owner, creating parent directories.
+fn write_private_file(path: &Path, data: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
 
-        let data = self.to_json_string()?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Create the file private so the data is never readable by others.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
 
-        let mut fh = std::fs::File::create(path)?;
-        let mut permissions = fh.metadata()?.permissions();
-        set_permissions_private(&mut permissions);
-        fh.set_permissions(permissions)?;
-        fh.write_all(data.as_bytes())?;
+    let mut fh = options.open(path)?;
+    // The mode only applies to new files. So existing files are restricted too.
+    let mut permissions = fh.metadata()?.permissions();
+    set_permissions_private(&mut permissions);
+    fh.set_permissions(permissions)?;
+    fh.write_all(data.as_bytes())?;
 
-        Ok(())
+    Ok(())
+}
+
+/// Whether JSON data is a key serialized by [UnifiedApiKey::to_encrypted_json_string()].
+fn is_encrypted_json(data: &[u8]) -> bool {
+    serde_json::from_slice::<EncryptedUnifiedApiKey>(data).is_ok()
+}
+
+/// Additional authenticated data of encrypted API keys.
+///
+/// The iteration count is authenticated so tampering with it is detected.
+fn encryption_aad(iterations: u32) -> Aad<String> {
+    Aad::from(format!("{ENCRYPTION_SCHEME}:{iterations}"))
+}
+
+/// Derive the AES-256-GCM key for a passphrase.
+fn encryption_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| anyhow::anyhow!("invalid PBKDF2 iteration count"))?;
+
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let key = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| anyhow::anyhow!("invalid encryption key"))?;
+
+    Ok(LessSafeKey::new(key))
+}
+
+/// Serialized form of a [UnifiedApiKey] encrypted with a passphrase.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedUnifiedApiKey {
+    encryption: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Describes where to obtain a [UnifiedApiKey] from.
+#[derive(Clone)]
+pub enum ApiKeySource {
+    /// A JSON file written by [UnifiedApiKey::write_json_file()] or
+    /// [UnifiedApiKey::write_encrypted_json_file()].
+    ///
+    /// The passphrase is only used if the file is encrypted. See
+    /// [UnifiedApiKey::from_json_with_passphrase()].
+    JsonFile {
+        path: PathBuf,
+        passphrase: Option<String>,
+    },
+
+    /// Environment variables. See [UnifiedApiKey::from_env()].
+    Environment,
+
+    /// A command printing the key's JSON. See [UnifiedApiKey::from_command()].
+    Command(String),
+}
+
+impl std::fmt::Debug for ApiKeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::JsonFile { path, .. } => f
+                .debug_struct("JsonFile")
+                .field("path", path)
+                .finish_non_exhaustive(),
+            Self::Environment => f.write_str("Environment"),
+            Self::Command(command) => f.debug_tuple("Command").field(command).finish(),
+        }
+    }
+}
+
+impl ApiKeySource {
+    /// Obtain the [UnifiedApiKey] from this source.
+    pub fn load(&self) -> Result<UnifiedApiKey> {
+        match self {
+            Self::JsonFile { path, passphrase } => UnifiedApiKey::from_json_with_passphrase(
+                std::fs::read(path)?,
+                passphrase.as_deref(),
+            ),
+            Self::Environment => UnifiedApiKey::from_env(),
+            Self::Command(command) => UnifiedApiKey::from_command(command),
+        }
     }
 }
 
@@ -133,3 +451,160 @@ impl TryFrom<UnifiedApiKey> for ConnectTokenEncoder {
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
 #[error("invalid PEM formatted private key")]
 pub struct InvalidPemPrivateKey;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+#[error("environment variable {0} is not set")]
+pub struct MissingEnvironmentVariable(pub &'static str);
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+#[error("API key command `{command}` failed ({status}): {stderr}")]
+pub struct ApiKeyCommandFailed {
+    pub command: String,
+    pub status: String,
+    pub stderr: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+#[error("API key is encrypted; a passphrase is required")]
+pub struct PassphraseRequired;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Error)]
+#[error("incorrect passphrase for encrypted API key")]
+pub struct InvalidPassphrase;
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        x509_certificate::{EcdsaCurve, InMemorySigningKeyPair, KeyAlgorithm},
+    };
+
+    fn generate_key() -> Result<UnifiedApiKey> {
+        let key_pair =
+            InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))?;
+        let pem = pem::encode(&pem::Pem::new(
+            "PRIVATE KEY",
+            key_pair.to_pkcs8_one_asymmetric_key_der().to_vec(),
+        ));
+
+        UnifiedApiKey::from_ecdsa_pem("issuer", "DEADBEEF42", pem.as_bytes())
+    }
+
+    fn assert_same_key(a: &UnifiedApiKey, b: &UnifiedApiKey) {
+        assert_eq!(a.issuer_id, b.issuer_id);
+        assert_eq!(a.key_id, b.key_id);
+        assert_eq!(a.private_key, b.private_key);
+    }
+
+    #[test]
+    fn environment() -> Result<()> {
+        let key = generate_key()?;
+        let pem = pem::encode(&pem::Pem::new(
+            "PRIVATE KEY",
+            STANDARD_ENGINE.decode(&key.private_key)?,
+        ));
+
+        let from_vars = |private_key: Option<&str>| {
+            UnifiedApiKey::from_vars(|name| match name {
+                ISSUER_ID_ENV => Some(key.issuer_id.clone()),
+                KEY_ID_ENV => Some(key.key_id.clone()),
+                PRIVATE_KEY_ENV => private_key.map(String::from),
+                _ => None,
+            })
+        };
+
+        let err = from_vars(None).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<MissingEnvironmentVariable>(),
+            Some(&MissingEnvironmentVariable(PRIVATE_KEY_ENV))
+        );
+
+        // The base64 encoded .p8 file and its PEM content are both accepted.
+        assert_same_key(&from_vars(Some(&STANDARD_ENGINE.encode(&pem)))?, &key);
+        assert_same_key(&from_vars(Some(&pem))?, &key);
+        assert!(from_vars(Some("not base64!")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted() -> Result<()> {
+        let key = generate_key()?;
+
+        let encrypted = key.encrypt("correct horse", 1000)?;
+        assert!(!encrypted.contains(&key.private_key));
+        assert_same_key(
+            &UnifiedApiKey::from_encrypted_json(&encrypted, "correct horse")?,
+            &key,
+        );
+
+        let err = UnifiedApiKey::from_encrypted_json(&encrypted, "wrong").unwrap_err();
+        assert!(err.downcast_ref::<InvalidPassphrase>().is_some());
+        let err = UnifiedApiKey::from_json(&encrypted).unwrap_err();
+        assert!(err.downcast_ref::<PassphraseRequired>().is_some());
+
+        // The iteration count is bounded.
+        let mut value = serde_json::from_str::<serde_json::Value>(&encrypted)?;
+        value["iterations"] = u32::MAX.into();
+        let err =
+            UnifiedApiKey::from_encrypted_json(value.to_string(), "correct horse").unwrap_err();
+        assert!(err.to_string().contains("exceeds maximum"));
+        value["iterations"] = 1001.into();
+        let err =
+            UnifiedApiKey::from_encrypted_json(value.to_string(), "correct horse").unwrap_err();
+        assert!(err.downcast_ref::<InvalidPassphrase>().is_some());
+
+        // Whether a file is encrypted is determined by its content.
+        let td = tempfile::tempdir()?;
+        let encrypted_path = td.path().join("encrypted.json");
+        key.write_encrypted_json_file(&encrypted_path, "correct horse")?;
+        let plain_path = td.path().join("plain.json");
+        key.write_json_file(&plain_path)?;
+
+        #[cfg(unix)]
+        for path in [&encrypted_path, &plain_path] {
+            assert_eq!(std::fs::metadata(path)?.permissions().mode() & 0o777, 0o600);
+        }
+
+        for path in [&encrypted_path, &plain_path] {
+            let source = ApiKeySource::JsonFile {
+                path: path.clone(),
+                passphrase: Some("correct horse".into()),
+            };
+            assert!(!format!("{source:?}").contains("correct horse"));
+            assert_same_key(&source.load()?, &key);
+        }
+
+        let source = ApiKeySource::JsonFile {
+            path: plain_path,
+            passphrase: None,
+        };
+        assert_same_key(&source.load()?, &key);
+        let source = ApiKeySource::JsonFile {
+            path: encrypted_path,
+            passphrase: None,
+        };
+        let err = source.load().unwrap_err();
+        assert!(err.downcast_ref::<PassphraseRequired>().is_some());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command() -> Result<()> {
+        let key = generate_key()?;
+        let td = tempfile::tempdir()?;
+        let path = td.path().join("key.json");
+        key.write_json_file(&path)?;
+
+        let source = ApiKeySource::Command(format!("cat '{}'", path.display()));
+        assert_same_key(&source.load()?, &key);
+
+        let err = UnifiedApiKey::from_command("echo nope >&2; exit 3").unwrap_err();
+        let err = err.downcast_ref::<ApiKeyCommandFailed>().unwrap();
+        assert_eq!(err.stderr, "nope");
+
+        Ok(())
+    }
+}
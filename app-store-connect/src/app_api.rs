@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
+use crate::{AppStoreConnectClient, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+const APPS_PATH: &str = "v1/apps";
+
+impl AppStoreConnectClient {
+    /// List all apps, following pagination.
+    pub fn list_apps(&self) -> Result<AppsResponse> {
+        self.apps(&ListQuery::new().limit(MAX_PAGE_LIMIT))?
+            .collect_all()
+    }
+
+    /// Obtain a [Paginator] over apps matching a query.
+    pub fn apps(&self, query: &ListQuery<AppFilter>) -> Result<Paginator<'_, AppsResponse>> {
+        self.paginate(APPS_PATH, query)
+    }
+
+    pub fn get_app(&self, id: &str) -> Result<AppResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{APPS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Find the app having a bundle identifier. e.g. `com.example.app`.
+    pub fn find_app_by_bundle_id(&self, bundle_id: &str) -> Result<Option<App>> {
+        self.apps(&ListQuery::new().filter(AppFilter::BundleId(bundle_id.into())))?
+            .items()
+            .next()
+            .transpose()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppResponse {
+    pub data: App,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppsResponse {
+    pub data: Vec<App>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(AppsResponse, App);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct App {
+    pub attributes: AppAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppAttributes {
+    pub name: String,
+    pub bundle_id: String,
+    pub sku: String,
+    pub primary_locale: String,
+}
+
+/// Filters for listing apps.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AppFilter {
+    Id(String),
+    BundleId(String),
+    Name(String),
+    Sku(String),
+}
+
+impl QueryFilter for AppFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::BundleId(_) => "bundleId",
+            Self::Name(_) => "name",
+            Self::Sku(_) => "sku",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::BundleId(v) | Self::Name(v) | Self::Sku(v) => v.clone(),
+        }
+    }
+}
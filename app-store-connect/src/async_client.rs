@@ -0,0 +1,580 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An async variant of [AppStoreConnectClient](crate::AppStoreConnectClient).
+//!
+//! [AsyncAppStoreConnectClient] uses the same request and response types, JWT
+//! handling, and [crate::RetryPolicy] as the blocking client. It requires a Tokio
+//! runtime and is available with the `async` crate feature.
+//!
+//! Typed methods cover the Notary API and provisioning (bundle IDs,
+//! certificates, devices, and profiles). Other endpoints can be reached via
+//! the generic [AsyncAppStoreConnectClient::get()], [AsyncAppStoreConnectClient::list()],
+//! [AsyncAppStoreConnectClient::post()], [AsyncAppStoreConnectClient::patch()],
+//! and [AsyncAppStoreConnectClient::delete()] methods using this crate's
+//! request and response types.
+
+use {
+    crate::{
+        api_key::ApiKeySource,
+        bundle_api::{
+            BundleIdCreateRequest, BundleIdPlatform, BundleIdResponse, BundleIdsResponse,
+            BUNDLE_IDS_PATH,
+        },
+        certs_api::{
+            CertificateCreateRequest, CertificateResponse, CertificateType, CertificatesResponse,
+            CERTIFICATES_PATH,
+        },
+        device_api::{DeviceCreateRequest, DeviceResponse, DevicesResponse, DEVICES_PATH},
+        error_message, file_sha256,
+        notary_api::{
+            NewSubmissionRequest, NewSubmissionResponse, SubmissionListResponse,
+            SubmissionLogResponse, SubmissionResponse, SubmissionResponseStatus, WaitLimitReached,
+            NOTARY_S3_REGION, SUBMISSIONS_PATH,
+        },
+        profile_api::{
+            ProfileCreateRequest, ProfileResponse, ProfileType, ProfilesResponse, PROFILES_PATH,
+        },
+        provisioning_profile::ProvisioningProfile,
+        query::{next_page_url, ListQuery, NoFilter, Page, QueryFilter, MAX_PAGE_LIMIT},
+        retry::RetryState,
+        s3, AppStoreConnectError, ClientConfig, ConnectTokenEncoder, Result, UnifiedApiKey,
+        USER_AGENT,
+    },
+    reqwest::{Client, ClientBuilder, Method, RequestBuilder, Response},
+    serde::{de::DeserializeOwned, Serialize},
+    serde_json::Value,
+    std::{
+        ops::{Deref, DerefMut},
+        path::Path,
+        time::{Duration, Instant},
+    },
+    tokio::fs::File,
+};
+
+/// An async client for App Store Connect API.
+///
+/// Instances can be shared between tasks (e.g. via [std::sync::Arc]) to run
+/// many requests concurrently. Configuration is available via [ClientConfig],
+/// which this type dereferences to.
+pub struct AsyncAppStoreConnectClient {
+    client: Client,
+    config: ClientConfig,
+}
+
+impl Deref for AsyncAppStoreConnectClient {
+    type Target = ClientConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.config
+    }
+}
+
+impl DerefMut for AsyncAppStoreConnectClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
+}
+
+impl AsyncAppStoreConnectClient {
+    pub fn from_json_path(path: &Path) -> Result<Self> {
+        let key = UnifiedApiKey::from_json_path(path)?;
+        Self::new(key.try_into()?)
+    }
+
+    /// Create a new client using an API key obtained from a [ApiKeySource].
+    pub fn from_api_key_source(source: &ApiKeySource) -> Result<Self> {
+        Self::new(source.load()?.try_into()?)
+    }
+
+    /// Create a new async client to the App Store Connect API.
+    pub fn new(connect_token: ConnectTokenEncoder) -> Result<Self> {
+        let client = ClientBuilder::default().user_agent(USER_AGENT).build()?;
+        Ok(Self {
+            client,
+            config: ClientConfig::new(connect_token),
+        })
+    }
+
+    /// Start an authenticated request expecting a JSON response.
+    fn request(&self, method: Method, url: impl reqwest::IntoUrl) -> Result<RequestBuilder> {
+        Ok(self
+            .client
+            .request(method, url)
+            .bearer_auth(self.get_token()?)
+            .header("Accept", "application/json"))
+    }
+
+    /// Send a request, retrying according to the client's [crate::RetryPolicy].
+    ///
+    /// Behaves like [crate::AppStoreConnectClient::send_request()].
+    pub async fn send_request(&self, request: RequestBuilder) -> Result<Response> {
+        let mut request = request.build()?;
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let mut retry = RetryState::default();
+
+        loop {
+            let mut next = request.try_clone();
+
+            log::debug!("{} {}", method, url);
+
+            let response = self.client.execute(request).await?;
+            self.config.observe_response(response.headers());
+
+            let status = response.status();
+
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let delay = retry.retry_delay(
+                &self.config,
+                &method,
+                &url,
+                status,
+                response.headers(),
+                next.as_mut().map(|r| r.headers_mut()),
+            )?;
+
+            match (delay, next) {
+                (Some(delay), Some(next)) => {
+                    tokio::time::sleep(delay).await;
+                    request = next;
+                }
+                _ => return Err(Self::response_error(&method, url, response).await),
+            }
+        }
+    }
+
+    /// Convert an unsuccessful response into an error.
+    async fn response_error(method: &Method, url: String, response: Response) -> anyhow::Error {
+        match response.bytes().await {
+            Ok(body) => AppStoreConnectError {
+                method: method.to_string(),
+                url,
+                message: error_message(body.as_ref()),
+            }
+            .into(),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Fetch a resource from an App Store Connect API path. e.g. `v1/apps/<id>`.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let req = self.request(Method::GET, self.api_endpoint(path))?;
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Fetch all pages of an App Store Connect API list endpoint and combine them.
+    pub async fn list<P: Page, F: QueryFilter>(
+        &self,
+        path: &str,
+        query: &ListQuery<F>,
+    ) -> Result<P> {
+        let mut url = Some(
+            reqwest::Url::parse_with_params(&self.api_endpoint(path), query.params()?)?.to_string(),
+        );
+        let mut res: Option<P> = None;
+
+        while let Some(next) = url.take() {
+            let req = self.request(Method::GET, next)?;
+            let page: P = self.send_request(req).await?.json().await?;
+            url = next_page_url(self.api_url(), &page)?;
+
+            match res.as_mut() {
+                Some(res) => res.append(page),
+                None => res = Some(page),
+            }
+        }
+
+        Ok(res.expect("at least one page is fetched"))
+    }
+
+    /// Create a resource by POSTing a request body to an App Store Connect API path.
+    pub async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let req = self
+            .request(Method::POST, self.api_endpoint(path))?
+            .header("Content-Type", "application/json")
+            .json(body);
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Modify a resource by PATCHing a request body to an App Store Connect API path.
+    pub async fn patch<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        let req = self
+            .request(Method::PATCH, self.api_endpoint(path))?
+            .header("Content-Type", "application/json")
+            .json(body);
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Delete the resource at an App Store Connect API path.
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        let req = self.request(Method::DELETE, self.api_endpoint(path))?;
+        self.send_request(req).await?;
+        Ok(())
+    }
+}
+
+/// Notary API.
+impl AsyncAppStoreConnectClient {
+    /// Create a submission to the Notary API.
+    pub async fn create_submission(
+        &self,
+        sha256: &str,
+        submission_name: &str,
+    ) -> Result<NewSubmissionResponse> {
+        let req = self
+            .request(Method::POST, self.notary_endpoint(SUBMISSIONS_PATH))?
+            .header("Content-Type", "application/json")
+            .json(&NewSubmissionRequest::new(sha256, submission_name));
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Upload the content of a submission to the S3 bucket designated by the Notary API.
+    ///
+    /// The file is streamed, not read into memory.
+    pub async fn upload_submission(
+        &self,
+        submission: &NewSubmissionResponse,
+        path: &Path,
+    ) -> Result<()> {
+        let attributes = &submission.data.attributes;
+
+        log::warn!(
+            "uploading {} to s3://{}/{}",
+            path.display(),
+            attributes.bucket,
+            attributes.object
+        );
+
+        let file = File::open(path).await?;
+        let len = file.metadata().await?.len();
+
+        let req = s3::put_object_async(
+            &self.client,
+            self.s3_endpoint_url.as_deref(),
+            NOTARY_S3_REGION,
+            &s3::Credentials {
+                access_key_id: &attributes.aws_access_key_id,
+                secret_access_key: &attributes.aws_secret_access_key,
+                session_token: Some(&attributes.aws_session_token),
+            },
+            &attributes.bucket,
+            &attributes.object,
+            file.into(),
+        )?
+        .header("Content-Length", len);
+        self.send_request(req).await?;
+
+        Ok(())
+    }
+
+    /// Submit a file (`.zip`, `.dmg`, or `.pkg`) for notarization.
+    ///
+    /// This creates a submission and uploads the file. It does not wait for the
+    /// notary service to process it. See [Self::wait_for_submission()] for that.
+    pub async fn submit_file(&self, path: &Path) -> Result<NewSubmissionResponse> {
+        let digest = {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || file_sha256(&path)).await??
+        };
+
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| "submission".to_string());
+
+        let submission = self.create_submission(&digest, &name).await?;
+        self.upload_submission(&submission, path).await?;
+
+        Ok(submission)
+    }
+
+    /// List previous submissions.
+    ///
+    /// The Notary API returns up to the 100 most recent submissions.
+    pub async fn list_submissions(&self) -> Result<SubmissionListResponse> {
+        let req = self.request(Method::GET, self.notary_endpoint(SUBMISSIONS_PATH))?;
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Fetch the status of a Notary API submission.
+    pub async fn get_submission(&self, submission_id: &str) -> Result<SubmissionResponse> {
+        let req = self.request(
+            Method::GET,
+            self.notary_endpoint(&format!("{SUBMISSIONS_PATH}/{submission_id}")),
+        )?;
+        Ok(self.send_request(req).await?.json().await?)
+    }
+
+    /// Fetch details about a single completed notarization.
+    pub async fn get_submission_log(&self, submission_id: &str) -> Result<Value> {
+        let req = self.request(
+            Method::GET,
+            self.notary_endpoint(&format!("{SUBMISSIONS_PATH}/{submission_id}/logs")),
+        )?;
+        let res: SubmissionLogResponse = self.send_request(req).await?.json().await?;
+
+        let url = res.data.attributes.developer_log_url;
+        Ok(self.client.get(url).send().await?.json::<Value>().await?)
+    }
+
+    /// Poll a submission until the notary service finishes processing it.
+    ///
+    /// Errors with [WaitLimitReached] if processing doesn't finish within `wait_limit`.
+    pub async fn wait_for_submission(
+        &self,
+        submission_id: &str,
+        wait_limit: Duration,
+        poll_interval: Duration,
+    ) -> Result<SubmissionResponse> {
+        let start_time = Instant::now();
+
+        loop {
+            let status = self.get_submission(submission_id).await?;
+            let elapsed = start_time.elapsed();
+
+            log::warn!(
+                "poll state of {} after {}s: {}",
+                submission_id,
+                elapsed.as_secs(),
+                status.data.attributes.status
+            );
+
+            if status.data.attributes.status != SubmissionResponseStatus::InProgress {
+                return Ok(status);
+            }
+
+            if elapsed >= wait_limit {
+                return Err(WaitLimitReached(elapsed).into());
+            }
+
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
+
+/// Provisioning: bundle IDs, certificates, devices, and profiles.
+impl AsyncAppStoreConnectClient {
+    pub async fn register_bundle_id(
+        &self,
+        identifier: &str,
+        name: &str,
+    ) -> Result<BundleIdResponse> {
+        self.post(
+            BUNDLE_IDS_PATH,
+            &BundleIdCreateRequest::new(identifier, name),
+        )
+        .await
+    }
+
+    /// List all bundle IDs, following pagination.
+    pub async fn list_bundle_ids(&self) -> Result<BundleIdsResponse> {
+        self.list(
+            BUNDLE_IDS_PATH,
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )
+        .await
+    }
+
+    pub async fn get_bundle_id(&self, id: &str) -> Result<BundleIdResponse> {
+        self.get(&format!("{BUNDLE_IDS_PATH}/{id}")).await
+    }
+
+    pub async fn delete_bundle_id(&self, id: &str) -> Result<()> {
+        self.delete(&format!("{BUNDLE_IDS_PATH}/{id}")).await
+    }
+
+    pub async fn create_certificate(
+        &self,
+        csr: String,
+        ty: CertificateType,
+    ) -> Result<CertificateResponse> {
+        self.post(CERTIFICATES_PATH, &CertificateCreateRequest::new(csr, ty))
+            .await
+    }
+
+    /// List all certificates, following pagination.
+    pub async fn list_certificates(&self) -> Result<CertificatesResponse> {
+        self.list(
+            CERTIFICATES_PATH,
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )
+        .await
+    }
+
+    pub async fn get_certificate(&self, id: &str) -> Result<CertificateResponse> {
+        self.get(&format!("{CERTIFICATES_PATH}/{id}")).await
+    }
+
+    pub async fn revoke_certificate(&self, id: &str) -> Result<()> {
+        self.delete(&format!("{CERTIFICATES_PATH}/{id}")).await
+    }
+
+    pub async fn register_device(
+        &self,
+        name: &str,
+        platform: BundleIdPlatform,
+        udid: &str,
+    ) -> Result<DeviceResponse> {
+        self.post(
+            DEVICES_PATH,
+            &DeviceCreateRequest::new(name, platform, udid),
+        )
+        .await
+    }
+
+    /// List all devices, following pagination.
+    pub async fn list_devices(&self) -> Result<DevicesResponse> {
+        self.list(
+            DEVICES_PATH,
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )
+        .await
+    }
+
+    pub async fn get_device(&self, id: &str) -> Result<DeviceResponse> {
+        self.get(&format!("{DEVICES_PATH}/{id}")).await
+    }
+
+    pub async fn create_profile(
+        &self,
+        name: &str,
+        profile_type: ProfileType,
+        bundle_id: &str,
+        certificates: &[String],
+        devices: Option<&[String]>,
+    ) -> Result<ProfileResponse> {
+        self.post(
+            PROFILES_PATH,
+            &ProfileCreateRequest::new(name, profile_type, bundle_id, certificates, devices),
+        )
+        .await
+    }
+
+    /// List all profiles, following pagination.
+    pub async fn list_profiles(&self) -> Result<ProfilesResponse> {
+        self.list(
+            PROFILES_PATH,
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )
+        .await
+    }
+
+    pub async fn get_profile(&self, id: &str) -> Result<ProfileResponse> {
+        self.get(&format!("{PROFILES_PATH}/{id}")).await
+    }
+
+    /// Fetch a profile and decode its content.
+    pub async fn download_profile(&self, id: &str) -> Result<ProvisioningProfile> {
+        self.get_profile(id).await?.data.attributes.decode_profile()
+    }
+
+    pub async fn delete_profile(&self, id: &str) -> Result<()> {
+        self.delete(&format!("{PROFILES_PATH}/{id}")).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            mock_server::{MockServer, MOCK_NOTARY_BUCKET},
+            query::JsonApiPage,
+        },
+        std::sync::Arc,
+    };
+
+    fn runtime() -> Result<tokio::runtime::Runtime> {
+        Ok(tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?)
+    }
+
+    #[test]
+    fn concurrent_submissions() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = Arc::new(server.async_client()?);
+        let temp_dir = tempfile::tempdir()?;
+
+        let paths = (0..3)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("app{i}.zip"));
+                std::fs::write(&path, format!("zip content {i}"))?;
+                Ok(path)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        runtime()?.block_on(async {
+            let tasks = paths
+                .into_iter()
+                .map(|path| {
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let submission = client.submit_file(&path).await?;
+                        let status = client
+                            .wait_for_submission(
+                                &submission.data.id,
+                                Duration::from_secs(10),
+                                Duration::from_millis(10),
+                            )
+                            .await?;
+                        Ok::<_, anyhow::Error>((submission, status))
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for (i, task) in tasks.into_iter().enumerate() {
+                let (submission, status) = task.await??;
+                assert_eq!(
+                    server.s3_object(MOCK_NOTARY_BUCKET, &submission.data.attributes.object),
+                    Some(format!("zip content {i}").into_bytes())
+                );
+                assert_eq!(
+                    status.data.attributes.status,
+                    SubmissionResponseStatus::Accepted
+                );
+            }
+
+            assert_eq!(client.list_submissions().await?.data.len(), 3);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn provisioning() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.async_client()?;
+
+        runtime()?.block_on(async {
+            let bundle_id = client
+                .register_bundle_id("com.example.app", "Example")
+                .await?;
+            assert_eq!(client.list_bundle_ids().await?.data.len(), 1);
+
+            let page: JsonApiPage = client
+                .list(
+                    BUNDLE_IDS_PATH,
+                    &ListQuery::<NoFilter>::new().fields("bundleIds", ["identifier"]),
+                )
+                .await?;
+            assert_eq!(page.data.len(), 1);
+
+            client.delete_bundle_id(&bundle_id.data.id).await?;
+            assert!(client.get_bundle_id(&bundle_id.data.id).await.is_err());
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,419 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bundle_api::BundleIdPlatform;
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
+use crate::{AppStoreConnectClient, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const BUILDS_PATH: &str = "v1/builds";
+const BUILD_BETA_DETAILS_PATH: &str = "v1/buildBetaDetails";
+const PRE_RELEASE_VERSIONS_PATH: &str = "v1/preReleaseVersions";
+
+impl AppStoreConnectClient {
+    /// Obtain a [Paginator] over builds matching a query.
+    pub fn builds(&self, query: &ListQuery<BuildFilter>) -> Result<Paginator<'_, BuildsResponse>> {
+        self.paginate(BUILDS_PATH, query)
+    }
+
+    /// List all builds of an app, newest first.
+    pub fn list_app_builds(&self, app_id: &str) -> Result<BuildsResponse> {
+        self.builds(
+            &ListQuery::new()
+                .filter(BuildFilter::App(app_id.into()))
+                .sort_descending("uploadedDate")
+                .limit(MAX_PAGE_LIMIT),
+        )?
+        .collect_all()
+    }
+
+    /// Find a build of an app by version and build number.
+    ///
+    /// `version` is the marketing version (`CFBundleShortVersionString`) and
+    /// `build_number` the build version (`CFBundleVersion`).
+    pub fn find_build(
+        &self,
+        app_id: &str,
+        version: &str,
+        build_number: &str,
+    ) -> Result<Option<Build>> {
+        self.builds(
+            &ListQuery::new()
+                .filter(BuildFilter::App(app_id.into()))
+                .filter(BuildFilter::PreReleaseVersion(version.into()))
+                .filter(BuildFilter::Version(build_number.into())),
+        )?
+        .items()
+        .next()
+        .transpose()
+    }
+
+    pub fn get_build(&self, id: &str) -> Result<BuildResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{BUILDS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Update attributes of a build. e.g. to expire it.
+    pub fn update_build(
+        &self,
+        id: &str,
+        attributes: BuildUpdateRequestAttributes,
+    ) -> Result<BuildResponse> {
+        let token = self.get_token()?;
+        let body = BuildUpdateRequest {
+            data: BuildUpdateRequestData {
+                attributes,
+                id: id.into(),
+                r#type: "builds".into(),
+            },
+        };
+        let req = self
+            .client
+            .patch(self.api_endpoint(&format!("{BUILDS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Poll a build until App Store Connect finishes processing it.
+    ///
+    /// Returns the build once its processing state is no longer
+    /// [BuildProcessingState::Processing]. Callers should check whether it is
+    /// [BuildProcessingState::Valid]. Errors with [BuildProcessingWaitLimitReached]
+    /// if processing doesn't finish within `wait_limit`.
+    pub fn wait_for_build_processing(
+        &self,
+        id: &str,
+        wait_limit: Duration,
+        poll_interval: Duration,
+    ) -> Result<BuildResponse> {
+        let start_time = Instant::now();
+
+        loop {
+            let build = self.get_build(id)?;
+            let elapsed = start_time.elapsed();
+
+            log::warn!(
+                "build {} processing state after {}s: {}",
+                id,
+                elapsed.as_secs(),
+                build.data.attributes.processing_state
+            );
+
+            if build.data.attributes.processing_state != BuildProcessingState::Processing {
+                return Ok(build);
+            }
+
+            if elapsed >= wait_limit {
+                return Err(BuildProcessingWaitLimitReached(elapsed).into());
+            }
+
+            std::thread::sleep(poll_interval);
+        }
+    }
+
+    /// Obtain the TestFlight details of a build.
+    pub fn get_build_beta_detail(&self, build_id: &str) -> Result<BuildBetaDetailResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{BUILDS_PATH}/{build_id}/buildBetaDetail")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Set whether testers are notified automatically when a build becomes available.
+    pub fn update_build_beta_detail(
+        &self,
+        id: &str,
+        auto_notify_enabled: bool,
+    ) -> Result<BuildBetaDetailResponse> {
+        let token = self.get_token()?;
+        let body = BuildBetaDetailUpdateRequest {
+            data: BuildBetaDetailUpdateRequestData {
+                attributes: BuildBetaDetailUpdateRequestAttributes {
+                    auto_notify_enabled,
+                },
+                id: id.into(),
+                r#type: "buildBetaDetails".into(),
+            },
+        };
+        let req = self
+            .client
+            .patch(self.api_endpoint(&format!("{BUILD_BETA_DETAILS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Obtain a [Paginator] over pre-release versions matching a query.
+    pub fn pre_release_versions(
+        &self,
+        query: &ListQuery<PreReleaseVersionFilter>,
+    ) -> Result<Paginator<'_, PreReleaseVersionsResponse>> {
+        self.paginate(PRE_RELEASE_VERSIONS_PATH, query)
+    }
+
+    pub fn get_pre_release_version(&self, id: &str) -> Result<PreReleaseVersionResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{PRE_RELEASE_VERSIONS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+}
+
+/// A build did not finish processing within the allowed time.
+#[derive(Clone, Copy, Debug, Error)]
+#[error("build still processing after waiting {}s", .0.as_secs())]
+pub struct BuildProcessingWaitLimitReached(pub Duration);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUpdateRequest {
+    pub data: BuildUpdateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUpdateRequestData {
+    pub attributes: BuildUpdateRequestAttributes,
+    pub id: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUpdateRequestAttributes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uses_non_exempt_encryption: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetailUpdateRequest {
+    pub data: BuildBetaDetailUpdateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetailUpdateRequestData {
+    pub attributes: BuildBetaDetailUpdateRequestAttributes,
+    pub id: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetailUpdateRequestAttributes {
+    pub auto_notify_enabled: bool,
+}
+
+/// Processing state of an uploaded build.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BuildProcessingState {
+    Processing,
+    Failed,
+    Invalid,
+    Valid,
+    #[serde(other)]
+    Unknown,
+}
+
+impl std::fmt::Display for BuildProcessingState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Processing => "PROCESSING",
+            Self::Failed => "FAILED",
+            Self::Invalid => "INVALID",
+            Self::Valid => "VALID",
+            Self::Unknown => "UNKNOWN",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildResponse {
+    pub data: Build,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildsResponse {
+    pub data: Vec<Build>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(BuildsResponse, Build);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Build {
+    pub attributes: BuildAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildAttributes {
+    /// The build number (`CFBundleVersion`).
+    pub version: String,
+    pub uploaded_date: String,
+    pub expiration_date: Option<String>,
+    #[serde(default)]
+    pub expired: bool,
+    pub min_os_version: Option<String>,
+    pub processing_state: BuildProcessingState,
+    pub uses_non_exempt_encryption: Option<bool>,
+}
+
+/// Filters for listing builds.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildFilter {
+    Id(String),
+    /// Id of the app.
+    App(String),
+    Expired(bool),
+    /// Marketing version (`CFBundleShortVersionString`).
+    PreReleaseVersion(String),
+    ProcessingState(BuildProcessingState),
+    /// Build number (`CFBundleVersion`).
+    Version(String),
+}
+
+impl QueryFilter for BuildFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::App(_) => "app",
+            Self::Expired(_) => "expired",
+            Self::PreReleaseVersion(_) => "preReleaseVersion.version",
+            Self::ProcessingState(_) => "processingState",
+            Self::Version(_) => "version",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::App(v) | Self::PreReleaseVersion(v) | Self::Version(v) => v.clone(),
+            Self::Expired(v) => v.to_string(),
+            Self::ProcessingState(v) => v.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetailResponse {
+    pub data: BuildBetaDetail,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetail {
+    pub attributes: BuildBetaDetailAttributes,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildBetaDetailAttributes {
+    #[serde(default)]
+    pub auto_notify_enabled: bool,
+    pub internal_build_state: Option<String>,
+    pub external_build_state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreReleaseVersionResponse {
+    pub data: PreReleaseVersion,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreReleaseVersionsResponse {
+    pub data: Vec<PreReleaseVersion>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(PreReleaseVersionsResponse, PreReleaseVersion);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreReleaseVersion {
+    pub attributes: PreReleaseVersionAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreReleaseVersionAttributes {
+    /// Marketing version (`CFBundleShortVersionString`).
+    pub version: String,
+    pub platform: String,
+}
+
+/// Filters for listing pre-release versions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PreReleaseVersionFilter {
+    /// Id of the app.
+    App(String),
+    Platform(BundleIdPlatform),
+    Version(String),
+}
+
+impl QueryFilter for PreReleaseVersionFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::App(_) => "app",
+            Self::Platform(_) => "platform",
+            Self::Version(_) => "version",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::App(v) | Self::Version(v) => v.clone(),
+            Self::Platform(v) => v.to_string(),
+        }
+    }
+}
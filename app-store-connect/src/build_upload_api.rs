@@ -0,0 +1,625 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Uploading app binaries to App Store Connect.
+//!
+//! This replaces `xcrun altool --upload-package` and Transporter. An upload
+//! consists of a `buildUploads` reservation for a version of an app, and a
+//! `buildUploadFiles` resource for the `.ipa` or `.pkg`. The latter describes
+//! the parts the file is to be uploaded in. Once all parts are uploaded, the
+//! file is committed with its checksum and App Store Connect processes the
+//! upload into a build.
+
+use crate::bundle_api::BundleIdPlatform;
+use crate::profile_api::{Ref, RefData};
+use crate::{file_sha256, AppStoreConnectClient, Result};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const BUILD_UPLOADS_PATH: &str = "v1/buildUploads";
+const BUILD_UPLOAD_FILES_PATH: &str = "v1/buildUploadFiles";
+
+impl AppStoreConnectClient {
+    /// Reserve an upload of a build of an app.
+    ///
+    /// `version` is the marketing version (`CFBundleShortVersionString`) and
+    /// `build_number` the build version (`CFBundleVersion`) of the binary.
+    pub fn create_build_upload(
+        &self,
+        app_id: &str,
+        version: &str,
+        build_number: &str,
+        platform: BundleIdPlatform,
+    ) -> Result<BuildUploadResponse> {
+        let token = self.get_token()?;
+        let body = BuildUploadCreateRequest {
+            data: BuildUploadCreateRequestData {
+                attributes: BuildUploadCreateRequestAttributes {
+                    cf_bundle_short_version_string: version.into(),
+                    cf_bundle_version: build_number.into(),
+                    platform: platform.to_string(),
+                },
+                relationships: BuildUploadCreateRequestRelationships {
+                    app: Ref {
+                        data: RefData {
+                            id: app_id.into(),
+                            r#type: "apps".into(),
+                        },
+                    },
+                },
+                r#type: "buildUploads".into(),
+            },
+        };
+        let req = self
+            .client
+            .post(self.api_endpoint(BUILD_UPLOADS_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    pub fn get_build_upload(&self, id: &str) -> Result<BuildUploadResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{BUILD_UPLOADS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Register the file of a build upload.
+    ///
+    /// The response describes the operations needed to upload the file's content.
+    pub fn create_build_upload_file(
+        &self,
+        build_upload_id: &str,
+        file_name: &str,
+        file_size: u64,
+        uti: BuildUploadFileUti,
+    ) -> Result<BuildUploadFileResponse> {
+        let token = self.get_token()?;
+        let body = BuildUploadFileCreateRequest {
+            data: BuildUploadFileCreateRequestData {
+                attributes: BuildUploadFileCreateRequestAttributes {
+                    asset_type: "ASSET".into(),
+                    file_name: file_name.into(),
+                    file_size,
+                    uti: uti.to_string(),
+                },
+                relationships: BuildUploadFileCreateRequestRelationships {
+                    build_upload: Ref {
+                        data: RefData {
+                            id: build_upload_id.into(),
+                            r#type: "buildUploads".into(),
+                        },
+                    },
+                },
+                r#type: "buildUploadFiles".into(),
+            },
+        };
+        let req = self
+            .client
+            .post(self.api_endpoint(BUILD_UPLOAD_FILES_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Upload the content of a build upload file by performing its upload operations.
+    ///
+    /// Each part is read into memory, so failed part uploads can be retried.
+    pub fn upload_build_upload_file_parts(
+        &self,
+        upload_file: &BuildUploadFile,
+        path: &Path,
+    ) -> Result<()> {
+        let mut file = File::open(path)?;
+        let operations = &upload_file.attributes.upload_operations;
+
+        for (i, operation) in operations.iter().enumerate() {
+            log::warn!(
+                "uploading part {} of {} of {} ({} bytes)",
+                i + 1,
+                operations.len(),
+                path.display(),
+                operation.length
+            );
+
+            let mut data = vec![0u8; operation.length as usize];
+            file.seek(SeekFrom::Start(operation.offset))?;
+            file.read_exact(&mut data)?;
+
+            let mut req = self.client.request(
+                Method::from_bytes(operation.method.as_bytes())?,
+                &operation.url,
+            );
+            for header in &operation.request_headers {
+                req = req.header(&header.name, &header.value);
+            }
+            self.send_request(req.body(data))?;
+        }
+
+        Ok(())
+    }
+
+    /// Mark a build upload file as uploaded.
+    ///
+    /// `sha256` is the hex encoded SHA-256 digest of the entire file. App Store
+    /// Connect verifies the uploaded content against it.
+    pub fn commit_build_upload_file(
+        &self,
+        id: &str,
+        sha256: &str,
+    ) -> Result<BuildUploadFileResponse> {
+        let token = self.get_token()?;
+        let body = BuildUploadFileUpdateRequest {
+            data: BuildUploadFileUpdateRequestData {
+                attributes: BuildUploadFileUpdateRequestAttributes {
+                    source_file_checksums: Checksums {
+                        file: Checksum {
+                            hash: sha256.into(),
+                            algorithm: "SHA_256".into(),
+                        },
+                    },
+                    uploaded: true,
+                },
+                id: id.into(),
+                r#type: "buildUploadFiles".into(),
+            },
+        };
+        let req = self
+            .client
+            .patch(self.api_endpoint(&format!("{BUILD_UPLOAD_FILES_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Upload an `.ipa` or `.pkg` as a new build of an app.
+    ///
+    /// This reserves the upload, uploads the file, and commits it. It does not
+    /// wait for App Store Connect to process the upload. See
+    /// [Self::wait_for_build_upload()] for that.
+    pub fn upload_build(
+        &self,
+        app_id: &str,
+        version: &str,
+        build_number: &str,
+        platform: BundleIdPlatform,
+        path: &Path,
+    ) -> Result<BuildUploadResponse> {
+        let uti = BuildUploadFileUti::from_path(path)?;
+        let file_size = std::fs::metadata(path)?.len();
+        let file_name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("upload.{}", uti.extension()));
+        let sha256 = file_sha256(path)?;
+
+        let upload = self.create_build_upload(app_id, version, build_number, platform)?;
+        let upload_file =
+            self.create_build_upload_file(&upload.data.id, &file_name, file_size, uti)?;
+        self.upload_build_upload_file_parts(&upload_file.data, path)?;
+        self.commit_build_upload_file(&upload_file.data.id, &sha256)?;
+
+        self.get_build_upload(&upload.data.id)
+    }
+
+    /// Poll a build upload until App Store Connect finishes processing it.
+    ///
+    /// Errors with [BuildUploadWaitLimitReached] if processing doesn't finish
+    /// within `wait_limit`. The returned upload may have failed. See
+    /// [BuildUploadResponse::into_result()].
+    pub fn wait_for_build_upload(
+        &self,
+        id: &str,
+        wait_limit: Duration,
+        poll_interval: Duration,
+    ) -> Result<BuildUploadResponse> {
+        let start_time = Instant::now();
+
+        loop {
+            let upload = self.get_build_upload(id)?;
+            let elapsed = start_time.elapsed();
+
+            log::warn!(
+                "build upload {} state after {}s: {}",
+                id,
+                elapsed.as_secs(),
+                upload.data.attributes.state.state
+            );
+
+            if !matches!(
+                upload.data.attributes.state.state,
+                BuildUploadStateValue::AwaitingUpload | BuildUploadStateValue::Processing
+            ) {
+                return Ok(upload);
+            }
+
+            if elapsed >= wait_limit {
+                return Err(BuildUploadWaitLimitReached(elapsed).into());
+            }
+
+            std::thread::sleep(poll_interval);
+        }
+    }
+}
+
+/// A build upload did not finish processing within the allowed time.
+#[derive(Clone, Copy, Debug, Error)]
+#[error("build upload still processing after waiting {}s", .0.as_secs())]
+pub struct BuildUploadWaitLimitReached(pub Duration);
+
+/// App Store Connect rejected a build upload.
+#[derive(Clone, Debug, Error)]
+#[error("build upload failed: {}", .0.join("; "))]
+pub struct BuildUploadFailed(pub Vec<String>);
+
+/// Type of a build upload file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BuildUploadFileUti {
+    /// An iOS, tvOS, or visionOS app archive.
+    Ipa,
+    /// A macOS installer package.
+    Pkg,
+}
+
+impl BuildUploadFileUti {
+    /// Resolve the type of a file from its extension.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("ipa") => Ok(Self::Ipa),
+            Some("pkg") => Ok(Self::Pkg),
+            _ => Err(anyhow::anyhow!(
+                "{} is not an .ipa or .pkg file",
+                path.display()
+            )),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ipa => "ipa",
+            Self::Pkg => "pkg",
+        }
+    }
+}
+
+impl std::fmt::Display for BuildUploadFileUti {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Ipa => "com.apple.ipa",
+            Self::Pkg => "com.apple.pkg",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadCreateRequest {
+    pub data: BuildUploadCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadCreateRequestData {
+    pub attributes: BuildUploadCreateRequestAttributes,
+    pub relationships: BuildUploadCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadCreateRequestAttributes {
+    pub cf_bundle_short_version_string: String,
+    pub cf_bundle_version: String,
+    pub platform: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadCreateRequestRelationships {
+    pub app: Ref,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadResponse {
+    pub data: BuildUpload,
+}
+
+impl BuildUploadResponse {
+    /// Convert the instance into a [Result].
+    ///
+    /// Will yield [Err] if App Store Connect rejected the upload.
+    pub fn into_result(self) -> Result<Self> {
+        let state = &self.data.attributes.state;
+        match state.state {
+            BuildUploadStateValue::Failed => Err(BuildUploadFailed(
+                state
+                    .errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.code, e.description))
+                    .collect(),
+            )
+            .into()),
+            _ => Ok(self),
+        }
+    }
+
+    /// The id of the build created from this upload, if processed.
+    pub fn build_id(&self) -> Option<&str> {
+        self.data.relationships.as_ref()?["build"]["data"]["id"].as_str()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUpload {
+    pub attributes: BuildUploadAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadAttributes {
+    pub cf_bundle_short_version_string: String,
+    pub cf_bundle_version: String,
+    pub platform: String,
+    pub created_date: Option<String>,
+    pub uploaded_date: Option<String>,
+    pub state: BuildUploadState,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadState {
+    pub state: BuildUploadStateValue,
+    #[serde(default)]
+    pub errors: Vec<StateDetail>,
+    #[serde(default)]
+    pub warnings: Vec<StateDetail>,
+    #[serde(default)]
+    pub infos: Vec<StateDetail>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BuildUploadStateValue {
+    AwaitingUpload,
+    Processing,
+    Failed,
+    Complete,
+    #[serde(other)]
+    Unknown,
+}
+
+impl std::fmt::Display for BuildUploadStateValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::AwaitingUpload => "AWAITING_UPLOAD",
+            Self::Processing => "PROCESSING",
+            Self::Failed => "FAILED",
+            Self::Complete => "COMPLETE",
+            Self::Unknown => "UNKNOWN",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDetail {
+    pub code: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileCreateRequest {
+    pub data: BuildUploadFileCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileCreateRequestData {
+    pub attributes: BuildUploadFileCreateRequestAttributes,
+    pub relationships: BuildUploadFileCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileCreateRequestAttributes {
+    pub asset_type: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub uti: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileCreateRequestRelationships {
+    pub build_upload: Ref,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileUpdateRequest {
+    pub data: BuildUploadFileUpdateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileUpdateRequestData {
+    pub attributes: BuildUploadFileUpdateRequestAttributes,
+    pub id: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileUpdateRequestAttributes {
+    pub source_file_checksums: Checksums,
+    pub uploaded: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checksums {
+    pub file: Checksum,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checksum {
+    pub hash: String,
+    pub algorithm: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileResponse {
+    pub data: BuildUploadFile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFile {
+    pub attributes: BuildUploadFileAttributes,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildUploadFileAttributes {
+    pub file_name: String,
+    pub file_size: u64,
+    pub uti: String,
+    #[serde(default)]
+    pub uploaded: bool,
+    #[serde(default)]
+    pub upload_operations: Vec<UploadOperation>,
+}
+
+/// An HTTP request uploading part of a file.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadOperation {
+    pub method: String,
+    pub url: String,
+    pub offset: u64,
+    pub length: u64,
+    #[serde(default)]
+    pub request_headers: Vec<HttpHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::mock_server::MockServer, serde_json::json, std::io::Write};
+
+    #[test]
+    fn upload_build() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+        server.insert_resource(json!({
+            "type": "apps",
+            "id": "app1",
+            "attributes": {
+                "name": "Example",
+                "bundleId": "com.example.app",
+                "sku": "EXAMPLE",
+                "primaryLocale": "en-US",
+            },
+        }));
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("Example.ipa");
+        let content = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        File::create(&path)?.write_all(&content)?;
+
+        assert!(client
+            .upload_build(
+                "app1",
+                "1.0",
+                "1",
+                BundleIdPlatform::Ios,
+                &temp_dir.path().join("Example.zip")
+            )
+            .is_err());
+
+        let upload = client.upload_build("app1", "1.0", "1", BundleIdPlatform::Ios, &path)?;
+        let upload = client
+            .wait_for_build_upload(
+                &upload.data.id,
+                Duration::from_secs(1),
+                Duration::from_millis(10),
+            )?
+            .into_result()?;
+        assert_eq!(
+            upload.data.attributes.state.state,
+            BuildUploadStateValue::Complete
+        );
+
+        let build_id = upload.build_id().expect("build should be created");
+        let build = client
+            .find_build("app1", "1.0", "1")?
+            .expect("build should be found");
+        assert_eq!(build.id, build_id);
+
+        // Uploads whose content doesn't match the checksum fail processing.
+        let upload = client.create_build_upload("app1", "1.0", "2", BundleIdPlatform::Ios)?;
+        let upload_file = client.create_build_upload_file(
+            &upload.data.id,
+            "Example.ipa",
+            content.len() as u64,
+            BuildUploadFileUti::Ipa,
+        )?;
+        assert!(upload_file.data.attributes.upload_operations.len() > 1);
+        client.upload_build_upload_file_parts(&upload_file.data, &path)?;
+        client.commit_build_upload_file(&upload_file.data.id, &"0".repeat(64))?;
+        let upload = client.wait_for_build_upload(
+            &upload.data.id,
+            Duration::from_secs(1),
+            Duration::from_millis(10),
+        )?;
+        assert_eq!(
+            upload.data.attributes.state.state,
+            BuildUploadStateValue::Failed
+        );
+        assert!(upload.build_id().is_none());
+        assert!(upload.into_result().is_err());
+
+        Ok(())
+    }
+}
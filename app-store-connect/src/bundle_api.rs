@@ -4,27 +4,26 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::profile_api::{Ref, RefData};
+use crate::query::NoFilter;
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
 use crate::{AppStoreConnectClient, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-const APPLE_CERTIFICATE_URL: &str = "https://api.appstoreconnect.apple.com/v1/bundleIds";
+pub(crate) const BUNDLE_IDS_PATH: &str = "v1/bundleIds";
+const BUNDLE_ID_CAPABILITIES_PATH: &str = "v1/bundleIdCapabilities";
 
 impl AppStoreConnectClient {
     pub fn register_bundle_id(&self, identifier: &str, name: &str) -> Result<BundleIdResponse> {
         let token = self.get_token()?;
-        let body = BundleIdCreateRequest {
-            data: BundleIdCreateRequestData {
-                attributes: BundleIdCreateRequestAttributes {
-                    identifier: identifier.into(),
-                    name: name.into(),
-                    platform: "UNIVERSAL".into(),
-                },
-                r#type: "bundleIds".into(),
-            },
-        };
+        let body = BundleIdCreateRequest::new(identifier, name);
         let req = self
             .client
-            .post(APPLE_CERTIFICATE_URL)
+            .post(self.api_endpoint(BUNDLE_IDS_PATH))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
@@ -32,31 +31,123 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// List all bundle IDs, following pagination.
     pub fn list_bundle_ids(&self) -> Result<BundleIdsResponse> {
+        self.bundle_ids(&ListQuery::new().limit(MAX_PAGE_LIMIT))?
+            .collect_all()
+    }
+
+    /// Obtain a [Paginator] over bundle IDs matching a query.
+    pub fn bundle_ids(
+        &self,
+        query: &ListQuery<BundleIdFilter>,
+    ) -> Result<Paginator<'_, BundleIdsResponse>> {
+        self.paginate(BUNDLE_IDS_PATH, query)
+    }
+
+    pub fn get_bundle_id(&self, id: &str) -> Result<BundleIdResponse> {
         let token = self.get_token()?;
         let req = self
             .client
-            .get(APPLE_CERTIFICATE_URL)
+            .get(self.api_endpoint(&format!("{BUNDLE_IDS_PATH}/{id}")))
             .bearer_auth(token)
             .header("Accept", "application/json");
         Ok(self.send_request(req)?.json()?)
     }
 
-    pub fn get_bundle_id(&self, id: &str) -> Result<BundleIdResponse> {
+    pub fn delete_bundle_id(&self, id: &str) -> Result<()> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .delete(self.api_endpoint(&format!("{BUNDLE_IDS_PATH}/{id}")))
+            .bearer_auth(token);
+        self.send_request(req)?;
+        Ok(())
+    }
+
+    /// List all capabilities enabled for a bundle ID, following pagination.
+    pub fn list_bundle_id_capabilities(
+        &self,
+        bundle_id: &str,
+    ) -> Result<BundleIdCapabilitiesResponse> {
+        self.paginate(
+            &format!("{BUNDLE_IDS_PATH}/{bundle_id}/bundleIdCapabilities"),
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )?
+        .collect_all()
+    }
+
+    /// Enable a capability for a bundle ID.
+    pub fn enable_bundle_id_capability(
+        &self,
+        bundle_id: &str,
+        capability_type: CapabilityType,
+        settings: &[CapabilitySetting],
+    ) -> Result<BundleIdCapabilityResponse> {
         let token = self.get_token()?;
+        let body = BundleIdCapabilityCreateRequest {
+            data: BundleIdCapabilityCreateRequestData {
+                attributes: BundleIdCapabilityRequestAttributes {
+                    capability_type: capability_type.to_string(),
+                    settings: settings.to_vec(),
+                },
+                relationships: BundleIdCapabilityCreateRequestRelationships {
+                    bundle_id: Ref {
+                        data: RefData {
+                            id: bundle_id.into(),
+                            r#type: "bundleIds".into(),
+                        },
+                    },
+                },
+                r#type: "bundleIdCapabilities".into(),
+            },
+        };
         let req = self
             .client
-            .get(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .post(self.api_endpoint(BUNDLE_ID_CAPABILITIES_PATH))
             .bearer_auth(token)
-            .header("Accept", "application/json");
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
         Ok(self.send_request(req)?.json()?)
     }
 
-    pub fn delete_bundle_id(&self, id: &str) -> Result<()> {
+    /// Replace the settings of an enabled capability.
+    pub fn update_bundle_id_capability(
+        &self,
+        id: &str,
+        capability_type: CapabilityType,
+        settings: &[CapabilitySetting],
+    ) -> Result<BundleIdCapabilityResponse> {
+        let token = self.get_token()?;
+        let body = BundleIdCapabilityUpdateRequest {
+            data: BundleIdCapabilityUpdateRequestData {
+                attributes: BundleIdCapabilityRequestAttributes {
+                    capability_type: capability_type.to_string(),
+                    settings: settings.to_vec(),
+                },
+                id: id.into(),
+                r#type: "bundleIdCapabilities".into(),
+            },
+        };
+        let req = self
+            .client
+            .patch(self.api_endpoint(&format!("{BUNDLE_ID_CAPABILITIES_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Disable a capability.
+    ///
+    /// `id` is the id of the `bundleIdCapabilities` resource, not of the bundle ID.
+    pub fn disable_bundle_id_capability(&self, id: &str) -> Result<()> {
         let token = self.get_token()?;
         let req = self
             .client
-            .delete(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .delete(self.api_endpoint(&format!("{BUNDLE_ID_CAPABILITIES_PATH}/{id}")))
             .bearer_auth(token);
         self.send_request(req)?;
         Ok(())
@@ -69,6 +160,21 @@ pub struct BundleIdCreateRequest {
     pub data: BundleIdCreateRequestData,
 }
 
+impl BundleIdCreateRequest {
+    pub fn new(identifier: &str, name: &str) -> Self {
+        Self {
+            data: BundleIdCreateRequestData {
+                attributes: BundleIdCreateRequestAttributes {
+                    identifier: identifier.into(),
+                    name: name.into(),
+                    platform: "UNIVERSAL".into(),
+                },
+                r#type: "bundleIds".into(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BundleIdCreateRequestData {
@@ -110,13 +216,22 @@ pub struct BundleIdResponse {
 #[serde(rename_all = "camelCase")]
 pub struct BundleIdsResponse {
     pub data: Vec<BundleId>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
 }
 
+impl_page!(BundleIdsResponse, BundleId);
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BundleId {
     pub attributes: BundleIdAttributes,
     pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -127,3 +242,381 @@ pub struct BundleIdAttributes {
     pub platform: String,
     pub seed_id: String,
 }
+
+/// Filters for listing bundle IDs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BundleIdFilter {
+    Id(String),
+    Identifier(String),
+    Name(String),
+    Platform(BundleIdPlatform),
+    SeedId(String),
+}
+
+impl QueryFilter for BundleIdFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::Identifier(_) => "identifier",
+            Self::Name(_) => "name",
+            Self::Platform(_) => "platform",
+            Self::SeedId(_) => "seedId",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::Identifier(v) | Self::Name(v) | Self::SeedId(v) => v.clone(),
+            Self::Platform(v) => v.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityCreateRequest {
+    pub data: BundleIdCapabilityCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityCreateRequestData {
+    pub attributes: BundleIdCapabilityRequestAttributes,
+    pub relationships: BundleIdCapabilityCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityCreateRequestRelationships {
+    pub bundle_id: Ref,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityUpdateRequest {
+    pub data: BundleIdCapabilityUpdateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityUpdateRequestData {
+    pub attributes: BundleIdCapabilityRequestAttributes,
+    pub id: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityRequestAttributes {
+    pub capability_type: String,
+    pub settings: Vec<CapabilitySetting>,
+}
+
+/// A capability that can be enabled for a bundle ID.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum CapabilityType {
+    AccessWifiInformation,
+    AppGroups,
+    AppleIdAuth,
+    ApplePay,
+    AssociatedDomains,
+    AutofillCredentialProvider,
+    Classkit,
+    CoremediaHlsLowLatency,
+    DataProtection,
+    GameCenter,
+    Healthkit,
+    Homekit,
+    HotSpot,
+    Icloud,
+    InAppPurchase,
+    InterAppAudio,
+    Maps,
+    Multipath,
+    NetworkCustomProtocol,
+    NetworkExtensions,
+    NfcTagReading,
+    PersonalVpn,
+    PushNotifications,
+    Sirikit,
+    SystemExtensionInstall,
+    UserManagement,
+    Wallet,
+    WirelessAccessoryConfiguration,
+}
+
+impl std::fmt::Display for CapabilityType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::AccessWifiInformation => "ACCESS_WIFI_INFORMATION",
+            Self::AppGroups => "APP_GROUPS",
+            Self::AppleIdAuth => "APPLE_ID_AUTH",
+            Self::ApplePay => "APPLE_PAY",
+            Self::AssociatedDomains => "ASSOCIATED_DOMAINS",
+            Self::AutofillCredentialProvider => "AUTOFILL_CREDENTIAL_PROVIDER",
+            Self::Classkit => "CLASSKIT",
+            Self::CoremediaHlsLowLatency => "COREMEDIA_HLS_LOW_LATENCY",
+            Self::DataProtection => "DATA_PROTECTION",
+            Self::GameCenter => "GAME_CENTER",
+            Self::Healthkit => "HEALTHKIT",
+            Self::Homekit => "HOMEKIT",
+            Self::HotSpot => "HOT_SPOT",
+            Self::Icloud => "ICLOUD",
+            Self::InAppPurchase => "IN_APP_PURCHASE",
+            Self::InterAppAudio => "INTER_APP_AUDIO",
+            Self::Maps => "MAPS",
+            Self::Multipath => "MULTIPATH",
+            Self::NetworkCustomProtocol => "NETWORK_CUSTOM_PROTOCOL",
+            Self::NetworkExtensions => "NETWORK_EXTENSIONS",
+            Self::NfcTagReading => "NFC_TAG_READING",
+            Self::PersonalVpn => "PERSONAL_VPN",
+            Self::PushNotifications => "PUSH_NOTIFICATIONS",
+            Self::Sirikit => "SIRIKIT",
+            Self::SystemExtensionInstall => "SYSTEM_EXTENSION_INSTALL",
+            Self::UserManagement => "USER_MANAGEMENT",
+            Self::Wallet => "WALLET",
+            Self::WirelessAccessoryConfiguration => "WIRELESS_ACCESSORY_CONFIGURATION",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// The key of a [CapabilitySetting].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CapabilitySettingKey {
+    /// Sign in with Apple app consent. Takes [CapabilityOptionKey::PrimaryAppConsent].
+    AppleIdAuthAppConsent,
+    /// Data protection level. Takes one of the `*Protection*` options.
+    DataProtectionPermissionLevel,
+    /// iCloud version. Takes [CapabilityOptionKey::Xcode5] or [CapabilityOptionKey::Xcode6].
+    IcloudVersion,
+    /// A key not known to this crate.
+    #[serde(other)]
+    #[value(skip)]
+    Unknown,
+}
+
+/// The key of a [CapabilityOption].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CapabilityOptionKey {
+    CompleteProtection,
+    PrimaryAppConsent,
+    ProtectedUnlessOpen,
+    ProtectedUntilFirstUserAuth,
+    #[serde(rename = "XCODE_5")]
+    Xcode5,
+    #[serde(rename = "XCODE_6")]
+    Xcode6,
+    /// An option not known to this crate.
+    #[serde(other)]
+    #[value(skip)]
+    Unknown,
+}
+
+/// A setting of a bundle ID capability.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitySetting {
+    pub key: CapabilitySettingKey,
+    pub options: Vec<CapabilityOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl CapabilitySetting {
+    /// Construct a setting with the given options enabled.
+    pub fn new(
+        key: CapabilitySettingKey,
+        options: impl IntoIterator<Item = CapabilityOptionKey>,
+    ) -> Self {
+        Self {
+            key,
+            options: options
+                .into_iter()
+                .map(|key| CapabilityOption {
+                    key,
+                    enabled: Some(true),
+                    name: None,
+                    description: None,
+                })
+                .collect(),
+            name: None,
+            description: None,
+        }
+    }
+}
+
+impl std::str::FromStr for CapabilitySetting {
+    type Err = String;
+
+    /// Parse a `<key>=<option>[,<option>...]` string, e.g. `icloud-version=xcode6`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use clap::ValueEnum;
+
+        let (key, options) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <key>=<option>[,<option>...]; got {s}"))?;
+
+        let key = CapabilitySettingKey::from_str(key, true)?;
+        let options = options
+            .split(',')
+            .map(|option| CapabilityOptionKey::from_str(option, true))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(key, options))
+    }
+}
+
+/// An option of a [CapabilitySetting].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilityOption {
+    pub key: CapabilityOptionKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityResponse {
+    pub data: BundleIdCapability,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilitiesResponse {
+    pub data: Vec<BundleIdCapability>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(BundleIdCapabilitiesResponse, BundleIdCapability);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapability {
+    pub attributes: BundleIdCapabilityAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleIdCapabilityAttributes {
+    pub capability_type: String,
+    #[serde(default)]
+    pub settings: Vec<CapabilitySetting>,
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::mock_server::MockServer};
+
+    #[test]
+    fn capability_setting_parse() {
+        assert_eq!(
+            "icloud-version=xcode6".parse::<CapabilitySetting>(),
+            Ok(CapabilitySetting::new(
+                CapabilitySettingKey::IcloudVersion,
+                [CapabilityOptionKey::Xcode6]
+            ))
+        );
+        assert!("icloud-version".parse::<CapabilitySetting>().is_err());
+        assert!("icloud-version=bogus".parse::<CapabilitySetting>().is_err());
+
+        assert_eq!(
+            serde_json::to_value(CapabilitySetting::new(
+                CapabilitySettingKey::IcloudVersion,
+                [CapabilityOptionKey::Xcode5]
+            ))
+            .unwrap(),
+            serde_json::json!({
+                "key": "ICLOUD_VERSION",
+                "options": [{ "key": "XCODE_5", "enabled": true }],
+            })
+        );
+
+        // Keys added by Apple don't break deserialization.
+        let setting = serde_json::from_value::<CapabilitySetting>(serde_json::json!({
+            "key": "NEW_SETTING",
+            "options": [{ "key": "NEW_OPTION", "enabled": false }],
+        }))
+        .unwrap();
+        assert_eq!(setting.key, CapabilitySettingKey::Unknown);
+        assert_eq!(setting.options[0].key, CapabilityOptionKey::Unknown);
+        assert!("unknown=xcode6".parse::<CapabilitySetting>().is_err());
+    }
+
+    #[test]
+    fn capabilities() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        let bundle = client.register_bundle_id("com.example.app", "Example")?;
+        let other = client.register_bundle_id("com.example.other", "Other")?;
+
+        let push = client.enable_bundle_id_capability(
+            &bundle.data.id,
+            CapabilityType::PushNotifications,
+            &[],
+        )?;
+        assert_eq!(push.data.attributes.capability_type, "PUSH_NOTIFICATIONS");
+
+        let icloud = client.enable_bundle_id_capability(
+            &bundle.data.id,
+            CapabilityType::Icloud,
+            &[CapabilitySetting::new(
+                CapabilitySettingKey::IcloudVersion,
+                [CapabilityOptionKey::Xcode5],
+            )],
+        )?;
+        client.enable_bundle_id_capability(&other.data.id, CapabilityType::AppGroups, &[])?;
+
+        // Enabling the same capability twice is a conflict.
+        assert!(client
+            .enable_bundle_id_capability(&bundle.data.id, CapabilityType::Icloud, &[])
+            .is_err());
+
+        let updated = client.update_bundle_id_capability(
+            &icloud.data.id,
+            CapabilityType::Icloud,
+            &[CapabilitySetting::new(
+                CapabilitySettingKey::IcloudVersion,
+                [CapabilityOptionKey::Xcode6],
+            )],
+        )?;
+        assert_eq!(
+            updated.data.attributes.settings[0].options[0].key,
+            CapabilityOptionKey::Xcode6
+        );
+
+        let mut types = client
+            .list_bundle_id_capabilities(&bundle.data.id)?
+            .data
+            .into_iter()
+            .map(|x| x.attributes.capability_type)
+            .collect::<Vec<_>>();
+        types.sort();
+        assert_eq!(types, vec!["ICLOUD", "PUSH_NOTIFICATIONS"]);
+
+        client.disable_bundle_id_capability(&push.data.id)?;
+        let capabilities = client.list_bundle_id_capabilities(&bundle.data.id)?;
+        assert_eq!(capabilities.data.len(), 1);
+        assert_eq!(capabilities.data[0].id, icloud.data.id);
+
+        Ok(())
+    }
+}
@@ -4,18 +4,27 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::{AppStoreConnectClient, Result};
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
+use crate::{ApiKeySource, AppStoreConnectClient, Result};
 use base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine};
 use rand::rngs::OsRng;
 use rsa::pkcs8::{EncodePrivateKey, LineEnding};
 use rsa::RsaPrivateKey;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use x509_certificate::{InMemorySigningKeyPair, X509CertificateBuilder};
 
-pub fn generate_signing_certificate(api_key: &Path, ty: CertificateType, pem: &Path) -> Result<()> {
+pub fn generate_signing_certificate(
+    api_key: &ApiKeySource,
+    ty: CertificateType,
+    pem: &Path,
+) -> Result<()> {
     let secret = RsaPrivateKey::new(&mut OsRng, 2048)?;
     let key = InMemorySigningKeyPair::from_pkcs8_der(secret.to_pkcs8_der()?.as_bytes())?;
     let mut builder = X509CertificateBuilder::default();
@@ -26,7 +35,7 @@ pub fn generate_signing_certificate(api_key: &Path, ty: CertificateType, pem: &P
     let csr = builder
         .create_certificate_signing_request(&key)?
         .encode_pem()?;
-    let cer = AppStoreConnectClient::from_json_path(api_key)?
+    let cer = AppStoreConnectClient::from_api_key_source(api_key)?
         .create_certificate(csr, ty)?
         .data
         .attributes
@@ -38,7 +47,7 @@ pub fn generate_signing_certificate(api_key: &Path, ty: CertificateType, pem: &P
     Ok(())
 }
 
-const APPLE_CERTIFICATE_URL: &str = "https://api.appstoreconnect.apple.com/v1/certificates";
+pub(crate) const CERTIFICATES_PATH: &str = "v1/certificates";
 
 impl AppStoreConnectClient {
     pub fn create_certificate(
@@ -47,18 +56,10 @@ impl AppStoreConnectClient {
         ty: CertificateType,
     ) -> Result<CertificateResponse> {
         let token = self.get_token()?;
-        let body = CertificateCreateRequest {
-            data: CertificateCreateRequestData {
-                attributes: CertificateCreateRequestAttributes {
-                    certificate_type: ty.to_string(),
-                    csr_content: csr,
-                },
-                r#type: "certificates".into(),
-            },
-        };
+        let body = CertificateCreateRequest::new(csr, ty);
         let req = self
             .client
-            .post(APPLE_CERTIFICATE_URL)
+            .post(self.api_endpoint(CERTIFICATES_PATH))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
@@ -66,21 +67,25 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// List all certificates, following pagination.
     pub fn list_certificates(&self) -> Result<CertificatesResponse> {
-        let token = self.get_token()?;
-        let req = self
-            .client
-            .get(APPLE_CERTIFICATE_URL)
-            .bearer_auth(token)
-            .header("Accept", "application/json");
-        Ok(self.send_request(req)?.json()?)
+        self.certificates(&ListQuery::new().limit(MAX_PAGE_LIMIT))?
+            .collect_all()
+    }
+
+    /// Obtain a [Paginator] over certificates matching a query.
+    pub fn certificates(
+        &self,
+        query: &ListQuery<CertificateFilter>,
+    ) -> Result<Paginator<'_, CertificatesResponse>> {
+        self.paginate(CERTIFICATES_PATH, query)
     }
 
     pub fn get_certificate(&self, id: &str) -> Result<CertificateResponse> {
         let token = self.get_token()?;
         let req = self
             .client
-            .get(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .get(self.api_endpoint(&format!("{CERTIFICATES_PATH}/{id}")))
             .bearer_auth(token)
             .header("Accept", "application/json");
         Ok(self.send_request(req)?.json()?)
@@ -90,7 +95,7 @@ impl AppStoreConnectClient {
         let token = self.get_token()?;
         let req = self
             .client
-            .delete(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .delete(self.api_endpoint(&format!("{CERTIFICATES_PATH}/{id}")))
             .bearer_auth(token);
         self.send_request(req)?;
         Ok(())
@@ -103,6 +108,20 @@ pub struct CertificateCreateRequest {
     pub data: CertificateCreateRequestData,
 }
 
+impl CertificateCreateRequest {
+    pub fn new(csr: String, ty: CertificateType) -> Self {
+        Self {
+            data: CertificateCreateRequestData {
+                attributes: CertificateCreateRequestAttributes {
+                    certificate_type: ty.to_string(),
+                    csr_content: csr,
+                },
+                r#type: "certificates".into(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CertificateCreateRequestData {
@@ -145,13 +164,22 @@ pub struct CertificateResponse {
 #[serde(rename_all = "camelCase")]
 pub struct CertificatesResponse {
     pub data: Vec<Certificate>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
 }
 
+impl_page!(CertificatesResponse, Certificate);
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Certificate {
     pub attributes: CertificateAttributes,
     pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -165,3 +193,30 @@ pub struct CertificateAttributes {
     pub serial_number: String,
     pub certificate_type: String,
 }
+
+/// Filters for listing certificates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CertificateFilter {
+    Id(String),
+    CertificateType(CertificateType),
+    DisplayName(String),
+    SerialNumber(String),
+}
+
+impl QueryFilter for CertificateFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::CertificateType(_) => "certificateType",
+            Self::DisplayName(_) => "displayName",
+            Self::SerialNumber(_) => "serialNumber",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::DisplayName(v) | Self::SerialNumber(v) => v.clone(),
+            Self::CertificateType(v) => v.to_string(),
+        }
+    }
+}
@@ -4,25 +4,78 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::bundle_api::{BundleId, BundleIdPlatform};
+use crate::api_key::{ApiKeySource, PASSPHRASE_ENV, PRIVATE_KEY_ENV};
+use crate::build_upload_api::{BuildUploadFileUti, BuildUploadResponse};
+use crate::bundle_api::{
+    BundleId, BundleIdCapability, BundleIdPlatform, CapabilitySetting, CapabilityType,
+};
 use crate::certs_api::{self, Certificate, CertificateType};
 use crate::device_api::Device;
+use crate::notary_api::{SubmissionResponseData, SubmissionResponseStatus};
 use crate::profile_api::{Profile, ProfileType};
+use crate::provisioning_profile::ProvisioningProfile;
 use crate::{AppStoreConnectClient, UnifiedApiKey};
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    #[clap(flatten)]
+    pub api_key: ApiKeyArgs,
+    #[clap(subcommand)]
+    pub command: Commands,
+}
+
+impl Args {
+    pub fn run(self) -> Result<()> {
+        self.command.run(&self.api_key)
+    }
+}
+
+/// Arguments selecting the API key.
+///
+/// The key is only resolved by commands talking to App Store Connect.
+#[derive(clap::Args)]
+pub struct ApiKeyArgs {
     /// Path to unified api key.
+    ///
+    /// Files written encrypted by create-api-key are decrypted with the
+    /// passphrase in APP_STORE_CONNECT_API_KEY_PASSPHRASE. Unencrypted files are
+    /// read as is.
     #[clap(long, global = true)]
     pub api_key: Option<PathBuf>,
-    #[clap(subcommand)]
-    pub command: Commands,
+    /// Command printing a unified api key to stdout.
+    ///
+    /// e.g. `security find-generic-password -s app-store-connect -w` to read a
+    /// key stored in the macOS keychain.
+    #[clap(long, global = true, conflicts_with = "api_key")]
+    pub api_key_command: Option<String>,
+}
+
+impl ApiKeyArgs {
+    /// Resolve where to obtain the API key from.
+    ///
+    /// Falls back to environment variables if neither `--api-key` nor
+    /// `--api-key-command` is given.
+    pub fn source(&self) -> Result<ApiKeySource> {
+        if let Some(command) = &self.api_key_command {
+            Ok(ApiKeySource::Command(command.clone()))
+        } else if let Some(path) = &self.api_key {
+            Ok(ApiKeySource::JsonFile {
+                path: path.clone(),
+                passphrase: std::env::var(PASSPHRASE_ENV).ok(),
+            })
+        } else if std::env::var_os(PRIVATE_KEY_ENV).is_some() {
+            Ok(ApiKeySource::Environment)
+        } else {
+            anyhow::bail!("missing --api-key");
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -36,6 +89,9 @@ pub enum Commands {
         pem: PathBuf,
     },
     /// Creates a unified api key.
+    ///
+    /// The key is written to --api-key, encrypted with the passphrase in
+    /// APP_STORE_CONNECT_API_KEY_PASSPHRASE if set.
     CreateApiKey {
         /// Issuer id.
         #[clap(long)]
@@ -58,35 +114,158 @@ pub enum Commands {
         #[clap(subcommand)]
         command: DeviceCommand,
     },
+    Notary {
+        #[clap(subcommand)]
+        command: NotaryCommand,
+    },
     Profile {
         #[clap(subcommand)]
         command: ProfileCommand,
     },
+    /// Upload an .ipa or .pkg as a new build of an app.
+    Upload {
+        /// Id of the app.
+        #[clap(
+            long,
+            conflicts_with = "bundle_id",
+            required_unless_present = "bundle_id"
+        )]
+        app_id: Option<String>,
+        /// Bundle identifier of the app. Used to look up its id.
+        #[clap(long)]
+        bundle_id: Option<String>,
+        /// Marketing version (CFBundleShortVersionString) of the build.
+        #[clap(long)]
+        bundle_short_version_string: String,
+        /// Build number (CFBundleVersion) of the build.
+        #[clap(long)]
+        bundle_version: String,
+        /// Platform of the build. Defaults to macOS for .pkg and iOS otherwise.
+        #[clap(long)]
+        platform: Option<BundleIdPlatform>,
+        /// Wait for App Store Connect to finish processing the build.
+        #[clap(long)]
+        wait: bool,
+        /// Maximum number of seconds to wait.
+        #[clap(long, default_value = "1800")]
+        max_wait_seconds: u64,
+        /// Path to file to upload.
+        path: PathBuf,
+    },
 }
 
 impl Commands {
-    pub fn run(self, api_key: &Path) -> Result<()> {
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
         match self {
             Self::GenerateSigningCertificate { r#type, pem } => {
-                certs_api::generate_signing_certificate(api_key, r#type, &pem)?;
+                certs_api::generate_signing_certificate(&api_key.source()?, r#type, &pem)?;
             }
             Self::CreateApiKey {
                 issuer_id,
                 key_id,
                 private_key,
             } => {
-                UnifiedApiKey::from_ecdsa_pem_path(issuer_id, key_id, private_key)?
-                    .write_json_file(api_key)?;
+                let key = UnifiedApiKey::from_ecdsa_pem_path(issuer_id, key_id, private_key)?;
+                match api_key.source()? {
+                    ApiKeySource::JsonFile {
+                        path,
+                        passphrase: None,
+                    } => key.write_json_file(path)?,
+                    ApiKeySource::JsonFile {
+                        path,
+                        passphrase: Some(passphrase),
+                    } => key.write_encrypted_json_file(path, &passphrase)?,
+                    _ => anyhow::bail!("create-api-key requires --api-key"),
+                }
             }
             Self::Bundle { command } => command.run(api_key)?,
             Self::Certificate { command } => command.run(api_key)?,
             Self::Device { command } => command.run(api_key)?,
+            Self::Notary { command } => command.run(api_key)?,
             Self::Profile { command } => command.run(api_key)?,
+            Self::Upload {
+                app_id,
+                bundle_id,
+                bundle_short_version_string,
+                bundle_version,
+                platform,
+                wait,
+                max_wait_seconds,
+                path,
+            } => {
+                let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
+                let app_id = match (app_id, bundle_id) {
+                    (Some(app_id), _) => app_id,
+                    (None, Some(bundle_id)) => {
+                        client
+                            .find_app_by_bundle_id(&bundle_id)?
+                            .ok_or_else(|| anyhow::anyhow!("no app with bundle id {bundle_id}"))?
+                            .id
+                    }
+                    (None, None) => unreachable!("clap requires one of --app-id or --bundle-id"),
+                };
+                let platform =
+                    platform.unwrap_or_else(|| match BuildUploadFileUti::from_path(&path) {
+                        Ok(BuildUploadFileUti::Pkg) => BundleIdPlatform::MacOs,
+                        _ => BundleIdPlatform::Ios,
+                    });
+
+                let upload = client.upload_build(
+                    &app_id,
+                    &bundle_short_version_string,
+                    &bundle_version,
+                    platform,
+                    &path,
+                )?;
+                println!("build upload id: {}", upload.data.id);
+
+                if wait {
+                    let wait_limit = Duration::from_secs(max_wait_seconds);
+                    let start_time = std::time::Instant::now();
+                    let upload = client
+                        .wait_for_build_upload(
+                            &upload.data.id,
+                            wait_limit,
+                            Duration::from_secs(10),
+                        )?
+                        .into_result()?;
+                    print_build_upload(&upload);
+
+                    if let Some(build_id) = upload.build_id() {
+                        let build = client.wait_for_build_processing(
+                            build_id,
+                            wait_limit.saturating_sub(start_time.elapsed()),
+                            Duration::from_secs(10),
+                        )?;
+                        println!(
+                            "build {} processing state: {}",
+                            build.data.id, build.data.attributes.processing_state
+                        );
+                    }
+                }
+            }
         }
         Ok(())
     }
 }
 
+fn print_build_upload(upload: &BuildUploadResponse) {
+    let attributes = &upload.data.attributes;
+    println!(
+        "build upload {} ({} ({})): {}",
+        upload.data.id,
+        attributes.cf_bundle_short_version_string,
+        attributes.cf_bundle_version,
+        attributes.state.state
+    );
+    for warning in &attributes.state.warnings {
+        println!("warning: {}: {}", warning.code, warning.description);
+    }
+    if let Some(build_id) = upload.build_id() {
+        println!("build id: {build_id}");
+    }
+}
+
 #[derive(Subcommand)]
 pub enum BundleCommand {
     Register {
@@ -106,11 +285,16 @@ pub enum BundleCommand {
         /// Id of bundle id to revoke.
         id: String,
     },
+    /// Manage capabilities of a bundle id.
+    Capability {
+        #[clap(subcommand)]
+        command: BundleCapabilityCommand,
+    },
 }
 
 impl BundleCommand {
-    pub fn run(self, api_key: &Path) -> Result<()> {
-        let client = AppStoreConnectClient::from_json_path(api_key)?;
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
+        let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
         match self {
             Self::Register { identifier, name } => {
                 let resp = client.register_bundle_id(&identifier, &name)?;
@@ -132,6 +316,7 @@ impl BundleCommand {
             Self::Delete { id } => {
                 client.delete_bundle_id(&id)?;
             }
+            Self::Capability { command } => command.run(&client)?,
         }
         Ok(())
     }
@@ -148,6 +333,112 @@ fn print_bundle_id(bundle_id: &BundleId) {
     );
 }
 
+#[derive(Subcommand)]
+pub enum BundleCapabilityCommand {
+    /// List capabilities enabled for a bundle id.
+    List {
+        /// Id of bundle id.
+        bundle_id: String,
+    },
+    /// Enable a capability for a bundle id.
+    Enable {
+        /// Id of bundle id.
+        #[clap(long)]
+        bundle_id: String,
+        /// Capability type.
+        #[clap(long)]
+        capability: CapabilityType,
+        /// Capability setting in the form <key>=<option>[,<option>...].
+        #[clap(long)]
+        setting: Vec<CapabilitySetting>,
+    },
+    /// Replace the settings of an enabled capability.
+    Update {
+        /// Id of capability.
+        id: String,
+        /// Capability type.
+        #[clap(long)]
+        capability: CapabilityType,
+        /// Capability setting in the form <key>=<option>[,<option>...].
+        #[clap(long)]
+        setting: Vec<CapabilitySetting>,
+    },
+    /// Disable a capability.
+    Disable {
+        /// Id of capability.
+        id: String,
+    },
+}
+
+impl BundleCapabilityCommand {
+    pub fn run(self, client: &AppStoreConnectClient) -> Result<()> {
+        match self {
+            Self::List { bundle_id } => {
+                let resp = client.list_bundle_id_capabilities(&bundle_id)?;
+                print_capability_header();
+                for capability in &resp.data {
+                    print_capability(capability);
+                }
+            }
+            Self::Enable {
+                bundle_id,
+                capability,
+                setting,
+            } => {
+                let resp = client.enable_bundle_id_capability(&bundle_id, capability, &setting)?;
+                print_capability_header();
+                print_capability(&resp.data);
+            }
+            Self::Update {
+                id,
+                capability,
+                setting,
+            } => {
+                let resp = client.update_bundle_id_capability(&id, capability, &setting)?;
+                print_capability_header();
+                print_capability(&resp.data);
+            }
+            Self::Disable { id } => {
+                client.disable_bundle_id_capability(&id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_capability_header() {
+    println!("{: <30} | {: <30} | {: <30}", "id", "type", "settings");
+}
+
+fn print_capability(capability: &BundleIdCapability) {
+    let settings = capability
+        .attributes
+        .settings
+        .iter()
+        .map(|setting| {
+            let options = setting
+                .options
+                .iter()
+                .filter(|option| option.enabled != Some(false))
+                .filter_map(|option| option.key.to_possible_value())
+                .map(|value| value.get_name().to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let key = setting
+                .key
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default();
+            format!("{key}={options}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!(
+        "{: <30} | {: <30} | {: <30}",
+        capability.id, capability.attributes.capability_type, settings,
+    );
+}
+
 #[derive(Subcommand)]
 pub enum CertificateCommand {
     Create {
@@ -169,8 +460,8 @@ pub enum CertificateCommand {
 }
 
 impl CertificateCommand {
-    pub fn run(self, api_key: &Path) -> Result<()> {
-        let client = AppStoreConnectClient::from_json_path(api_key)?;
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
+        let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
         match self {
             Self::Create { csr, r#type } => {
                 let csr = std::fs::read_to_string(csr)?;
@@ -237,8 +528,8 @@ pub enum DeviceCommand {
 }
 
 impl DeviceCommand {
-    pub fn run(self, api_key: &Path) -> Result<()> {
-        let client = AppStoreConnectClient::from_json_path(api_key)?;
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
+        let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
         match self {
             Self::Register {
                 name,
@@ -281,6 +572,116 @@ fn print_device(device: &Device) {
     );
 }
 
+#[derive(Subcommand)]
+pub enum NotaryCommand {
+    /// List previous submissions.
+    List {
+        /// Only list submissions with this status. Can be repeated.
+        #[clap(long)]
+        status: Vec<SubmissionResponseStatus>,
+    },
+    /// Print the status of a submission.
+    Info {
+        /// Id of submission.
+        id: String,
+    },
+    /// Print the log of a completed submission.
+    Log {
+        /// Id of submission.
+        id: String,
+    },
+    /// Submit a .zip, .dmg, or .pkg file for notarization.
+    Submit {
+        /// Wait for the notary service to finish processing the submission.
+        #[clap(long)]
+        wait: bool,
+        /// Maximum number of seconds to wait.
+        #[clap(long, default_value = "600")]
+        max_wait_seconds: u64,
+        /// Path to file to notarize.
+        path: PathBuf,
+    },
+    /// Wait for a submission to finish processing.
+    Wait {
+        /// Maximum number of seconds to wait.
+        #[clap(long, default_value = "600")]
+        max_wait_seconds: u64,
+        /// Id of submission.
+        id: String,
+    },
+}
+
+impl NotaryCommand {
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
+        let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
+        let wait = |id: &str, max_wait_seconds: u64| -> Result<()> {
+            let resp = client.wait_for_submission(
+                id,
+                Duration::from_secs(max_wait_seconds),
+                Duration::from_secs(3),
+            )?;
+            print_submission_header();
+            print_submission(&resp.data);
+            resp.into_result()?;
+            Ok(())
+        };
+
+        match self {
+            Self::List { status } => {
+                let submissions = client.list_submissions_with_status(&status)?;
+                print_submission_header();
+                for submission in &submissions {
+                    print_submission(submission);
+                }
+            }
+            Self::Info { id } => {
+                let resp = client.get_submission(&id)?;
+                print_submission_header();
+                print_submission(&resp.data);
+            }
+            Self::Log { id } => {
+                let log = client.get_submission_log(&id)?;
+                println!("{}", serde_json::to_string_pretty(&log)?);
+            }
+            Self::Submit {
+                wait: should_wait,
+                max_wait_seconds,
+                path,
+            } => {
+                let submission = client.submit_file(&path)?;
+                println!("submission id: {}", submission.data.id);
+                if should_wait {
+                    wait(&submission.data.id, max_wait_seconds)?;
+                }
+            }
+            Self::Wait {
+                max_wait_seconds,
+                id,
+            } => {
+                wait(&id, max_wait_seconds)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_submission_header() {
+    println!(
+        "{: <36} | {: <28} | {: <11} | {: <30}",
+        "id", "created", "status", "name"
+    );
+}
+
+fn print_submission(submission: &SubmissionResponseData) {
+    println!(
+        "{: <36} | {: <28} | {: <11} | {: <30}",
+        submission.id,
+        submission.attributes.created_date,
+        submission.attributes.status.to_string(),
+        submission.attributes.name,
+    );
+}
+
 #[derive(Subcommand)]
 pub enum ProfileCommand {
     Create {
@@ -309,11 +710,30 @@ pub enum ProfileCommand {
         /// Id of device.
         id: String,
     },
+    /// Download a profile and install it for Xcode or embed it in a bundle.
+    Install {
+        /// Id of profile.
+        id: String,
+        /// Path to a bundle to embed the profile in.
+        #[clap(long)]
+        bundle: Option<PathBuf>,
+    },
+    /// Print the contents of a provisioning profile file.
+    Inspect {
+        /// Path to provisioning profile.
+        path: PathBuf,
+    },
 }
 
 impl ProfileCommand {
-    pub fn run(self, api_key: &Path) -> Result<()> {
-        let client = AppStoreConnectClient::from_json_path(api_key)?;
+    pub fn run(self, api_key: &ApiKeyArgs) -> Result<()> {
+        // Inspecting a local file doesn't require an API key.
+        if let Self::Inspect { path } = &self {
+            print_provisioning_profile(&ProvisioningProfile::from_path(path)?);
+            return Ok(());
+        }
+
+        let client = AppStoreConnectClient::from_api_key_source(&api_key.source()?)?;
         match self {
             Self::Create {
                 name,
@@ -347,11 +767,69 @@ impl ProfileCommand {
             Self::Delete { id } => {
                 client.delete_profile(&id)?;
             }
+            Self::Install { id, bundle } => {
+                let profile = client.download_profile(&id)?;
+                profile.verify()?;
+                let path = if let Some(bundle) = bundle {
+                    profile.embed_in_bundle(bundle)?
+                } else {
+                    profile.install()?
+                };
+                println!("installed {}", path.display());
+            }
+            Self::Inspect { .. } => unreachable!("handled above"),
         }
         Ok(())
     }
 }
 
+fn print_provisioning_profile(profile: &ProvisioningProfile) {
+    let date = |time: Option<std::time::SystemTime>| {
+        time.map(|t| plist::Date::from(t).to_xml_format())
+            .unwrap_or_default()
+    };
+
+    println!("name: {}", profile.name().unwrap_or_default());
+    println!("uuid: {}", profile.uuid().unwrap_or_default());
+    println!("app id name: {}", profile.app_id_name().unwrap_or_default());
+    println!(
+        "application identifier: {}",
+        profile.application_identifier().unwrap_or_default()
+    );
+    println!("team: {}", profile.team_identifiers().join(", "));
+    println!("team name: {}", profile.team_name().unwrap_or_default());
+    println!("platforms: {}", profile.platforms().join(", "));
+    println!("created: {}", date(profile.creation_date()));
+    println!("expires: {}", date(profile.expiration_date()));
+    match profile.developer_certificates() {
+        Ok(certs) => {
+            println!("certificates:");
+            for cert in certs {
+                println!("  {}", cert.subject_common_name().unwrap_or_default());
+            }
+        }
+        Err(e) => println!("certificates: error parsing: {e}"),
+    }
+    if profile.provisions_all_devices() {
+        println!("devices: all");
+    } else {
+        println!("devices:");
+        for device in profile.provisioned_devices() {
+            println!("  {device}");
+        }
+    }
+    println!("entitlements:");
+    if let Some(entitlements) = profile.entitlements() {
+        for (key, value) in entitlements {
+            println!("  {key}: {value:?}");
+        }
+    }
+    match profile.verify() {
+        Ok(()) => println!("signature: signed by Apple"),
+        Err(e) => println!("signature: {e}"),
+    }
+}
+
 fn print_profile_header() {
     println!(
         "{: <10} | {: <20} | {: <20} | {: <20}",
@@ -371,3 +849,41 @@ fn print_profile(profile: &Profile) {
         profile.id, profile.attributes.name, profile.attributes.profile_type, expiration_date,
     );
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::provisioning_profile::sign_profile_plist,
+        x509_certificate::{EcdsaCurve, KeyAlgorithm, X509CertificateBuilder},
+    };
+
+    #[test]
+    fn profile_inspect_without_api_key() -> Result<()> {
+        let mut builder = X509CertificateBuilder::default();
+        builder
+            .subject()
+            .append_common_name_utf8_string("Profile Signing")
+            .unwrap();
+        let (cert, key) =
+            builder.create_with_random_keypair(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))?;
+
+        let mut plist = plist::Dictionary::new();
+        plist.insert("Name".into(), "Example".into());
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("example.mobileprovision");
+        std::fs::write(&path, sign_profile_plist(&plist, &key, &cert)?)?;
+
+        let args = Args::try_parse_from([
+            "app-store-connect".as_ref(),
+            "profile".as_ref(),
+            "inspect".as_ref(),
+            path.as_os_str(),
+        ])?;
+        assert!(args.api_key.api_key.is_none());
+        assert!(args.api_key.api_key_command.is_none());
+        args.run()?;
+
+        Ok(())
+    }
+}
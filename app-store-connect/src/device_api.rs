@@ -5,10 +5,15 @@
 // except according to those terms.
 
 use crate::bundle_api::BundleIdPlatform;
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
 use crate::{AppStoreConnectClient, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-const APPLE_CERTIFICATE_URL: &str = "https://api.appstoreconnect.apple.com/v1/devices";
+pub(crate) const DEVICES_PATH: &str = "v1/devices";
 
 impl AppStoreConnectClient {
     pub fn register_device(
@@ -18,19 +23,10 @@ impl AppStoreConnectClient {
         udid: &str,
     ) -> Result<DeviceResponse> {
         let token = self.get_token()?;
-        let body = DeviceCreateRequest {
-            data: DeviceCreateRequestData {
-                attributes: DeviceCreateRequestAttributes {
-                    name: name.into(),
-                    platform: platform.to_string(),
-                    udid: udid.into(),
-                },
-                r#type: "devices".into(),
-            },
-        };
+        let body = DeviceCreateRequest::new(name, platform, udid);
         let req = self
             .client
-            .post(APPLE_CERTIFICATE_URL)
+            .post(self.api_endpoint(DEVICES_PATH))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
@@ -38,21 +34,25 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// List all devices, following pagination.
     pub fn list_devices(&self) -> Result<DevicesResponse> {
-        let token = self.get_token()?;
-        let req = self
-            .client
-            .get(APPLE_CERTIFICATE_URL)
-            .bearer_auth(token)
-            .header("Accept", "application/json");
-        Ok(self.send_request(req)?.json()?)
+        self.devices(&ListQuery::new().limit(MAX_PAGE_LIMIT))?
+            .collect_all()
+    }
+
+    /// Obtain a [Paginator] over devices matching a query.
+    pub fn devices(
+        &self,
+        query: &ListQuery<DeviceFilter>,
+    ) -> Result<Paginator<'_, DevicesResponse>> {
+        self.paginate(DEVICES_PATH, query)
     }
 
     pub fn get_device(&self, id: &str) -> Result<DeviceResponse> {
         let token = self.get_token()?;
         let req = self
             .client
-            .get(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .get(self.api_endpoint(&format!("{DEVICES_PATH}/{id}")))
             .bearer_auth(token)
             .header("Accept", "application/json");
         Ok(self.send_request(req)?.json()?)
@@ -65,6 +65,21 @@ pub struct DeviceCreateRequest {
     pub data: DeviceCreateRequestData,
 }
 
+impl DeviceCreateRequest {
+    pub fn new(name: &str, platform: BundleIdPlatform, udid: &str) -> Self {
+        Self {
+            data: DeviceCreateRequestData {
+                attributes: DeviceCreateRequestAttributes {
+                    name: name.into(),
+                    platform: platform.to_string(),
+                    udid: udid.into(),
+                },
+                r#type: "devices".into(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DeviceCreateRequestData {
@@ -90,13 +105,22 @@ pub struct DeviceResponse {
 #[serde(rename_all = "camelCase")]
 pub struct DevicesResponse {
     pub data: Vec<Device>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
 }
 
+impl_page!(DevicesResponse, Device);
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Device {
     pub attributes: DeviceAttributes,
     pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -110,3 +134,50 @@ pub struct DeviceAttributes {
     pub udid: String,
     pub added_date: String,
 }
+
+/// Status of a registered device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeviceStatus {
+    Enabled,
+    Disabled,
+}
+
+impl std::fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Enabled => "ENABLED",
+            Self::Disabled => "DISABLED",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Filters for listing devices.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeviceFilter {
+    Id(String),
+    Name(String),
+    Platform(BundleIdPlatform),
+    Status(DeviceStatus),
+    Udid(String),
+}
+
+impl QueryFilter for DeviceFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::Name(_) => "name",
+            Self::Platform(_) => "platform",
+            Self::Status(_) => "status",
+            Self::Udid(_) => "udid",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::Name(v) | Self::Udid(v) => v.clone(),
+            Self::Platform(v) => v.to_string(),
+            Self::Status(v) => v.to_string(),
+        }
+    }
+}
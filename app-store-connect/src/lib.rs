@@ -6,32 +6,257 @@
 
 mod api_key;
 mod api_token;
+pub mod app_api;
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod build_api;
+pub mod build_upload_api;
 pub mod bundle_api;
 pub mod certs_api;
 pub mod cli;
 pub mod device_api;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock_server;
 pub mod notary_api;
 pub mod profile_api;
+pub mod provisioning_profile;
+pub mod query;
+mod retry;
+mod s3;
+pub mod testflight_api;
 
 use {
-    reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response},
+    reqwest::{
+        blocking::{Client, ClientBuilder, RequestBuilder, Response},
+        header::HeaderMap,
+        Method,
+    },
+    ring::digest::{Context, SHA256},
     serde_json::Value,
-    std::{path::Path, sync::Mutex},
+    std::{
+        fs::File,
+        io::Read,
+        ops::{Deref, DerefMut},
+        path::Path,
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    },
     thiserror::Error,
 };
 
-pub use crate::api_key::{InvalidPemPrivateKey, UnifiedApiKey};
+pub use crate::api_key::{
+    ApiKeyCommandFailed, ApiKeySource, InvalidPassphrase, InvalidPemPrivateKey,
+    MissingEnvironmentVariable, PassphraseRequired, UnifiedApiKey, ISSUER_ID_ENV, KEY_ID_ENV,
+    PASSPHRASE_ENV, PRIVATE_KEY_ENV,
+};
 pub use crate::api_token::{AppStoreConnectToken, ConnectTokenEncoder, MissingApiKey};
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncAppStoreConnectClient;
+use crate::retry::RetryState;
+pub use crate::retry::{RateLimit, RetryPolicy, RATE_LIMIT_HEADER};
 
 pub type Result<T> = anyhow::Result<T>;
 
+/// Default base URL of the App Store Connect API.
+pub const APP_STORE_CONNECT_API_URL: &str = "https://api.appstoreconnect.apple.com";
+
+/// Default base URL of the Notary API.
+pub const NOTARY_API_URL: &str = "https://appstoreconnect.apple.com";
+
+/// Default lifetime of minted JWTs.
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Maximum lifetime of JWTs accepted by Apple.
+pub const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(20 * 60);
+
+/// Tokens are re-minted when they are within this duration of expiring.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// A minted token and when it expires.
+struct CachedToken {
+    token: AppStoreConnectToken,
+    expires: SystemTime,
+}
+
+impl CachedToken {
+    /// Obtain the token in `cache`, minting a new one if it is missing or close to expiring.
+    fn get_or_mint(
+        cache: &Mutex<Option<Self>>,
+        connect_token: &ConnectTokenEncoder,
+        lifetime: Duration,
+    ) -> Result<String> {
+        let mut token = cache.lock().unwrap();
+
+        let now = SystemTime::now();
+        let margin = std::cmp::min(TOKEN_REFRESH_MARGIN, lifetime / 2);
+
+        if token
+            .as_ref()
+            .map(|t| t.expires <= now + margin)
+            .unwrap_or(true)
+        {
+            token.replace(Self {
+                token: connect_token.new_token(lifetime.as_secs())?,
+                expires: now + lifetime,
+            });
+        }
+
+        Ok(token.as_ref().unwrap().token.clone())
+    }
+}
+
+/// User agent of API requests.
+const USER_AGENT: &str = "asconnect crate (https://crates.io/crates/asconnect)";
+
+/// Configuration and authentication state of an API client.
+///
+/// This is shared by [AppStoreConnectClient] and `AsyncAppStoreConnectClient`,
+/// which dereference to it. So its methods can be called on clients directly.
+pub struct ClientConfig {
+    connect_token: ConnectTokenEncoder,
+    token: Mutex<Option<CachedToken>>,
+    token_lifetime: Duration,
+    retry_policy: RetryPolicy,
+    rate_limit: Mutex<Option<RateLimit>>,
+    api_url: String,
+    notary_url: String,
+    s3_endpoint_url: Option<String>,
+}
+
+impl ClientConfig {
+    fn new(connect_token: ConnectTokenEncoder) -> Self {
+        Self {
+            connect_token,
+            token: Mutex::new(None),
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+            retry_policy: RetryPolicy::default(),
+            rate_limit: Mutex::new(None),
+            api_url: APP_STORE_CONNECT_API_URL.to_string(),
+            notary_url: NOTARY_API_URL.to_string(),
+            s3_endpoint_url: None,
+        }
+    }
+
+    /// The base URL of the App Store Connect API this client talks to.
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Set the base URL of the App Store Connect API.
+    ///
+    /// Defaults to [APP_STORE_CONNECT_API_URL]. Changing this is mainly useful for
+    /// talking to a mock server in tests.
+    pub fn set_api_url(&mut self, url: impl ToString) {
+        self.api_url = url.to_string();
+    }
+
+    /// The base URL of the Notary API this client talks to.
+    pub fn notary_url(&self) -> &str {
+        &self.notary_url
+    }
+
+    /// Set the base URL of the Notary API.
+    ///
+    /// Defaults to [NOTARY_API_URL].
+    pub fn set_notary_url(&mut self, url: impl ToString) {
+        self.notary_url = url.to_string();
+    }
+
+    /// The S3 endpoint Notary API submissions are uploaded to, if overridden.
+    pub fn s3_endpoint_url(&self) -> Option<&str> {
+        self.s3_endpoint_url.as_deref()
+    }
+
+    /// Set the S3 endpoint Notary API submissions are uploaded to.
+    ///
+    /// By default, AWS's S3 endpoint for the bucket's region is used. When set,
+    /// path-style URLs relative to this endpoint are used.
+    pub fn set_s3_endpoint_url(&mut self, url: impl ToString) {
+        self.s3_endpoint_url = Some(url.to_string());
+    }
+
+    /// Resolve the URL of an App Store Connect API path. e.g. `v1/bundleIds`.
+    pub(crate) fn api_endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.api_url.trim_end_matches('/'), path)
+    }
+
+    /// Resolve the URL of a Notary API path. e.g. `notary/v2/submissions`.
+    pub(crate) fn notary_endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.notary_url.trim_end_matches('/'), path)
+    }
+
+    /// The lifetime of JWTs minted by this client.
+    pub fn token_lifetime(&self) -> Duration {
+        self.token_lifetime
+    }
+
+    /// Set the lifetime of JWTs minted by this client.
+    ///
+    /// Defaults to [DEFAULT_TOKEN_LIFETIME]. Values are capped at [MAX_TOKEN_LIFETIME],
+    /// as Apple rejects tokens valid for longer. Tokens are transparently re-minted
+    /// shortly before they expire.
+    pub fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.token_lifetime = std::cmp::min(lifetime, MAX_TOKEN_LIFETIME);
+        self.invalidate_token();
+    }
+
+    /// The policy for retrying failed requests.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Set the policy for retrying failed requests.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// The rate limit reported by the most recent API response, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    /// Obtain a JWT for authenticating API requests.
+    ///
+    /// Tokens are cached and re-minted when close to expiring.
+    pub fn get_token(&self) -> Result<String> {
+        CachedToken::get_or_mint(&self.token, &self.connect_token, self.token_lifetime)
+    }
+
+    /// Discard the cached JWT so the next request mints a new one.
+    fn invalidate_token(&self) {
+        self.token.lock().unwrap().take();
+    }
+
+    /// Record state conveyed by the headers of an API response.
+    fn observe_response(&self, headers: &HeaderMap) {
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            self.rate_limit.lock().unwrap().replace(rate_limit);
+        }
+    }
+}
+
 /// A client for App Store Connect API.
 ///
 /// The client isn't generic. Don't get any ideas.
+///
+/// Configuration is available via [ClientConfig], which this type dereferences to.
 pub struct AppStoreConnectClient {
     client: Client,
-    connect_token: ConnectTokenEncoder,
-    token: Mutex<Option<AppStoreConnectToken>>,
+    config: ClientConfig,
+}
+
+impl Deref for AppStoreConnectClient {
+    type Target = ClientConfig;
+
+    fn deref(&self) -> &Self::Target {
+        &self.config
+    }
+}
+
+impl DerefMut for AppStoreConnectClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
 }
 
 impl AppStoreConnectClient {
@@ -40,57 +265,103 @@ impl AppStoreConnectClient {
         AppStoreConnectClient::new(key.try_into()?)
     }
 
+    /// Create a new client using an API key obtained from a [ApiKeySource].
+    pub fn from_api_key_source(source: &ApiKeySource) -> Result<Self> {
+        Self::new(source.load()?.try_into()?)
+    }
+
     /// Create a new client to the App Store Connect API.
     pub fn new(connect_token: ConnectTokenEncoder) -> Result<Self> {
-        let client = ClientBuilder::default()
-            .user_agent("asconnect crate (https://crates.io/crates/asconnect)")
-            .build()?;
+        let client = ClientBuilder::default().user_agent(USER_AGENT).build()?;
         Ok(Self {
             client,
-            connect_token,
-            token: Mutex::new(None),
+            config: ClientConfig::new(connect_token),
         })
     }
 
-    pub fn get_token(&self) -> Result<String> {
-        let mut token = self.token.lock().unwrap();
-
-        // TODO need to handle token expiration.
-        if token.is_none() {
-            token.replace(self.connect_token.new_token(300)?);
-        }
-
-        Ok(token.as_ref().unwrap().clone())
-    }
-
+    /// Send a request, retrying according to the client's [RetryPolicy].
+    ///
+    /// If the request is retried after a `401 Unauthorized` response, its bearer
+    /// token is replaced by a newly minted one. Requests with streaming bodies
+    /// can't be cloned and are never retried.
     pub fn send_request(&self, request: RequestBuilder) -> Result<Response> {
-        let request = request.build()?;
-        let method = request.method().to_string();
+        let mut request = request.build()?;
+        let method = request.method().clone();
         let url = request.url().to_string();
+        let mut retry = RetryState::default();
+
+        loop {
+            let mut next = request.try_clone();
 
-        log::debug!("{} {}", request.method(), url);
+            log::debug!("{} {}", method, url);
 
-        let response = self.client.execute(request)?;
+            let response = self.client.execute(request)?;
+            self.config.observe_response(response.headers());
 
-        if response.status().is_success() {
-            Ok(response)
-        } else {
-            let body = response.bytes()?;
+            let status = response.status();
 
-            let message = if let Ok(value) = serde_json::from_slice::<Value>(body.as_ref()) {
-                serde_json::to_string_pretty(&value)?
-            } else {
-                String::from_utf8_lossy(body.as_ref()).into()
-            };
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let delay = retry.retry_delay(
+                &self.config,
+                &method,
+                &url,
+                status,
+                response.headers(),
+                next.as_mut().map(|r| r.headers_mut()),
+            )?;
 
-            Err(AppStoreConnectError {
-                method,
-                url,
-                message,
+            match (delay, next) {
+                (Some(delay), Some(next)) => {
+                    std::thread::sleep(delay);
+                    request = next;
+                }
+                _ => return Err(self.response_error(&method, url, response)),
             }
-            .into())
         }
     }
+
+    /// Convert an unsuccessful response into an error.
+    fn response_error(&self, method: &Method, url: String, response: Response) -> anyhow::Error {
+        let body = match response.bytes() {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+
+        AppStoreConnectError {
+            method: method.to_string(),
+            url,
+            message: error_message(body.as_ref()),
+        }
+        .into()
+    }
+}
+
+/// Render the body of an error response, pretty-printing JSON.
+fn error_message(body: &[u8]) -> String {
+    if let Ok(value) = serde_json::from_slice::<Value>(body) {
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    } else {
+        String::from_utf8_lossy(body).into()
+    }
+}
+
+/// Compute the hex encoded SHA-256 digest of a file.
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = [0u8; 65536];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        context.update(&buffer[0..count]);
+    }
+
+    Ok(hex::encode(context.finish()))
 }
 
 #[derive(Clone, Debug, Error)]
@@ -100,3 +371,157 @@ pub struct AppStoreConnectError {
     url: String,
     message: String,
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::mock_server::{MockResponse, MockServer},
+    };
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn token_refresh() -> Result<()> {
+        let server = MockServer::start()?;
+        let mut client = server.client()?;
+
+        let token = client.get_token()?;
+        assert_eq!(client.get_token()?, token);
+
+        // Tokens close to expiry are re-minted.
+        client.token.lock().unwrap().as_mut().unwrap().expires =
+            SystemTime::now() + Duration::from_secs(30);
+        let refreshed = client.get_token()?;
+        assert_ne!(refreshed, token);
+        assert_eq!(client.get_token()?, refreshed);
+
+        client.set_token_lifetime(Duration::from_secs(3600));
+        assert_eq!(client.token_lifetime(), MAX_TOKEN_LIFETIME);
+        assert_ne!(client.get_token()?, refreshed);
+
+        // The mock server accepts tokens with the maximum lifetime.
+        client.list_devices()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn retries() -> Result<()> {
+        let server = MockServer::start()?;
+        let mut client = server.client()?;
+        client.set_retry_policy(fast_retries());
+
+        server.enqueue_response(
+            "GET",
+            "/v1/devices",
+            MockResponse::error(429, "RATE_LIMIT_EXCEEDED", "slow down")
+                .with_header("Retry-After", "0"),
+        );
+        server.enqueue_response(
+            "GET",
+            "/v1/devices",
+            MockResponse::error(503, "SERVICE_UNAVAILABLE", "try again"),
+        );
+        client.list_devices()?;
+        assert_eq!(server.requests().len(), 3);
+
+        let rate_limit = client.rate_limit().expect("rate limit recorded");
+        assert_eq!(rate_limit.limit, Some(3600));
+
+        // Errors are surfaced after exhausting retries.
+        for _ in 0..3 {
+            server.enqueue_response(
+                "GET",
+                "/v1/devices",
+                MockResponse::error(500, "INTERNAL_ERROR", "boom"),
+            );
+        }
+        let err = client.list_devices().unwrap_err();
+        assert!(err.to_string().contains("INTERNAL_ERROR"));
+        assert_eq!(server.requests().len(), 6);
+
+        // Client errors aren't retried.
+        assert!(client.get_device("missing").is_err());
+        assert_eq!(server.requests().len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn server_errors_not_retried_for_posts() -> Result<()> {
+        let server = MockServer::start()?;
+        let mut client = server.client()?;
+        client.set_retry_policy(fast_retries());
+
+        // The device may have been registered before the server failed.
+        server.enqueue_response(
+            "POST",
+            "/v1/devices",
+            MockResponse::error(500, "INTERNAL_ERROR", "boom"),
+        );
+        let err = client
+            .register_device("phone", bundle_api::BundleIdPlatform::Ios, "udid")
+            .unwrap_err();
+        assert!(err.to_string().contains("INTERNAL_ERROR"));
+        assert_eq!(server.requests().len(), 1);
+
+        // Rate limited requests weren't processed, so they are resent.
+        server.enqueue_response(
+            "POST",
+            "/v1/devices",
+            MockResponse::error(429, "RATE_LIMIT_EXCEEDED", "slow down")
+                .with_header("Retry-After", "0"),
+        );
+        client.register_device("phone", bundle_api::BundleIdPlatform::Ios, "udid")?;
+        assert_eq!(server.requests().len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unauthorized_remints_token() -> Result<()> {
+        let server = MockServer::start()?;
+        let mut client = server.client()?;
+        client.set_retry_policy(fast_retries());
+
+        let token = client.get_token()?;
+
+        server.enqueue_response(
+            "GET",
+            "/v1/devices",
+            MockResponse::error(401, "NOT_AUTHORIZED", "expired"),
+        );
+        client.list_devices()?;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some(format!("Bearer {token}").as_str())
+        );
+        assert_ne!(
+            requests[0].header("authorization"),
+            requests[1].header("authorization")
+        );
+
+        // Repeated rejections are only retried once.
+        for _ in 0..2 {
+            server.enqueue_response(
+                "GET",
+                "/v1/devices",
+                MockResponse::error(401, "NOT_AUTHORIZED", "bad key"),
+            );
+        }
+        assert!(client.list_devices().is_err());
+        assert_eq!(server.requests().len(), 4);
+
+        Ok(())
+    }
+}
@@ -10,10 +10,5 @@ use clap::Parser;
 
 fn main() -> Result<()> {
     env_logger::init();
-    let args = Args::parse();
-    if let Some(api_key) = args.api_key.as_ref() {
-        args.command.run(api_key)
-    } else {
-        anyhow::bail!("missing --api-key");
-    }
+    Args::parse().run()
 }
@@ -0,0 +1,2019 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An in-process mock of the App Store Connect and Notary API servers.
+//!
+//! [MockServer] listens on a random localhost port and implements enough of
+//! Apple's HTTP services to exercise clients without network access:
+//!
+//! * App Store Connect API (`/v1/<type>[/<id>]`) backed by a generic JSON:API
+//!   resource store. `bundleIds`, `certificates`, `devices`, and `profiles`
+//!   get realistic attributes on creation. Profiles carry provisioning profile
+//!   data signed by [MockServer::profile_signing_certificate()].
+//!   `bundleIdCapabilities` are validated against their bundle ID. TestFlight
+//!   resources (`betaGroups`, `betaTesters`, `betaAppReviewSubmissions`) get
+//!   default attributes too. Related resources can be listed via
+//!   `/v1/<type>/<id>/<relationship>` and modified via
+//!   `/v1/<type>/<id>/relationships/<relationship>`. List requests support
+//!   `filter[...]` (including on relationships, e.g. `filter[app]` or
+//!   `filter[preReleaseVersion.version]`), `fields[...]`, `include`, `sort`,
+//!   `limit`, and pagination via `links.next`.
+//! * Build uploads (`buildUploads` and `buildUploadFiles`). Files are uploaded
+//!   in parts via `PUT /mock-upload/<file>/<offset>`. Committing a file verifies
+//!   its size and SHA-256 checksum and then creates a processed build.
+//! * Notary API (`/notary/v2/submissions`). Submissions report `In Progress`
+//!   until their S3 object is uploaded and then report a configurable status.
+//! * A fake S3 accepting path-style `PUT /<bucket>/<key>` uploads. Requests
+//!   must carry an AWS SigV4 `Authorization` header, which isn't verified.
+//! * The notarization ticket lookup service ([MOCK_TICKET_LOOKUP_PATH]).
+//!
+//! API requests must carry a JWT signed by the server's randomly generated API
+//! key, which is obtainable via [MockServer::api_key()]. Responses to them carry
+//! an `X-Rate-Limit` header counting down from an hourly limit of 3600.
+//!
+//! This module is available with the `mock-server` crate feature.
+
+use {
+    crate::{
+        provisioning_profile::sign_profile_plist, AppStoreConnectClient, ConnectTokenEncoder,
+        Result, UnifiedApiKey,
+    },
+    base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine},
+    jsonwebtoken::{Algorithm, DecodingKey, Validation},
+    ring::digest::{digest, SHA256},
+    serde_json::{json, Value},
+    std::{
+        collections::{BTreeMap, HashMap, VecDeque},
+        io::{BufRead, BufReader, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::JoinHandle,
+    },
+    x509_certificate::{
+        CapturedX509Certificate, EcdsaCurve, InMemorySigningKeyPair, KeyAlgorithm, Sign,
+        X509CertificateBuilder,
+    },
+};
+
+/// Key ID of the API key accepted by [MockServer].
+pub const MOCK_KEY_ID: &str = "MOCKKEY123";
+
+/// Issuer ID of the API key accepted by [MockServer].
+pub const MOCK_ISSUER_ID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Path of the notarization ticket lookup endpoint.
+pub const MOCK_TICKET_LOOKUP_PATH: &str =
+    "database/1/com.apple.gk.ticket-delivery/production/public/records/lookup";
+
+/// S3 bucket that Notary API submissions are uploaded to.
+pub const MOCK_NOTARY_BUCKET: &str = "notary-submissions";
+
+/// Team ID reported in provisioning profiles issued by [MockServer].
+pub const MOCK_TEAM_ID: &str = "MOCKTEAM00";
+
+/// Timestamp reported for dates in created resources.
+const MOCK_DATE: &str = "2023-01-01T00:00:00.000+0000";
+
+/// Expiration timestamp reported for created resources.
+const MOCK_EXPIRATION_DATE: &str = "2030-01-01T00:00:00.000+0000";
+
+/// Hourly request limit reported in `X-Rate-Limit` headers.
+const MOCK_RATE_LIMIT: u64 = 3600;
+
+/// Number of resources per page when a request doesn't specify `limit`.
+const MOCK_DEFAULT_PAGE_LIMIT: usize = 20;
+
+/// Size of the parts build upload files are uploaded in.
+const MOCK_UPLOAD_PART_SIZE: u64 = 65536;
+
+/// Maximum lifetime of a JWT accepted by the App Store Connect API.
+const MAX_TOKEN_LIFETIME: u64 = 20 * 60;
+
+/// An HTTP request received by [MockServer].
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// HTTP method. e.g. `GET`.
+    pub method: String,
+    /// Request path, without the query string.
+    pub path: String,
+    /// Query string, if present.
+    pub query: Option<String>,
+    /// Request headers. Names are lowercase.
+    pub headers: Vec<(String, String)>,
+    /// Request body.
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Obtain the value of a header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Obtain the decoded query string parameters.
+    pub fn query_params(&self) -> Vec<(String, String)> {
+        let Some(query) = &self.query else {
+            return vec![];
+        };
+
+        reqwest::Url::parse(&format!("http://localhost/?{query}"))
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Obtain the decoded value of a query string parameter.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query_params()
+            .into_iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Parse the request body as JSON.
+    pub fn json(&self) -> Result<Value> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// An HTTP response sent by [MockServer].
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    /// HTTP status code.
+    pub status: u16,
+    /// Additional response headers.
+    pub headers: Vec<(String, String)>,
+    /// Response body.
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    /// Construct a response with an empty body.
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    /// Construct a response with a JSON body.
+    pub fn json(status: u16, value: &Value) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: serde_json::to_vec(value).expect("JSON serialization should not fail"),
+        }
+    }
+
+    /// Construct a JSON:API error response.
+    pub fn error(status: u16, code: &str, detail: &str) -> Self {
+        Self::json(
+            status,
+            &json!({
+                "errors": [{
+                    "id": uuid_like(status as u64),
+                    "status": status.to_string(),
+                    "code": code,
+                    "title": code,
+                    "detail": detail,
+                }]
+            }),
+        )
+    }
+
+    /// Add a header to the response.
+    pub fn with_header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A Notary API submission tracked by [MockServer].
+#[derive(Clone, Debug)]
+struct MockSubmission {
+    name: String,
+    sha256: String,
+}
+
+struct MockState {
+    /// Resources by type and ID.
+    resources: BTreeMap<String, BTreeMap<String, Value>>,
+    submissions: BTreeMap<String, MockSubmission>,
+    s3_objects: BTreeMap<(String, String), Vec<u8>>,
+    /// Uploaded parts of build upload files by file ID and offset.
+    upload_parts: BTreeMap<(String, u64), Vec<u8>>,
+    tickets: HashMap<String, Vec<u8>>,
+    requests: Vec<MockRequest>,
+    responses: VecDeque<(String, String, MockResponse)>,
+    notary_status: Option<String>,
+    rate_limit_remaining: u64,
+    next_id: u64,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            resources: BTreeMap::new(),
+            submissions: BTreeMap::new(),
+            s3_objects: BTreeMap::new(),
+            upload_parts: BTreeMap::new(),
+            tickets: HashMap::new(),
+            requests: vec![],
+            responses: VecDeque::new(),
+            notary_status: None,
+            rate_limit_remaining: MOCK_RATE_LIMIT,
+            next_id: 0,
+        }
+    }
+}
+
+impl MockState {
+    fn new_id(&mut self) -> String {
+        self.next_id += 1;
+        uuid_like(self.next_id)
+    }
+}
+
+/// Format a number as a UUID-like string.
+fn uuid_like(v: u64) -> String {
+    let s = format!("{v:032X}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &s[0..8],
+        &s[8..12],
+        &s[12..16],
+        &s[16..20],
+        &s[20..32]
+    )
+}
+
+struct MockServerInner {
+    url: String,
+    decoding_key: DecodingKey,
+    profile_signing_key: InMemorySigningKeyPair,
+    profile_signing_certificate: CapturedX509Certificate,
+    state: Mutex<MockState>,
+}
+
+/// A mock App Store Connect / Notary API / S3 server running in a background thread.
+///
+/// The server is shut down when the instance is dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    private_key: Vec<u8>,
+    inner: Arc<MockServerInner>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start a new server listening on a random localhost port.
+    pub fn start() -> Result<Self> {
+        let key_pair =
+            InMemorySigningKeyPair::generate_random(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))?;
+        let private_key = key_pair.to_pkcs8_one_asymmetric_key_der().to_vec();
+        let decoding_key = DecodingKey::from_ec_der(&key_pair.public_key_data());
+
+        let mut builder = X509CertificateBuilder::default();
+        builder
+            .subject()
+            .append_common_name_utf8_string("Mock Provisioning Profile Signing")
+            .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+        let (profile_signing_certificate, profile_signing_key) =
+            builder.create_with_random_keypair(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+
+        let inner = Arc::new(MockServerInner {
+            url: format!("http://{address}"),
+            decoding_key,
+            profile_signing_key,
+            profile_signing_certificate,
+            state: Mutex::new(MockState::default()),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let inner = inner.clone();
+            let shutdown = shutdown.clone();
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    if let Ok(stream) = stream {
+                        let inner = inner.clone();
+                        std::thread::spawn(move || {
+                            if let Err(e) = inner.serve_connection(stream) {
+                                log::warn!("mock server connection error: {e}");
+                            }
+                        });
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            address,
+            private_key,
+            inner,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// The base URL of the server. e.g. `http://127.0.0.1:12345`.
+    ///
+    /// This is the base URL of all emulated services.
+    pub fn url(&self) -> &str {
+        &self.inner.url
+    }
+
+    /// The URL of the notarization ticket lookup endpoint.
+    pub fn ticket_lookup_url(&self) -> String {
+        format!("{}/{}", self.url(), MOCK_TICKET_LOOKUP_PATH)
+    }
+
+    /// The API key accepted by this server.
+    pub fn api_key(&self) -> UnifiedApiKey {
+        UnifiedApiKey {
+            issuer_id: MOCK_ISSUER_ID.to_string(),
+            key_id: MOCK_KEY_ID.to_string(),
+            private_key: STANDARD_ENGINE.encode(&self.private_key),
+        }
+    }
+
+    /// The certificate signing provisioning profiles created by this server.
+    ///
+    /// Use it with [crate::provisioning_profile::ProvisioningProfile::verify_with_trust_anchors()].
+    pub fn profile_signing_certificate(&self) -> &CapturedX509Certificate {
+        &self.inner.profile_signing_certificate
+    }
+
+    /// Obtain a [ConnectTokenEncoder] minting tokens accepted by this server.
+    pub fn token_encoder(&self) -> Result<ConnectTokenEncoder> {
+        self.api_key().try_into()
+    }
+
+    /// Obtain a client configured to talk to this server.
+    pub fn client(&self) -> Result<AppStoreConnectClient> {
+        let mut client = AppStoreConnectClient::new(self.token_encoder()?)?;
+        client.set_api_url(self.url());
+        client.set_notary_url(self.url());
+        client.set_s3_endpoint_url(self.url());
+
+        Ok(client)
+    }
+
+    /// Obtain an async client configured to talk to this server.
+    #[cfg(feature = "async")]
+    pub fn async_client(&self) -> Result<crate::AsyncAppStoreConnectClient> {
+        let mut client = crate::AsyncAppStoreConnectClient::new(self.token_encoder()?)?;
+        client.set_api_url(self.url());
+        client.set_notary_url(self.url());
+        client.set_s3_endpoint_url(self.url());
+
+        Ok(client)
+    }
+
+    /// Insert a JSON:API resource object into the resource store.
+    ///
+    /// The object must have `type` and `id` keys.
+    pub fn insert_resource(&self, resource: Value) {
+        let ty = resource["type"].as_str().expect("resource has type");
+        let id = resource["id"].as_str().expect("resource has id");
+
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .resources
+            .entry(ty.to_string())
+            .or_default()
+            .insert(id.to_string(), resource.clone());
+    }
+
+    /// Obtain all resources of a given type.
+    pub fn resources(&self, ty: &str) -> Vec<Value> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .resources
+            .get(ty)
+            .map(|x| x.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Obtain the content of an object uploaded to the fake S3.
+    pub fn s3_object(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .s3_objects
+            .get(&(bucket.to_string(), key.to_string()))
+            .cloned()
+    }
+
+    /// Set the status Notary API submissions report once uploaded.
+    ///
+    /// Defaults to `Accepted`.
+    pub fn set_notary_status(&self, status: &str) {
+        self.inner.state.lock().unwrap().notary_status = Some(status.to_string());
+    }
+
+    /// Register a notarization ticket to be returned by ticket lookups.
+    pub fn insert_ticket(&self, record_name: &str, ticket: &[u8]) {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .tickets
+            .insert(record_name.to_string(), ticket.to_vec());
+    }
+
+    /// Register a response to send for the next request matching a method and path.
+    ///
+    /// Responses are consumed in the order they are registered. This is useful for
+    /// simulating server errors.
+    pub fn enqueue_response(&self, method: &str, path: &str, response: MockResponse) {
+        self.inner.state.lock().unwrap().responses.push_back((
+            method.to_string(),
+            path.to_string(),
+            response,
+        ));
+    }
+
+    /// Obtain all requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.inner.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the accept loop.
+        let _ = TcpStream::connect(self.address);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn read_request(reader: &mut impl BufRead, stream: &mut TcpStream) -> Result<Option<MockRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow::anyhow!("malformed request line: {line}"));
+    };
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    };
+
+    let mut request = MockRequest {
+        method: method.to_string(),
+        path,
+        query,
+        headers: vec![],
+        body: vec![],
+    };
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            request
+                .headers
+                .push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    if request
+        .header("expect")
+        .map(|v| v.eq_ignore_ascii_case("100-continue"))
+        .unwrap_or(false)
+    {
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    if request
+        .header("transfer-encoding")
+        .map(|v| v.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false)
+    {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let size_hex = line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size_hex, 16)?;
+
+            if size == 0 {
+                // Consume trailers.
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                }
+                break;
+            }
+
+            let mut chunk = vec![0; size];
+            reader.read_exact(&mut chunk)?;
+            request.body.extend(chunk);
+            let mut crlf = [0; 2];
+            reader.read_exact(&mut crlf)?;
+        }
+    } else if let Some(length) = request.header("content-length") {
+        let mut body = vec![0; length.parse::<usize>()?];
+        reader.read_exact(&mut body)?;
+        request.body = body;
+    }
+
+    Ok(Some(request))
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+impl MockServerInner {
+    fn serve_connection(&self, mut stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let Some(request) = read_request(&mut reader, &mut stream)? else {
+            return Ok(());
+        };
+
+        let response = self.handle(request);
+
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            reason_phrase(response.status),
+            response.body.len()
+        );
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&response.body)?;
+        stream.flush()?;
+
+        Ok(())
+    }
+
+    fn handle(&self, request: MockRequest) -> MockResponse {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(request.clone());
+
+        if let Some(pos) = state
+            .responses
+            .iter()
+            .position(|(method, path, _)| *method == request.method && *path == request.path)
+        {
+            return state.responses.remove(pos).expect("index is valid").2;
+        }
+
+        let path = request.path.trim_matches('/').to_string();
+        let segments = path.split('/').collect::<Vec<_>>();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("POST", _) if path == MOCK_TICKET_LOOKUP_PATH => {
+                self.lookup_tickets(&mut state, &request)
+            }
+            ("GET", ["notary-logs", id]) => self.notary_log(&state, id),
+            (_, ["v1", ..]) | (_, ["notary", "v2", ..]) => {
+                if let Err(response) = self.authorize(&request) {
+                    return response;
+                }
+
+                state.rate_limit_remaining = state.rate_limit_remaining.saturating_sub(1);
+                let rate_limit = format!(
+                    "user-hour-lim:{};user-hour-rem:{};",
+                    MOCK_RATE_LIMIT, state.rate_limit_remaining
+                );
+
+                let response = match (request.method.as_str(), segments.as_slice()) {
+                    ("POST", ["notary", "v2", "submissions"]) => {
+                        self.create_submission(&mut state, &request)
+                    }
+                    ("GET", ["notary", "v2", "submissions"]) => self.list_submissions(&state),
+                    ("GET", ["notary", "v2", "submissions", id]) => self.get_submission(&state, id),
+                    ("GET", ["notary", "v2", "submissions", id, "logs"]) => {
+                        self.submission_logs(&state, id)
+                    }
+                    ("GET", ["v1", ty]) => self.list_resources(&state, ty, &request),
+                    ("POST", ["v1", ty]) => self.create_resource(&mut state, ty, &request),
+                    ("GET", ["v1", ty, id]) => self.get_resource(&state, ty, id),
+                    ("POST", ["v1", ty, id, "relationships", related]) => {
+                        self.update_relationship(&mut state, ty, id, related, &request, true)
+                    }
+                    ("DELETE", ["v1", ty, id, "relationships", related]) => {
+                        self.update_relationship(&mut state, ty, id, related, &request, false)
+                    }
+                    ("GET", ["v1", ty, id, related]) => {
+                        self.list_related_resources(&state, ty, id, related, &request)
+                    }
+                    ("PATCH", ["v1", ty, id]) => self.update_resource(&mut state, ty, id, &request),
+                    ("DELETE", ["v1", ty, id]) => self.delete_resource(&mut state, ty, id),
+                    _ => not_found(&request.path),
+                };
+
+                response.with_header("X-Rate-Limit", rate_limit)
+            }
+            ("PUT", ["mock-upload", id, offset]) => {
+                if request.header("content-type") != Some("application/octet-stream") {
+                    return MockResponse::error(400, "InvalidRequest", "missing Content-Type");
+                }
+
+                let Ok(offset) = offset.parse::<u64>() else {
+                    return not_found(&request.path);
+                };
+                state
+                    .upload_parts
+                    .insert((id.to_string(), offset), request.body.clone());
+
+                MockResponse::empty(200)
+            }
+            ("PUT", [bucket, key @ ..]) if !key.is_empty() => {
+                // Signatures aren't verified. But requests must at least be signed.
+                if !request
+                    .header("authorization")
+                    .map(|v| v.starts_with("AWS4-HMAC-SHA256 "))
+                    .unwrap_or(false)
+                {
+                    return MockResponse::error(403, "AccessDenied", "request is not signed");
+                }
+
+                state
+                    .s3_objects
+                    .insert((bucket.to_string(), key.join("/")), request.body.clone());
+
+                MockResponse::empty(200).with_header("ETag", "\"mock\"")
+            }
+            _ => not_found(&request.path),
+        }
+    }
+
+    /// Validate the JWT in a request.
+    fn authorize(&self, request: &MockRequest) -> std::result::Result<(), MockResponse> {
+        let unauthorized = |detail: &str| {
+            MockResponse::error(
+                401,
+                "NOT_AUTHORIZED",
+                &format!("Authentication credentials are missing or invalid. {detail}"),
+            )
+        };
+
+        let token = request
+            .header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| unauthorized("missing bearer token"))?;
+
+        let header =
+            jsonwebtoken::decode_header(token).map_err(|e| unauthorized(&e.to_string()))?;
+        if header.kid.as_deref() != Some(MOCK_KEY_ID) {
+            return Err(unauthorized("unknown key id"));
+        }
+
+        let mut validation = Validation::new(Algorithm::ES256);
+        validation.set_audience(&["appstoreconnect-v1"]);
+        validation.set_issuer(&[MOCK_ISSUER_ID]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+        let claims = jsonwebtoken::decode::<Value>(token, &self.decoding_key, &validation)
+            .map_err(|e| unauthorized(&e.to_string()))?
+            .claims;
+
+        let iat = claims["iat"].as_u64().unwrap_or_default();
+        let exp = claims["exp"].as_u64().unwrap_or_default();
+        if exp.saturating_sub(iat) > MAX_TOKEN_LIFETIME {
+            return Err(unauthorized("token lifetime exceeds 20 minutes"));
+        }
+
+        Ok(())
+    }
+
+    fn resource_url(&self, ty: &str, id: &str) -> String {
+        format!("{}/v1/{}/{}", self.url, ty, id)
+    }
+
+    fn list_resources(&self, state: &MockState, ty: &str, request: &MockRequest) -> MockResponse {
+        let data = state
+            .resources
+            .get(ty)
+            .map(|x| x.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        self.list_page(state, data, &format!("{}/v1/{}", self.url, ty), request)
+    }
+
+    /// List the resources of type `related` associated with resource `ty/id`.
+    ///
+    /// Relationships are resolved from either side: the parent's relationship
+    /// data or related resources whose relationships reference the parent.
+    fn list_related_resources(
+        &self,
+        state: &MockState,
+        ty: &str,
+        id: &str,
+        related: &str,
+        request: &MockRequest,
+    ) -> MockResponse {
+        let Some(parent) = state.resources.get(ty).and_then(|x| x.get(id)) else {
+            return not_found(&format!("{ty}/{id}"));
+        };
+
+        let data = parent["relationships"].get(related).map(|x| &x["data"]);
+
+        // To-one relationships yield a single resource.
+        if let Some(Value::Object(r)) = data {
+            let resource = r
+                .get("type")
+                .and_then(|v| v.as_str())
+                .zip(r.get("id").and_then(|v| v.as_str()))
+                .and_then(|(ty, id)| state.resources.get(ty)?.get(id));
+
+            return match resource {
+                Some(resource) => MockResponse::json(
+                    200,
+                    &json!({
+                        "data": resource,
+                        "links": { "self": format!("{}/v1/{}/{}/{}", self.url, ty, id, related) },
+                    }),
+                ),
+                None => not_found(&format!("{ty}/{id}/{related}")),
+            };
+        }
+
+        let data = if let Some(data) = data {
+            relationship_refs(data)
+                .iter()
+                .filter_map(|r| {
+                    state
+                        .resources
+                        .get(r["type"].as_str()?)?
+                        .get(r["id"].as_str()?)
+                        .cloned()
+                })
+                .collect::<Vec<_>>()
+        } else {
+            state
+                .resources
+                .get(related)
+                .map(|x| {
+                    x.values()
+                        .filter(|resource| {
+                            resource["relationships"]
+                                .as_object()
+                                .map(|relationships| {
+                                    relationships.values().any(|r| {
+                                        relationship_refs(&r["data"]).iter().any(|r| {
+                                            r["type"].as_str() == Some(ty)
+                                                && r["id"].as_str() == Some(id)
+                                        })
+                                    })
+                                })
+                                .unwrap_or(false)
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        self.list_page(
+            state,
+            data,
+            &format!("{}/v1/{}/{}/{}", self.url, ty, id, related),
+            request,
+        )
+    }
+
+    /// Apply list query parameters to resources and render a page of results.
+    fn list_page(
+        &self,
+        state: &MockState,
+        mut data: Vec<Value>,
+        base: &str,
+        request: &MockRequest,
+    ) -> MockResponse {
+        let params = request.query_params();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+
+        for (key, value) in &params {
+            if let Some(field) = key
+                .strip_prefix("filter[")
+                .and_then(|x| x.strip_suffix(']'))
+            {
+                let values = value.split(',').collect::<Vec<_>>();
+
+                data.retain(|resource| {
+                    filter_values(state, resource, field)
+                        .iter()
+                        .any(|v| values.contains(&v.as_str()))
+                });
+            }
+        }
+
+        // Apply sort keys from least to most significant. Values are compared by
+        // their JSON serialization, which is good enough for strings.
+        if let Some(sort) = param("sort") {
+            for key in sort.split(',').rev() {
+                let (descending, field) = match key.strip_prefix('-') {
+                    Some(field) => (true, field),
+                    None => (false, key),
+                };
+
+                data.sort_by(|a, b| {
+                    let ordering = a["attributes"][field]
+                        .to_string()
+                        .cmp(&b["attributes"][field].to_string());
+
+                    if descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+            }
+        }
+
+        let limit = match param("limit").map(|v| v.parse::<usize>()) {
+            None => MOCK_DEFAULT_PAGE_LIMIT,
+            Some(Ok(limit)) if (1..=200).contains(&limit) => limit,
+            Some(_) => {
+                return MockResponse::error(
+                    400,
+                    "PARAMETER_ERROR.INVALID",
+                    "limit must be between 1 and 200",
+                )
+            }
+        };
+        let cursor = param("cursor")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let total = data.len();
+        let mut page = data
+            .into_iter()
+            .skip(cursor)
+            .take(limit)
+            .collect::<Vec<_>>();
+
+        let mut included: Vec<Value> = vec![];
+        if let Some(include) = param("include") {
+            for resource in &page {
+                for relationship in include.split(',') {
+                    let refs = relationship_refs(&resource["relationships"][relationship]["data"]);
+
+                    for r in refs {
+                        let (Some(ref_ty), Some(ref_id)) = (r["type"].as_str(), r["id"].as_str())
+                        else {
+                            continue;
+                        };
+
+                        if let Some(related) =
+                            state.resources.get(ref_ty).and_then(|x| x.get(ref_id))
+                        {
+                            if !included.contains(related) {
+                                included.push(related.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (key, value) in &params {
+            if let Some(fields_ty) = key
+                .strip_prefix("fields[")
+                .and_then(|x| x.strip_suffix(']'))
+            {
+                let fields = value.split(',').collect::<Vec<_>>();
+
+                for resource in page.iter_mut().chain(included.iter_mut()) {
+                    if resource["type"].as_str() != Some(fields_ty) {
+                        continue;
+                    }
+
+                    if let Some(attributes) = resource["attributes"].as_object_mut() {
+                        attributes.retain(|k, _| fields.contains(&k.as_str()));
+                    }
+                }
+            }
+        }
+
+        let page_url = |cursor: Option<usize>| {
+            let mut params = params
+                .iter()
+                .filter(|(k, _)| k != "cursor")
+                .cloned()
+                .collect::<Vec<_>>();
+            if let Some(cursor) = cursor {
+                params.push(("cursor".to_string(), cursor.to_string()));
+            }
+
+            reqwest::Url::parse_with_params(base, params)
+                .expect("URL should be valid")
+                .to_string()
+        };
+
+        let mut links = json!({
+            "self": page_url((cursor > 0).then_some(cursor)),
+            "first": page_url(None),
+        });
+        if cursor + limit < total {
+            links["next"] = Value::String(page_url(Some(cursor + limit)));
+        }
+
+        let mut body = json!({
+            "data": page,
+            "links": links,
+            "meta": { "paging": { "total": total, "limit": limit } },
+        });
+        if param("include").is_some() {
+            body["included"] = Value::Array(included);
+        }
+
+        MockResponse::json(200, &body)
+    }
+
+    fn get_resource(&self, state: &MockState, ty: &str, id: &str) -> MockResponse {
+        match state.resources.get(ty).and_then(|x| x.get(id)) {
+            Some(resource) => MockResponse::json(
+                200,
+                &json!({
+                    "data": resource,
+                    "links": { "self": self.resource_url(ty, id) },
+                }),
+            ),
+            None => not_found(&format!("{ty}/{id}")),
+        }
+    }
+
+    fn create_resource(
+        &self,
+        state: &mut MockState,
+        ty: &str,
+        request: &MockRequest,
+    ) -> MockResponse {
+        let body = match request.json() {
+            Ok(v) => v,
+            Err(e) => return MockResponse::error(400, "PARAMETER_ERROR.INVALID", &e.to_string()),
+        };
+
+        let data = &body["data"];
+        if data["type"].as_str() != Some(ty) {
+            return MockResponse::error(
+                409,
+                "ENTITY_ERROR.INCORRECT_TYPE",
+                &format!("resource type must be {ty}"),
+            );
+        }
+
+        if ty == "bundleIdCapabilities" {
+            let bundle_id = data["relationships"]["bundleId"]["data"]["id"]
+                .as_str()
+                .unwrap_or_default();
+            if !state
+                .resources
+                .get("bundleIds")
+                .map(|x| x.contains_key(bundle_id))
+                .unwrap_or(false)
+            {
+                return MockResponse::error(
+                    409,
+                    "ENTITY_ERROR.RELATIONSHIP.INVALID",
+                    "bundleId relationship must reference an existing bundle ID",
+                );
+            }
+
+            let capability_type = &data["attributes"]["capabilityType"];
+            if state
+                .resources
+                .get(ty)
+                .map(|x| {
+                    x.values().any(|c| {
+                        c["relationships"]["bundleId"]["data"]["id"].as_str() == Some(bundle_id)
+                            && &c["attributes"]["capabilityType"] == capability_type
+                    })
+                })
+                .unwrap_or(false)
+            {
+                return MockResponse::error(
+                    409,
+                    "ENTITY_ERROR",
+                    &format!("capability {capability_type} is already enabled"),
+                );
+            }
+        }
+
+        // Resources which must reference an existing parent.
+        let parent = match ty {
+            "buildUploads" => Some(("app", "apps")),
+            "buildUploadFiles" => Some(("buildUpload", "buildUploads")),
+            _ => None,
+        };
+        if let Some((relationship, parent_ty)) = parent {
+            let parent_id = data["relationships"][relationship]["data"]["id"]
+                .as_str()
+                .unwrap_or_default();
+            if !state
+                .resources
+                .get(parent_ty)
+                .map(|x| x.contains_key(parent_id))
+                .unwrap_or(false)
+            {
+                return MockResponse::error(
+                    409,
+                    "ENTITY_ERROR.RELATIONSHIP.INVALID",
+                    &format!("{relationship} relationship must reference an existing resource"),
+                );
+            }
+        }
+
+        let id = state.new_id();
+        let mut attributes = data["attributes"].as_object().cloned().unwrap_or_default();
+
+        let profile_content = if ty == "profiles" {
+            match self.profile_content(state, &id, &attributes, &data["relationships"]) {
+                Ok(v) => v,
+                Err(e) => return MockResponse::error(500, "UNEXPECTED_ERROR", &e.to_string()),
+            }
+        } else {
+            String::new()
+        };
+
+        let defaults = match ty {
+            "bundleIds" => json!({ "seedId": "MOCKSEED00" }),
+            "certificates" => json!({
+                // Not a real certificate: the DER of the submitted CSR.
+                "certificateContent": attributes
+                    .get("csrContent")
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| pem::parse(v).ok())
+                    .map(|v| STANDARD_ENGINE.encode(v.contents()))
+                    .unwrap_or_default(),
+                "displayName": "Mock Certificate",
+                "name": "Mock Certificate",
+                "expirationDate": MOCK_EXPIRATION_DATE,
+                "platform": null,
+                "serialNumber": id.replace('-', ""),
+            }),
+            "devices" => json!({
+                "deviceClass": if attributes.get("platform").and_then(|v| v.as_str()) == Some("MAC_OS") {
+                    "MAC"
+                } else {
+                    "IPHONE"
+                },
+                "model": null,
+                "status": "ENABLED",
+                "addedDate": MOCK_DATE,
+            }),
+            "profiles" => json!({
+                "platform": if attributes
+                    .get("profileType")
+                    .and_then(|v| v.as_str())
+                    .map(|v| v.starts_with("MAC_"))
+                    .unwrap_or(false)
+                {
+                    "MAC_OS"
+                } else {
+                    "IOS"
+                },
+                "profileContent": profile_content,
+                "uuid": id,
+                "createdDate": MOCK_DATE,
+                "profileState": "ACTIVE",
+                "expirationDate": MOCK_EXPIRATION_DATE,
+            }),
+            "betaGroups" => json!({
+                "isInternalGroup": false,
+                "publicLinkEnabled": false,
+                "createdDate": MOCK_DATE,
+            }),
+            "betaTesters" => json!({ "inviteType": "EMAIL" }),
+            "buildUploads" => json!({
+                "createdDate": MOCK_DATE,
+                "uploadedDate": null,
+                "state": { "state": "AWAITING_UPLOAD", "errors": [], "warnings": [], "infos": [] },
+            }),
+            "buildUploadFiles" => json!({
+                "uploaded": false,
+                "uploadOperations": self.upload_operations(
+                    &id,
+                    attributes.get("fileSize").and_then(|v| v.as_u64()).unwrap_or_default(),
+                ),
+            }),
+            "betaAppReviewSubmissions" => json!({
+                "betaReviewState": "WAITING_FOR_REVIEW",
+                "submittedDate": MOCK_DATE,
+            }),
+            _ => json!({}),
+        };
+
+        for (k, v) in defaults.as_object().expect("defaults are objects") {
+            attributes.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+
+        let mut resource = json!({
+            "type": ty,
+            "id": id,
+            "attributes": attributes,
+            "links": { "self": self.resource_url(ty, &id) },
+        });
+        if let Some(relationships) = data.get("relationships") {
+            resource["relationships"] = relationships.clone();
+        }
+
+        state
+            .resources
+            .entry(ty.to_string())
+            .or_default()
+            .insert(id.clone(), resource.clone());
+
+        MockResponse::json(
+            201,
+            &json!({
+                "data": resource,
+                "links": { "self": self.resource_url(ty, &id) },
+            }),
+        )
+    }
+
+    /// Produce base64 encoded, signed provisioning profile data for a new profile.
+    fn profile_content(
+        &self,
+        state: &MockState,
+        uuid: &str,
+        attributes: &serde_json::Map<String, Value>,
+        relationships: &Value,
+    ) -> Result<String> {
+        let related = |name: &str, ty: &str| {
+            relationship_refs(&relationships[name]["data"])
+                .iter()
+                .filter_map(|r| state.resources.get(ty)?.get(r["id"].as_str()?).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let bundle = related("bundleId", "bundleIds").into_iter().next();
+        let bundle_attribute = |name: &str| {
+            bundle
+                .as_ref()
+                .and_then(|b| b["attributes"][name].as_str())
+                .map(|v| v.to_string())
+        };
+        let seed_id = bundle_attribute("seedId").unwrap_or_else(|| MOCK_TEAM_ID.to_string());
+        let identifier = bundle_attribute("identifier").unwrap_or_else(|| "*".to_string());
+
+        let is_mac = attributes
+            .get("profileType")
+            .and_then(|v| v.as_str())
+            .map(|v| v.starts_with("MAC_"))
+            .unwrap_or(false);
+
+        let mut entitlements = plist::Dictionary::new();
+        entitlements.insert(
+            if is_mac {
+                "com.apple.application-identifier"
+            } else {
+                "application-identifier"
+            }
+            .into(),
+            format!("{seed_id}.{identifier}").into(),
+        );
+        entitlements.insert(
+            "com.apple.developer.team-identifier".into(),
+            MOCK_TEAM_ID.into(),
+        );
+
+        let certificates = related("certificates", "certificates")
+            .iter()
+            .filter_map(|c| c["attributes"]["certificateContent"].as_str())
+            .map(|c| Ok(plist::Value::Data(STANDARD_ENGINE.decode(c)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let devices = related("devices", "devices")
+            .iter()
+            .filter_map(|d| d["attributes"]["udid"].as_str())
+            .map(plist::Value::from)
+            .collect::<Vec<_>>();
+
+        let mut plist = plist::Dictionary::new();
+        plist.insert(
+            "AppIDName".into(),
+            bundle_attribute("name").unwrap_or_default().into(),
+        );
+        plist.insert(
+            "ApplicationIdentifierPrefix".into(),
+            vec![plist::Value::from(seed_id)].into(),
+        );
+        plist.insert(
+            "CreationDate".into(),
+            plist::Date::from_xml_format("2023-01-01T00:00:00Z")?.into(),
+        );
+        plist.insert("DeveloperCertificates".into(), certificates.into());
+        plist.insert("Entitlements".into(), entitlements.into());
+        plist.insert(
+            "ExpirationDate".into(),
+            plist::Date::from_xml_format("2030-01-01T00:00:00Z")?.into(),
+        );
+        plist.insert(
+            "Name".into(),
+            attributes
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .into(),
+        );
+        plist.insert(
+            "Platform".into(),
+            vec![plist::Value::from(if is_mac { "OSX" } else { "iOS" })].into(),
+        );
+        if !devices.is_empty() {
+            plist.insert("ProvisionedDevices".into(), devices.into());
+        }
+        plist.insert(
+            "TeamIdentifier".into(),
+            vec![plist::Value::from(MOCK_TEAM_ID)].into(),
+        );
+        plist.insert("TeamName".into(), "Mock Team".into());
+        plist.insert("UUID".into(), uuid.into());
+        plist.insert("Version".into(), 1.into());
+
+        let data = sign_profile_plist(
+            &plist,
+            &self.profile_signing_key,
+            &self.profile_signing_certificate,
+        )?;
+
+        Ok(STANDARD_ENGINE.encode(data))
+    }
+
+    fn update_resource(
+        &self,
+        state: &mut MockState,
+        ty: &str,
+        id: &str,
+        request: &MockRequest,
+    ) -> MockResponse {
+        let body = match request.json() {
+            Ok(v) => v,
+            Err(e) => return MockResponse::error(400, "PARAMETER_ERROR.INVALID", &e.to_string()),
+        };
+
+        let Some(resource) = state.resources.get_mut(ty).and_then(|x| x.get_mut(id)) else {
+            return not_found(&format!("{ty}/{id}"));
+        };
+
+        if let (Some(existing), Some(new)) = (
+            resource["attributes"].as_object_mut(),
+            body["data"]["attributes"].as_object(),
+        ) {
+            for (k, v) in new {
+                existing.insert(k.clone(), v.clone());
+            }
+        }
+
+        let uploaded = ty == "buildUploadFiles" && resource["attributes"]["uploaded"] == true;
+        if uploaded {
+            self.process_build_upload_file(state, id);
+        }
+
+        let resource = state.resources[ty][id].clone();
+        MockResponse::json(
+            200,
+            &json!({
+                "data": resource,
+                "links": { "self": self.resource_url(ty, id) },
+            }),
+        )
+    }
+
+    /// Upload operations for a build upload file of `size` bytes.
+    fn upload_operations(&self, id: &str, size: u64) -> Value {
+        (0..size)
+            .step_by(MOCK_UPLOAD_PART_SIZE as usize)
+            .map(|offset| {
+                json!({
+                    "method": "PUT",
+                    "url": format!("{}/mock-upload/{}/{}", self.url, id, offset),
+                    "offset": offset,
+                    "length": std::cmp::min(MOCK_UPLOAD_PART_SIZE, size - offset),
+                    "requestHeaders": [
+                        { "name": "Content-Type", "value": "application/octet-stream" },
+                    ],
+                })
+            })
+            .collect()
+    }
+
+    /// Process a committed build upload file.
+    ///
+    /// The uploaded parts are verified against the file's size and SHA-256
+    /// checksum. On success, a build (and its pre-release version, if needed) is
+    /// created and the build upload completes. Otherwise it fails.
+    fn process_build_upload_file(&self, state: &mut MockState, id: &str) {
+        let file = state.resources["buildUploadFiles"][id].clone();
+        let Some(upload_id) = file["relationships"]["buildUpload"]["data"]["id"].as_str() else {
+            return;
+        };
+        let upload = state.resources["buildUploads"][upload_id].clone();
+
+        let content = state
+            .upload_parts
+            .range((id.to_string(), 0)..=(id.to_string(), u64::MAX))
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect::<Vec<_>>();
+        let checksum = &file["attributes"]["sourceFileChecksums"]["file"];
+
+        let error = if Some(content.len() as u64) != file["attributes"]["fileSize"].as_u64() {
+            Some((
+                "INCOMPLETE_UPLOAD",
+                "uploaded content doesn't match the file size",
+            ))
+        } else if checksum["algorithm"] != "SHA_256"
+            || checksum["hash"].as_str().map(|x| x.to_lowercase())
+                != Some(hex::encode(digest(&SHA256, &content)))
+        {
+            Some((
+                "CHECKSUM_MISMATCH",
+                "uploaded content doesn't match the checksum",
+            ))
+        } else {
+            None
+        };
+
+        let state_value = if let Some((code, description)) = error {
+            json!({
+                "state": "FAILED",
+                "errors": [{ "code": code, "description": description }],
+                "warnings": [],
+                "infos": [],
+            })
+        } else {
+            let app_id = upload["relationships"]["app"]["data"]["id"].clone();
+            let attributes = &upload["attributes"];
+            let version = &attributes["cfBundleShortVersionString"];
+
+            let existing_version = state.resources.get("preReleaseVersions").and_then(|x| {
+                x.values()
+                    .find(|v| {
+                        v["relationships"]["app"]["data"]["id"] == app_id
+                            && &v["attributes"]["version"] == version
+                    })
+                    .map(|v| v["id"].clone())
+            });
+            let version_id = match existing_version {
+                Some(id) => id,
+                None => {
+                    let version_id = state.new_id();
+                    self.insert(
+                        state,
+                        json!({
+                            "type": "preReleaseVersions",
+                            "id": version_id,
+                            "attributes": {
+                                "version": version,
+                                "platform": attributes["platform"],
+                            },
+                            "relationships": {
+                                "app": { "data": { "type": "apps", "id": app_id } },
+                            },
+                        }),
+                    );
+                    json!(version_id)
+                }
+            };
+
+            let detail_id = state.new_id();
+            self.insert(
+                state,
+                json!({
+                    "type": "buildBetaDetails",
+                    "id": detail_id,
+                    "attributes": {
+                        "autoNotifyEnabled": false,
+                        "internalBuildState": "READY_FOR_BETA_TESTING",
+                        "externalBuildState": "READY_FOR_BETA_SUBMISSION",
+                    },
+                }),
+            );
+
+            let build_id = state.new_id();
+            self.insert(
+                state,
+                json!({
+                    "type": "builds",
+                    "id": build_id,
+                    "attributes": {
+                        "version": attributes["cfBundleVersion"],
+                        "uploadedDate": MOCK_DATE,
+                        "expirationDate": MOCK_EXPIRATION_DATE,
+                        "expired": false,
+                        "minOsVersion": null,
+                        "processingState": "VALID",
+                        "usesNonExemptEncryption": null,
+                    },
+                    "relationships": {
+                        "app": { "data": { "type": "apps", "id": app_id } },
+                        "preReleaseVersion": {
+                            "data": { "type": "preReleaseVersions", "id": version_id },
+                        },
+                        "buildBetaDetail": {
+                            "data": { "type": "buildBetaDetails", "id": detail_id },
+                        },
+                    },
+                }),
+            );
+
+            let upload = state
+                .resources
+                .get_mut("buildUploads")
+                .and_then(|x| x.get_mut(upload_id))
+                .expect("build upload exists");
+            upload["relationships"]["build"] =
+                json!({ "data": { "type": "builds", "id": build_id } });
+            upload["attributes"]["uploadedDate"] = json!(MOCK_DATE);
+
+            json!({ "state": "COMPLETE", "errors": [], "warnings": [], "infos": [] })
+        };
+
+        if let Some(upload) = state
+            .resources
+            .get_mut("buildUploads")
+            .and_then(|x| x.get_mut(upload_id))
+        {
+            upload["attributes"]["state"] = state_value;
+        }
+    }
+
+    /// Store a resource, filling in its `links`.
+    fn insert(&self, state: &mut MockState, mut resource: Value) {
+        let ty = resource["type"].as_str().unwrap_or_default().to_string();
+        let id = resource["id"].as_str().unwrap_or_default().to_string();
+        resource["links"] = json!({ "self": self.resource_url(&ty, &id) });
+        state.resources.entry(ty).or_default().insert(id, resource);
+    }
+
+    /// Add or remove resource identifiers from a to-many relationship.
+    fn update_relationship(
+        &self,
+        state: &mut MockState,
+        ty: &str,
+        id: &str,
+        related: &str,
+        request: &MockRequest,
+        add: bool,
+    ) -> MockResponse {
+        let body = match request.json() {
+            Ok(v) => v,
+            Err(e) => return MockResponse::error(400, "PARAMETER_ERROR.INVALID", &e.to_string()),
+        };
+        let refs = relationship_refs(&body["data"]);
+
+        if let Some(missing) = refs.iter().find(|r| {
+            r["type"]
+                .as_str()
+                .zip(r["id"].as_str())
+                .and_then(|(ty, id)| state.resources.get(ty)?.get(id))
+                .is_none()
+        }) {
+            return MockResponse::error(
+                409,
+                "ENTITY_ERROR.RELATIONSHIP.INVALID",
+                &format!("{missing} does not exist"),
+            );
+        }
+
+        let Some(resource) = state.resources.get_mut(ty).and_then(|x| x.get_mut(id)) else {
+            return not_found(&format!("{ty}/{id}"));
+        };
+
+        if !resource["relationships"].is_object() {
+            resource["relationships"] = json!({});
+        }
+        let data = &mut resource["relationships"][related]["data"];
+        if !data.is_array() {
+            *data = json!([]);
+        }
+        let data = data.as_array_mut().expect("data is an array");
+
+        for r in refs {
+            let existing = data
+                .iter()
+                .position(|x| x["id"] == r["id"] && x["type"] == r["type"]);
+
+            match (add, existing) {
+                (true, None) => data.push(json!({ "type": r["type"], "id": r["id"] })),
+                (false, Some(pos)) => {
+                    data.remove(pos);
+                }
+                _ => {}
+            }
+        }
+
+        MockResponse::empty(204)
+    }
+
+    fn delete_resource(&self, state: &mut MockState, ty: &str, id: &str) -> MockResponse {
+        match state.resources.get_mut(ty).and_then(|x| x.remove(id)) {
+            Some(_) => MockResponse::empty(204),
+            None => not_found(&format!("{ty}/{id}")),
+        }
+    }
+
+    fn create_submission(&self, state: &mut MockState, request: &MockRequest) -> MockResponse {
+        let body = match request.json() {
+            Ok(v) => v,
+            Err(e) => return MockResponse::error(400, "PARAMETER_ERROR.INVALID", &e.to_string()),
+        };
+
+        let (Some(sha256), Some(name)) = (body["sha256"].as_str(), body["submissionName"].as_str())
+        else {
+            return MockResponse::error(
+                400,
+                "PARAMETER_ERROR.REQUIRED",
+                "sha256 and submissionName are required",
+            );
+        };
+
+        let id = state.new_id().to_lowercase();
+        state.submissions.insert(
+            id.clone(),
+            MockSubmission {
+                name: name.to_string(),
+                sha256: sha256.to_string(),
+            },
+        );
+
+        MockResponse::json(
+            200,
+            &json!({
+                "data": {
+                    "type": "newSubmissions",
+                    "id": id,
+                    "attributes": {
+                        "awsAccessKeyId": "MOCKACCESSKEY",
+                        "awsSecretAccessKey": "mock-secret-access-key",
+                        "awsSessionToken": "mock-session-token",
+                        "bucket": MOCK_NOTARY_BUCKET,
+                        "object": format!("prod/{id}"),
+                    },
+                },
+                "meta": {},
+            }),
+        )
+    }
+
+    fn submission_status(&self, state: &MockState, id: &str) -> String {
+        if state
+            .s3_objects
+            .contains_key(&(MOCK_NOTARY_BUCKET.to_string(), format!("prod/{id}")))
+        {
+            state
+                .notary_status
+                .clone()
+                .unwrap_or_else(|| "Accepted".to_string())
+        } else {
+            "In Progress".to_string()
+        }
+    }
+
+    fn submission_data(&self, state: &MockState, id: &str, submission: &MockSubmission) -> Value {
+        json!({
+            "type": "submissions",
+            "id": id,
+            "attributes": {
+                "createdDate": MOCK_DATE,
+                "name": submission.name,
+                "status": self.submission_status(state, id),
+            },
+        })
+    }
+
+    fn list_submissions(&self, state: &MockState) -> MockResponse {
+        let data = state
+            .submissions
+            .iter()
+            .map(|(id, submission)| self.submission_data(state, id, submission))
+            .collect::<Vec<_>>();
+
+        MockResponse::json(200, &json!({ "data": data, "meta": {} }))
+    }
+
+    fn get_submission(&self, state: &MockState, id: &str) -> MockResponse {
+        match state.submissions.get(id) {
+            Some(submission) => MockResponse::json(
+                200,
+                &json!({
+                    "data": self.submission_data(state, id, submission),
+                    "meta": {},
+                }),
+            ),
+            None => not_found(&format!("submissions/{id}")),
+        }
+    }
+
+    fn submission_logs(&self, state: &MockState, id: &str) -> MockResponse {
+        if !state.submissions.contains_key(id) {
+            return not_found(&format!("submissions/{id}"));
+        }
+
+        MockResponse::json(
+            200,
+            &json!({
+                "data": {
+                    "type": "submissionsLog",
+                    "id": id,
+                    "attributes": {
+                        "developerLogUrl": format!("{}/notary-logs/{}", self.url, id),
+                    },
+                },
+                "meta": {},
+            }),
+        )
+    }
+
+    fn notary_log(&self, state: &MockState, id: &str) -> MockResponse {
+        let Some(submission) = state.submissions.get(id) else {
+            return not_found(&format!("notary-logs/{id}"));
+        };
+
+        let status = self.submission_status(state, id);
+
+        MockResponse::json(
+            200,
+            &json!({
+                "jobId": id,
+                "status": status,
+                "statusSummary": if status == "Accepted" { "Ready for distribution" } else { "" },
+                "archiveFilename": submission.name,
+                "sha256": submission.sha256,
+                "issues": null,
+                "ticketContents": null,
+            }),
+        )
+    }
+
+    fn lookup_tickets(&self, state: &mut MockState, request: &MockRequest) -> MockResponse {
+        let body = match request.json() {
+            Ok(v) => v,
+            Err(e) => return MockResponse::error(400, "BAD_REQUEST", &e.to_string()),
+        };
+
+        let records = body["records"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|record| record["recordName"].as_str().map(|x| x.to_string()))
+            .map(|name| match state.tickets.get(&name) {
+                Some(ticket) => {
+                    let event = json!({
+                        "deviceID": "2",
+                        "timestamp": 1672531200000u64,
+                        "userRecordName": "_mock",
+                    });
+
+                    json!({
+                        "recordName": name,
+                        "recordType": "DeveloperIDTicket",
+                        "fields": {
+                            "signedTicket": {
+                                "type": "BYTES",
+                                "value": STANDARD_ENGINE.encode(ticket),
+                            },
+                        },
+                        "pluginFields": {},
+                        "recordChangeTag": "mock",
+                        "created": event,
+                        "modified": event,
+                        "deleted": false,
+                    })
+                }
+                None => json!({
+                    "recordName": name,
+                    "reason": "Record not found",
+                    "serverErrorCode": "NOT_FOUND",
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        MockResponse::json(200, &json!({ "records": records }))
+    }
+}
+
+/// Resolve the values a `filter[field]` parameter is matched against.
+///
+/// `field` is `id`, an attribute name, a relationship name (matching related
+/// resource IDs), or a dotted path through a relationship. e.g.
+/// `preReleaseVersion.version`.
+fn filter_values(state: &MockState, resource: &Value, field: &str) -> Vec<String> {
+    let scalar = |v: &Value| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(_) | Value::Number(_) => Some(v.to_string()),
+        _ => None,
+    };
+
+    if field == "id" {
+        return scalar(&resource["id"]).into_iter().collect();
+    }
+
+    if let Some(value) = resource["attributes"].get(field) {
+        return scalar(value).into_iter().collect();
+    }
+
+    let (relationship, rest) = match field.split_once('.') {
+        Some((relationship, rest)) => (relationship, Some(rest)),
+        None => (field, None),
+    };
+
+    relationship_refs(&resource["relationships"][relationship]["data"])
+        .iter()
+        .flat_map(|r| match rest {
+            None => scalar(&r["id"]).into_iter().collect::<Vec<_>>(),
+            Some(rest) => r["type"]
+                .as_str()
+                .zip(r["id"].as_str())
+                .and_then(|(ty, id)| state.resources.get(ty)?.get(id))
+                .map(|related| filter_values(state, related, rest))
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Obtain the resource identifiers in a relationship's `data`.
+fn relationship_refs(data: &Value) -> Vec<Value> {
+    match data {
+        Value::Array(refs) => refs.clone(),
+        Value::Object(_) => vec![data.clone()],
+        _ => vec![],
+    }
+}
+
+fn not_found(what: &str) -> MockResponse {
+    MockResponse::error(
+        404,
+        "NOT_FOUND",
+        &format!("The specified resource does not exist: {what}"),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            bundle_api::BundleIdPlatform, certs_api::CertificateType, profile_api::ProfileType,
+        },
+    };
+
+    #[test]
+    fn bundle_ids() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        let created = client.register_bundle_id("com.example.app", "Example")?;
+        assert_eq!(created.data.attributes.identifier, "com.example.app");
+        assert_eq!(created.data.attributes.seed_id, "MOCKSEED00");
+
+        let listed = client.list_bundle_ids()?;
+        assert_eq!(listed.data.len(), 1);
+        assert_eq!(listed.data[0].id, created.data.id);
+
+        let fetched = client.get_bundle_id(&created.data.id)?;
+        assert_eq!(fetched.data.attributes.name, "Example");
+
+        client.delete_bundle_id(&created.data.id)?;
+        assert!(client.list_bundle_ids()?.data.is_empty());
+
+        let err = client.get_bundle_id(&created.data.id).unwrap_err();
+        assert!(err.to_string().contains("NOT_FOUND"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn devices_certificates_profiles() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        let device = client.register_device("Mac", BundleIdPlatform::MacOs, "UDID")?;
+        assert_eq!(device.data.attributes.device_class, "MAC");
+        assert_eq!(client.list_devices()?.data.len(), 1);
+
+        let csr = pem::encode(&pem::Pem::new("CERTIFICATE REQUEST", b"csr".to_vec()));
+        let cert = client.create_certificate(csr, CertificateType::Development)?;
+        assert_eq!(cert.data.attributes.certificate_type, "DEVELOPMENT");
+        assert_eq!(
+            STANDARD_ENGINE.decode(&cert.data.attributes.certificate_content)?,
+            b"csr"
+        );
+
+        let bundle = client.register_bundle_id("com.example.app", "Example")?;
+        let profile = client.create_profile(
+            "Profile",
+            ProfileType::MacAppDevelopment,
+            &bundle.data.id,
+            std::slice::from_ref(&cert.data.id),
+            Some(std::slice::from_ref(&device.data.id)),
+        )?;
+        assert_eq!(profile.data.attributes.platform, "MAC_OS");
+
+        let decoded = client.download_profile(&profile.data.id)?;
+        decoded.verify_with_trust_anchors(std::slice::from_ref(
+            server.profile_signing_certificate(),
+        ))?;
+        assert!(decoded.verify().is_err());
+        assert_eq!(decoded.uuid(), Some(profile.data.id.as_str()));
+        assert_eq!(decoded.platforms(), vec!["OSX"]);
+        assert_eq!(
+            decoded.application_identifier(),
+            Some("MOCKSEED00.com.example.app")
+        );
+        assert_eq!(decoded.team_identifiers(), vec![MOCK_TEAM_ID]);
+        assert_eq!(decoded.provisioned_devices(), vec!["UDID"]);
+        assert_eq!(
+            decoded.developer_certificates_der(),
+            vec![b"csr".as_slice()]
+        );
+        assert_eq!(
+            server.resources("profiles")[0]["relationships"]["bundleId"]["data"]["id"],
+            bundle.data.id
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn notary_submission() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        let submission = client.create_submission("deadbeef", "app.zip")?;
+        let id = submission.data.id;
+        assert_eq!(submission.data.attributes.bucket, MOCK_NOTARY_BUCKET);
+
+        let status = client.get_submission(&id)?;
+        assert_eq!(
+            status.data.attributes.status,
+            crate::notary_api::SubmissionResponseStatus::InProgress
+        );
+
+        let url = format!(
+            "{}/{}/{}",
+            server.url(),
+            submission.data.attributes.bucket,
+            submission.data.attributes.object
+        );
+        let http = reqwest::blocking::Client::new();
+        assert_eq!(
+            http.put(&url).body(b"unsigned".to_vec()).send()?.status(),
+            403
+        );
+        http.put(url)
+            .header("Authorization", "AWS4-HMAC-SHA256 Credential=mock")
+            .body(b"zip content".to_vec())
+            .send()?
+            .error_for_status()?;
+        assert_eq!(
+            server.s3_object(MOCK_NOTARY_BUCKET, &submission.data.attributes.object),
+            Some(b"zip content".to_vec())
+        );
+
+        let status = client.get_submission(&id)?.into_result()?;
+        assert_eq!(status.data.attributes.name, "app.zip");
+
+        let log = client.get_submission_log(&id)?;
+        assert_eq!(log["status"], "Accepted");
+        assert_eq!(log["sha256"], "deadbeef");
+
+        server.set_notary_status("Invalid");
+        assert!(client.get_submission(&id)?.into_result().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn authentication() -> Result<()> {
+        let server = MockServer::start()?;
+
+        // A token signed by a different key is rejected.
+        let other = MockServer::start()?;
+        let mut client = AppStoreConnectClient::new(other.token_encoder()?)?;
+        client.set_api_url(server.url());
+
+        let err = client.list_bundle_ids().unwrap_err();
+        assert!(err.to_string().contains("NOT_AUTHORIZED"));
+
+        // As is no token at all.
+        let res = reqwest::blocking::get(format!("{}/v1/bundleIds", server.url()))?;
+        assert_eq!(res.status().as_u16(), 401);
+
+        // Tokens valid for too long are rejected.
+        let token = server.token_encoder()?.new_token(3600)?;
+        let res = reqwest::blocking::Client::new()
+            .get(format!("{}/v1/bundleIds", server.url()))
+            .bearer_auth(token)
+            .send()?;
+        assert_eq!(res.status().as_u16(), 401);
+
+        Ok(())
+    }
+
+    #[test]
+    fn enqueued_responses() -> Result<()> {
+        let server = MockServer::start()?;
+        let mut client = server.client()?;
+        client.set_retry_policy(crate::RetryPolicy::none());
+
+        server.enqueue_response(
+            "GET",
+            "/v1/devices",
+            MockResponse::error(500, "INTERNAL_ERROR", "boom"),
+        );
+
+        assert!(client.list_devices().is_err());
+        assert!(client.list_devices()?.data.is_empty());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0]
+            .header("authorization")
+            .unwrap()
+            .starts_with("Bearer "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ticket_lookup() -> Result<()> {
+        let server = MockServer::start()?;
+        server.insert_ticket("2/2/deadbeef", b"ticket");
+
+        let res: Value = reqwest::blocking::Client::new()
+            .post(server.ticket_lookup_url())
+            .json(&json!({ "records": [{ "recordName": "2/2/deadbeef" }, { "recordName": "2/2/missing" }] }))
+            .send()?
+            .json()?;
+
+        assert_eq!(
+            res["records"][0]["fields"]["signedTicket"]["value"],
+            STANDARD_ENGINE.encode(b"ticket")
+        );
+        assert_eq!(res["records"][1]["serverErrorCode"], "NOT_FOUND");
+
+        Ok(())
+    }
+}
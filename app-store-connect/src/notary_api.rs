@@ -9,15 +9,31 @@
 //! See also <https://developer.apple.com/documentation/notaryapi>.
 
 use {
-    crate::{AppStoreConnectClient, Result},
+    crate::{file_sha256, s3, AppStoreConnectClient, Result},
+    reqwest::blocking::Body,
     serde::{Deserialize, Serialize},
     serde_json::Value,
+    std::{
+        fs::File,
+        path::Path,
+        time::{Duration, Instant},
+    },
     thiserror::Error,
 };
 
+/// URL of the Notary API submissions endpoint when using the default [crate::NOTARY_API_URL].
 pub const APPLE_NOTARY_SUBMIT_SOFTWARE_URL: &str =
     "https://appstoreconnect.apple.com/notary/v2/submissions";
 
+/// Path of the submissions endpoint relative to the Notary API base URL.
+pub(crate) const SUBMISSIONS_PATH: &str = "notary/v2/submissions";
+
+/// AWS region of the S3 bucket submissions are uploaded to.
+///
+/// The region is not given anywhere in the Apple documentation. From manually
+/// testing all available regions, it appears to be us-west-2.
+pub const NOTARY_S3_REGION: &str = "us-west-2";
+
 /// A notification that the notary service sends you when notarization finishes.
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -35,6 +51,16 @@ pub struct NewSubmissionRequest {
     pub submission_name: String,
 }
 
+impl NewSubmissionRequest {
+    pub fn new(sha256: &str, submission_name: &str) -> Self {
+        Self {
+            notifications: Vec::new(),
+            sha256: sha256.to_string(),
+            submission_name: submission_name.to_string(),
+        }
+    }
+}
+
 /// Information that you use to upload your software for notarization.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -64,7 +90,7 @@ pub struct NewSubmissionResponse {
     pub meta: Value,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, clap::ValueEnum)]
 #[serde(rename_all = "PascalCase")]
 pub enum SubmissionResponseStatus {
     Accepted,
@@ -73,6 +99,7 @@ pub enum SubmissionResponseStatus {
     Invalid,
     Rejected,
     #[serde(other)]
+    #[value(skip)]
     Unknown,
 }
 
@@ -131,6 +158,21 @@ impl SubmissionResponse {
 #[error("notarization {0}")]
 pub struct NotarizationError(SubmissionResponseStatus);
 
+/// A submission did not finish processing within the allowed time.
+#[derive(Clone, Copy, Debug, Error)]
+#[error("submission still in progress after waiting {}s", .0.as_secs())]
+pub struct WaitLimitReached(pub Duration);
+
+/// The notary service’s response to a request for the list of previous submissions.
+///
+/// The service returns up to the 100 most recent submissions.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionListResponse {
+    pub data: Vec<SubmissionResponseData>,
+    pub meta: Value,
+}
+
 /// Information about the log associated with the submission.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -164,14 +206,10 @@ impl AppStoreConnectClient {
     ) -> Result<NewSubmissionResponse> {
         let token = self.get_token()?;
 
-        let body = NewSubmissionRequest {
-            notifications: Vec::new(),
-            sha256: sha256.to_string(),
-            submission_name: submission_name.to_string(),
-        };
+        let body = NewSubmissionRequest::new(sha256, submission_name);
         let req = self
             .client
-            .post(APPLE_NOTARY_SUBMIT_SOFTWARE_URL)
+            .post(self.notary_endpoint(SUBMISSIONS_PATH))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
@@ -180,15 +218,130 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// Upload the content of a submission to the S3 bucket designated by the Notary API.
+    ///
+    /// `path` must be the file whose SHA-256 was given to [Self::create_submission()].
+    pub fn upload_submission(&self, submission: &NewSubmissionResponse, path: &Path) -> Result<()> {
+        let attributes = &submission.data.attributes;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        log::warn!(
+            "uploading {} to s3://{}/{}",
+            path.display(),
+            attributes.bucket,
+            attributes.object
+        );
+
+        let req = s3::put_object(
+            &self.client,
+            self.s3_endpoint_url.as_deref(),
+            NOTARY_S3_REGION,
+            &s3::Credentials {
+                access_key_id: &attributes.aws_access_key_id,
+                secret_access_key: &attributes.aws_secret_access_key,
+                session_token: Some(&attributes.aws_session_token),
+            },
+            &attributes.bucket,
+            &attributes.object,
+            Body::sized(file, len),
+        )?;
+        self.send_request(req)?;
+
+        Ok(())
+    }
+
+    /// Submit a file (`.zip`, `.dmg`, or `.pkg`) for notarization.
+    ///
+    /// This creates a submission and uploads the file. It does not wait for the
+    /// notary service to process it. See [Self::wait_for_submission()] for that.
+    pub fn submit_file(&self, path: &Path) -> Result<NewSubmissionResponse> {
+        let digest = file_sha256(path)?;
+
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| "submission".to_string());
+
+        let submission = self.create_submission(&digest, &name)?;
+        self.upload_submission(&submission, path)?;
+
+        Ok(submission)
+    }
+
+    /// List previous submissions.
+    ///
+    /// The Notary API returns up to the 100 most recent submissions.
+    pub fn list_submissions(&self) -> Result<SubmissionListResponse> {
+        let token = self.get_token()?;
+
+        let req = self
+            .client
+            .get(self.notary_endpoint(SUBMISSIONS_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// List previous submissions having any of the given statuses.
+    ///
+    /// The Notary API doesn't support filtering, so this is performed on the
+    /// results of [Self::list_submissions()]. An empty `statuses` matches all
+    /// submissions.
+    pub fn list_submissions_with_status(
+        &self,
+        statuses: &[SubmissionResponseStatus],
+    ) -> Result<Vec<SubmissionResponseData>> {
+        Ok(self
+            .list_submissions()?
+            .data
+            .into_iter()
+            .filter(|x| statuses.is_empty() || statuses.contains(&x.attributes.status))
+            .collect())
+    }
+
+    /// Poll a submission until the notary service finishes processing it.
+    ///
+    /// Errors with [WaitLimitReached] if processing doesn't finish within `wait_limit`.
+    pub fn wait_for_submission(
+        &self,
+        submission_id: &str,
+        wait_limit: Duration,
+        poll_interval: Duration,
+    ) -> Result<SubmissionResponse> {
+        let start_time = Instant::now();
+
+        loop {
+            let status = self.get_submission(submission_id)?;
+            let elapsed = start_time.elapsed();
+
+            log::warn!(
+                "poll state after {}s: {}",
+                elapsed.as_secs(),
+                status.data.attributes.status
+            );
+
+            if status.data.attributes.status != SubmissionResponseStatus::InProgress {
+                return Ok(status);
+            }
+
+            if elapsed >= wait_limit {
+                return Err(WaitLimitReached(elapsed).into());
+            }
+
+            std::thread::sleep(poll_interval);
+        }
+    }
+
     /// Fetch the status of a Notary API submission.
     pub fn get_submission(&self, submission_id: &str) -> Result<SubmissionResponse> {
         let token = self.get_token()?;
 
         let req = self
             .client
-            .get(format!(
-                "{APPLE_NOTARY_SUBMIT_SOFTWARE_URL}/{submission_id}"
-            ))
+            .get(self.notary_endpoint(&format!("{SUBMISSIONS_PATH}/{submission_id}")))
             .bearer_auth(token)
             .header("Accept", "application/json");
 
@@ -201,9 +354,7 @@ impl AppStoreConnectClient {
 
         let req = self
             .client
-            .get(format!(
-                "{APPLE_NOTARY_SUBMIT_SOFTWARE_URL}/{submission_id}/logs"
-            ))
+            .get(self.notary_endpoint(&format!("{SUBMISSIONS_PATH}/{submission_id}/logs")))
             .bearer_auth(token)
             .header("Accept", "application/json");
 
@@ -215,3 +366,68 @@ impl AppStoreConnectClient {
         Ok(logs)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::mock_server::{MockServer, MOCK_NOTARY_BUCKET},
+    };
+
+    #[test]
+    fn submit_and_list() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("app.zip");
+        std::fs::write(&path, b"zip content")?;
+
+        let submitted = client.submit_file(&path)?;
+        assert_eq!(
+            server.s3_object(MOCK_NOTARY_BUCKET, &submitted.data.attributes.object),
+            Some(b"zip content".to_vec())
+        );
+
+        let put = server
+            .requests()
+            .into_iter()
+            .find(|r| r.method == "PUT")
+            .expect("S3 upload performed");
+        assert!(put
+            .header("authorization")
+            .unwrap()
+            .starts_with("AWS4-HMAC-SHA256 Credential="));
+        assert_eq!(
+            put.header("x-amz-security-token"),
+            Some(submitted.data.attributes.aws_session_token.as_str())
+        );
+
+        let status = client.wait_for_submission(
+            &submitted.data.id,
+            Duration::from_secs(10),
+            Duration::from_millis(10),
+        )?;
+        assert_eq!(status.data.attributes.name, "app.zip");
+        assert_eq!(
+            status.data.attributes.status,
+            SubmissionResponseStatus::Accepted
+        );
+
+        // Never uploaded, so remains in progress.
+        let pending = client.create_submission(&"0".repeat(64), "pending.dmg")?;
+        let err = client
+            .wait_for_submission(&pending.data.id, Duration::ZERO, Duration::ZERO)
+            .unwrap_err();
+        assert!(err.downcast_ref::<WaitLimitReached>().is_some());
+
+        assert_eq!(client.list_submissions()?.data.len(), 2);
+        let in_progress =
+            client.list_submissions_with_status(&[SubmissionResponseStatus::InProgress])?;
+        assert_eq!(in_progress.len(), 1);
+        assert_eq!(in_progress[0].id, pending.data.id);
+        assert_eq!(client.list_submissions_with_status(&[])?.len(), 2);
+
+        Ok(())
+    }
+}
@@ -4,10 +4,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::provisioning_profile::ProvisioningProfile;
+use crate::query::{
+    impl_page, ListQuery, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
 use crate::{AppStoreConnectClient, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-const APPLE_CERTIFICATE_URL: &str = "https://api.appstoreconnect.apple.com/v1/profiles";
+pub(crate) const PROFILES_PATH: &str = "v1/profiles";
 
 impl AppStoreConnectClient {
     pub fn create_profile(
@@ -19,44 +25,10 @@ impl AppStoreConnectClient {
         devices: Option<&[String]>,
     ) -> Result<ProfileResponse> {
         let token = self.get_token()?;
-        let body = ProfileCreateRequest {
-            data: ProfileCreateRequestData {
-                attributes: ProfileCreateRequestAttributes {
-                    name: name.into(),
-                    profile_type: profile_type.to_string(),
-                },
-                relationships: ProfileCreateRequestRelationships {
-                    bundle_id: Ref {
-                        data: RefData {
-                            id: bundle_id.into(),
-                            r#type: "bundleIds".into(),
-                        },
-                    },
-                    certificates: Refs {
-                        data: certificates
-                            .iter()
-                            .map(|certificate| RefData {
-                                id: certificate.into(),
-                                r#type: "certificates".into(),
-                            })
-                            .collect(),
-                    },
-                    devices: devices.map(|devices| Refs {
-                        data: devices
-                            .iter()
-                            .map(|device| RefData {
-                                id: device.into(),
-                                r#type: "devices".into(),
-                            })
-                            .collect(),
-                    }),
-                },
-                r#type: "profiles".into(),
-            },
-        };
+        let body = ProfileCreateRequest::new(name, profile_type, bundle_id, certificates, devices);
         let req = self
             .client
-            .post(APPLE_CERTIFICATE_URL)
+            .post(self.api_endpoint(PROFILES_PATH))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
@@ -64,31 +36,40 @@ impl AppStoreConnectClient {
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// List all profiles, following pagination.
     pub fn list_profiles(&self) -> Result<ProfilesResponse> {
-        let token = self.get_token()?;
-        let req = self
-            .client
-            .get(APPLE_CERTIFICATE_URL)
-            .bearer_auth(token)
-            .header("Accept", "application/json");
-        Ok(self.send_request(req)?.json()?)
+        self.profiles(&ListQuery::new().limit(MAX_PAGE_LIMIT))?
+            .collect_all()
+    }
+
+    /// Obtain a [Paginator] over profiles matching a query.
+    pub fn profiles(
+        &self,
+        query: &ListQuery<ProfileFilter>,
+    ) -> Result<Paginator<'_, ProfilesResponse>> {
+        self.paginate(PROFILES_PATH, query)
     }
 
     pub fn get_profile(&self, id: &str) -> Result<ProfileResponse> {
         let token = self.get_token()?;
         let req = self
             .client
-            .get(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .get(self.api_endpoint(&format!("{PROFILES_PATH}/{id}")))
             .bearer_auth(token)
             .header("Accept", "application/json");
         Ok(self.send_request(req)?.json()?)
     }
 
+    /// Download and decode a provisioning profile.
+    pub fn download_profile(&self, id: &str) -> Result<ProvisioningProfile> {
+        self.get_profile(id)?.data.attributes.decode_profile()
+    }
+
     pub fn delete_profile(&self, id: &str) -> Result<()> {
         let token = self.get_token()?;
         let req = self
             .client
-            .delete(format!("{APPLE_CERTIFICATE_URL}/{id}"))
+            .delete(self.api_endpoint(&format!("{PROFILES_PATH}/{id}")))
             .bearer_auth(token);
         self.send_request(req)?;
         Ok(())
@@ -101,6 +82,52 @@ pub struct ProfileCreateRequest {
     pub data: ProfileCreateRequestData,
 }
 
+impl ProfileCreateRequest {
+    pub fn new(
+        name: &str,
+        profile_type: ProfileType,
+        bundle_id: &str,
+        certificates: &[String],
+        devices: Option<&[String]>,
+    ) -> Self {
+        Self {
+            data: ProfileCreateRequestData {
+                attributes: ProfileCreateRequestAttributes {
+                    name: name.into(),
+                    profile_type: profile_type.to_string(),
+                },
+                relationships: ProfileCreateRequestRelationships {
+                    bundle_id: Ref {
+                        data: RefData {
+                            id: bundle_id.into(),
+                            r#type: "bundleIds".into(),
+                        },
+                    },
+                    certificates: Refs {
+                        data: certificates
+                            .iter()
+                            .map(|certificate| RefData {
+                                id: certificate.into(),
+                                r#type: "certificates".into(),
+                            })
+                            .collect(),
+                    },
+                    devices: devices.map(|devices| Refs {
+                        data: devices
+                            .iter()
+                            .map(|device| RefData {
+                                id: device.into(),
+                                r#type: "devices".into(),
+                            })
+                            .collect(),
+                    }),
+                },
+                r#type: "profiles".into(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProfileCreateRequestData {
@@ -175,13 +202,22 @@ pub struct ProfileResponse {
 #[serde(rename_all = "camelCase")]
 pub struct ProfilesResponse {
     pub data: Vec<Profile>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
 }
 
+impl_page!(ProfilesResponse, Profile);
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Profile {
     pub attributes: ProfileAttributes,
     pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -196,3 +232,55 @@ pub struct ProfileAttributes {
     pub profile_type: String,
     pub expiration_date: String,
 }
+
+impl ProfileAttributes {
+    /// Decode [Self::profile_content] into a [ProvisioningProfile].
+    pub fn decode_profile(&self) -> Result<ProvisioningProfile> {
+        ProvisioningProfile::from_base64(&self.profile_content)
+    }
+}
+
+/// State of a provisioning profile.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProfileState {
+    Active,
+    Invalid,
+}
+
+impl std::fmt::Display for ProfileState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Active => "ACTIVE",
+            Self::Invalid => "INVALID",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Filters for listing provisioning profiles.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProfileFilter {
+    Id(String),
+    Name(String),
+    ProfileState(ProfileState),
+    ProfileType(ProfileType),
+}
+
+impl QueryFilter for ProfileFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::Name(_) => "name",
+            Self::ProfileState(_) => "profileState",
+            Self::ProfileType(_) => "profileType",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::Name(v) => v.clone(),
+            Self::ProfileState(v) => v.to_string(),
+            Self::ProfileType(v) => v.to_string(),
+        }
+    }
+}
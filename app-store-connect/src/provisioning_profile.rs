@@ -0,0 +1,620 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Provisioning profiles.
+//!
+//! A provisioning profile is a CMS `SignedData` structure, signed by Apple,
+//! wrapping an XML plist describing which app, team, devices, certificates,
+//! and entitlements the profile authorizes.
+
+use {
+    crate::Result,
+    anyhow::{anyhow, Context},
+    base64::{engine::general_purpose::STANDARD as STANDARD_ENGINE, Engine},
+    cryptographic_message_syntax::SignedData,
+    std::{
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+    thiserror::Error,
+    x509_certificate::CapturedX509Certificate,
+};
+
+/// DER encoding of the Apple Inc. Root certificate.
+///
+/// Provisioning profiles are signed by a certificate chaining to this one.
+const APPLE_INC_ROOT_CERTIFICATE: &[u8] = include_bytes!("apple-certs/AppleIncRootCertificate.cer");
+
+/// Common name of the certificate Apple signs provisioning profiles with.
+///
+/// Developer certificates also chain to the Apple Inc. Root certificate. So the
+/// signer must be pinned to tell Apple signed profiles apart from profiles signed
+/// by any developer.
+const PROFILE_SIGNING_COMMON_NAME: &str = "Apple iPhone OS Provisioning Profile Signing";
+
+/// Filename of a provisioning profile embedded in an iOS-style bundle.
+pub const EMBEDDED_MOBILEPROVISION: &str = "embedded.mobileprovision";
+
+/// Filename of a provisioning profile embedded in a macOS bundle's `Contents` directory.
+pub const EMBEDDED_PROVISIONPROFILE: &str = "embedded.provisionprofile";
+
+/// A provisioning profile failed signature verification.
+#[derive(Clone, Debug, Error)]
+#[error("provisioning profile signature is invalid: {0}")]
+pub struct InvalidProfileSignature(String);
+
+/// Resolve the directory Xcode and the system install provisioning profiles to.
+///
+/// This is `~/Library/MobileDevice/Provisioning Profiles`.
+pub fn provisioning_profiles_dir() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .ok_or_else(|| anyhow!("unable to resolve home directory"))?
+        .join("Library")
+        .join("MobileDevice")
+        .join("Provisioning Profiles"))
+}
+
+/// A parsed provisioning profile.
+///
+/// Instances are constructed from the raw profile data, which is the content of
+/// `.mobileprovision` / `.provisionprofile` files and the base64 decoded
+/// `profileContent` attribute of profiles in the App Store Connect API.
+///
+/// Parsing does not verify the signature. Call [Self::verify()] for that.
+#[derive(Clone)]
+pub struct ProvisioningProfile {
+    data: Vec<u8>,
+    signed_data: SignedData,
+    plist: plist::Dictionary,
+}
+
+impl std::fmt::Debug for ProvisioningProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProvisioningProfile")
+            .field("plist", &self.plist)
+            .finish()
+    }
+}
+
+impl ProvisioningProfile {
+    /// Parse a provisioning profile from its raw, CMS encoded data.
+    pub fn from_der(data: impl Into<Vec<u8>>) -> Result<Self> {
+        let data = data.into();
+
+        let signed_data =
+            SignedData::parse_ber(&data).context("parsing provisioning profile CMS data")?;
+        let content = signed_data
+            .signed_content()
+            .ok_or_else(|| anyhow!("provisioning profile does not contain signed content"))?;
+
+        let plist = plist::Value::from_reader_xml(content)
+            .context("parsing provisioning profile plist")?
+            .into_dictionary()
+            .ok_or_else(|| anyhow!("provisioning profile plist is not a dictionary"))?;
+
+        Ok(Self {
+            data,
+            signed_data,
+            plist,
+        })
+    }
+
+    /// Parse a provisioning profile from base64 encoded data.
+    ///
+    /// This is the encoding of the `profileContent` attribute in the App Store Connect API.
+    pub fn from_base64(data: &str) -> Result<Self> {
+        Self::from_der(STANDARD_ENGINE.decode(data)?)
+    }
+
+    /// Parse a provisioning profile from a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        Self::from_der(std::fs::read(path).with_context(|| format!("reading {}", path.display()))?)
+    }
+
+    /// The raw, CMS encoded profile data.
+    pub fn as_der(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The parsed CMS structure.
+    pub fn signed_data(&self) -> &SignedData {
+        &self.signed_data
+    }
+
+    /// The profile's plist dictionary.
+    pub fn plist(&self) -> &plist::Dictionary {
+        &self.plist
+    }
+
+    fn string(&self, key: &str) -> Option<&str> {
+        self.plist.get(key).and_then(|v| v.as_string())
+    }
+
+    fn strings(&self, key: &str) -> Vec<&str> {
+        self.plist
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|v| v.as_string()).collect())
+            .unwrap_or_default()
+    }
+
+    fn date(&self, key: &str) -> Option<SystemTime> {
+        self.plist
+            .get(key)
+            .and_then(|v| v.as_date())
+            .map(SystemTime::from)
+    }
+
+    /// The name of the profile.
+    pub fn name(&self) -> Option<&str> {
+        self.string("Name")
+    }
+
+    /// The UUID of the profile.
+    pub fn uuid(&self) -> Option<&str> {
+        self.string("UUID")
+    }
+
+    /// The name of the app ID the profile is for.
+    pub fn app_id_name(&self) -> Option<&str> {
+        self.string("AppIDName")
+    }
+
+    /// The application identifier the profile is for. e.g. `TEAMID.com.example.app`.
+    ///
+    /// This is the `application-identifier` entitlement, or
+    /// `com.apple.application-identifier` for macOS profiles.
+    pub fn application_identifier(&self) -> Option<&str> {
+        let entitlements = self.entitlements()?;
+
+        entitlements
+            .get("application-identifier")
+            .or_else(|| entitlements.get("com.apple.application-identifier"))
+            .and_then(|v| v.as_string())
+    }
+
+    /// Application identifier prefixes (seed IDs) the profile covers.
+    pub fn application_identifier_prefixes(&self) -> Vec<&str> {
+        self.strings("ApplicationIdentifierPrefix")
+    }
+
+    /// Identifiers of the teams owning the profile.
+    pub fn team_identifiers(&self) -> Vec<&str> {
+        self.strings("TeamIdentifier")
+    }
+
+    /// The name of the team owning the profile.
+    pub fn team_name(&self) -> Option<&str> {
+        self.string("TeamName")
+    }
+
+    /// Platforms the profile is valid for. e.g. `iOS` or `OSX`.
+    pub fn platforms(&self) -> Vec<&str> {
+        self.strings("Platform")
+    }
+
+    /// Entitlements granted by the profile.
+    pub fn entitlements(&self) -> Option<&plist::Dictionary> {
+        self.plist
+            .get("Entitlements")
+            .and_then(|v| v.as_dictionary())
+    }
+
+    /// When the profile was created.
+    pub fn creation_date(&self) -> Option<SystemTime> {
+        self.date("CreationDate")
+    }
+
+    /// When the profile expires.
+    pub fn expiration_date(&self) -> Option<SystemTime> {
+        self.date("ExpirationDate")
+    }
+
+    /// Whether the profile has expired.
+    ///
+    /// Profiles without an expiration date are considered expired.
+    pub fn is_expired(&self) -> bool {
+        self.expiration_date()
+            .map(|expires| expires <= SystemTime::now())
+            .unwrap_or(true)
+    }
+
+    /// UDIDs of devices the profile is valid on.
+    ///
+    /// Empty for distribution profiles.
+    pub fn provisioned_devices(&self) -> Vec<&str> {
+        self.strings("ProvisionedDevices")
+    }
+
+    /// Whether the profile is valid on all devices.
+    ///
+    /// This is the case for enterprise and Developer ID profiles.
+    pub fn provisions_all_devices(&self) -> bool {
+        self.plist
+            .get("ProvisionsAllDevices")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false)
+    }
+
+    /// DER encoded signing certificates the profile is valid for.
+    pub fn developer_certificates_der(&self) -> Vec<&[u8]> {
+        self.plist
+            .get("DeveloperCertificates")
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|v| v.as_data()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Parsed signing certificates the profile is valid for.
+    pub fn developer_certificates(&self) -> Result<Vec<CapturedX509Certificate>> {
+        self.developer_certificates_der()
+            .into_iter()
+            .map(|der| Ok(CapturedX509Certificate::from_der(der.to_vec())?))
+            .collect()
+    }
+
+    /// Verify the profile was signed by Apple.
+    ///
+    /// This verifies the CMS signature, that the signing certificate is Apple's
+    /// `Apple iPhone OS Provisioning Profile Signing` certificate, and that it chains
+    /// to the Apple Inc. Root certificate.
+    pub fn verify(&self) -> Result<()> {
+        self.verify_signers(
+            &[CapturedX509Certificate::from_der(
+                APPLE_INC_ROOT_CERTIFICATE,
+            )?],
+            Some(PROFILE_SIGNING_COMMON_NAME),
+        )
+    }
+
+    /// Verify the profile's signature against custom trust anchors.
+    ///
+    /// The signing certificate must be one of `anchors` or chain to one of them via
+    /// certificates embedded in the profile. Certificate validity periods are not
+    /// checked.
+    pub fn verify_with_trust_anchors(&self, anchors: &[CapturedX509Certificate]) -> Result<()> {
+        self.verify_signers(anchors, None)
+    }
+
+    /// Verify signers chain to `anchors` and optionally have a given common name.
+    fn verify_signers(
+        &self,
+        anchors: &[CapturedX509Certificate],
+        signer_common_name: Option<&str>,
+    ) -> Result<()> {
+        let mut signers = self.signed_data.signers().peekable();
+        if signers.peek().is_none() {
+            return Err(InvalidProfileSignature("profile has no signers".into()).into());
+        }
+
+        for signer in signers {
+            signer
+                .verify_signature_with_signed_data(&self.signed_data)
+                .map_err(|e| InvalidProfileSignature(e.to_string()))?;
+            signer
+                .verify_message_digest_with_signed_data(&self.signed_data)
+                .map_err(|e| InvalidProfileSignature(e.to_string()))?;
+
+            let (issuer, serial) = signer.certificate_issuer_and_serial().ok_or_else(|| {
+                InvalidProfileSignature("signer is not identified by issuer and serial".into())
+            })?;
+
+            let cert = self
+                .signed_data
+                .certificates()
+                .find(|cert| cert.issuer_name() == issuer && cert.serial_number_asn1() == serial)
+                .ok_or_else(|| InvalidProfileSignature("signing certificate not found".into()))?;
+
+            let mut chain = vec![cert];
+            chain.extend(cert.resolve_signing_chain(self.signed_data.certificates()));
+            let last = chain.last().expect("chain is not empty");
+
+            let trusted = anchors.iter().any(|anchor| {
+                chain.contains(&anchor) || last.verify_signed_by_certificate(anchor).is_ok()
+            });
+
+            if !trusted {
+                return Err(InvalidProfileSignature(format!(
+                    "signing certificate {} does not chain to a trusted certificate",
+                    cert.subject_common_name().unwrap_or_default()
+                ))
+                .into());
+            }
+
+            if let Some(expected) = signer_common_name {
+                let common_name = cert.subject_common_name();
+
+                if common_name.as_deref() != Some(expected) {
+                    return Err(InvalidProfileSignature(format!(
+                        "signing certificate {} is not {}",
+                        common_name.unwrap_or_default(),
+                        expected
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The filename the profile is installed as.
+    ///
+    /// This is `<UUID>.mobileprovision`, or `<UUID>.provisionprofile` for macOS profiles.
+    pub fn install_filename(&self) -> Result<String> {
+        let uuid = self
+            .uuid()
+            .ok_or_else(|| anyhow!("provisioning profile does not have a UUID"))?;
+
+        let extension = if self.platforms().contains(&"OSX") {
+            "provisionprofile"
+        } else {
+            "mobileprovision"
+        };
+
+        Ok(format!("{uuid}.{extension}"))
+    }
+
+    /// Install the profile into [provisioning_profiles_dir()].
+    ///
+    /// Returns the path of the written file.
+    pub fn install(&self) -> Result<PathBuf> {
+        self.install_to_dir(provisioning_profiles_dir()?)
+    }
+
+    /// Write the profile into a directory using [Self::install_filename()].
+    ///
+    /// The directory is created if it doesn't exist. Returns the path of the written file.
+    pub fn install_to_dir(&self, dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let path = dir.join(self.install_filename()?);
+        std::fs::write(&path, &self.data)?;
+
+        Ok(path)
+    }
+
+    /// Embed the profile into a bundle.
+    ///
+    /// Bundles with a `Contents` directory (macOS style) receive
+    /// `Contents/embedded.provisionprofile`. Other bundles receive
+    /// `embedded.mobileprovision`. Returns the path of the written file.
+    pub fn embed_in_bundle(&self, bundle_dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let bundle_dir = bundle_dir.as_ref();
+        let contents = bundle_dir.join("Contents");
+
+        let path = if contents.is_dir() {
+            contents.join(EMBEDDED_PROVISIONPROFILE)
+        } else {
+            bundle_dir.join(EMBEDDED_MOBILEPROVISION)
+        };
+        std::fs::write(&path, &self.data)?;
+
+        Ok(path)
+    }
+}
+
+/// Produce provisioning profile data by signing a plist.
+///
+/// Only Apple can produce profiles devices accept. This exists for testing.
+#[cfg(any(test, feature = "mock-server"))]
+pub(crate) fn sign_profile_plist(
+    plist: &plist::Dictionary,
+    signing_key: &x509_certificate::InMemorySigningKeyPair,
+    signing_certificate: &CapturedX509Certificate,
+) -> Result<Vec<u8>> {
+    use cryptographic_message_syntax::{SignedDataBuilder, SignerBuilder};
+
+    let mut content = vec![];
+    plist::Value::Dictionary(plist.clone()).to_writer_xml(&mut content)?;
+
+    Ok(SignedDataBuilder::default()
+        .content_inline(content)
+        .certificate(signing_certificate.clone())
+        .signer(SignerBuilder::new(signing_key, signing_certificate.clone()))
+        .build_der()?)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        x509_certificate::{
+            EcdsaCurve, InMemorySigningKeyPair, KeyAlgorithm, X509CertificateBuilder,
+        },
+    };
+
+    fn signing_certificate(name: &str) -> (CapturedX509Certificate, InMemorySigningKeyPair) {
+        let mut builder = X509CertificateBuilder::default();
+        builder
+            .subject()
+            .append_common_name_utf8_string(name)
+            .unwrap();
+
+        builder
+            .create_with_random_keypair(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))
+            .unwrap()
+    }
+
+    fn profile_plist(developer_certificate: &CapturedX509Certificate) -> plist::Dictionary {
+        let mut entitlements = plist::Dictionary::new();
+        entitlements.insert(
+            "application-identifier".into(),
+            "TEAMID1234.com.example.app".into(),
+        );
+        entitlements.insert("get-task-allow".into(), true.into());
+
+        let mut plist = plist::Dictionary::new();
+        plist.insert("AppIDName".into(), "Example".into());
+        plist.insert(
+            "ApplicationIdentifierPrefix".into(),
+            vec![plist::Value::from("TEAMID1234")].into(),
+        );
+        plist.insert(
+            "CreationDate".into(),
+            plist::Date::from_xml_format("2023-01-01T00:00:00Z")
+                .unwrap()
+                .into(),
+        );
+        plist.insert(
+            "DeveloperCertificates".into(),
+            vec![plist::Value::Data(
+                developer_certificate.encode_der().unwrap(),
+            )]
+            .into(),
+        );
+        plist.insert("Entitlements".into(), entitlements.into());
+        plist.insert(
+            "ExpirationDate".into(),
+            plist::Date::from_xml_format("2024-01-01T00:00:00Z")
+                .unwrap()
+                .into(),
+        );
+        plist.insert("Name".into(), "Example Development".into());
+        plist.insert("Platform".into(), vec![plist::Value::from("iOS")].into());
+        plist.insert(
+            "ProvisionedDevices".into(),
+            vec![plist::Value::from("UDID1"), plist::Value::from("UDID2")].into(),
+        );
+        plist.insert(
+            "TeamIdentifier".into(),
+            vec![plist::Value::from("TEAMID1234")].into(),
+        );
+        plist.insert("TeamName".into(), "Example Team".into());
+        plist.insert("UUID".into(), "11111111-2222-3333-4444-555555555555".into());
+        plist.insert("Version".into(), 1.into());
+
+        plist
+    }
+
+    #[test]
+    fn parse_and_verify() -> Result<()> {
+        let (developer_cert, _) = signing_certificate("Apple Development: Example");
+        let (signing_cert, signing_key) = signing_certificate("Profile Signing");
+
+        let data =
+            sign_profile_plist(&profile_plist(&developer_cert), &signing_key, &signing_cert)?;
+        let profile = ProvisioningProfile::from_der(data)?;
+
+        assert_eq!(profile.name(), Some("Example Development"));
+        assert_eq!(profile.uuid(), Some("11111111-2222-3333-4444-555555555555"));
+        assert_eq!(profile.app_id_name(), Some("Example"));
+        assert_eq!(
+            profile.application_identifier(),
+            Some("TEAMID1234.com.example.app")
+        );
+        assert_eq!(
+            profile.application_identifier_prefixes(),
+            vec!["TEAMID1234"]
+        );
+        assert_eq!(profile.team_identifiers(), vec!["TEAMID1234"]);
+        assert_eq!(profile.team_name(), Some("Example Team"));
+        assert_eq!(profile.platforms(), vec!["iOS"]);
+        assert_eq!(profile.provisioned_devices(), vec!["UDID1", "UDID2"]);
+        assert!(!profile.provisions_all_devices());
+        assert!(profile.is_expired());
+        assert!(profile.creation_date() < profile.expiration_date());
+        assert_eq!(
+            profile.entitlements().unwrap().get("get-task-allow"),
+            Some(&plist::Value::Boolean(true))
+        );
+        assert_eq!(
+            profile.developer_certificates()?,
+            vec![developer_cert.clone()]
+        );
+
+        // Not signed by Apple.
+        let err = profile.verify().unwrap_err();
+        assert!(err.downcast_ref::<InvalidProfileSignature>().is_some());
+
+        profile.verify_with_trust_anchors(std::slice::from_ref(&signing_cert))?;
+
+        let (other_cert, _) = signing_certificate("Other");
+        assert!(profile.verify_with_trust_anchors(&[other_cert]).is_err());
+
+        // Certificates chaining to the root but not being Apple's profile signing
+        // certificate are rejected. e.g. developer certificates issued by WWDR.
+        let (root_cert, root_key) = signing_certificate("Root");
+        let issue = |name: &str| {
+            let mut builder = X509CertificateBuilder::default();
+            builder
+                .subject()
+                .append_common_name_utf8_string(name)
+                .unwrap();
+            *builder.issuer() = root_cert.subject_name().clone();
+            builder.serial_number(2);
+
+            // The certificate has the root's key, as the builder can only sign
+            // certificates with their own key.
+            builder.create_with_key_pair(&root_key).unwrap()
+        };
+
+        let developer_signed = ProvisioningProfile::from_der(sign_profile_plist(
+            &profile_plist(&developer_cert),
+            &root_key,
+            &issue("Apple Development: Mallory"),
+        )?)?;
+        developer_signed.verify_with_trust_anchors(std::slice::from_ref(&root_cert))?;
+        let err = developer_signed
+            .verify_signers(
+                std::slice::from_ref(&root_cert),
+                Some(PROFILE_SIGNING_COMMON_NAME),
+            )
+            .unwrap_err();
+        assert!(err.downcast_ref::<InvalidProfileSignature>().is_some());
+
+        let apple_signed = ProvisioningProfile::from_der(sign_profile_plist(
+            &profile_plist(&developer_cert),
+            &root_key,
+            &issue(PROFILE_SIGNING_COMMON_NAME),
+        )?)?;
+        apple_signed.verify_signers(
+            std::slice::from_ref(&root_cert),
+            Some(PROFILE_SIGNING_COMMON_NAME),
+        )?;
+
+        assert!(ProvisioningProfile::from_der(b"not a profile".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn install_and_embed() -> Result<()> {
+        let (signing_cert, signing_key) = signing_certificate("Profile Signing");
+        let data = sign_profile_plist(&profile_plist(&signing_cert), &signing_key, &signing_cert)?;
+        let profile = ProvisioningProfile::from_der(data.clone())?;
+
+        let temp_dir = tempfile::tempdir()?;
+
+        let path = profile.install_to_dir(temp_dir.path().join("Provisioning Profiles"))?;
+        assert_eq!(
+            path.file_name().unwrap(),
+            "11111111-2222-3333-4444-555555555555.mobileprovision"
+        );
+        assert_eq!(std::fs::read(path)?, data);
+
+        let ios_bundle = temp_dir.path().join("Example.app");
+        std::fs::create_dir(&ios_bundle)?;
+        assert_eq!(
+            profile.embed_in_bundle(&ios_bundle)?,
+            ios_bundle.join(EMBEDDED_MOBILEPROVISION)
+        );
+
+        let macos_bundle = temp_dir.path().join("Example-macOS.app");
+        std::fs::create_dir_all(macos_bundle.join("Contents"))?;
+        assert_eq!(
+            profile.embed_in_bundle(&macos_bundle)?,
+            macos_bundle
+                .join("Contents")
+                .join(EMBEDDED_PROVISIONPROFILE)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Querying and paginating list endpoints.
+//!
+//! App Store Connect API list endpoints return resources one page at a time and
+//! accept `filter[<field>]`, `fields[<type>]`, `include`, `sort`, and `limit` query
+//! parameters. [ListQuery] builds these parameters and [Paginator] follows the
+//! `links.next` URL of each page until all resources have been fetched.
+
+use {
+    crate::{AppStoreConnectClient, Result},
+    serde::{de::DeserializeOwned, Deserialize},
+    serde_json::Value,
+    std::marker::PhantomData,
+};
+
+/// Maximum page size accepted by list endpoints.
+pub const MAX_PAGE_LIMIT: u32 = 200;
+
+/// A filter that can be applied to a list endpoint.
+///
+/// Each resource has an enum of filters it supports. e.g.
+/// [crate::device_api::DeviceFilter].
+pub trait QueryFilter {
+    /// The name of the field being filtered. e.g. `platform`.
+    fn field(&self) -> &'static str;
+
+    /// The value to filter by.
+    fn value(&self) -> String;
+}
+
+/// A filter type for endpoints that don't support filtering.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NoFilter {}
+
+impl QueryFilter for NoFilter {
+    fn field(&self) -> &'static str {
+        match *self {}
+    }
+
+    fn value(&self) -> String {
+        match *self {}
+    }
+}
+
+/// Query parameters for a list endpoint.
+///
+/// `F` is the type of filters supported by the endpoint. Multiple filters on the
+/// same field match resources having any of the values.
+#[derive(Clone, Debug)]
+pub struct ListQuery<F> {
+    filters: Vec<(&'static str, Vec<String>)>,
+    fields: Vec<(String, Vec<String>)>,
+    include: Vec<String>,
+    sort: Vec<String>,
+    limit: Option<u32>,
+    _filter: PhantomData<F>,
+}
+
+impl<F> Default for ListQuery<F> {
+    fn default() -> Self {
+        Self {
+            filters: vec![],
+            fields: vec![],
+            include: vec![],
+            sort: vec![],
+            limit: None,
+            _filter: PhantomData,
+        }
+    }
+}
+
+impl<F: QueryFilter> ListQuery<F> {
+    /// Construct an empty query.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return resources matching a filter.
+    pub fn filter(mut self, filter: F) -> Self {
+        let field = filter.field();
+        let value = filter.value();
+
+        if let Some((_, values)) = self.filters.iter_mut().find(|(f, _)| *f == field) {
+            values.push(value);
+        } else {
+            self.filters.push((field, vec![value]));
+        }
+
+        self
+    }
+
+    /// Only return the given fields of resources of a type.
+    ///
+    /// e.g. `fields("devices", ["name", "udid"])`.
+    ///
+    /// Response types of this crate require their attributes to be present. Sparse
+    /// fieldsets on the primary resource therefore require [JsonApiPage].
+    pub fn fields(
+        mut self,
+        resource_type: impl ToString,
+        fields: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.fields.push((
+            resource_type.to_string(),
+            fields.into_iter().map(|x| x.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Include related resources in the response. e.g. `bundleId`.
+    ///
+    /// Related resources are available in the `included` field of pages.
+    pub fn include(mut self, relationship: impl ToString) -> Self {
+        self.include.push(relationship.to_string());
+        self
+    }
+
+    /// Sort by a field in ascending order.
+    pub fn sort(mut self, field: impl ToString) -> Self {
+        self.sort.push(field.to_string());
+        self
+    }
+
+    /// Sort by a field in descending order.
+    pub fn sort_descending(mut self, field: impl ToString) -> Self {
+        self.sort.push(format!("-{}", field.to_string()));
+        self
+    }
+
+    /// Set the maximum number of resources per page.
+    ///
+    /// Values are capped at [MAX_PAGE_LIMIT].
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(std::cmp::min(limit, MAX_PAGE_LIMIT));
+        self
+    }
+
+    /// Obtain the query string parameters, in a stable order.
+    ///
+    /// Multiple values of a parameter are comma delimited. So this errors if a
+    /// filter value contains a comma, as it can't be expressed.
+    pub fn params(&self) -> Result<Vec<(String, String)>> {
+        let mut res = vec![];
+
+        for (field, values) in &self.filters {
+            if let Some(value) = values.iter().find(|v| v.contains(',')) {
+                return Err(anyhow::anyhow!(
+                    "filter[{field}] value cannot contain a comma: {value}"
+                ));
+            }
+
+            res.push((format!("filter[{field}]"), values.join(",")));
+        }
+        for (ty, fields) in &self.fields {
+            res.push((format!("fields[{ty}]"), fields.join(",")));
+        }
+        if !self.include.is_empty() {
+            res.push(("include".to_string(), self.include.join(",")));
+        }
+        if !self.sort.is_empty() {
+            res.push(("sort".to_string(), self.sort.join(",")));
+        }
+        if let Some(limit) = self.limit {
+            res.push(("limit".to_string(), limit.to_string()));
+        }
+
+        Ok(res)
+    }
+}
+
+/// Links in a paged response.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PagedDocumentLinks {
+    /// URL of this page.
+    #[serde(rename = "self")]
+    pub self_link: Option<String>,
+    /// URL of the first page.
+    pub first: Option<String>,
+    /// URL of the next page, if there is one.
+    pub next: Option<String>,
+}
+
+/// Paging information in a paged response.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PagingInformation {
+    pub paging: Paging,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Paging {
+    /// Total number of resources matching the query.
+    pub total: u64,
+    /// Maximum number of resources per page.
+    pub limit: u64,
+}
+
+/// A page of resources returned by a list endpoint.
+pub trait Page: DeserializeOwned {
+    /// The type of resource in the page.
+    type Item;
+
+    /// URL of the next page, if there is one.
+    fn next_url(&self) -> Option<&str>;
+
+    /// Obtain the resources in this page.
+    fn into_items(self) -> Vec<Self::Item>;
+
+    /// Append the resources (and included resources) of another page to this one.
+    fn append(&mut self, other: Self);
+}
+
+/// A page of untyped JSON:API resources.
+///
+/// This can be used with any list endpoint, including ones not otherwise
+/// supported by this crate.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonApiPage {
+    pub data: Vec<Value>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+/// Implement [Page] for a response type having `data`, `included`, and `links` fields.
+macro_rules! impl_page {
+    ($page:ty, $item:ty) => {
+        impl $crate::query::Page for $page {
+            type Item = $item;
+
+            fn next_url(&self) -> Option<&str> {
+                self.links.next.as_deref()
+            }
+
+            fn into_items(self) -> Vec<Self::Item> {
+                self.data
+            }
+
+            fn append(&mut self, other: Self) {
+                self.data.extend(other.data);
+                self.included.extend(other.included);
+                self.links.next = other.links.next;
+            }
+        }
+    };
+}
+
+pub(crate) use impl_page;
+
+impl_page!(JsonApiPage, Value);
+
+/// Resolve the URL of the page following a page.
+///
+/// Requests for pages carry the bearer token. So `links.next` URLs not having the
+/// same origin as the API URL are rejected rather than followed.
+pub(crate) fn next_page_url(api_url: &str, page: &impl Page) -> Result<Option<String>> {
+    let Some(next) = page.next_url() else {
+        return Ok(None);
+    };
+
+    if reqwest::Url::parse(next)?.origin() != reqwest::Url::parse(api_url)?.origin() {
+        return Err(anyhow::anyhow!(
+            "refusing to follow next page URL not on {api_url}: {next}"
+        ));
+    }
+
+    Ok(Some(next.to_string()))
+}
+
+/// An iterator over the pages of a list endpoint.
+///
+/// Each call to `next()` performs an HTTP request for the next page.
+pub struct Paginator<'a, P> {
+    client: &'a AppStoreConnectClient,
+    next: Option<String>,
+    _page: PhantomData<P>,
+}
+
+impl<'a, P: Page> Paginator<'a, P> {
+    pub(crate) fn new(client: &'a AppStoreConnectClient, url: String) -> Self {
+        Self {
+            client,
+            next: Some(url),
+            _page: PhantomData,
+        }
+    }
+
+    /// Obtain an iterator over the resources in all pages.
+    pub fn items(self) -> impl Iterator<Item = Result<P::Item>> + 'a
+    where
+        P: 'a,
+    {
+        self.flat_map(|page| match page {
+            Ok(page) => page.into_items().into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        })
+    }
+
+    /// Fetch all pages and combine them into a single page.
+    pub fn collect_all(self) -> Result<P> {
+        let mut res: Option<P> = None;
+
+        for page in self {
+            let page = page?;
+
+            match res.as_mut() {
+                Some(res) => res.append(page),
+                None => res = Some(page),
+            }
+        }
+
+        Ok(res.expect("paginator always yields at least one page"))
+    }
+}
+
+impl<'a, P: Page> Iterator for Paginator<'a, P> {
+    type Item = Result<P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let url = self.next.take()?;
+
+        let page = (|| -> Result<P> {
+            let token = self.client.get_token()?;
+            let req = self
+                .client
+                .client
+                .get(url)
+                .bearer_auth(token)
+                .header("Accept", "application/json");
+
+            let page = self.client.send_request(req)?.json()?;
+            self.next = next_page_url(self.client.api_url(), &page)?;
+
+            Ok(page)
+        })();
+
+        Some(page)
+    }
+}
+
+impl AppStoreConnectClient {
+    /// Resolve the URL of a list endpoint with query parameters.
+    pub(crate) fn list_url<F: QueryFilter>(
+        &self,
+        path: &str,
+        query: &ListQuery<F>,
+    ) -> Result<String> {
+        Ok(reqwest::Url::parse_with_params(&self.api_endpoint(path), query.params()?)?.to_string())
+    }
+
+    /// Obtain a [Paginator] for an arbitrary App Store Connect API list endpoint.
+    ///
+    /// `path` is relative to the API base URL. e.g. `v1/apps`.
+    pub fn paginate<P: Page, F: QueryFilter>(
+        &self,
+        path: &str,
+        query: &ListQuery<F>,
+    ) -> Result<Paginator<'_, P>> {
+        Ok(Paginator::new(self, self.list_url(path, query)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            bundle_api::BundleIdPlatform,
+            device_api::{DeviceFilter, DevicesResponse},
+            mock_server::{MockResponse, MockServer},
+        },
+        serde_json::json,
+    };
+
+    #[test]
+    fn params() {
+        let query = ListQuery::new()
+            .filter(DeviceFilter::Platform(BundleIdPlatform::Ios))
+            .filter(DeviceFilter::Name("a".into()))
+            .filter(DeviceFilter::Name("b".into()))
+            .fields("devices", ["name", "udid"])
+            .include("bundleId")
+            .sort("name")
+            .sort_descending("udid")
+            .limit(500);
+
+        assert_eq!(
+            query.params().unwrap(),
+            vec![
+                ("filter[platform]".to_string(), "IOS".to_string()),
+                ("filter[name]".to_string(), "a,b".to_string()),
+                ("fields[devices]".to_string(), "name,udid".to_string()),
+                ("include".to_string(), "bundleId".to_string()),
+                ("sort".to_string(), "name,-udid".to_string()),
+                ("limit".to_string(), "200".to_string()),
+            ]
+        );
+
+        assert!(ListQuery::new()
+            .filter(DeviceFilter::Name("a,b".into()))
+            .params()
+            .is_err());
+    }
+
+    fn insert_devices(server: &MockServer, count: usize) {
+        for i in 0..count {
+            server.insert_resource(json!({
+                "type": "devices",
+                "id": format!("device{i:03}"),
+                "attributes": {
+                    "deviceClass": "IPHONE",
+                    "model": null,
+                    "name": format!("Device {i:03}"),
+                    "platform": if i % 2 == 0 { "IOS" } else { "MAC_OS" },
+                    "status": "ENABLED",
+                    "udid": format!("UDID{i:03}"),
+                    "addedDate": "2023-01-01T00:00:00.000+0000",
+                },
+            }));
+        }
+    }
+
+    #[test]
+    fn paginate() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+        insert_devices(&server, 45);
+
+        let pages = client
+            .paginate::<DevicesResponse, _>(
+                "v1/devices",
+                &ListQuery::<DeviceFilter>::new().limit(20),
+            )?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            pages.iter().map(|p| p.data.len()).collect::<Vec<_>>(),
+            vec![20, 20, 5]
+        );
+
+        // The convenience method follows all pages.
+        let devices = client.list_devices()?;
+        assert_eq!(devices.data.len(), 45);
+        assert!(devices.links.next.is_none());
+
+        let names = client
+            .devices(
+                &ListQuery::new()
+                    .filter(DeviceFilter::Platform(BundleIdPlatform::MacOs))
+                    .sort_descending("name")
+                    .limit(5),
+            )?
+            .items()
+            .map(|d| d.map(|d| d.attributes.name))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(names.len(), 22);
+        assert_eq!(names[0], "Device 043");
+        assert_eq!(names[21], "Device 001");
+
+        // Sparse fieldsets work with untyped pages.
+        let page = client
+            .paginate::<JsonApiPage, _>(
+                "v1/devices",
+                &ListQuery::<DeviceFilter>::new()
+                    .filter(DeviceFilter::Udid("UDID007".into()))
+                    .fields("devices", ["name"]),
+            )?
+            .collect_all()?;
+        assert_eq!(page.data.len(), 1);
+        assert_eq!(page.data[0]["attributes"], json!({ "name": "Device 007" }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn paginate_foreign_next() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+
+        server.enqueue_response(
+            "GET",
+            "/v1/devices",
+            MockResponse::json(
+                200,
+                &json!({
+                    "data": [],
+                    "links": { "next": "https://attacker.example/v1/devices?cursor=1" },
+                }),
+            ),
+        );
+
+        let mut pages =
+            client.paginate::<JsonApiPage, _>("v1/devices", &ListQuery::<DeviceFilter>::new())?;
+        assert!(pages
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .to_string()
+            .contains("refusing to follow"));
+        assert!(pages.next().is_none());
+        assert_eq!(server.requests().len(), 1);
+
+        Ok(())
+    }
+}
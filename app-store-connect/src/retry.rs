@@ -0,0 +1,361 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Retry and rate limiting policies for API requests.
+
+use {
+    crate::{ClientConfig, Result},
+    reqwest::{
+        header::{HeaderMap, HeaderValue, AUTHORIZATION},
+        Method, StatusCode,
+    },
+    std::time::Duration,
+};
+
+/// Name of the HTTP response header conveying App Store Connect API rate limits.
+pub const RATE_LIMIT_HEADER: &str = "X-Rate-Limit";
+
+/// Describes how failed API requests are retried.
+///
+/// Requests are retried when the server responds with `401 Unauthorized` (after
+/// minting a new JWT) or `429 Too Many Requests`, as these are rejected before
+/// being processed. `5xx` server errors are only retried for idempotent methods
+/// (`GET`, `PUT`, `PATCH`, `DELETE`, ...). A server error may be returned after a
+/// `POST` created a resource, so resending it could create duplicate certificates,
+/// devices, or submissions.
+///
+/// The delay before a retry is the value of the `Retry-After` response header, if
+/// present. Requests aren't retried if this exceeds [Self::max_backoff], since
+/// retrying sooner than the server asked would fail again. Otherwise, if the server
+/// reports the rate limit is exhausted via the `X-Rate-Limit` header, the delay is
+/// [Self::max_backoff]. Otherwise the delay grows exponentially from
+/// [Self::initial_backoff], capped at [Self::max_backoff].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of times to retry a request.
+    ///
+    /// 0 disables retries.
+    pub max_retries: u32,
+
+    /// Delay before the first retry when the server doesn't say how long to wait.
+    pub initial_backoff: Duration,
+
+    /// Maximum delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a request with the given method failing with a response status is
+    /// eligible for retry.
+    pub fn is_retryable(method: &Method, status: StatusCode) -> bool {
+        status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || (status.is_server_error() && is_idempotent(method))
+    }
+
+    /// The exponential backoff delay for a 0-based retry attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+
+        std::cmp::min(
+            self.initial_backoff.saturating_mul(factor),
+            self.max_backoff,
+        )
+    }
+
+    /// Resolve how long to wait before retrying a failed response.
+    ///
+    /// `attempt` is the 0-based number of the retry about to be performed. Returns
+    /// [None] if the request should not be retried.
+    pub fn retry_delay(
+        &self,
+        attempt: u32,
+        method: &Method,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries || !Self::is_retryable(method, status) {
+            return None;
+        }
+
+        // The request failed because our token was rejected. A new token is minted
+        // for the retry, so there's no reason to wait.
+        if status == StatusCode::UNAUTHORIZED {
+            return Some(Duration::ZERO);
+        }
+
+        if let Some(delay) = retry_after(headers) {
+            (delay <= self.max_backoff).then_some(delay)
+        } else if status == StatusCode::TOO_MANY_REQUESTS
+            && RateLimit::from_headers(headers).and_then(|x| x.remaining) == Some(0)
+        {
+            Some(self.max_backoff)
+        } else {
+            Some(self.backoff(attempt))
+        }
+    }
+}
+
+/// Whether sending a request with the given method multiple times has the same
+/// effect as sending it once.
+///
+/// `PATCH` requests of the App Store Connect API set attributes to given values.
+/// So they are treated as idempotent.
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::PATCH
+    )
+}
+
+/// Tracks the retries of a single request.
+///
+/// This holds the retry decisions shared by the blocking and async clients. They
+/// only send requests and wait for the returned delays.
+#[derive(Default)]
+pub(crate) struct RetryState {
+    attempt: u32,
+    refreshed_token: bool,
+}
+
+impl RetryState {
+    /// Decide whether to retry a request that failed with `status`.
+    ///
+    /// `next` holds the headers of a copy of the request to send next, or [None]
+    /// if the request can't be copied. Returns the delay before sending the copy,
+    /// or [None] if the failure is final. When retrying a `401 Unauthorized`
+    /// response, the bearer token of the copy is replaced by a newly minted one.
+    pub(crate) fn retry_delay(
+        &mut self,
+        config: &ClientConfig,
+        method: &Method,
+        url: &str,
+        status: StatusCode,
+        response_headers: &HeaderMap,
+        next: Option<&mut HeaderMap>,
+    ) -> Result<Option<Duration>> {
+        let policy = config.retry_policy();
+
+        let (Some(delay), Some(next)) = (
+            policy.retry_delay(self.attempt, method, status, response_headers),
+            next,
+        ) else {
+            return Ok(None);
+        };
+
+        if status == StatusCode::UNAUTHORIZED {
+            // A rejected token won't become valid again. Retrying only makes sense
+            // if we can present a new one. And if a fresh token is also rejected,
+            // another won't fare better.
+            if self.refreshed_token || !next.contains_key(AUTHORIZATION) {
+                return Ok(None);
+            }
+
+            self.refreshed_token = true;
+            config.invalidate_token();
+            next.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", config.get_token()?))?,
+            );
+        }
+
+        log::warn!(
+            "{} {} failed with {}; retrying in {:.1}s (attempt {} of {})",
+            method,
+            url,
+            status,
+            delay.as_secs_f64(),
+            self.attempt + 1,
+            policy.max_retries
+        );
+
+        self.attempt += 1;
+
+        Ok(Some(delay))
+    }
+}
+
+/// Parse the `Retry-After` header.
+///
+/// Only the delay-seconds form is supported.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// API rate limit state reported by the server.
+///
+/// The App Store Connect API reports rate limits in the `X-Rate-Limit` response
+/// header. e.g. `user-hour-lim:3600;user-hour-rem:3545;`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Number of requests allowed per hour.
+    pub limit: Option<u64>,
+
+    /// Number of requests remaining in the current hour.
+    pub remaining: Option<u64>,
+}
+
+impl RateLimit {
+    /// Parse the value of an `X-Rate-Limit` header.
+    ///
+    /// Returns [None] if no known fields are present.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut res = Self {
+            limit: None,
+            remaining: None,
+        };
+
+        for field in value.split(';') {
+            let Some((key, value)) = field.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().parse::<u64>().ok();
+
+            match key.trim() {
+                "user-hour-lim" => res.limit = value,
+                "user-hour-rem" => res.remaining = value,
+                _ => {}
+            }
+        }
+
+        if res.limit.is_some() || res.remaining.is_some() {
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    /// Resolve the rate limit from response headers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::parse(headers.get(RATE_LIMIT_HEADER)?.to_str().ok()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, reqwest::header::HeaderValue};
+
+    #[test]
+    fn rate_limit_parse() {
+        assert_eq!(
+            RateLimit::parse("user-hour-lim:3600;user-hour-rem:3545;"),
+            Some(RateLimit {
+                limit: Some(3600),
+                remaining: Some(3545)
+            })
+        );
+        assert_eq!(
+            RateLimit::parse("user-hour-rem:0"),
+            Some(RateLimit {
+                limit: None,
+                remaining: Some(0)
+            })
+        );
+        assert_eq!(RateLimit::parse("garbage"), None);
+    }
+
+    #[test]
+    fn retry_delays() {
+        let policy = RetryPolicy::default();
+        let empty = HeaderMap::new();
+
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::INTERNAL_SERVER_ERROR, &empty),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.retry_delay(2, &Method::GET, StatusCode::BAD_GATEWAY, &empty),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            policy.retry_delay(3, &Method::GET, StatusCode::BAD_GATEWAY, &empty),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::NOT_FOUND, &empty),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::UNAUTHORIZED, &empty),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            RetryPolicy::none().retry_delay(0, &Method::GET, StatusCode::BAD_GATEWAY, &empty),
+            None
+        );
+        assert_eq!(policy.backoff(30), policy.max_backoff);
+
+        // Server errors may occur after non-idempotent requests took effect.
+        assert_eq!(
+            policy.retry_delay(0, &Method::POST, StatusCode::INTERNAL_SERVER_ERROR, &empty),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::PATCH, StatusCode::BAD_GATEWAY, &empty),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::POST, StatusCode::TOO_MANY_REQUESTS, &empty),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::POST, StatusCode::UNAUTHORIZED, &empty),
+            Some(Duration::ZERO)
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Retry-After", HeaderValue::from_static("7"));
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(Duration::from_secs(7))
+        );
+        headers.insert("Retry-After", HeaderValue::from_static("60"));
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::SERVICE_UNAVAILABLE, &headers),
+            Some(policy.max_backoff)
+        );
+        headers.insert("Retry-After", HeaderValue::from_static("3600"));
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::SERVICE_UNAVAILABLE, &headers),
+            None
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RATE_LIMIT_HEADER,
+            HeaderValue::from_static("user-hour-lim:3600;user-hour-rem:0;"),
+        );
+        assert_eq!(
+            policy.retry_delay(0, &Method::GET, StatusCode::TOO_MANY_REQUESTS, &headers),
+            Some(policy.max_backoff)
+        );
+    }
+}
@@ -0,0 +1,636 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::build_api::BuildsResponse;
+use crate::profile_api::{Ref, RefData, Refs};
+use crate::query::{
+    impl_page, ListQuery, NoFilter, PagedDocumentLinks, Paginator, PagingInformation, QueryFilter,
+    MAX_PAGE_LIMIT,
+};
+use crate::{AppStoreConnectClient, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const BETA_APP_REVIEW_SUBMISSIONS_PATH: &str = "v1/betaAppReviewSubmissions";
+const BETA_GROUPS_PATH: &str = "v1/betaGroups";
+const BETA_TESTERS_PATH: &str = "v1/betaTesters";
+
+fn refs(ids: &[String], r#type: &str) -> Refs {
+    Refs {
+        data: ids
+            .iter()
+            .map(|id| RefData {
+                id: id.into(),
+                r#type: r#type.into(),
+            })
+            .collect(),
+    }
+}
+
+impl AppStoreConnectClient {
+    /// Obtain a [Paginator] over TestFlight beta groups matching a query.
+    pub fn beta_groups(
+        &self,
+        query: &ListQuery<BetaGroupFilter>,
+    ) -> Result<Paginator<'_, BetaGroupsResponse>> {
+        self.paginate(BETA_GROUPS_PATH, query)
+    }
+
+    /// List all beta groups of an app.
+    pub fn list_app_beta_groups(&self, app_id: &str) -> Result<BetaGroupsResponse> {
+        self.beta_groups(
+            &ListQuery::new()
+                .filter(BetaGroupFilter::App(app_id.into()))
+                .limit(MAX_PAGE_LIMIT),
+        )?
+        .collect_all()
+    }
+
+    pub fn get_beta_group(&self, id: &str) -> Result<BetaGroupResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{BETA_GROUPS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    /// Create an external beta group for an app.
+    pub fn create_beta_group(&self, app_id: &str, name: &str) -> Result<BetaGroupResponse> {
+        let token = self.get_token()?;
+        let body = BetaGroupCreateRequest {
+            data: BetaGroupCreateRequestData {
+                attributes: BetaGroupCreateRequestAttributes { name: name.into() },
+                relationships: BetaGroupCreateRequestRelationships {
+                    app: Ref {
+                        data: RefData {
+                            id: app_id.into(),
+                            r#type: "apps".into(),
+                        },
+                    },
+                },
+                r#type: "betaGroups".into(),
+            },
+        };
+        let req = self
+            .client
+            .post(self.api_endpoint(BETA_GROUPS_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    pub fn delete_beta_group(&self, id: &str) -> Result<()> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .delete(self.api_endpoint(&format!("{BETA_GROUPS_PATH}/{id}")))
+            .bearer_auth(token);
+        self.send_request(req)?;
+        Ok(())
+    }
+
+    /// List the builds available to a beta group.
+    pub fn list_beta_group_builds(&self, group_id: &str) -> Result<BuildsResponse> {
+        self.paginate(
+            &format!("{BETA_GROUPS_PATH}/{group_id}/builds"),
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )?
+        .collect_all()
+    }
+
+    /// Make builds available to the testers of a beta group.
+    pub fn add_builds_to_beta_group(&self, group_id: &str, build_ids: &[String]) -> Result<()> {
+        self.update_beta_group_relationship(group_id, "builds", "builds", build_ids, true)
+    }
+
+    pub fn remove_builds_from_beta_group(
+        &self,
+        group_id: &str,
+        build_ids: &[String],
+    ) -> Result<()> {
+        self.update_beta_group_relationship(group_id, "builds", "builds", build_ids, false)
+    }
+
+    /// List the testers in a beta group.
+    pub fn list_beta_group_testers(&self, group_id: &str) -> Result<BetaTestersResponse> {
+        self.paginate(
+            &format!("{BETA_GROUPS_PATH}/{group_id}/betaTesters"),
+            &ListQuery::<NoFilter>::new().limit(MAX_PAGE_LIMIT),
+        )?
+        .collect_all()
+    }
+
+    pub fn add_beta_testers_to_beta_group(
+        &self,
+        group_id: &str,
+        tester_ids: &[String],
+    ) -> Result<()> {
+        self.update_beta_group_relationship(
+            group_id,
+            "betaTesters",
+            "betaTesters",
+            tester_ids,
+            true,
+        )
+    }
+
+    pub fn remove_beta_testers_from_beta_group(
+        &self,
+        group_id: &str,
+        tester_ids: &[String],
+    ) -> Result<()> {
+        self.update_beta_group_relationship(
+            group_id,
+            "betaTesters",
+            "betaTesters",
+            tester_ids,
+            false,
+        )
+    }
+
+    fn update_beta_group_relationship(
+        &self,
+        group_id: &str,
+        relationship: &str,
+        r#type: &str,
+        ids: &[String],
+        add: bool,
+    ) -> Result<()> {
+        let token = self.get_token()?;
+        let url = self.api_endpoint(&format!(
+            "{BETA_GROUPS_PATH}/{group_id}/relationships/{relationship}"
+        ));
+        let req = if add {
+            self.client.post(url)
+        } else {
+            self.client.delete(url)
+        }
+        .bearer_auth(token)
+        .header("Content-Type", "application/json")
+        .json(&refs(ids, r#type));
+        self.send_request(req)?;
+        Ok(())
+    }
+
+    /// Obtain a [Paginator] over TestFlight beta testers matching a query.
+    pub fn beta_testers(
+        &self,
+        query: &ListQuery<BetaTesterFilter>,
+    ) -> Result<Paginator<'_, BetaTestersResponse>> {
+        self.paginate(BETA_TESTERS_PATH, query)
+    }
+
+    /// Invite a beta tester, optionally adding them to beta groups.
+    pub fn create_beta_tester(
+        &self,
+        email: &str,
+        first_name: Option<&str>,
+        last_name: Option<&str>,
+        group_ids: &[String],
+    ) -> Result<BetaTesterResponse> {
+        let token = self.get_token()?;
+        let body = BetaTesterCreateRequest {
+            data: BetaTesterCreateRequestData {
+                attributes: BetaTesterCreateRequestAttributes {
+                    email: email.into(),
+                    first_name: first_name.map(|x| x.into()),
+                    last_name: last_name.map(|x| x.into()),
+                },
+                relationships: BetaTesterCreateRequestRelationships {
+                    beta_groups: refs(group_ids, "betaGroups"),
+                },
+                r#type: "betaTesters".into(),
+            },
+        };
+        let req = self
+            .client
+            .post(self.api_endpoint(BETA_TESTERS_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    pub fn delete_beta_tester(&self, id: &str) -> Result<()> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .delete(self.api_endpoint(&format!("{BETA_TESTERS_PATH}/{id}")))
+            .bearer_auth(token);
+        self.send_request(req)?;
+        Ok(())
+    }
+
+    /// Submit a build for TestFlight beta app review.
+    ///
+    /// Review is required before a build can be tested by external groups.
+    pub fn submit_for_beta_review(
+        &self,
+        build_id: &str,
+    ) -> Result<BetaAppReviewSubmissionResponse> {
+        let token = self.get_token()?;
+        let body = BetaAppReviewSubmissionCreateRequest {
+            data: BetaAppReviewSubmissionCreateRequestData {
+                relationships: BetaAppReviewSubmissionCreateRequestRelationships {
+                    build: Ref {
+                        data: RefData {
+                            id: build_id.into(),
+                            r#type: "builds".into(),
+                        },
+                    },
+                },
+                r#type: "betaAppReviewSubmissions".into(),
+            },
+        };
+        let req = self
+            .client
+            .post(self.api_endpoint(BETA_APP_REVIEW_SUBMISSIONS_PATH))
+            .bearer_auth(token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&body);
+        Ok(self.send_request(req)?.json()?)
+    }
+
+    pub fn get_beta_app_review_submission(
+        &self,
+        id: &str,
+    ) -> Result<BetaAppReviewSubmissionResponse> {
+        let token = self.get_token()?;
+        let req = self
+            .client
+            .get(self.api_endpoint(&format!("{BETA_APP_REVIEW_SUBMISSIONS_PATH}/{id}")))
+            .bearer_auth(token)
+            .header("Accept", "application/json");
+        Ok(self.send_request(req)?.json()?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupCreateRequest {
+    pub data: BetaGroupCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupCreateRequestData {
+    pub attributes: BetaGroupCreateRequestAttributes,
+    pub relationships: BetaGroupCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupCreateRequestAttributes {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupCreateRequestRelationships {
+    pub app: Ref,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupResponse {
+    pub data: BetaGroup,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupsResponse {
+    pub data: Vec<BetaGroup>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(BetaGroupsResponse, BetaGroup);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroup {
+    pub attributes: BetaGroupAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaGroupAttributes {
+    pub name: String,
+    pub created_date: Option<String>,
+    #[serde(default)]
+    pub is_internal_group: bool,
+    #[serde(default)]
+    pub public_link_enabled: bool,
+    pub public_link: Option<String>,
+}
+
+/// Filters for listing beta groups.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BetaGroupFilter {
+    Id(String),
+    /// Id of the app.
+    App(String),
+    IsInternalGroup(bool),
+    Name(String),
+}
+
+impl QueryFilter for BetaGroupFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::App(_) => "app",
+            Self::IsInternalGroup(_) => "isInternalGroup",
+            Self::Name(_) => "name",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::App(v) | Self::Name(v) => v.clone(),
+            Self::IsInternalGroup(v) => v.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterCreateRequest {
+    pub data: BetaTesterCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterCreateRequestData {
+    pub attributes: BetaTesterCreateRequestAttributes,
+    pub relationships: BetaTesterCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterCreateRequestAttributes {
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterCreateRequestRelationships {
+    pub beta_groups: Refs,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterResponse {
+    pub data: BetaTester,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTestersResponse {
+    pub data: Vec<BetaTester>,
+    #[serde(default)]
+    pub included: Vec<Value>,
+    #[serde(default)]
+    pub links: PagedDocumentLinks,
+    pub meta: Option<PagingInformation>,
+}
+
+impl_page!(BetaTestersResponse, BetaTester);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTester {
+    pub attributes: BetaTesterAttributes,
+    pub id: String,
+    #[serde(default)]
+    pub relationships: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaTesterAttributes {
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub invite_type: Option<String>,
+}
+
+/// Filters for listing beta testers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BetaTesterFilter {
+    Id(String),
+    /// Id of an app the tester has access to.
+    Apps(String),
+    /// Id of a beta group the tester belongs to.
+    BetaGroups(String),
+    Email(String),
+}
+
+impl QueryFilter for BetaTesterFilter {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Id(_) => "id",
+            Self::Apps(_) => "apps",
+            Self::BetaGroups(_) => "betaGroups",
+            Self::Email(_) => "email",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            Self::Id(v) | Self::Apps(v) | Self::BetaGroups(v) | Self::Email(v) => v.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmissionCreateRequest {
+    pub data: BetaAppReviewSubmissionCreateRequestData,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmissionCreateRequestData {
+    pub relationships: BetaAppReviewSubmissionCreateRequestRelationships,
+    pub r#type: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmissionCreateRequestRelationships {
+    pub build: Ref,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmissionResponse {
+    pub data: BetaAppReviewSubmission,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmission {
+    pub attributes: BetaAppReviewSubmissionAttributes,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetaAppReviewSubmissionAttributes {
+    /// e.g. `WAITING_FOR_REVIEW`, `IN_REVIEW`, `REJECTED` or `APPROVED`.
+    pub beta_review_state: String,
+    pub submitted_date: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{build_api::BuildProcessingState, mock_server::MockServer},
+        serde_json::json,
+        std::time::Duration,
+    };
+
+    fn insert_app_and_build(server: &MockServer) {
+        server.insert_resource(json!({
+            "type": "apps",
+            "id": "app1",
+            "attributes": {
+                "name": "Example",
+                "bundleId": "com.example.app",
+                "sku": "EXAMPLE",
+                "primaryLocale": "en-US",
+            },
+        }));
+        server.insert_resource(json!({
+            "type": "preReleaseVersions",
+            "id": "prv1",
+            "attributes": { "version": "1.2.0", "platform": "IOS" },
+            "relationships": { "app": { "data": { "type": "apps", "id": "app1" } } },
+        }));
+        server.insert_resource(json!({
+            "type": "buildBetaDetails",
+            "id": "bbd1",
+            "attributes": {
+                "autoNotifyEnabled": false,
+                "internalBuildState": "READY_FOR_BETA_TESTING",
+                "externalBuildState": "READY_FOR_BETA_SUBMISSION",
+            },
+        }));
+        server.insert_resource(json!({
+            "type": "builds",
+            "id": "build1",
+            "attributes": {
+                "version": "42",
+                "uploadedDate": "2023-01-01T00:00:00Z",
+                "expired": false,
+                "processingState": "VALID",
+            },
+            "relationships": {
+                "app": { "data": { "type": "apps", "id": "app1" } },
+                "preReleaseVersion": { "data": { "type": "preReleaseVersions", "id": "prv1" } },
+                "buildBetaDetail": { "data": { "type": "buildBetaDetails", "id": "bbd1" } },
+            },
+        }));
+    }
+
+    #[test]
+    fn testflight_distribution() -> Result<()> {
+        let server = MockServer::start()?;
+        let client = server.client()?;
+        insert_app_and_build(&server);
+
+        let app = client
+            .find_app_by_bundle_id("com.example.app")?
+            .expect("app should be found");
+        assert_eq!(app.id, "app1");
+        assert!(client.find_app_by_bundle_id("com.example.other")?.is_none());
+
+        assert!(client.find_build(&app.id, "1.2.0", "41")?.is_none());
+        let build = client
+            .find_build(&app.id, "1.2.0", "42")?
+            .expect("build should be found");
+        assert_eq!(build.id, "build1");
+
+        let build = client.wait_for_build_processing(
+            &build.id,
+            Duration::from_secs(1),
+            Duration::from_millis(10),
+        )?;
+        assert_eq!(
+            build.data.attributes.processing_state,
+            BuildProcessingState::Valid
+        );
+
+        let detail = client.get_build_beta_detail(&build.data.id)?;
+        assert!(!detail.data.attributes.auto_notify_enabled);
+        let detail = client.update_build_beta_detail(&detail.data.id, true)?;
+        assert!(detail.data.attributes.auto_notify_enabled);
+
+        let group = client.create_beta_group(&app.id, "External")?;
+        assert_eq!(group.data.attributes.name, "External");
+        assert!(!group.data.attributes.is_internal_group);
+        assert_eq!(client.list_app_beta_groups(&app.id)?.data.len(), 1);
+
+        client.add_builds_to_beta_group(&group.data.id, std::slice::from_ref(&build.data.id))?;
+        let builds = client.list_beta_group_builds(&group.data.id)?;
+        assert_eq!(builds.data.len(), 1);
+        assert_eq!(builds.data[0].id, "build1");
+
+        let tester = client.create_beta_tester("tester@example.com", Some("Test"), None, &[])?;
+        client.add_beta_testers_to_beta_group(
+            &group.data.id,
+            std::slice::from_ref(&tester.data.id),
+        )?;
+        assert_eq!(
+            client.list_beta_group_testers(&group.data.id)?.data.len(),
+            1
+        );
+
+        // Adding a nonexistent build is rejected.
+        assert!(client
+            .add_builds_to_beta_group(&group.data.id, &["missing".to_string()])
+            .is_err());
+
+        let submission = client.submit_for_beta_review(&build.data.id)?;
+        assert_eq!(
+            submission.data.attributes.beta_review_state,
+            "WAITING_FOR_REVIEW"
+        );
+        client.get_beta_app_review_submission(&submission.data.id)?;
+
+        client
+            .remove_builds_from_beta_group(&group.data.id, std::slice::from_ref(&build.data.id))?;
+        assert!(client
+            .list_beta_group_builds(&group.data.id)?
+            .data
+            .is_empty());
+
+        client.delete_beta_tester(&tester.data.id)?;
+        client.delete_beta_group(&group.data.id)?;
+        assert!(client.list_app_beta_groups(&app.id)?.data.is_empty());
+
+        Ok(())
+    }
+}
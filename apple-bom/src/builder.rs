@@ -8,18 +8,22 @@
 
 use {
     crate::{
+        checksum::Cksum,
         error::Error,
         format::{
             BomBlock, BomBlockBomInfo, BomBlockFile, BomBlockPathInfoIndex, BomBlockPathRecord,
-            BomBlockPathRecordPointer, BomBlockPaths, BomBlockTree, BomBlockTreePointer,
-            BomBlockVIndex, BomBlocksEntry, BomBlocksIndex, BomHeader, BomInfoEntry, BomPathsEntry,
-            BomVar, BomVarsIndex,
+            BomBlockPathRecordPointer, BomBlockPaths, BomBlockSize64, BomBlockTree,
+            BomBlockTreePointer, BomBlockVIndex, BomBlocksEntry, BomBlocksIndex, BomHeader,
+            BomInfoEntry, BomPathRecordArchitecture, BomPathsEntry, BomVar, BomVarsIndex,
+            PATH_RECORD_ARCHITECTURE_DEFAULT, PATH_RECORD_ARCHITECTURE_MACHO,
         },
         path::{BomPath, BomPathType},
     },
     chrono::{DateTime, Utc},
-    scroll::IOwrite,
-    simple_file_manifest::{S_IFDIR, S_IRGRP, S_IROTH, S_IRUSR, S_IWUSR, S_IXGRP, S_IXUSR},
+    scroll::{IOwrite, Pread},
+    simple_file_manifest::{
+        S_IFDIR, S_IFLNK, S_IFREG, S_IRGRP, S_IROTH, S_IRUSR, S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR,
+    },
     std::{
         borrow::Cow,
         collections::{BTreeMap, HashMap},
@@ -29,43 +33,321 @@ use {
     },
 };
 
-fn crc32_path(path: &Path) -> std::io::Result<(u32, usize)> {
-    let mut h = crc32fast::Hasher::new();
+/// Bit mask for the file type bits in a file mode.
+const S_IFMT: u32 = 0o170000;
+
+const MH_MAGIC: u32 = 0xfeedface;
+const MH_CIGAM: u32 = 0xcefaedfe;
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+const MH_CIGAM_64: u32 = 0xcffaedfe;
+const FAT_MAGIC: u32 = 0xcafebabe;
+const FAT_MAGIC_64: u32 = 0xcafebabf;
+
+/// Java class files share the fat binary magic. Their version number occupies
+/// the same field as the number of fat architectures and is always larger
+/// than this.
+const FAT_MAX_ARCHITECTURES: u32 = 30;
+
+/// An architecture slice within a Mach-O binary.
+struct MachOSlice {
+    cpu_type: u32,
+    cpu_subtype: u32,
+    offset: u64,
+    size: u64,
+}
+
+/// Resolve the architecture slices of a Mach-O binary from its leading bytes.
+///
+/// Returns `None` if the data doesn't look like a Mach-O binary.
+fn macho_slices(header: &[u8], file_size: u64) -> Option<Vec<MachOSlice>> {
+    let magic = header.pread_with::<u32>(0, scroll::BE).ok()?;
+
+    let slices = match magic {
+        MH_MAGIC | MH_MAGIC_64 | MH_CIGAM | MH_CIGAM_64 => {
+            let endian = if magic == MH_MAGIC || magic == MH_MAGIC_64 {
+                scroll::BE
+            } else {
+                scroll::LE
+            };
+
+            vec![MachOSlice {
+                cpu_type: header.pread_with(4, endian).ok()?,
+                cpu_subtype: header.pread_with(8, endian).ok()?,
+                offset: 0,
+                size: file_size,
+            }]
+        }
+        FAT_MAGIC | FAT_MAGIC_64 => {
+            let count = header.pread_with::<u32>(4, scroll::BE).ok()?;
+            if count == 0 || count > FAT_MAX_ARCHITECTURES {
+                return None;
+            }
+
+            let offset = &mut 8;
+            let mut slices = Vec::with_capacity(count as usize);
+
+            for _ in 0..count {
+                let cpu_type = header.gread_with::<u32>(offset, scroll::BE).ok()?;
+                let cpu_subtype = header.gread_with::<u32>(offset, scroll::BE).ok()?;
+
+                let (slice_offset, size) = if magic == FAT_MAGIC {
+                    let slice_offset = header.gread_with::<u32>(offset, scroll::BE).ok()?;
+                    let size = header.gread_with::<u32>(offset, scroll::BE).ok()?;
+                    // align.
+                    *offset += 4;
+
+                    (slice_offset as u64, size as u64)
+                } else {
+                    let slice_offset = header.gread_with::<u64>(offset, scroll::BE).ok()?;
+                    let size = header.gread_with::<u64>(offset, scroll::BE).ok()?;
+                    // align + reserved.
+                    *offset += 8;
+
+                    (slice_offset, size)
+                };
+
+                if slice_offset.checked_add(size)? > file_size {
+                    return None;
+                }
+
+                slices.push(MachOSlice {
+                    cpu_type,
+                    cpu_subtype,
+                    offset: slice_offset,
+                    size,
+                });
+            }
+
+            slices
+        }
+        _ => return None,
+    };
+
+    Some(slices)
+}
+
+/// Computes checksums of file content and its Mach-O architecture slices.
+struct ContentHasher {
+    hasher: Cksum,
+    slices: Vec<(MachOSlice, Cksum)>,
+    offset: u64,
+}
+
+impl ContentHasher {
+    fn new(slices: Vec<MachOSlice>) -> Self {
+        Self {
+            hasher: Cksum::new(),
+            slices: slices
+                .into_iter()
+                .map(|slice| (slice, Cksum::new()))
+                .collect(),
+            offset: 0,
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+
+        let chunk_end = self.offset + chunk.len() as u64;
+
+        for (slice, hasher) in self.slices.iter_mut() {
+            let start = slice.offset.max(self.offset);
+            let end = (slice.offset + slice.size).min(chunk_end);
+
+            if start < end {
+                hasher.update(&chunk[(start - self.offset) as usize..(end - self.offset) as usize]);
+            }
+        }
+
+        self.offset = chunk_end;
+    }
+
+    fn finalize(self) -> (u32, Vec<BomPathRecordArchitecture>) {
+        // Architecture records can't express slices of 4 GiB or more. Record such
+        // files as plain files rather than emitting truncated sizes.
+        let architectures = self
+            .slices
+            .into_iter()
+            .map(|(slice, hasher)| {
+                Some(BomPathRecordArchitecture {
+                    cpu_type: slice.cpu_type,
+                    cpu_subtype: slice.cpu_subtype,
+                    size: u32::try_from(slice.size).ok()?,
+                    checksum: hasher.finalize(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+
+        (self.hasher.finalize(), architectures)
+    }
+}
+
+/// Compute the checksum and Mach-O architecture records of content in a reader.
+///
+/// `size` is the number of bytes to consume from the reader. Content is
+/// streamed so arbitrarily large files can be processed.
+fn checksum_reader(
+    reader: &mut impl Read,
+    size: u64,
+) -> std::io::Result<(u32, Vec<BomPathRecordArchitecture>)> {
+    // Mach-O and fat headers are small. But fat headers can describe many
+    // architectures. A page's worth of data is plenty.
+    let mut header = vec![0u8; size.min(4096) as usize];
+    reader.read_exact(&mut header)?;
+
+    let mut hasher = ContentHasher::new(macho_slices(&header, size).unwrap_or_default());
+    hasher.update(&header);
 
-    let mut fh = std::fs::File::open(path)?;
     let mut buffer = [0u8; 32768];
-    let mut file_size = 0;
 
-    loop {
-        let bytes_read = fh.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+    while hasher.offset < size {
+        let wanted = (size - hasher.offset).min(buffer.len() as u64) as usize;
+        let count = reader.read(&mut buffer[0..wanted])?;
+
+        if count == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
         }
-        file_size += bytes_read;
-        h.update(&buffer[0..bytes_read]);
+
+        hasher.update(&buffer[0..count]);
     }
 
-    Ok((h.finalize(), file_size))
+    Ok(hasher.finalize())
 }
 
-fn crc32_data(data: &[u8]) -> u32 {
-    let mut h = crc32fast::Hasher::new();
-    h.update(data);
-    h.finalize()
+/// Copy metadata from the filesystem to a BOM path.
+fn apply_filesystem_metadata(path: &mut BomPath, metadata: &std::fs::Metadata) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        path.set_file_mode(metadata.mode() as u16);
+        path.set_user_id(metadata.uid());
+        path.set_group_id(metadata.gid());
+    }
+
+    if let Ok(mtime) = metadata.modified() {
+        path.set_modified_time(DateTime::<Utc>::from(mtime));
+    }
+}
+
+/// Resolve the (device, inode) of a file if it has multiple hard links.
+#[cfg(unix)]
+fn hard_link_inode(path: &Path) -> std::io::Result<Option<(u64, u64)>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+
+    Ok(if metadata.nlink() > 1 {
+        Some((metadata.dev(), metadata.ino()))
+    } else {
+        None
+    })
+}
+
+#[cfg(not(unix))]
+fn hard_link_inode(_path: &Path) -> std::io::Result<Option<(u64, u64)>> {
+    Ok(None)
+}
+
+/// Compute the checksum and Mach-O architecture records of file content.
+fn checksum_data(data: &[u8]) -> (u32, Vec<BomPathRecordArchitecture>) {
+    let mut hasher = ContentHasher::new(macho_slices(data, data.len() as u64).unwrap_or_default());
+    hasher.update(data);
+
+    hasher.finalize()
 }
 
 fn validate_bom_path(s: &str) -> Result<(), Error> {
-    if s.starts_with('.') {
-        Err(Error::BadPath(s.to_string(), "path cannot start with ."))
-    } else if s.starts_with('/') {
+    if s.starts_with('/') {
         Err(Error::BadPath(s.to_string(), "path cannot start with /"))
     } else if s.contains('\\') {
         Err(Error::BadPath(s.to_string(), "path cannot contain \\"))
+    } else if s.split('/').any(|c| c == "." || c == "..") {
+        Err(Error::BadPath(
+            s.to_string(),
+            "path cannot contain . or .. components",
+        ))
     } else {
         Ok(())
     }
 }
 
+/// Maximum size of blocks holding [BomBlockPaths].
+const PATHS_BLOCK_SIZE: u32 = 4096;
+
+/// Emit blocks for a tree holding paths entries.
+///
+/// The tree's root Paths block is a pointer to the Paths blocks holding
+/// meaningful records. Due to the block size limit, there can be multiple of
+/// these, chained together.
+///
+/// Returns the block index of the emitted [BomBlockTree].
+fn push_paths_tree(blocks: &mut Vec<BomBlock>, entries: Vec<BomPathsEntry>) -> u32 {
+    let tree_index = blocks.len() as u32;
+
+    blocks.push(BomBlock::Tree(BomBlockTree {
+        block_paths_index: tree_index + 1,
+        block_size: PATHS_BLOCK_SIZE,
+        path_count: entries.len() as u32,
+        ..Default::default()
+    }));
+
+    // Determine final set of Paths blocks holding meaningful records.
+    let mut paths_blocks = vec![];
+    let mut paths_block = BomBlockPaths {
+        is_path_info: 1,
+        ..Default::default()
+    };
+    for entry in entries {
+        paths_block.count += 1;
+        paths_block.paths.push(entry);
+
+        let remaining_bytes = PATHS_BLOCK_SIZE - 12 - 8 * paths_block.count as u32;
+
+        // Running out of room. Flush block.
+        if remaining_bytes < 16 {
+            paths_blocks.push(paths_block.clone());
+            paths_block = BomBlockPaths {
+                is_path_info: 1,
+                ..Default::default()
+            };
+        }
+    }
+
+    if paths_block.count > 0 || paths_blocks.is_empty() {
+        paths_blocks.push(paths_block);
+    }
+
+    // 1st Paths block is a pointer to meaningful one.
+    blocks.push(BomBlock::Paths(BomBlockPaths {
+        is_path_info: 0,
+        count: 1,
+        paths: vec![BomPathsEntry {
+            block_index: blocks.len() as u32 + 1,
+            file_index: if let Some(entry) = paths_blocks[0].paths.first() {
+                entry.file_index
+            } else {
+                0
+            },
+        }],
+        ..Default::default()
+    }));
+
+    let count = paths_blocks.len();
+    for (i, paths) in paths_blocks.into_iter().enumerate() {
+        let index = blocks.len() as u32;
+
+        blocks.push(BomBlock::Paths(BomBlockPaths {
+            next_paths_block_index: if i == count - 1 { 0 } else { index + 1 },
+            previous_paths_block_index: if i == 0 { 0 } else { index - 1 },
+            ..paths
+        }));
+    }
+
+    tree_index
+}
+
 /// Entity for constructing new BOM data structures.
 #[derive(Clone, Debug)]
 pub struct BomBuilder {
@@ -75,6 +357,18 @@ pub struct BomBuilder {
     /// at BOM generation time.
     paths: BTreeMap<String, BomPath>,
 
+    /// Hard links. Keys are link paths. Values are the path they link to.
+    hard_links: BTreeMap<String, String>,
+
+    /// Filesystem (device, inode) of files added from paths.
+    ///
+    /// Used to detect hard links. Values are the BOM path of the first file
+    /// seen having that inode.
+    inodes: HashMap<(u64, u64), String>,
+
+    /// Explicit metadata for the root directory.
+    root: Option<BomPath>,
+
     default_mtime: DateTime<Utc>,
 
     default_uid: u32,
@@ -88,6 +382,9 @@ impl Default for BomBuilder {
     fn default() -> Self {
         Self {
             paths: Default::default(),
+            hard_links: Default::default(),
+            inodes: Default::default(),
+            root: None,
             default_mtime: Utc::now(),
             default_uid: 0,
             default_gid: 0,
@@ -107,6 +404,146 @@ impl Default for BomBuilder {
 }
 
 impl BomBuilder {
+    /// Construct an instance from a cpio archive.
+    ///
+    /// Every entry in the archive is recorded with its exact mode, ownership,
+    /// and modified time. Symlink targets and file checksums are derived from
+    /// entry data. Entries sharing an inode are recorded as hard links.
+    ///
+    /// The reader must yield uncompressed cpio data. Payloads in flat
+    /// packages are typically compressed and need to be decompressed first.
+    pub fn from_cpio_reader(reader: impl Read + 'static) -> Result<Self, Error> {
+        let mut builder = Self::default();
+        let mut reader = cpio_archive::reader(reader)?;
+
+        // (device, inode) to BOM path of first file seen with it.
+        let mut inodes = HashMap::<(u32, u32), String>::new();
+
+        while let Some(header) = reader.read_next()? {
+            let name = header.name();
+            let bom_path = name.strip_prefix("./").unwrap_or(name);
+
+            let entry = if bom_path == "." || bom_path.is_empty() {
+                builder.root_directory()
+            } else {
+                match header.mode() & S_IFMT {
+                    S_IFDIR => builder.add_directory(bom_path)?,
+                    S_IFLNK => {
+                        let mut target = String::new();
+                        reader.read_to_string(&mut target)?;
+
+                        builder.add_symlink(bom_path, target)?
+                    }
+                    S_IFREG => {
+                        let inode = (header.device(), header.inode());
+
+                        let target = if header.nlink() > 1 {
+                            inodes.get(&inode).cloned()
+                        } else {
+                            None
+                        };
+
+                        if let Some(target) = target {
+                            // Some cpio variants only store data for the final
+                            // member of a hard link group.
+                            if header.file_size() > 0 && builder.paths[&target].size() == 0 {
+                                let (crc32, architectures) =
+                                    checksum_reader(&mut reader, header.file_size())?;
+                                builder.set_file_content(
+                                    &target,
+                                    header.file_size(),
+                                    crc32,
+                                    architectures,
+                                );
+                            }
+
+                            builder.add_hard_link(bom_path, target)?
+                        } else {
+                            if header.nlink() > 1 {
+                                inodes.insert(inode, bom_path.to_string());
+                            }
+
+                            let (crc32, architectures) =
+                                checksum_reader(&mut reader, header.file_size())?;
+                            builder.add_file(
+                                bom_path.to_string(),
+                                header.file_size(),
+                                crc32,
+                                architectures,
+                            )?
+                        }
+                    }
+                    _ => {
+                        return Err(Error::BadPath(
+                            name.to_string(),
+                            "unsupported file type in cpio archive",
+                        ));
+                    }
+                }
+            };
+
+            entry.set_file_mode(header.mode() as u16);
+            entry.set_user_id(header.uid());
+            entry.set_group_id(header.gid());
+            entry.set_modified_time(header.modified_time());
+        }
+
+        Ok(builder)
+    }
+
+    /// Construct an instance from a directory tree on the filesystem.
+    ///
+    /// Every directory, file, and symlink under the directory is recorded. On
+    /// UNIX, exact modes and ownership are recorded and files sharing an inode
+    /// are recorded as hard links. Symlinks are not followed.
+    pub fn from_directory(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let root = path.as_ref();
+        let mut builder = Self::default();
+
+        for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            let rel_path = entry
+                .path()
+                .strip_prefix(root)
+                .expect("walked path should be relative to root");
+            // Lossy conversion would record paths which don't exist.
+            let bom_path = rel_path
+                .components()
+                .map(|c| {
+                    c.as_os_str().to_str().ok_or_else(|| {
+                        Error::BadPath(rel_path.display().to_string(), "path is not valid UTF-8")
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?
+                .join("/");
+
+            let path = if bom_path.is_empty() {
+                builder.root_directory()
+            } else if metadata.is_dir() {
+                builder.add_directory(bom_path)?
+            } else if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(entry.path())?;
+                let target = target.to_str().ok_or_else(|| {
+                    Error::BadPath(bom_path.clone(), "symlink target is not valid UTF-8")
+                })?;
+                builder.add_symlink(bom_path, target)?
+            } else if metadata.is_file() {
+                builder.add_file_from_path(bom_path, entry.path())?
+            } else {
+                return Err(Error::BadPath(
+                    bom_path,
+                    "unsupported file type in directory",
+                ));
+            };
+
+            apply_filesystem_metadata(path, &metadata);
+        }
+
+        Ok(builder)
+    }
+
     fn default_file_path(&self) -> BomPath {
         BomPath {
             path_type: BomPathType::File,
@@ -118,6 +555,7 @@ impl BomBuilder {
             size: 0,
             crc32: None,
             link_name: None,
+            architectures: vec![],
         }
     }
 
@@ -148,6 +586,12 @@ impl BomBuilder {
 
     /// Add a file to this BOM with file content derived from a filesystem path.
     ///
+    /// If the file is a Mach-O binary, a record is added for each of its
+    /// architectures.
+    ///
+    /// On UNIX, if the file is a hard link to a file previously added via this
+    /// method, it is recorded as a hard link to that file.
+    ///
     /// A mutable reference to the just-added entry is returned to allow
     /// for further customization.
     pub fn add_file_from_path(
@@ -159,38 +603,191 @@ impl BomBuilder {
         validate_bom_path(&bom_path)?;
         let path = path.as_ref();
 
-        let (crc32, file_size) = crc32_path(path)?;
+        if let Some(inode) = hard_link_inode(path)? {
+            match self.inodes.get(&inode) {
+                Some(target) if self.paths.contains_key(target) => {
+                    let target = target.clone();
+                    return self.add_hard_link(bom_path, target);
+                }
+                _ => {
+                    self.inodes.insert(inode, bom_path.clone());
+                }
+            }
+        }
+
+        let file_size = std::fs::metadata(path)?.len();
+        let mut fh = std::fs::File::open(path)?;
+        let (crc32, architectures) = checksum_reader(&mut fh, file_size)?;
+
+        self.add_file(bom_path, file_size, crc32, architectures)
+    }
+
+    /// Add a file to this BOM with content specified from a slice.
+    ///
+    /// If the data is a Mach-O binary, a record is added for each of its
+    /// architectures.
+    ///
+    /// A mutable reference to the just-added entry is returned to allow
+    /// for further customization.
+    pub fn add_file_from_data(
+        &mut self,
+        bom_path: impl ToString,
+        data: impl AsRef<[u8]>,
+    ) -> Result<&mut BomPath, Error> {
+        let bom_path = bom_path.to_string();
+        validate_bom_path(&bom_path)?;
+
+        let data = data.as_ref();
+        let (crc32, architectures) = checksum_data(data);
+
+        self.add_file(bom_path, data.len() as _, crc32, architectures)
+    }
+
+    fn add_file(
+        &mut self,
+        bom_path: String,
+        size: u64,
+        crc32: u32,
+        architectures: Vec<BomPathRecordArchitecture>,
+    ) -> Result<&mut BomPath, Error> {
+        validate_bom_path(&bom_path)?;
 
         let mut path = self.default_file_path();
         path.path = bom_path.clone();
-        path.size = file_size;
+        path.size = size as _;
         path.crc32 = Some(crc32);
+        path.architectures = architectures;
 
         self.paths.insert(bom_path.clone(), path);
 
         Ok(self.paths.get_mut(&bom_path).unwrap())
     }
 
-    /// Add a file to this BOM with content specified from a slice.
+    /// Update the content of a file and all paths hard linked to it.
+    fn set_file_content(
+        &mut self,
+        bom_path: &str,
+        size: u64,
+        crc32: u32,
+        architectures: Vec<BomPathRecordArchitecture>,
+    ) {
+        let links = self
+            .hard_links
+            .iter()
+            .filter(|(_, target)| target.as_str() == bom_path)
+            .map(|(link, _)| link.clone())
+            .collect::<Vec<_>>();
+
+        for path in std::iter::once(bom_path.to_string()).chain(links) {
+            if let Some(entry) = self.paths.get_mut(&path) {
+                entry.size = size as _;
+                entry.crc32 = Some(crc32);
+                entry.architectures = architectures.clone();
+            }
+        }
+    }
+
+    /// Add a directory to this BOM.
+    ///
+    /// Directories holding added paths are emitted automatically. Adding
+    /// them explicitly allows customizing their metadata.
     ///
     /// A mutable reference to the just-added entry is returned to allow
     /// for further customization.
-    pub fn add_file_from_data(
+    pub fn add_directory(&mut self, bom_path: impl ToString) -> Result<&mut BomPath, Error> {
+        let bom_path = bom_path.to_string();
+        validate_bom_path(&bom_path)?;
+
+        let mut path = self.default_file_path();
+        path.path_type = BomPathType::Directory;
+        path.path = bom_path.clone();
+        path.file_mode = self.default_mode_dir;
+
+        self.paths.insert(bom_path.clone(), path);
+
+        Ok(self.paths.get_mut(&bom_path).unwrap())
+    }
+
+    /// Add a symlink to this BOM.
+    ///
+    /// A mutable reference to the just-added entry is returned to allow
+    /// for further customization.
+    pub fn add_symlink(
         &mut self,
         bom_path: impl ToString,
-        data: impl AsRef<[u8]>,
+        target: impl ToString,
     ) -> Result<&mut BomPath, Error> {
         let bom_path = bom_path.to_string();
         validate_bom_path(&bom_path)?;
+        let target = target.to_string();
 
-        let data = data.as_ref();
-
+        // Like files, the size and checksum are derived from the link target.
         let mut path = self.default_file_path();
+        path.path_type = BomPathType::Link;
+        path.path = bom_path.clone();
+        // lrwxr-xr-x
+        path.file_mode =
+            (S_IFLNK | S_IRUSR | S_IWUSR | S_IXUSR | S_IRGRP | S_IXGRP | S_IROTH | S_IXOTH) as u16;
+        path.size = target.len();
+        path.crc32 = Some(checksum_data(target.as_bytes()).0);
+        path.link_name = Some(target);
+
+        self.paths.insert(bom_path.clone(), path);
+
+        Ok(self.paths.get_mut(&bom_path).unwrap())
+    }
+
+    /// Obtain the root directory of this BOM.
+    ///
+    /// This allows customizing the metadata of the root directory, which
+    /// otherwise has an empty mode and is owned by UID and GID 0.
+    pub fn root_directory(&mut self) -> &mut BomPath {
+        if self.root.is_none() {
+            let mut path = self.default_file_path();
+            path.path_type = BomPathType::Directory;
+            path.path = ".".to_string();
+            path.file_mode = self.default_mode_dir;
+
+            self.root = Some(path);
+        }
+
+        self.root.as_mut().expect("root should be defined")
+    }
+
+    /// Add a hard link to a file previously added to this BOM.
+    ///
+    /// The new path inherits the metadata of the path it links to and both
+    /// paths are recorded in the BOM's hard link index.
+    ///
+    /// A mutable reference to the just-added entry is returned to allow
+    /// for further customization.
+    pub fn add_hard_link(
+        &mut self,
+        bom_path: impl ToString,
+        target: impl ToString,
+    ) -> Result<&mut BomPath, Error> {
+        let bom_path = bom_path.to_string();
+        validate_bom_path(&bom_path)?;
+
+        // Always link to the root of an existing group so groups stay flat.
+        let mut target = target.to_string();
+        if let Some(primary) = self.hard_links.get(&target) {
+            target = primary.clone();
+        }
+
+        let mut path = match self.paths.get(&target) {
+            Some(path) if matches!(path.path_type(), BomPathType::File) => path.clone(),
+            _ => {
+                return Err(Error::BadPath(
+                    target,
+                    "hard link target must be a previously added file",
+                ));
+            }
+        };
         path.path = bom_path.clone();
-        path.size = data.len();
-        path.crc32 = Some(crc32_data(data));
 
         self.paths.insert(bom_path.clone(), path);
+        self.hard_links.insert(bom_path.clone(), target);
 
         Ok(self.paths.get_mut(&bom_path).unwrap())
     }
@@ -200,6 +797,7 @@ impl BomBuilder {
         // Index is the path ID. Value is the filename as stored in the BOM.
         let mut path_to_path_id = HashMap::with_capacity(self.paths.len() + 1);
         let mut records = Vec::with_capacity(self.paths.len() + 1);
+        let mut large_sizes = vec![];
 
         // Root directory is special.
         path_to_path_id.insert(".".to_string(), 1u32);
@@ -208,19 +806,24 @@ impl BomBuilder {
             path_type: BomPathType::Directory.into(),
             a: 1,
             architecture: 1,
-            mode: 0,
-            user: 0,
-            group: 0,
-            mtime: 0,
+            mode: self.root.as_ref().map(|p| p.file_mode()).unwrap_or(0),
+            user: self.root.as_ref().map(|p| p.user_id()).unwrap_or(0),
+            group: self.root.as_ref().map(|p| p.group_id()).unwrap_or(0),
+            mtime: self
+                .root
+                .as_ref()
+                .map(|p| p.modified_time().timestamp() as u32)
+                .unwrap_or(0),
             size: 0,
             b: 1,
             checksum_or_type: 0,
+            architectures: vec![],
             link_name_length: 0,
             link_name: None,
         };
         let file = BomBlockFile {
             parent_path_id: 0,
-            name: Cow::from(CString::new(b".\0".to_vec()).expect("string is null terminated")),
+            name: Cow::from(CString::new(".").expect("string should not contain NULL")),
         };
 
         records.push((1u32, path_record, file));
@@ -249,7 +852,7 @@ impl BomBuilder {
                 let path_record = BomBlockPathRecord {
                     path_type: BomPathType::Directory.into(),
                     a: 1,
-                    architecture: 15,
+                    architecture: PATH_RECORD_ARCHITECTURE_DEFAULT,
                     mode: self.default_mode_dir,
                     user: self.default_uid,
                     group: self.default_gid,
@@ -257,15 +860,15 @@ impl BomBuilder {
                     size: 0,
                     b: 1,
                     checksum_or_type: 0,
+                    architectures: vec![],
                     link_name_length: 0,
                     link_name: None,
                 };
 
-                let mut path_cstring = Vec::<u8>::with_capacity(path.as_bytes().len());
-                path_cstring.extend(path.as_bytes());
-                path_cstring.push(0);
+                // File blocks only hold the final path component. The full
+                // path is derived by walking parents.
                 let path_cstring =
-                    CString::new(path_cstring).expect("C string should be well formed");
+                    CString::new(parent_parts[i - 1]).expect("C string should be well formed");
 
                 let file = BomBlockFile {
                     parent_path_id,
@@ -284,15 +887,17 @@ impl BomBuilder {
                 .expect("parent path should be present");
             let path_id = path_to_path_id.len() as u32 + 1;
 
-            let mut path_cstring = Vec::<u8>::with_capacity(path.as_bytes().len() + 1);
-            path_cstring.extend(path.as_bytes());
-            path_cstring.push(0);
-            let path_cstring = CString::new(path_cstring).expect("should be valid C string");
+            let path_cstring =
+                CString::new(path_parts[path_parts.len() - 1]).expect("should be valid C string");
 
             let path_record = BomBlockPathRecord {
                 path_type: entry.path_type().into(),
                 a: 1,
-                architecture: 15,
+                architecture: if entry.architectures().is_empty() {
+                    PATH_RECORD_ARCHITECTURE_DEFAULT
+                } else {
+                    PATH_RECORD_ARCHITECTURE_DEFAULT | PATH_RECORD_ARCHITECTURE_MACHO
+                },
                 mode: entry.file_mode(),
                 user: entry.user_id(),
                 group: entry.group_id(),
@@ -300,6 +905,7 @@ impl BomBuilder {
                 size: entry.size() as _,
                 b: 1,
                 checksum_or_type: entry.crc32().unwrap_or(0),
+                architectures: entry.architectures().to_vec(),
                 link_name_length: if let Some(link_name) = entry.link_name() {
                     link_name.as_bytes().len() as u32 + 1
                 } else {
@@ -313,6 +919,10 @@ impl BomBuilder {
                 name: Cow::from(path_cstring),
             };
 
+            if entry.size() as u64 > u32::MAX as u64 {
+                large_sizes.push((path_id, entry.size() as u64));
+            }
+
             path_to_path_id.insert(path, path_id);
             records.push((path_id, path_record, file));
         }
@@ -320,6 +930,7 @@ impl BomBuilder {
         // We now have all our paths assembled. It is now time to produce the blocks.
         let mut blocks = vec![];
         let mut paths_entries = Vec::with_capacity(records.len());
+        let mut path_info_indices = HashMap::with_capacity(records.len());
 
         // Block at index 0 is the special empty block.
         blocks.push(BomBlock::Empty);
@@ -356,7 +967,7 @@ impl BomBuilder {
 
         let mut vars_index = BomVarsIndex {
             count: 1,
-            vars: vec![BomVar::new(blocks.len() as _, "BomInfo")?],
+            vars: vec![BomVar::new(blocks.len() as u32 - 1, "BomInfo")?],
         };
 
         // If we wanted to adhere to the order in Apple's tooling, we would emit
@@ -379,6 +990,7 @@ impl BomBuilder {
                 block_index: path_info_index,
                 file_index,
             });
+            path_info_indices.insert(path_id, path_info_index);
         }
 
         // There are additional Tree, Paths, PathRecordPointer, and TreePointer
@@ -416,94 +1028,38 @@ impl BomBuilder {
         }
 
         // The Paths variable points to a Tree + Paths.
-        //
-        // The Paths blocks are a bit complicated. The primary Paths is a pointer
-        // to another one. And, due to the 4096 block size limit, there can be multiple
-        // Paths blocks.
-        const PATHS_BLOCK_SIZE: u32 = 4096;
-
-        blocks.push(BomBlock::Tree(BomBlockTree {
-            block_paths_index: blocks.len() as u32 + 1,
-            block_size: PATHS_BLOCK_SIZE,
-            path_count: paths_entries.len() as u32,
-            ..Default::default()
-        }));
+        let paths_tree_index = push_paths_tree(&mut blocks, paths_entries);
         vars_index.count += 1;
         vars_index
             .vars
-            .push(BomVar::new(blocks.len() as _, "Paths")?);
+            .push(BomVar::new(paths_tree_index, "Paths")?);
 
-        // Determine final set of Paths blocks holding meaningful records.
-        let mut paths_blocks = vec![];
-        let mut paths_block = BomBlockPaths {
-            is_path_info: 1,
-            ..Default::default()
-        };
-        for path_entry in paths_entries {
-            paths_block.count += 1;
-            paths_block.paths.push(path_entry);
-
-            let remaining_bytes = PATHS_BLOCK_SIZE - 12 - 8 * paths_block.count as u32;
-
-            // Running out of room. Flush block.
-            if remaining_bytes < 16 {
-                paths_blocks.push(paths_block.clone());
-                paths_block = BomBlockPaths {
-                    is_path_info: 1,
-                    ..Default::default()
-                };
+        // Add records for other variables.
+
+        // HLIndex is Tree + Paths. Each hard link group is emitted contiguously,
+        // starting with the path other paths link to.
+        let mut hl_groups = BTreeMap::<&str, Vec<&str>>::new();
+        for (link, target) in &self.hard_links {
+            if self.paths.contains_key(link) && self.paths.contains_key(target) {
+                hl_groups.entry(target).or_default().push(link);
             }
         }
 
-        if paths_block.count > 0 || paths_blocks.is_empty() {
-            paths_blocks.push(paths_block);
-        }
+        let mut hl_entries = vec![];
+        for (target, links) in hl_groups {
+            let target_index = path_info_indices[&path_to_path_id[&format!("./{target}")]];
 
-        // 1st Paths block is a pointer to meaningful one.
-        blocks.push(BomBlock::Paths(BomBlockPaths {
-            is_path_info: 0,
-            count: 1,
-            paths: vec![BomPathsEntry {
-                block_index: blocks.len() as u32 + 1,
-                file_index: if let Some(entry) = paths_blocks[0].paths.get(0) {
-                    entry.file_index
-                } else {
-                    0
-                },
-            }],
-            ..Default::default()
-        }));
-
-        for (i, paths) in paths_blocks.iter().enumerate() {
-            blocks.push(BomBlock::Paths(BomBlockPaths {
-                is_path_info: paths.is_path_info,
-                count: paths.count,
-                next_paths_block_index: if i == paths_blocks.len() - 1 {
-                    0
-                } else {
-                    blocks.len() as u32 + 2
-                },
-                previous_paths_block_index: if i == 0 { 0 } else { blocks.len() as u32 },
-                paths: paths.paths.clone(),
-            }));
+            for path in std::iter::once(target).chain(links) {
+                hl_entries.push(BomPathsEntry {
+                    block_index: path_info_indices[&path_to_path_id[&format!("./{path}")]],
+                    file_index: target_index,
+                });
+            }
         }
 
-        // Add records for other variables.
-
-        // HLIndex is Tree + Paths.
-        blocks.push(BomBlock::Tree(BomBlockTree {
-            block_paths_index: blocks.len() as u32 + 1,
-            block_size: PATHS_BLOCK_SIZE,
-            ..Default::default()
-        }));
+        let hl_tree_index = push_paths_tree(&mut blocks, hl_entries);
         vars_index.count += 1;
-        vars_index
-            .vars
-            .push(BomVar::new(blocks.len() as _, "HLIndex")?);
-        blocks.push(BomBlock::Paths(BomBlockPaths {
-            is_path_info: 1,
-            ..Default::default()
-        }));
+        vars_index.vars.push(BomVar::new(hl_tree_index, "HLIndex")?);
 
         // VIndex is VIndex + Tree + Paths.
         blocks.push(BomBlock::VIndex(BomBlockVIndex {
@@ -515,7 +1071,7 @@ impl BomBuilder {
         vars_index.count += 1;
         vars_index
             .vars
-            .push(BomVar::new(blocks.len() as _, "VIndex")?);
+            .push(BomVar::new(blocks.len() as u32 - 1, "VIndex")?);
         blocks.push(BomBlock::Tree(BomBlockTree {
             block_paths_index: blocks.len() as u32 + 1,
             block_size: PATHS_BLOCK_SIZE,
@@ -526,20 +1082,24 @@ impl BomBuilder {
             ..Default::default()
         }));
 
-        // Size64 is Tree + Paths.
-        blocks.push(BomBlock::Tree(BomBlockTree {
-            block_paths_index: blocks.len() as u32 + 1,
-            block_size: PATHS_BLOCK_SIZE,
-            ..Default::default()
-        }));
+        // Size64 is Tree + Paths. Each entry refers to a path whose size doesn't
+        // fit in the 32-bit size field of its path record.
+        let mut size64_entries = Vec::with_capacity(large_sizes.len());
+        for (path_id, size) in large_sizes {
+            let size_index = blocks.len() as u32;
+            blocks.push(BomBlock::Size64(BomBlockSize64 { size }));
+
+            size64_entries.push(BomPathsEntry {
+                block_index: path_info_indices[&path_id],
+                file_index: size_index,
+            });
+        }
+
+        let size64_tree_index = push_paths_tree(&mut blocks, size64_entries);
         vars_index.count += 1;
         vars_index
             .vars
-            .push(BomVar::new(blocks.len() as _, "Size64")?);
-        blocks.push(BomBlock::Paths(BomBlockPaths {
-            is_path_info: 1,
-            ..Default::default()
-        }));
+            .push(BomVar::new(size64_tree_index, "Size64")?);
 
         // Now that we've assembled all the blocks as data structures, it is time to write
         // them out.
@@ -617,3 +1177,350 @@ impl BomBuilder {
         Ok(writer.into_inner())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{checksum::cksum_data, format::ParsedBom},
+        chrono::TimeZone,
+    };
+
+    #[test]
+    fn build_parse_roundtrip() -> Result<(), Error> {
+        let mut builder = BomBuilder::default();
+        builder.add_file_from_data("a/b/file", b"hello")?;
+        let link = builder.add_file_from_data("a/link", b"")?;
+        link.path_type = BomPathType::Link;
+        link.set_link_name(Some("b/file".into()));
+
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+
+        // Variables point at the blocks holding their data. The count
+        // includes the null path.
+        assert_eq!(bom.bom_info()?.number_of_paths, 6);
+
+        // Full paths are reconstructed from the final component of each path.
+        let paths = bom.paths()?;
+        assert_eq!(
+            paths.iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec![".", "./a", "./a/b", "./a/b/file", "./a/link"]
+        );
+
+        let link = paths.iter().find(|p| p.path() == "./a/link").unwrap();
+        assert_eq!(link.link_name(), Some("b/file"));
+
+        Ok(())
+    }
+
+    fn thin_macho(cpu_type: u32, cpu_subtype: u32, size: usize) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(MH_MAGIC_64.to_le_bytes());
+        data.extend(cpu_type.to_le_bytes());
+        data.extend(cpu_subtype.to_le_bytes());
+        data.resize(size, 0x42);
+
+        data
+    }
+
+    fn fat_macho(slices: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend(FAT_MAGIC.to_be_bytes());
+        header.extend((slices.len() as u32).to_be_bytes());
+
+        let mut body = Vec::<u8>::new();
+
+        for (cpu_type, cpu_subtype, slice) in slices {
+            header.extend(cpu_type.to_be_bytes());
+            header.extend(cpu_subtype.to_be_bytes());
+            header.extend((4096 + body.len() as u32).to_be_bytes());
+            header.extend((slice.len() as u32).to_be_bytes());
+            header.extend(0u32.to_be_bytes());
+
+            body.extend(*slice);
+        }
+
+        header.resize(4096, 0);
+        header.extend(body);
+
+        header
+    }
+
+    #[test]
+    fn macho_architectures() -> Result<(), Error> {
+        let x86 = thin_macho(0x01000007, 3, 100);
+        let arm = thin_macho(0x0100000c, 0, 200);
+        let fat = fat_macho(&[(0x01000007, 3, &x86), (0x0100000c, 0, &arm)]);
+
+        let mut builder = BomBuilder::default();
+        builder.add_file_from_data("fat", &fat)?;
+        builder.add_file_from_data("thin", &arm)?;
+        builder.add_file_from_data("text", b"APPL????")?;
+
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+        let paths = bom.paths()?;
+
+        let fat_path = paths.iter().find(|p| p.path() == "./fat").unwrap();
+        assert_eq!(fat_path.crc32(), Some(cksum_data(&fat)));
+        assert_eq!(
+            fat_path.architectures(),
+            &[
+                BomPathRecordArchitecture {
+                    cpu_type: 0x01000007,
+                    cpu_subtype: 3,
+                    size: 100,
+                    checksum: cksum_data(&x86),
+                },
+                BomPathRecordArchitecture {
+                    cpu_type: 0x0100000c,
+                    cpu_subtype: 0,
+                    size: 200,
+                    checksum: cksum_data(&arm),
+                }
+            ]
+        );
+
+        let thin_path = paths.iter().find(|p| p.path() == "./thin").unwrap();
+        assert_eq!(
+            thin_path.architectures(),
+            &[BomPathRecordArchitecture {
+                cpu_type: 0x0100000c,
+                cpu_subtype: 0,
+                size: 200,
+                checksum: cksum_data(&arm),
+            }]
+        );
+
+        let text_path = paths.iter().find(|p| p.path() == "./text").unwrap();
+        assert_eq!(text_path.crc32(), Some(742937289));
+        assert!(text_path.architectures().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn macho_architecture_too_large() {
+        // Fat64 binaries can have slices too large for architecture records.
+        let mut hasher = ContentHasher::new(vec![
+            MachOSlice {
+                cpu_type: 0x01000007,
+                cpu_subtype: 3,
+                offset: 0,
+                size: 4,
+            },
+            MachOSlice {
+                cpu_type: 0x0100000c,
+                cpu_subtype: 0,
+                offset: 4,
+                size: 1 << 32,
+            },
+        ]);
+        hasher.update(b"data");
+
+        let (crc32, architectures) = hasher.finalize();
+        assert_eq!(crc32, cksum_data(b"data"));
+        assert!(architectures.is_empty());
+    }
+
+    #[test]
+    fn hard_links() -> Result<(), Error> {
+        let mut builder = BomBuilder::default();
+        builder.add_file_from_data("a/file", b"content")?;
+        builder.add_file_from_data("b/other", b"other")?;
+        builder.add_hard_link("b/link0", "a/file")?;
+        builder.add_hard_link("c/link1", "b/link0")?;
+
+        assert!(matches!(
+            builder.add_hard_link("bad", "missing"),
+            Err(Error::BadPath(_, _))
+        ));
+
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+
+        let groups = bom.hl_index()?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec!["./a/file", "./b/link0", "./c/link1"]
+        );
+        assert!(groups[0]
+            .iter()
+            .all(|p| p.crc32() == Some(cksum_data(b"content"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn size64() -> Result<(), Error> {
+        let large_size = 5 * 1024 * 1024 * 1024;
+
+        let mut builder = BomBuilder::default();
+        builder.add_file_from_data("small", b"small")?;
+        builder
+            .add_file_from_data("large", b"large")?
+            .set_size(large_size);
+
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+
+        let size64 = bom.size64()?;
+        assert_eq!(size64.len(), 1);
+        assert_eq!(size64[0].path(), "./large");
+        assert_eq!(size64[0].size(), large_size);
+
+        let paths = bom.paths()?;
+        let large = paths.iter().find(|p| p.path() == "./large").unwrap();
+        assert_eq!(large.size(), large_size);
+        let small = paths.iter().find(|p| p.path() == "./small").unwrap();
+        assert_eq!(small.size(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn many_paths() -> Result<(), Error> {
+        let mut builder = BomBuilder::default();
+        for i in 0..2000 {
+            builder.add_file_from_data(format!("dir/file{i:04}"), b"")?;
+        }
+
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+
+        let paths = bom.paths()?;
+        // Root + dir + files.
+        assert_eq!(paths.len(), 2002);
+        assert_eq!(paths[2001].path(), "./dir/file1999");
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_cpio() -> Result<(), Error> {
+        let mtime = Utc.timestamp_opt(1700000000, 0).unwrap();
+        let mut builder = cpio_archive::OdcBuilder::new(Cursor::new(Vec::<u8>::new()));
+        builder.default_mtime(mtime);
+        builder.default_user_id(501);
+        builder.default_group_id(20);
+        builder.append_file_from_data("dir/file", b"APPL????", 0o100755)?;
+
+        let mut header = builder.next_header();
+        header.name = "./dir/link".to_string();
+        header.mode = 0o120755;
+        header.file_size = 4;
+        builder.append_header_with_data(header, b"file")?;
+
+        let cpio_data = builder.into_inner()?.into_inner();
+
+        let builder = BomBuilder::from_cpio_reader(Cursor::new(cpio_data))?;
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+        let paths = bom.paths()?;
+
+        assert_eq!(
+            paths.iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec![".", "./dir", "./dir/file", "./dir/link"]
+        );
+
+        let file = &paths[2];
+        assert_eq!(file.symbolic_mode(), "-rwxr-xr-x");
+        assert_eq!(file.user_id(), 501);
+        assert_eq!(file.group_id(), 20);
+        assert_eq!(file.modified_time(), &mtime);
+        assert_eq!(file.size(), 8);
+        assert_eq!(file.crc32(), Some(742937289));
+
+        let link = &paths[3];
+        assert_eq!(link.symbolic_mode(), "lrwxr-xr-x");
+        assert_eq!(link.link_name(), Some("file"));
+        assert_eq!(link.size(), 4);
+        assert_eq!(link.crc32(), Some(cksum_data(b"file")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn bad_paths() -> Result<(), Error> {
+        let mut builder = BomBuilder::default();
+
+        for path in [
+            ".", "..", "./a", "../a", "a/./b", "a/../b", "a/..", "/a", "a\\b",
+        ] {
+            assert!(
+                matches!(
+                    builder.add_file_from_data(path, b"data"),
+                    Err(Error::BadPath(_, _))
+                ),
+                "{path}"
+            );
+        }
+
+        builder.add_file_from_data(".DS_Store", b"data")?;
+        builder.add_file_from_data("a/..b/.c", b"data")?;
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_directory_non_utf8() -> Result<(), Error> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let td = tempfile::tempdir()?;
+        std::fs::write(
+            td.path().join(std::ffi::OsStr::from_bytes(b"file\xff")),
+            b"data",
+        )?;
+
+        assert!(matches!(
+            BomBuilder::from_directory(td.path()),
+            Err(Error::BadPath(_, _))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_directory() -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempfile::tempdir()?;
+        let root = td.path();
+
+        std::fs::create_dir(root.join("dir"))?;
+        std::fs::write(root.join("dir/file"), b"APPL????")?;
+        std::fs::set_permissions(
+            root.join("dir/file"),
+            std::fs::Permissions::from_mode(0o755),
+        )?;
+        std::fs::hard_link(root.join("dir/file"), root.join("hardlink"))?;
+        std::os::unix::fs::symlink("dir/file", root.join("symlink"))?;
+
+        let builder = BomBuilder::from_directory(root)?;
+        let data = builder.build_bom()?;
+        let bom = ParsedBom::parse(&data)?;
+        let paths = bom.paths()?;
+
+        assert_eq!(
+            paths.iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec![".", "./dir", "./dir/file", "./hardlink", "./symlink"]
+        );
+        assert_eq!(paths[2].symbolic_mode(), "-rwxr-xr-x");
+        assert_eq!(paths[2].crc32(), Some(742937289));
+        assert_eq!(paths[3].crc32(), Some(742937289));
+        assert_eq!(paths[4].link_name(), Some("dir/file"));
+
+        let groups = bom.hl_index()?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].iter().map(|p| p.path()).collect::<Vec<_>>(),
+            vec!["./dir/file", "./hardlink"]
+        );
+
+        Ok(())
+    }
+}
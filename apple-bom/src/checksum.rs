@@ -0,0 +1,135 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checksums as stored in BOM path records.
+//!
+//! The 32-bit checksum in BOM path records is not the zlib/PNG CRC32
+//! that most people think of when they hear CRC32. Rather, it is the CRC
+//! computed by the POSIX `cksum` utility: a non-reflected CRC32 using the
+//! `0x04c11db7` polynomial, with the content length folded in after the
+//! content. This is the value printed by `lsbom -p c`.
+
+use std::io::Read;
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+static TABLE: [u32; 256] = build_table();
+
+/// Incremental hasher for the POSIX `cksum` CRC.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cksum {
+    crc: u32,
+    length: u64,
+}
+
+impl Cksum {
+    /// Construct a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_byte(&mut self, byte: u8) {
+        self.crc = (self.crc << 8) ^ TABLE[((self.crc >> 24) as u8 ^ byte) as usize];
+    }
+
+    /// Feed data into the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.update_byte(*byte);
+        }
+
+        self.length += data.len() as u64;
+    }
+
+    /// The number of bytes fed into this hasher.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Obtain the checksum of all data fed into the hasher.
+    pub fn finalize(mut self) -> u32 {
+        let mut length = self.length;
+
+        while length != 0 {
+            self.update_byte(length as u8);
+            length >>= 8;
+        }
+
+        !self.crc
+    }
+}
+
+/// Compute the `cksum` CRC of a slice of data.
+pub fn cksum_data(data: &[u8]) -> u32 {
+    let mut h = Cksum::new();
+    h.update(data);
+    h.finalize()
+}
+
+/// Compute the `cksum` CRC of all content in a reader.
+///
+/// Returns the checksum and the number of bytes read.
+pub fn cksum_reader(reader: &mut impl Read) -> std::io::Result<(u32, u64)> {
+    let mut h = Cksum::new();
+    let mut buffer = [0u8; 32768];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        h.update(&buffer[0..bytes_read]);
+    }
+
+    let length = h.length();
+
+    Ok((h.finalize(), length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cksum_values() {
+        // Values verified against the `cksum` program.
+        assert_eq!(cksum_data(b""), 4294967295);
+        assert_eq!(cksum_data(b"APPL????"), 742937289);
+        assert_eq!(cksum_data(b"123456789"), 930766865);
+    }
+
+    #[test]
+    fn cksum_incremental() {
+        let mut h = Cksum::new();
+        h.update(b"1234");
+        h.update(b"56789");
+        assert_eq!(h.finalize(), cksum_data(b"123456789"));
+    }
+}
@@ -3,23 +3,130 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#[allow(unused)]
-mod error;
-#[allow(unused)]
-mod format;
-#[allow(unused)]
-mod path;
-
 use {
-    crate::{
-        error::Error,
-        format::{BomBlock, ParsedBom},
-    },
-    clap::{value_parser, Arg, ArgAction, Command},
+    apple_bom::{builder::BomBuilder, format::BomBlock, BomPath, BomPathType, Error, ParsedBom},
+    clap::{value_parser, Arg, ArgAction, ArgMatches, Command},
     std::path::PathBuf,
 };
 
-type BomResult<T> = Result<T, error::Error>;
+type BomResult<T> = Result<T, Error>;
+
+/// Render a path as a line of `lsbom` output.
+///
+/// `params` holds `lsbom -p` parameter characters. If `None`, the default
+/// `lsbom` format is used.
+fn lsbom_line(path: &BomPath, params: Option<&str>) -> BomResult<String> {
+    let checksum = path.crc32().map(|x| x.to_string()).unwrap_or_default();
+
+    let Some(params) = params else {
+        let mut fields = vec![
+            path.path().to_string(),
+            format!("{:o}", path.file_mode()),
+            format!("{}/{}", path.user_id(), path.group_id()),
+        ];
+
+        match path.path_type() {
+            BomPathType::File => {
+                fields.push(path.size().to_string());
+                fields.push(checksum);
+            }
+            BomPathType::Link => {
+                fields.push(path.size().to_string());
+                fields.push(checksum);
+                fields.push(path.link_name().unwrap_or_default().to_string());
+            }
+            BomPathType::Directory | BomPathType::Dev | BomPathType::Other(_) => {}
+        }
+
+        return Ok(fields.join("\t"));
+    };
+
+    let mut fields = vec![];
+
+    for param in params.chars() {
+        fields.push(match param {
+            'c' => checksum.clone(),
+            'f' => path.path().to_string(),
+            'F' => format!("\"{}\"", path.path()),
+            'g' => path.group_id().to_string(),
+            'm' => format!("{:o}", path.file_mode()),
+            'M' => path.symbolic_mode(),
+            's' => path.size().to_string(),
+            't' => path.modified_time().timestamp().to_string(),
+            'T' => path
+                .modified_time()
+                .format("%a %b %e %H:%M:%S %Y")
+                .to_string(),
+            'u' => path.user_id().to_string(),
+            '/' => format!("{}/{}", path.user_id(), path.group_id()),
+            _ => {
+                return Err(Error::CliBadArgs(format!(
+                    "unsupported lsbom parameter: {param}"
+                )));
+            }
+        });
+    }
+
+    Ok(fields.join("\t"))
+}
+
+fn command_lsbom(args: &ArgMatches) -> BomResult<()> {
+    let path = args
+        .get_one::<PathBuf>("path")
+        .expect("path should be required");
+
+    let bom_data = std::fs::read(path)?;
+    let bom = ParsedBom::parse(&bom_data)?;
+
+    let directories = args.get_flag("directories");
+    let files = args.get_flag("files");
+    let links = args.get_flag("links");
+    let filter = directories || files || links;
+
+    let params = if args.get_flag("names_only") {
+        Some("f")
+    } else {
+        args.get_one::<String>("params").map(|x| x.as_str())
+    };
+
+    for path in bom.paths()? {
+        if filter {
+            let wanted = match path.path_type() {
+                BomPathType::Directory => directories,
+                BomPathType::File => files,
+                BomPathType::Link => links,
+                BomPathType::Dev | BomPathType::Other(_) => false,
+            };
+
+            if !wanted {
+                continue;
+            }
+        }
+
+        println!("{}", lsbom_line(&path, params)?);
+    }
+
+    Ok(())
+}
+
+fn command_mkbom(args: &ArgMatches) -> BomResult<()> {
+    let source = args
+        .get_one::<PathBuf>("source")
+        .expect("source should be required");
+    let output = args
+        .get_one::<PathBuf>("output")
+        .expect("output should be required");
+
+    let builder = if args.get_flag("cpio") {
+        BomBuilder::from_cpio_reader(std::io::BufReader::new(std::fs::File::open(source)?))?
+    } else {
+        BomBuilder::from_directory(source)?
+    };
+
+    std::fs::write(output, builder.build_bom()?)?;
+
+    Ok(())
+}
 
 fn main_impl() -> BomResult<()> {
     let matches = Command::new("Apple BOM Dumper")
@@ -52,8 +159,81 @@ fn main_impl() -> BomResult<()> {
                 .default_value("header")
                 .help("Which content to show"),
         )
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("lsbom")
+                .about("List the contents of a BOM, like Apple's lsbom")
+                .arg(
+                    Arg::new("directories")
+                        .short('d')
+                        .action(ArgAction::SetTrue)
+                        .help("List directories"),
+                )
+                .arg(
+                    Arg::new("files")
+                        .short('f')
+                        .action(ArgAction::SetTrue)
+                        .help("List files"),
+                )
+                .arg(
+                    Arg::new("links")
+                        .short('l')
+                        .action(ArgAction::SetTrue)
+                        .help("List symlinks"),
+                )
+                .arg(
+                    Arg::new("names_only")
+                        .short('s')
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("params")
+                        .help("Only print path names"),
+                )
+                .arg(
+                    Arg::new("params")
+                        .short('p')
+                        .action(ArgAction::Set)
+                        .help("Print only the given fields (characters from cfFgmMstTu/)"),
+                )
+                .arg(
+                    Arg::new("path")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Path to BOM file"),
+                ),
+        )
+        .subcommand(
+            Command::new("mkbom")
+                .about("Create a BOM from a directory or cpio archive, like Apple's mkbom")
+                .arg(
+                    Arg::new("cpio")
+                        .long("cpio")
+                        .action(ArgAction::SetTrue)
+                        .help("Treat the source as an uncompressed cpio archive"),
+                )
+                .arg(
+                    Arg::new("source")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Directory or cpio archive to create a BOM from"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true)
+                        .help("Path of BOM file to write"),
+                ),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("lsbom", args)) => return command_lsbom(args),
+        Some(("mkbom", args)) => return command_mkbom(args),
+        _ => {}
+    }
+
     let path = matches
         .get_one::<PathBuf>("path")
         .expect("path should be required");
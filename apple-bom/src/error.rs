@@ -26,6 +26,12 @@ pub enum Error {
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("cpio error: {0}")]
+    Cpio(#[from] cpio_archive::Error),
+
+    #[error("directory walking error: {0}")]
+    WalkDir(#[from] walkdir::Error),
+
     #[error("bad arguments: {0}")]
     CliBadArgs(String),
 
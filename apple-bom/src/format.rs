@@ -43,6 +43,7 @@
 //! * [BomBlockPathRecord]
 //! * [BomBlockPathRecordPointer]
 //! * [BomBlockPaths]
+//! * [BomBlockSize64]
 //! * [BomBlockTree]
 //! * [BomBlockTreePointer]
 //! * [BomBlockVIndex]
@@ -65,13 +66,23 @@
 //!
 //! Defines hard links. Its block data is [BomBlockTree].
 //!
+//! Each [BomPathsEntry] in the tree describes a path belonging to a group of
+//! hard links. `block_index` refers to the [BomBlockPathInfoIndex] of the
+//! path and `file_index` refers to the [BomBlockPathInfoIndex] of the first
+//! path in its group.
+//!
 //! ## VIndex
 //!
 //! Unknown. Its block data is [BomBlockVIndex].
 //!
 //! ## Size64
 //!
-//! Unknown. Its block data is [BomBlockTree].
+//! Defines sizes of paths too large to be represented by the 32-bit
+//! [BomBlockPathRecord::size] field. Its block data is [BomBlockTree].
+//!
+//! Each [BomPathsEntry] in the tree has a `block_index` referring to the
+//! [BomBlockPathInfoIndex] of a path and a `file_index` referring to a
+//! [BomBlockSize64] holding its size.
 //!
 //! # Layout
 //!
@@ -293,13 +304,13 @@ impl BomVar {
     pub fn new(block_index: u32, name: impl ToString) -> Result<Self, Error> {
         let name = name.to_string();
 
-        if name.as_bytes().len() > 254 {
+        if name.as_bytes().len() > 255 {
             return Err(Error::BadVariableString);
         }
 
         Ok(Self {
             block_index,
-            name_length: name.as_bytes().len() as u8 + 1,
+            name_length: name.as_bytes().len() as u8,
             name,
         })
     }
@@ -309,7 +320,6 @@ impl BomVar {
         writer.iowrite_with(self.block_index, scroll::BE)?;
         writer.iowrite_with(self.name_length, scroll::BE)?;
         writer.write_all(self.name.as_bytes())?;
-        writer.write_all(b"\0")?;
 
         Ok(())
     }
@@ -672,6 +682,38 @@ impl BomBlockPathInfoIndex {
     }
 }
 
+/// Value of [BomBlockPathRecord::architecture] for most paths.
+pub const PATH_RECORD_ARCHITECTURE_DEFAULT: u16 = 0x000f;
+
+/// Bit in [BomBlockPathRecord::architecture] denoting a Mach-O binary.
+///
+/// When set, the path record has [BomPathRecordArchitecture] entries
+/// describing each architecture slice in the binary.
+pub const PATH_RECORD_ARCHITECTURE_MACHO: u16 = 0x2000;
+
+/// Describes a single architecture in a Mach-O binary.
+///
+/// These records follow the checksum of [BomBlockPathRecord] for Mach-O
+/// binaries. Universal binaries have a record for each slice. Thin binaries
+/// have a single record describing the entire file.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq, IOwrite, Pread, Pwrite, SizeWith)]
+pub struct BomPathRecordArchitecture {
+    /// Mach-O CPU type.
+    pub cpu_type: u32,
+
+    /// Mach-O CPU subtype.
+    pub cpu_subtype: u32,
+
+    /// Size in bytes of this architecture's slice.
+    pub size: u32,
+
+    /// Checksum of this architecture's slice.
+    ///
+    /// Uses the same algorithm as [BomBlockPathRecord::checksum_or_type].
+    pub checksum: u32,
+}
+
 /// Block type defining low-level path information.
 ///
 /// This is where most of the metadata defining a BOM path lives.
@@ -688,7 +730,8 @@ pub struct BomBlockPathRecord<'a> {
 
     /// File architecture.
     ///
-    /// Probably corresponds to value in Mach-O header.
+    /// Usually [PATH_RECORD_ARCHITECTURE_DEFAULT]. Mach-O binaries have
+    /// the [PATH_RECORD_ARCHITECTURE_MACHO] bit set.
     pub architecture: u16,
 
     /// File mode.
@@ -710,8 +753,17 @@ pub struct BomBlockPathRecord<'a> {
     pub b: u8,
 
     /// CRC32 checksum or device type.
+    ///
+    /// The checksum is the CRC computed by the POSIX `cksum` utility. See
+    /// [crate::checksum].
     pub checksum_or_type: u32,
 
+    /// Per-architecture records for Mach-O binaries.
+    ///
+    /// Only present if [Self::architecture] has the
+    /// [PATH_RECORD_ARCHITECTURE_MACHO] bit set.
+    pub architectures: Vec<BomPathRecordArchitecture>,
+
     /// Length of link name.
     ///
     /// May be non-0 for non-link path records.
@@ -736,14 +788,33 @@ impl<'a> BomBlockPathRecord<'a> {
         writer.iowrite_with(self.size, scroll::BE)?;
         writer.iowrite_with(self.b, scroll::BE)?;
         writer.iowrite_with(self.checksum_or_type, scroll::BE)?;
+        if self.has_architectures() {
+            // Purpose of this byte is unknown. Apple tooling writes 1.
+            writer.iowrite_with(1u8, scroll::BE)?;
+            writer.iowrite_with(self.architectures.len() as u32, scroll::BE)?;
+            for arch in &self.architectures {
+                writer.iowrite_with(*arch, scroll::BE)?;
+            }
+        }
         writer.iowrite_with(self.link_name_length, scroll::BE)?;
         if let Some(link_name) = &self.link_name {
             writer.write_all(link_name.to_bytes_with_nul())?;
         }
+        if self.has_architectures() {
+            // Apple tooling writes trailing padding after the architecture
+            // records.
+            writer.iowrite_with(0u32, scroll::BE)?;
+        }
 
         Ok(())
     }
 
+    /// Whether this record has per-architecture records.
+    pub fn has_architectures(&self) -> bool {
+        self.path_type == u8::from(BomPathType::File)
+            && self.architecture & PATH_RECORD_ARCHITECTURE_MACHO != 0
+    }
+
     /// Obtain the link name of this record, if present.
     pub fn string_link_name(&self) -> Option<String> {
         self.link_name
@@ -768,6 +839,23 @@ impl<'a> scroll::ctx::TryFromCtx<'a, scroll::Endian> for BomBlockPathRecord<'a>
         let size = data.gread_with(offset, le)?;
         let b = data.gread_with(offset, le)?;
         let checksum_or_type = data.gread_with(offset, le)?;
+
+        let architectures = if path_type == BomPathType::File.into()
+            && architecture & PATH_RECORD_ARCHITECTURE_MACHO != 0
+        {
+            let _unknown: u8 = data.gread_with(offset, le)?;
+            let count: u32 = data.gread_with(offset, le)?;
+
+            let mut architectures = vec![];
+            for _ in 0..count {
+                architectures.push(data.gread_with(offset, le)?);
+            }
+
+            architectures
+        } else {
+            vec![]
+        };
+
         let link_name_length = data.gread_with(offset, le)?;
 
         let link_name = if path_type == BomPathType::Link.into() && link_name_length > 0 {
@@ -791,6 +879,7 @@ impl<'a> scroll::ctx::TryFromCtx<'a, scroll::Endian> for BomBlockPathRecord<'a>
                 size,
                 b,
                 checksum_or_type,
+                architectures,
                 link_name_length,
                 link_name,
             },
@@ -799,6 +888,16 @@ impl<'a> scroll::ctx::TryFromCtx<'a, scroll::Endian> for BomBlockPathRecord<'a>
     }
 }
 
+/// Block type holding the 64-bit size of a path.
+///
+/// Instances are referred to by entries in the `Size64` tree.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, IOwrite, Pread, Pwrite, SizeWith)]
+pub struct BomBlockSize64 {
+    /// Size in bytes.
+    pub size: u64,
+}
+
 /// Block type for various variables describing a collection/tree of paths.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, IOwrite, Pwrite, SizeWith)]
@@ -886,10 +985,39 @@ impl BomBlockTree {
         Ok(paths)
     }
 
+    /// Resolve all [BomPathsEntry] in this tree.
+    ///
+    /// This follows the chain of [BomBlockPaths] instances.
+    pub fn entries(&self, bom: &ParsedBom) -> Result<Vec<BomPathsEntry>, Error> {
+        let mut res = Vec::with_capacity(self.path_count as _);
+        let mut paths = self.root_paths(bom)?;
+
+        loop {
+            res.extend(paths.paths.iter().copied());
+
+            if paths.next_paths_block_index != 0 {
+                paths = bom.block_as_paths(paths.next_paths_block_index as _)?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(res)
+    }
+
     /// Resolve all [BomPath] in this tree.
     ///
     /// This contains the logic for iterating over multiple [BomBlockPaths] instances.
     pub fn bom_paths(&self, bom: &ParsedBom) -> Result<Vec<BomPath>, Error> {
+        Ok(self
+            .bom_paths_with_ids(bom)?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect())
+    }
+
+    /// Resolve all [BomPath] in this tree along with their path IDs.
+    pub fn bom_paths_with_ids(&self, bom: &ParsedBom) -> Result<Vec<(u32, BomPath)>, Error> {
         let mut res = Vec::with_capacity(self.path_count as _);
 
         let mut paths = self.root_paths(bom)?;
@@ -911,7 +1039,7 @@ impl BomBlockTree {
                     filename = format!("{}/{}", resolve_file.string_file_name(), filename);
                 }
 
-                res.push(BomPath::from_record(filename, &record)?);
+                res.push((path_id, BomPath::from_record(filename, &record)?));
 
                 files_by_id.insert(path_id, file);
             }
@@ -1030,6 +1158,7 @@ pub enum BomBlock<'a> {
     PathRecord(BomBlockPathRecord<'a>),
     PathRecordPointer(BomBlockPathRecordPointer),
     Paths(BomBlockPaths),
+    Size64(BomBlockSize64),
     Tree(BomBlockTree),
     TreePointer(BomBlockTreePointer),
     VIndex(BomBlockVIndex),
@@ -1121,6 +1250,9 @@ impl<'a> BomBlock<'a> {
             Self::Paths(b) => {
                 b.write(writer)?;
             }
+            Self::Size64(b) => {
+                writer.iowrite_with(*b, scroll::BE)?;
+            }
             Self::Tree(b) => {
                 writer.iowrite_with(*b, scroll::BE)?;
             }
@@ -1206,26 +1338,115 @@ impl<'a> ParsedBom<'a> {
         self.block_as_bom_info(var.block_index as _)
     }
 
-    pub fn hl_index(&self) -> Result<Vec<BomPath>, Error> {
+    /// Resolve hard link groups from the HLIndex tree.
+    ///
+    /// Each entry is a group of paths referring to the same file. The first
+    /// path in each group is the one other paths are linked to.
+    pub fn hl_index(&self) -> Result<Vec<Vec<BomPath>>, Error> {
         let var = self.find_variable("HLIndex")?;
         let tree = self.block_as_tree(var.block_index as _)?;
 
-        tree.bom_paths(self)
+        let entries = tree.entries(self)?;
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = self
+            .paths_with_ids()?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let mut groups: Vec<(u32, Vec<BomPath>)> = vec![];
+
+        for entry in entries {
+            let path_id = entry.path_info(self)?.path_id;
+            let primary_id = self
+                .block_as_path_info_index(entry.file_index as _)?
+                .path_id;
+            let path = paths.remove(&path_id).ok_or(Error::BadIndex)?;
+
+            if let Some((_, group)) = groups.iter_mut().find(|(id, _)| *id == primary_id) {
+                group.push(path);
+            } else {
+                groups.push((primary_id, vec![path]));
+            }
+        }
+
+        Ok(groups.into_iter().map(|(_, group)| group).collect())
     }
 
+    /// Resolve all paths in this BOM.
+    ///
+    /// Sizes recorded in the Size64 tree take precedence over the 32-bit
+    /// size in path records.
     pub fn paths(&self) -> Result<Vec<BomPath>, Error> {
+        Ok(self
+            .paths_with_ids()?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect())
+    }
+
+    /// Resolve all paths in this BOM along with their path IDs.
+    fn paths_with_ids(&self) -> Result<Vec<(u32, BomPath)>, Error> {
         let index = self.find_variable("Paths")?;
         let tree = self.block_as_tree(index.block_index as _)?;
 
-        tree.bom_paths(self)
+        let sizes = self.size64_by_id()?;
+
+        Ok(tree
+            .bom_paths_with_ids(self)?
+            .into_iter()
+            .map(|(path_id, mut path)| {
+                if let Some(size) = sizes.get(&path_id) {
+                    path.size = *size as _;
+                }
+
+                (path_id, path)
+            })
+            .collect())
+    }
+
+    /// Resolve 64-bit path sizes from the Size64 tree, keyed by path ID.
+    fn size64_by_id(&self) -> Result<HashMap<u32, u64>, Error> {
+        let var = match self.find_variable("Size64") {
+            Ok(var) => var,
+            Err(Error::NoVar(_)) => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let tree = self.block_as_tree(var.block_index as _)?;
+
+        tree.entries(self)?
+            .into_iter()
+            .map(|entry| {
+                let path_id = entry.path_info(self)?.path_id;
+                let size = self.block_as_size64(entry.file_index as _)?.size;
+
+                Ok((path_id, size))
+            })
+            .collect()
     }
 
     /// Resolve the Size64 tree.
+    ///
+    /// Returns paths whose size doesn't fit in 32 bits.
     pub fn size64(&self) -> Result<Vec<BomPath>, Error> {
-        let var = self.find_variable("Size64")?;
-        let tree = self.block_as_tree(var.block_index as _)?;
+        let sizes = self.size64_by_id()?;
+        if sizes.is_empty() {
+            return Ok(vec![]);
+        }
 
-        tree.bom_paths(self)
+        let mut paths = self
+            .paths_with_ids()?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let mut path_ids = sizes.keys().copied().collect::<Vec<_>>();
+        path_ids.sort_unstable();
+
+        path_ids
+            .into_iter()
+            .map(|path_id| paths.remove(&path_id).ok_or(Error::BadIndex))
+            .collect()
     }
 
     /// Resolve the V Index.
@@ -1278,6 +1499,11 @@ impl<'a> ParsedBom<'a> {
         Ok(data.pread_with(0, scroll::BE)?)
     }
 
+    /// Attempt to resolve a block at an index as a [BomBlockSize64].
+    pub fn block_as_size64(&self, index: usize) -> Result<BomBlockSize64, Error> {
+        Ok(self.block_data(index)?.pread_with(0, scroll::BE)?)
+    }
+
     /// Attempt to resolve a black at an index as a [BomBlockTree].
     pub fn block_as_tree(&self, index: usize) -> Result<BomBlockTree, Error> {
         let data = self.block_data(index)?;
@@ -1301,6 +1527,25 @@ mod tests {
 
     const PYTHON_DATA: &[u8] = include_bytes!("testdata/python-applications.bom");
 
+    #[test]
+    fn bom_var_roundtrip() -> Result<(), Error> {
+        let var = BomVar::new(3, "Paths")?;
+        assert_eq!(var.name_length, 5);
+
+        let mut data = vec![];
+        var.write(&mut data)?;
+        assert_eq!(data, b"\0\0\0\x03\x05Paths");
+
+        let parsed = data.pread_with::<BomVar>(0, scroll::BE)?;
+        assert_eq!(parsed.block_index, 3);
+        assert_eq!(parsed.name, "Paths");
+
+        assert!(BomVar::new(0, "a".repeat(255)).is_ok());
+        assert!(BomVar::new(0, "a".repeat(256)).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn parse_python() -> Result<(), Error> {
         let bom = crate::format::ParsedBom::parse(PYTHON_DATA)?;
@@ -1322,6 +1567,31 @@ mod tests {
             .find(|p| p.path() == "./Python 3.9/ReadMe.rtf")
             .unwrap();
         assert_eq!(readme.symbolic_mode(), "-rw-r--r--");
+        assert!(readme.architectures().is_empty());
+
+        let launcher = bom
+            .paths()?
+            .into_iter()
+            .find(|p| p.path() == "./Python 3.9/Python Launcher.app/Contents/MacOS/Python Launcher")
+            .unwrap();
+        assert_eq!(launcher.crc32(), Some(0x8a763b72));
+        assert_eq!(
+            launcher.architectures(),
+            &[
+                BomPathRecordArchitecture {
+                    cpu_type: 0x01000007,
+                    cpu_subtype: 3,
+                    size: 80416,
+                    checksum: 0x58ceb338,
+                },
+                BomPathRecordArchitecture {
+                    cpu_type: 0x0100000c,
+                    cpu_subtype: 0,
+                    size: 112608,
+                    checksum: 0xf38b440b,
+                }
+            ]
+        );
 
         Ok(())
     }
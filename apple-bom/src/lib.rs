@@ -21,6 +21,7 @@
 //! Writing support is still a work in progress.
 
 pub mod builder;
+pub mod checksum;
 pub mod error;
 pub use error::Error;
 pub mod format;
@@ -6,7 +6,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 use {
-    crate::error::Error,
+    crate::{error::Error, format::BomPathRecordArchitecture},
     chrono::{DateTime, TimeZone, Utc},
     simple_file_manifest::{
         S_IRGRP, S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR,
@@ -76,6 +76,7 @@ pub struct BomPath {
     pub(crate) size: usize,
     pub(crate) crc32: Option<u32>,
     pub(crate) link_name: Option<String>,
+    pub(crate) architectures: Vec<BomPathRecordArchitecture>,
 }
 
 impl BomPath {
@@ -106,6 +107,7 @@ impl BomPath {
             size: record.size as _,
             crc32,
             link_name: record.string_link_name(),
+            architectures: record.architectures.clone(),
         })
     }
 
@@ -256,6 +258,21 @@ impl BomPath {
         old
     }
 
+    /// Per-architecture records for Mach-O binaries.
+    ///
+    /// Empty for paths that aren't Mach-O binaries.
+    pub fn architectures(&self) -> &[BomPathRecordArchitecture] {
+        &self.architectures
+    }
+
+    /// Set the per-architecture records for this path.
+    pub fn set_architectures(
+        &mut self,
+        value: Vec<BomPathRecordArchitecture>,
+    ) -> Vec<BomPathRecordArchitecture> {
+        std::mem::replace(&mut self.architectures, value)
+    }
+
     /// The path that this link refers to.
     pub fn link_name(&self) -> Option<&str> {
         self.link_name.as_deref()
@@ -263,15 +280,9 @@ impl BomPath {
 
     /// The path that this link refers to, as a [CString].
     pub fn link_name_cstring(&self) -> Option<CString> {
-        if let Some(link_name) = &self.link_name {
-            let mut data = Vec::<u8>::with_capacity(link_name.as_bytes().len() + 1);
-            data.extend(link_name.as_bytes());
-            data.push(0);
-
-            Some(CString::new(data).expect("should be valid C string"))
-        } else {
-            None
-        }
+        self.link_name
+            .as_ref()
+            .map(|link_name| CString::new(link_name.as_str()).expect("should be valid C string"))
     }
 
     /// Set the link name for this path.
@@ -115,15 +115,10 @@ impl DirectoryBundle {
 
         // Frameworks must have a `Resources/Info.plist`. It is tempting to look for the
         // `.framework` extension as well. However
-        let (package_type, info_plist_path) = if framework_plist.is_file() {
-            (BundlePackageType::Framework, framework_plist)
+        let (is_framework, info_plist_path) = if framework_plist.is_file() {
+            (true, framework_plist)
         } else if app_plist.is_file() {
-            if root_name.ends_with(".app") {
-                (BundlePackageType::App, app_plist)
-            } else {
-                // This can definitely lead to false positives.
-                (BundlePackageType::Bundle, app_plist)
-            }
+            (false, app_plist)
         } else {
             return Err(anyhow!("Info.plist not found; not a valid bundle"));
         };
@@ -135,6 +130,34 @@ impl DirectoryBundle {
             .into_dictionary()
             .ok_or_else(|| anyhow!("{} is not a dictionary", info_plist_path.display()))?;
 
+        let package_type = if is_framework {
+            BundlePackageType::Framework
+        } else {
+            let extension_type = Path::new(&root_name)
+                .extension()
+                .and_then(|x| x.to_str())
+                .and_then(BundlePackageType::from_path_extension);
+            let code_type = info_plist
+                .get("CFBundlePackageType")
+                .and_then(|x| x.as_string())
+                .and_then(BundlePackageType::from_package_type_code);
+
+            // The directory extension is the most specific indicator of the bundle
+            // type. Fall back to CFBundlePackageType for extensionless bundles. But
+            // never trust it for applications and frameworks, as those have
+            // well-defined extensions and structure and a stray Info.plist would
+            // lead to false positives.
+            match (extension_type, code_type) {
+                (Some(t), _) => t,
+                (None, Some(BundlePackageType::App | BundlePackageType::Framework)) => {
+                    // This can definitely lead to false positives.
+                    BundlePackageType::Bundle
+                }
+                (None, Some(t)) => t,
+                (None, None) => BundlePackageType::Bundle,
+            }
+        };
+
         Ok(Self {
             root: directory.to_path_buf(),
             root_name,
@@ -176,8 +199,8 @@ impl DirectoryBundle {
     /// Obtain the path to the `Info.plist` file.
     pub fn info_plist_path(&self) -> PathBuf {
         match self.package_type {
-            BundlePackageType::App | BundlePackageType::Bundle => self.resolve_path("Info.plist"),
             BundlePackageType::Framework => self.root.join("Resources").join("Info.plist"),
+            _ => self.resolve_path("Info.plist"),
         }
     }
 
@@ -260,6 +283,49 @@ impl DirectoryBundle {
         }
     }
 
+    /// Obtain the `NSExtension` dictionary from the `Info.plist`.
+    ///
+    /// This dictionary is defined by app extensions and describes how the
+    /// extension integrates with its host.
+    pub fn ns_extension(&self) -> Result<Option<&plist::Dictionary>> {
+        if let Some(value) = self.info_plist.get("NSExtension") {
+            Ok(Some(value.as_dictionary().ok_or_else(|| {
+                anyhow!("NSExtension is not a dictionary")
+            })?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Obtain the identifier of the extension point this bundle plugs into.
+    ///
+    /// This retrieves `NSExtensionPointIdentifier` from the `NSExtension`
+    /// dictionary or, for ExtensionKit extensions, `EXExtensionPointIdentifier`
+    /// from the `EXAppExtensionAttributes` dictionary.
+    pub fn extension_point_identifier(&self) -> Result<Option<String>> {
+        for (dict_key, key) in [
+            ("NSExtension", "NSExtensionPointIdentifier"),
+            ("EXAppExtensionAttributes", "EXExtensionPointIdentifier"),
+        ] {
+            if let Some(value) = self.info_plist.get(dict_key) {
+                let dict = value
+                    .as_dictionary()
+                    .ok_or_else(|| anyhow!("{} is not a dictionary", dict_key))?;
+
+                if let Some(value) = dict.get(key) {
+                    return Ok(Some(
+                        value
+                            .as_string()
+                            .ok_or_else(|| anyhow!("{} is not a string", key))?
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Obtain all files within this bundle.
     ///
     /// The iteration order is deterministic.
@@ -304,7 +370,7 @@ impl DirectoryBundle {
 
     /// Obtain all files in this bundle as a [FileManifest].
     pub fn files_manifest(&self, traverse_nested: bool) -> Result<FileManifest> {
-        let mut m = FileManifest::default();
+        let mut m = FileManifest::new_with_links();
 
         for f in self.files(traverse_nested)? {
             m.add_file_entry(f.relative_path(), f.as_file_entry()?)?;
@@ -635,6 +701,42 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn shallow_framework() -> Result<()> {
+        let (_temp, td) = temp_dir()?;
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert("CFBundleIdentifier".into(), "com.example.fw".into());
+        dict.insert("CFBundlePackageType".into(), "FMWK".into());
+        let info_plist = plist::Value::from(dict);
+
+        // Info.plist at the root, as on iOS.
+        let root = td.join("Shallow.framework");
+        create_dir_all(root.join("Headers"))?;
+        create_dir_all(root.join("Modules"))?;
+        info_plist.to_file_xml(root.join("Info.plist"))?;
+        std::fs::write(root.join("Shallow"), b"binary")?;
+        std::fs::write(root.join("Headers/Shallow.h"), b"")?;
+        std::fs::write(root.join("Modules/module.modulemap"), b"")?;
+
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert_eq!(bundle.package_type(), BundlePackageType::Framework);
+        assert!(bundle.shallow());
+        assert_eq!(bundle.identifier()?, Some("com.example.fw".to_string()));
+        assert!(bundle.nested_bundles(true)?.is_empty());
+
+        // The .framework extension wins over the Contents/ layout.
+        let root = td.join("Contents.framework");
+        create_dir_all(root.join("Contents"))?;
+        info_plist.to_file_xml(root.join("Contents/Info.plist"))?;
+
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert_eq!(bundle.package_type(), BundlePackageType::Framework);
+        assert_eq!(bundle.identifier()?, Some("com.example.fw".to_string()));
+
+        Ok(())
+    }
+
     #[test]
     fn simple_bundle() -> Result<()> {
         let (_temp, td) = temp_dir()?;
@@ -652,6 +754,54 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn package_types() -> Result<()> {
+        let (_temp, td) = temp_dir()?;
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert("CFBundlePackageType".into(), "XPC!".into());
+        let mut extension = plist::Dictionary::new();
+        extension.insert(
+            "NSExtensionPointIdentifier".into(),
+            "com.apple.widgetkit-extension".into(),
+        );
+        dict.insert("NSExtension".into(), extension.into());
+        let xpc_plist = plist::Value::from(dict);
+
+        for (name, package_type) in [
+            ("Widget.appex", BundlePackageType::AppExtension),
+            ("Service.xpc", BundlePackageType::XpcService),
+            ("Driver.kext", BundlePackageType::KernelExtension),
+            ("Thing.plugin", BundlePackageType::Plugin),
+            ("Preview.qlgenerator", BundlePackageType::QuickLookGenerator),
+            ("Import.mdimporter", BundlePackageType::SpotlightImporter),
+            ("Net.systemextension", BundlePackageType::SystemExtension),
+            ("Driver.dext", BundlePackageType::DriverExtension),
+            ("Stuff.bundle", BundlePackageType::Bundle),
+            // No recognized extension falls back to CFBundlePackageType.
+            ("Service", BundlePackageType::XpcService),
+        ] {
+            let root = td.join(name);
+            let contents = root.join("Contents");
+            create_dir_all(&contents)?;
+            xpc_plist.to_file_xml(contents.join("Info.plist"))?;
+
+            let bundle = DirectoryBundle::new_from_path(&root)?;
+            assert_eq!(bundle.package_type(), package_type, "{}", name);
+            assert_eq!(bundle.info_plist_path(), contents.join("Info.plist"));
+        }
+
+        let bundle = DirectoryBundle::new_from_path(&td.join("Widget.appex"))?;
+        assert!(bundle.package_type().is_extension());
+        assert!(bundle.ns_extension()?.is_some());
+        assert_eq!(
+            bundle.extension_point_identifier()?,
+            Some("com.apple.widgetkit-extension".to_string())
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn framework_in_app() -> Result<()> {
         let (_temp, td) = temp_dir()?;
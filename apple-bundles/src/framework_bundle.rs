@@ -0,0 +1,459 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Framework Bundles
+
+See https://developer.apple.com/library/archive/documentation/MacOSX/Conceptual/BPFrameworks/Concepts/FrameworkAnatomy.html
+for documentation of the framework bundle format.
+
+macOS frameworks are *versioned*:
+
+```text
+MyFramework.framework/
+  MyFramework -> Versions/Current/MyFramework
+  Headers -> Versions/Current/Headers
+  Modules -> Versions/Current/Modules
+  Resources -> Versions/Current/Resources
+  Versions/
+    A/
+      MyFramework
+      Headers/
+      Modules/
+        module.modulemap
+      Resources/
+        Info.plist
+    Current -> A
+```
+
+Frameworks on iOS and its derived platforms are *shallow*: there is no
+`Versions/` directory, and the binary, `Info.plist`, and resources live at
+the root of the `.framework` directory.
+*/
+
+use {
+    crate::BundlePackageType,
+    anyhow::{anyhow, Context, Result},
+    simple_file_manifest::{FileEntry, FileManifest, FileManifestError},
+    std::path::{Path, PathBuf},
+};
+
+/// The directory layout of a framework bundle.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameworkLayout {
+    /// Versioned layout used on macOS.
+    Versioned,
+    /// Shallow layout used on iOS, tvOS, and watchOS.
+    Shallow,
+}
+
+/// Primitive used to iteratively construct a framework bundle.
+///
+/// Files are added relative to the framework *version* directory
+/// (`Versions/<version>/`), regardless of the layout. e.g. resources are
+/// added under `Resources/`. Paths are translated to their final location
+/// when the bundle is materialized. For the shallow layout, `Resources/` is
+/// flattened into the framework root.
+///
+/// # Examples
+///
+/// ```
+/// use apple_bundles::{FrameworkBundleBuilder, FrameworkLayout};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut builder = FrameworkBundleBuilder::new("MyFramework", FrameworkLayout::Versioned)?;
+///
+/// builder.set_info_plist_required_keys("com.example.my-framework", "1.0");
+/// builder.set_binary(vec![42])?;
+/// builder.add_file_headers("MyFramework.h", b"void hello(void);\n".to_vec())?;
+/// builder.set_umbrella_module_map("MyFramework.h")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FrameworkBundleBuilder {
+    /// Name of the framework.
+    name: String,
+
+    /// The layout of the framework.
+    layout: FrameworkLayout,
+
+    /// Name of the version directory in versioned frameworks.
+    version: String,
+
+    /// The `Info.plist` content.
+    info_plist: plist::Dictionary,
+
+    /// Files relative to the version directory.
+    files: FileManifest,
+}
+
+impl FrameworkBundleBuilder {
+    /// Create a new framework bundle builder.
+    ///
+    /// The `Info.plist` will be populated with keys defining the bundle name,
+    /// package type, and main executable.
+    pub fn new(name: impl ToString, layout: FrameworkLayout) -> Result<Self> {
+        let name = name.to_string();
+
+        if name.is_empty() || name.contains('/') {
+            return Err(anyhow!("invalid framework name: {:?}", name));
+        }
+
+        let mut info_plist = plist::Dictionary::new();
+        info_plist.insert("CFBundleName".into(), name.clone().into());
+        info_plist.insert("CFBundleExecutable".into(), name.clone().into());
+        info_plist.insert(
+            "CFBundlePackageType".into(),
+            BundlePackageType::Framework.to_string().into(),
+        );
+
+        Ok(Self {
+            name,
+            layout,
+            version: "A".to_string(),
+            info_plist,
+            files: FileManifest::default(),
+        })
+    }
+
+    /// The name of the framework.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The layout of the framework.
+    pub fn layout(&self) -> FrameworkLayout {
+        self.layout
+    }
+
+    /// The name of the version directory used by the versioned layout.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Set the name of the version directory used by the versioned layout.
+    ///
+    /// Defaults to `A`.
+    pub fn set_version(&mut self, version: impl ToString) -> Result<()> {
+        let version = version.to_string();
+
+        if version.is_empty() || version.contains('/') || version == "Current" {
+            return Err(anyhow!("invalid framework version: {:?}", version));
+        }
+
+        self.version = version;
+
+        Ok(())
+    }
+
+    /// Obtain the `Info.plist` content.
+    pub fn info_plist(&self) -> &plist::Dictionary {
+        &self.info_plist
+    }
+
+    /// Obtain the value of a key in the `Info.plist` file.
+    pub fn get_info_plist_key(&self, key: &str) -> Option<&plist::Value> {
+        self.info_plist.get(key)
+    }
+
+    /// Set the value of a key in the `Info.plist` file.
+    ///
+    /// If an existing key is replaced, `Some(Value)` will be returned.
+    pub fn set_info_plist_key(
+        &mut self,
+        key: impl ToString,
+        value: impl Into<plist::Value>,
+    ) -> Option<plist::Value> {
+        self.info_plist.insert(key.to_string(), value.into())
+    }
+
+    /// Defines required keys in the `Info.plist` file.
+    ///
+    /// `identifier` sets `CFBundleIdentifier`, the bundle identifier.
+    /// `version` sets `CFBundleVersion` and `CFBundleShortVersionString`.
+    pub fn set_info_plist_required_keys(
+        &mut self,
+        identifier: impl ToString,
+        version: impl ToString,
+    ) {
+        let version = version.to_string();
+
+        self.set_info_plist_key("CFBundleIdentifier", identifier.to_string());
+        self.set_info_plist_key("CFBundleVersion", version.clone());
+        self.set_info_plist_key("CFBundleShortVersionString", version);
+    }
+
+    /// Add a file to the framework.
+    ///
+    /// The path is relative to the framework version directory.
+    pub fn add_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.files.add_file_entry(path, entry)
+    }
+
+    /// Set the framework's binary.
+    ///
+    /// This is typically a dynamic library. The file is named after the
+    /// framework and marked as executable.
+    pub fn set_binary(&mut self, entry: impl Into<FileEntry>) -> Result<(), FileManifestError> {
+        let mut entry = entry.into();
+        entry.set_executable(true);
+
+        self.add_file(self.name.clone(), entry)
+    }
+
+    /// Add a file to the `Resources/` directory.
+    pub fn add_file_resources(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(PathBuf::from("Resources").join(path), entry)
+    }
+
+    /// Add a file to the `Headers/` directory.
+    pub fn add_file_headers(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(PathBuf::from("Headers").join(path), entry)
+    }
+
+    /// Add a file to the `Modules/` directory.
+    pub fn add_file_modules(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(PathBuf::from("Modules").join(path), entry)
+    }
+
+    /// Write a `Modules/module.modulemap` exporting an umbrella header.
+    ///
+    /// `umbrella_header` is the path of the header within `Headers/`. It should
+    /// have been added via [Self::add_file_headers()].
+    pub fn set_umbrella_module_map(&mut self, umbrella_header: &str) -> Result<()> {
+        if !self
+            .files
+            .has_path(PathBuf::from("Headers").join(umbrella_header))
+        {
+            return Err(anyhow!(
+                "umbrella header {} not present in Headers/",
+                umbrella_header
+            ));
+        }
+
+        let module_map = format!(
+            "framework module {} {{\n  umbrella header \"{}\"\n\n  export *\n  module * {{ export * }}\n}}\n",
+            self.name, umbrella_header
+        );
+
+        Ok(self.add_file_modules("module.modulemap", module_map.into_bytes())?)
+    }
+
+    /// Resolve the final path of a file relative to the framework root.
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        match self.layout {
+            FrameworkLayout::Versioned => PathBuf::from("Versions").join(&self.version).join(path),
+            FrameworkLayout::Shallow => {
+                if let Ok(stripped) = path.strip_prefix("Resources") {
+                    stripped.to_path_buf()
+                } else {
+                    path.to_path_buf()
+                }
+            }
+        }
+    }
+
+    /// Obtain the [FileManifest] of the materialized framework.
+    ///
+    /// Paths are relative to the `.framework` directory. The manifest includes
+    /// the `Info.plist` and, for versioned frameworks, the `Versions/Current`
+    /// and top-level alias symlinks.
+    pub fn manifest(&self) -> Result<FileManifest> {
+        let mut m = FileManifest::new_with_links();
+
+        for (path, entry) in self.files.iter_entries() {
+            m.add_file_entry(self.resolve_path(path), entry.clone())?;
+        }
+
+        let mut info_plist = vec![];
+        plist::Value::from(self.info_plist.clone())
+            .to_writer_xml(&mut info_plist)
+            .context("serializing Info.plist")?;
+        m.add_file_entry(
+            self.resolve_path(Path::new("Resources/Info.plist")),
+            info_plist,
+        )?;
+
+        if self.layout == FrameworkLayout::Versioned {
+            m.add_symlink("Versions/Current", &self.version)?;
+
+            let mut aliases = vec![];
+            if self.files.has_path(&self.name) {
+                aliases.push(self.name.as_str());
+            }
+            for dir in ["Headers", "Modules", "Resources"] {
+                // Resources always exists because it holds the Info.plist.
+                if dir == "Resources"
+                    || self
+                        .files
+                        .iter_entries()
+                        .any(|(path, _)| path.starts_with(dir))
+                {
+                    aliases.push(dir);
+                }
+            }
+
+            for alias in aliases {
+                m.add_symlink(alias, format!("Versions/Current/{alias}"))?;
+            }
+        }
+
+        Ok(m)
+    }
+
+    /// Materialize this framework to the specified directory.
+    ///
+    /// All files comprising this framework will be written to a directory
+    /// named `<name>.framework` in the directory specified. The path of this
+    /// directory will be returned.
+    ///
+    /// If the destination framework directory exists, it is removed first so
+    /// the symlinks in versioned frameworks can be created.
+    pub fn materialize_bundle(&self, dest_dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let bundle_dir = dest_dir.as_ref().join(format!("{}.framework", self.name));
+
+        self.manifest()?
+            .materialize_files_with_replace(&bundle_dir)
+            .context("materializing FileManifest")?;
+
+        Ok(bundle_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::DirectoryBundle};
+
+    fn builder(layout: FrameworkLayout) -> Result<FrameworkBundleBuilder> {
+        let mut builder = FrameworkBundleBuilder::new("MyFramework", layout)?;
+        builder.set_info_plist_required_keys("com.example.my-framework", "1.0");
+        builder.set_binary(vec![42])?;
+        builder.add_file_headers("MyFramework.h", vec![42])?;
+        builder.add_file_resources("data.txt", vec![42])?;
+        builder.set_umbrella_module_map("MyFramework.h")?;
+
+        Ok(builder)
+    }
+
+    fn paths(m: &FileManifest) -> Vec<String> {
+        m.iter_entries()
+            .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn versioned_layout() -> Result<()> {
+        let builder = builder(FrameworkLayout::Versioned)?;
+        let m = builder.manifest()?;
+
+        assert_eq!(
+            paths(&m),
+            vec![
+                "Headers",
+                "Modules",
+                "MyFramework",
+                "Resources",
+                "Versions/A/Headers/MyFramework.h",
+                "Versions/A/Modules/module.modulemap",
+                "Versions/A/MyFramework",
+                "Versions/A/Resources/Info.plist",
+                "Versions/A/Resources/data.txt",
+                "Versions/Current",
+            ]
+        );
+        assert_eq!(
+            m.get("Versions/Current").unwrap().link_target(),
+            Some(Path::new("A"))
+        );
+        assert_eq!(
+            m.get("MyFramework").unwrap().link_target(),
+            Some(Path::new("Versions/Current/MyFramework"))
+        );
+        assert!(m.get("Versions/A/MyFramework").unwrap().is_executable());
+
+        let module_map = String::from_utf8(
+            m.get("Versions/A/Modules/module.modulemap")
+                .unwrap()
+                .resolve_content()?,
+        )?;
+        assert!(module_map.starts_with("framework module MyFramework {"));
+        assert!(module_map.contains("umbrella header \"MyFramework.h\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shallow_layout() -> Result<()> {
+        let builder = builder(FrameworkLayout::Shallow)?;
+
+        assert_eq!(
+            paths(&builder.manifest()?),
+            vec![
+                "Headers/MyFramework.h",
+                "Info.plist",
+                "Modules/module.modulemap",
+                "MyFramework",
+                "data.txt",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bad_module_map() -> Result<()> {
+        let mut builder = FrameworkBundleBuilder::new("MyFramework", FrameworkLayout::Shallow)?;
+        assert!(builder.set_umbrella_module_map("MyFramework.h").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn materialize() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        for (layout, dir) in [
+            (FrameworkLayout::Versioned, "versioned"),
+            (FrameworkLayout::Shallow, "shallow"),
+        ] {
+            let builder = builder(layout)?;
+            builder.materialize_bundle(td.path().join(dir))?;
+            // Materializing again replaces the existing bundle.
+            let path = builder.materialize_bundle(td.path().join(dir))?;
+
+            let bundle = DirectoryBundle::new_from_path(&path)?;
+            assert_eq!(bundle.package_type(), BundlePackageType::Framework);
+            assert_eq!(
+                bundle.identifier()?,
+                Some("com.example.my-framework".to_string())
+            );
+
+            if layout == FrameworkLayout::Versioned {
+                assert_eq!(bundle.framework_versions()?, vec!["A".to_string()]);
+                assert_eq!(bundle.validate()?, vec![]);
+            }
+        }
+
+        Ok(())
+    }
+}
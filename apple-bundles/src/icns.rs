@@ -0,0 +1,527 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Apple Icon Image (`.icns`) files
+
+An `.icns` file is a `icns` magic and big-endian `u32` total length followed
+by a sequence of elements. Each element has a 4 byte OSType, a big-endian
+`u32` length (including the 8 byte element header), and data.
+
+Modern icon representations (`ic07` through `ic14`) hold PNG images. This
+module can read and write any element type but only knows how to produce
+the PNG based types. This allows producing icons for application bundles
+without Apple's `iconutil`.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    std::io::Write,
+};
+
+/// Magic bytes at the start of an `.icns` file.
+const ICNS_MAGIC: &[u8; 4] = b"icns";
+
+/// PNG based icon element types.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum IcnsIconType {
+    /// 128x128.
+    Ic07,
+    /// 256x256.
+    Ic08,
+    /// 512x512.
+    Ic09,
+    /// 1024x1024 (512x512@2x).
+    Ic10,
+    /// 32x32 (16x16@2x).
+    Ic11,
+    /// 64x64 (32x32@2x).
+    Ic12,
+    /// 256x256 (128x128@2x).
+    Ic13,
+    /// 512x512 (256x256@2x).
+    Ic14,
+}
+
+impl IcnsIconType {
+    /// All PNG based icon types, in the order they are written.
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Ic07,
+            Self::Ic08,
+            Self::Ic09,
+            Self::Ic10,
+            Self::Ic11,
+            Self::Ic12,
+            Self::Ic13,
+            Self::Ic14,
+        ]
+    }
+
+    /// The OSType of this element.
+    pub fn ostype(&self) -> [u8; 4] {
+        *match self {
+            Self::Ic07 => b"ic07",
+            Self::Ic08 => b"ic08",
+            Self::Ic09 => b"ic09",
+            Self::Ic10 => b"ic10",
+            Self::Ic11 => b"ic11",
+            Self::Ic12 => b"ic12",
+            Self::Ic13 => b"ic13",
+            Self::Ic14 => b"ic14",
+        }
+    }
+
+    /// Resolve an instance from an OSType.
+    pub fn from_ostype(ostype: &[u8; 4]) -> Option<Self> {
+        Self::all().iter().find(|x| &x.ostype() == ostype).copied()
+    }
+
+    /// The width and height of the image in pixels.
+    pub fn pixel_size(&self) -> u32 {
+        match self {
+            Self::Ic11 => 32,
+            Self::Ic12 => 64,
+            Self::Ic07 => 128,
+            Self::Ic08 | Self::Ic13 => 256,
+            Self::Ic09 | Self::Ic14 => 512,
+            Self::Ic10 => 1024,
+        }
+    }
+}
+
+/// An element in an `.icns` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IcnsElement {
+    /// The OSType of the element.
+    pub ostype: [u8; 4],
+    /// The raw element data.
+    pub data: Vec<u8>,
+}
+
+/// An `.icns` file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IcnsFile {
+    elements: Vec<IcnsElement>,
+}
+
+impl IcnsFile {
+    /// Parse `.icns` file data.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || &data[0..4] != ICNS_MAGIC {
+            return Err(anyhow!("not an icns file"));
+        }
+
+        let length = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        if length > data.len() || length < 8 {
+            return Err(anyhow!("icns file length {} is invalid", length));
+        }
+
+        let mut elements = vec![];
+        let mut offset = 8;
+
+        while offset < length {
+            if offset + 8 > length {
+                return Err(anyhow!("truncated icns element header at {}", offset));
+            }
+
+            let ostype = [
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ];
+            let element_length = u32::from_be_bytes([
+                data[offset + 4],
+                data[offset + 5],
+                data[offset + 6],
+                data[offset + 7],
+            ]) as usize;
+
+            if element_length < 8 || offset + element_length > length {
+                return Err(anyhow!(
+                    "icns element {} has invalid length {}",
+                    String::from_utf8_lossy(&ostype),
+                    element_length
+                ));
+            }
+
+            elements.push(IcnsElement {
+                ostype,
+                data: data[offset + 8..offset + element_length].to_vec(),
+            });
+
+            offset += element_length;
+        }
+
+        Ok(Self { elements })
+    }
+
+    /// Build an instance from PNG images.
+    ///
+    /// For every [IcnsIconType], an image having the exact dimensions of the
+    /// icon type is used if available. Otherwise, if `resize` is true, the
+    /// smallest image larger than the icon type (or the largest image if none
+    /// are larger) is resampled to the required size. If `resize` is false, it
+    /// is an error for any icon type to lack a matching image.
+    ///
+    /// All source images must be square.
+    pub fn from_png_images(images: &[impl AsRef<[u8]>], resize: bool) -> Result<Self> {
+        let mut decoded = images
+            .iter()
+            .map(|data| {
+                let image = RgbaImage::decode_png(data.as_ref())?;
+                if image.width != image.height {
+                    return Err(anyhow!(
+                        "icon images must be square; got {}x{}",
+                        image.width,
+                        image.height
+                    ));
+                }
+
+                Ok((data.as_ref(), image))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if decoded.is_empty() {
+            return Err(anyhow!("at least 1 image is required"));
+        }
+
+        decoded.sort_by_key(|(_, image)| image.width);
+
+        let mut res = Self::default();
+
+        for icon_type in IcnsIconType::all() {
+            let size = icon_type.pixel_size();
+
+            let png = if let Some((data, _)) = decoded.iter().find(|(_, x)| x.width == size) {
+                data.to_vec()
+            } else if resize {
+                let (_, source) = decoded
+                    .iter()
+                    .find(|(_, x)| x.width > size)
+                    .unwrap_or_else(|| decoded.last().expect("images is not empty"));
+
+                source.resize(size, size).encode_png()?
+            } else {
+                return Err(anyhow!(
+                    "no {}x{} image for {}; provide one or enable resizing",
+                    size,
+                    size,
+                    String::from_utf8_lossy(&icon_type.ostype())
+                ));
+            };
+
+            res.set_png(*icon_type, png)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Elements in this file.
+    pub fn elements(&self) -> &[IcnsElement] {
+        &self.elements
+    }
+
+    /// Obtain the data of the element having the specified OSType.
+    pub fn get(&self, ostype: &[u8; 4]) -> Option<&[u8]> {
+        self.elements
+            .iter()
+            .find(|x| &x.ostype == ostype)
+            .map(|x| x.data.as_slice())
+    }
+
+    /// Obtain the PNG data for an icon type.
+    pub fn get_png(&self, icon_type: IcnsIconType) -> Option<&[u8]> {
+        self.get(&icon_type.ostype())
+    }
+
+    /// Set the data of an element, replacing an existing element of the same OSType.
+    pub fn set(&mut self, ostype: [u8; 4], data: Vec<u8>) {
+        if let Some(element) = self.elements.iter_mut().find(|x| x.ostype == ostype) {
+            element.data = data;
+        } else {
+            self.elements.push(IcnsElement { ostype, data });
+        }
+    }
+
+    /// Set the PNG image for an icon type.
+    ///
+    /// Errors if the data isn't a PNG having the dimensions of the icon type.
+    pub fn set_png(&mut self, icon_type: IcnsIconType, data: Vec<u8>) -> Result<()> {
+        let (width, height) = png_dimensions(&data)?;
+        let size = icon_type.pixel_size();
+
+        if width != size || height != size {
+            return Err(anyhow!(
+                "{} requires a {}x{} image; got {}x{}",
+                String::from_utf8_lossy(&icon_type.ostype()),
+                size,
+                size,
+                width,
+                height
+            ));
+        }
+
+        self.set(icon_type.ostype(), data);
+
+        Ok(())
+    }
+
+    /// Write the `.icns` file to a writer.
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        let length = 8 + self
+            .elements
+            .iter()
+            .map(|x| 8 + x.data.len())
+            .sum::<usize>();
+
+        let length = u32::try_from(length).context("icns file too large")?;
+
+        writer.write_all(ICNS_MAGIC)?;
+        writer.write_all(&length.to_be_bytes())?;
+
+        for element in &self.elements {
+            writer.write_all(&element.ostype)?;
+            writer.write_all(&(8 + element.data.len() as u32).to_be_bytes())?;
+            writer.write_all(&element.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Obtain the serialized `.icns` file data.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        self.write(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+/// Obtain the width and height of a PNG image.
+pub fn png_dimensions(data: &[u8]) -> Result<(u32, u32)> {
+    let reader = png::Decoder::new(data)
+        .read_info()
+        .context("reading PNG header")?;
+    let info = reader.info();
+
+    Ok((info.width, info.height))
+}
+
+/// An 8-bit RGBA image.
+struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    fn decode_png(data: &[u8]) -> Result<Self> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().context("reading PNG header")?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).context("decoding PNG")?;
+        let buf = &buf[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf.to_vec(),
+            png::ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|p| [*p, *p, *p, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(anyhow!("indexed PNG was not expanded"));
+            }
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>> {
+        let mut data = vec![];
+
+        {
+            let mut encoder = png::Encoder::new(&mut data, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.pixels)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Resample to new dimensions using area averaging.
+    ///
+    /// Each destination pixel is the coverage weighted average of the source
+    /// pixels it overlaps. Colors are weighted by alpha so transparent pixels
+    /// don't bleed into their neighbors.
+    fn resize(&self, width: u32, height: u32) -> Self {
+        let scale_x = self.width as f64 / width as f64;
+        let scale_y = self.height as f64 / height as f64;
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+        for y in 0..height {
+            let y0 = y as f64 * scale_y;
+            let y1 = y0 + scale_y;
+
+            for x in 0..width {
+                let x0 = x as f64 * scale_x;
+                let x1 = x0 + scale_x;
+
+                let mut sums = [0f64; 4];
+                let mut total = 0f64;
+
+                let mut sy = y0.floor() as u32;
+                while (sy as f64) < y1 && sy < self.height {
+                    let wy = (y1.min(sy as f64 + 1.0) - y0.max(sy as f64)).max(0.0);
+
+                    let mut sx = x0.floor() as u32;
+                    while (sx as f64) < x1 && sx < self.width {
+                        let wx = (x1.min(sx as f64 + 1.0) - x0.max(sx as f64)).max(0.0);
+                        let weight = wx * wy;
+
+                        let offset = (sy as usize * self.width as usize + sx as usize) * 4;
+                        let p = &self.pixels[offset..offset + 4];
+                        let alpha = p[3] as f64;
+
+                        sums[0] += p[0] as f64 * alpha * weight;
+                        sums[1] += p[1] as f64 * alpha * weight;
+                        sums[2] += p[2] as f64 * alpha * weight;
+                        sums[3] += alpha * weight;
+                        total += weight;
+
+                        sx += 1;
+                    }
+
+                    sy += 1;
+                }
+
+                let alpha_sum = sums[3];
+                for channel in &sums[0..3] {
+                    let value = if alpha_sum > 0.0 {
+                        channel / alpha_sum
+                    } else {
+                        0.0
+                    };
+                    pixels.push(value.round().clamp(0.0, 255.0) as u8);
+                }
+                let alpha = if total > 0.0 { alpha_sum / total } else { 0.0 };
+                pixels.push(alpha.round().clamp(0.0, 255.0) as u8);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_png(size: u32, rgba: [u8; 4]) -> Vec<u8> {
+        RgbaImage {
+            width: size,
+            height: size,
+            pixels: rgba
+                .iter()
+                .copied()
+                .cycle()
+                .take(size as usize * size as usize * 4)
+                .collect(),
+        }
+        .encode_png()
+        .unwrap()
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let mut icns = IcnsFile::default();
+        icns.set_png(IcnsIconType::Ic07, solid_png(128, [255, 0, 0, 255]))?;
+        icns.set(*b"info", vec![1, 2, 3]);
+        assert!(icns
+            .set_png(IcnsIconType::Ic08, solid_png(128, [0, 0, 0, 0]))
+            .is_err());
+
+        let data = icns.to_vec()?;
+        assert_eq!(&data[0..4], b"icns");
+        assert_eq!(
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            data.len()
+        );
+
+        let parsed = IcnsFile::parse(&data)?;
+        assert_eq!(parsed, icns);
+        assert_eq!(parsed.get(b"info"), Some([1u8, 2, 3].as_slice()));
+        assert_eq!(
+            png_dimensions(parsed.get_png(IcnsIconType::Ic07).unwrap())?,
+            (128, 128)
+        );
+
+        assert!(IcnsFile::parse(b"icns\0\0\0\x10ic07\0\0\0\x20").is_err());
+        assert!(IcnsFile::parse(b"nope").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_png_images() -> Result<()> {
+        let source = solid_png(1024, [10, 20, 30, 255]);
+
+        assert!(IcnsFile::from_png_images(&[&source], false).is_err());
+
+        let icns = IcnsFile::from_png_images(&[&source], true)?;
+        assert_eq!(icns.elements().len(), 8);
+
+        for icon_type in IcnsIconType::all() {
+            let data = icns.get_png(*icon_type).unwrap();
+            let image = RgbaImage::decode_png(data)?;
+            assert_eq!(image.width, icon_type.pixel_size());
+            assert_eq!(&image.pixels[0..4], &[10, 20, 30, 255]);
+        }
+
+        // The exact size image is used verbatim.
+        assert_eq!(icns.get_png(IcnsIconType::Ic10), Some(source.as_slice()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resize_alpha() {
+        // Half transparent red, half opaque blue.
+        let mut pixels = vec![];
+        for x in 0..2 {
+            if x == 0 {
+                pixels.extend([255, 0, 0, 0]);
+            } else {
+                pixels.extend([0, 0, 255, 255]);
+            }
+        }
+        let image = RgbaImage {
+            width: 2,
+            height: 1,
+            pixels,
+        };
+
+        let resized = image.resize(1, 1);
+        // Transparent red does not bleed into the color.
+        assert_eq!(resized.pixels, vec![0, 0, 255, 128]);
+    }
+}
@@ -0,0 +1,603 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! iOS, tvOS, and watchOS Application Bundles
+
+Application bundles on iOS and its derived platforms are *shallow*: there is
+no `Contents/` directory and the `Info.plist`, main executable, and resources
+all live at the root of the `.app` directory.
+
+See https://developer.apple.com/documentation/bundleresources/information_property_list
+for documentation of the `Info.plist` keys used by these platforms.
+*/
+
+use {
+    crate::BundlePackageType,
+    anyhow::{anyhow, Context, Result},
+    simple_file_manifest::{FileEntry, FileManifest, FileManifestError},
+    std::path::{Path, PathBuf},
+};
+
+/// An Apple platform derived from iOS.
+///
+/// Each platform has a device and a simulator variant, as the two are
+/// distinct in `CFBundleSupportedPlatforms`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IosFamilyPlatform {
+    /// iOS / iPadOS devices.
+    IPhoneOs,
+    /// The iOS simulator.
+    IPhoneSimulator,
+    /// tvOS devices.
+    AppleTvOs,
+    /// The tvOS simulator.
+    AppleTvSimulator,
+    /// watchOS devices.
+    WatchOs,
+    /// The watchOS simulator.
+    WatchSimulator,
+}
+
+impl IosFamilyPlatform {
+    /// The value of this platform in `CFBundleSupportedPlatforms`.
+    pub fn as_supported_platform(&self) -> &'static str {
+        match self {
+            Self::IPhoneOs => "iPhoneOS",
+            Self::IPhoneSimulator => "iPhoneSimulator",
+            Self::AppleTvOs => "AppleTVOS",
+            Self::AppleTvSimulator => "AppleTVSimulator",
+            Self::WatchOs => "WatchOS",
+            Self::WatchSimulator => "WatchSimulator",
+        }
+    }
+
+    /// The device families an application on this platform targets by default.
+    pub fn default_device_families(&self) -> &'static [DeviceFamily] {
+        match self {
+            Self::IPhoneOs | Self::IPhoneSimulator => &[DeviceFamily::IPhone, DeviceFamily::IPad],
+            Self::AppleTvOs | Self::AppleTvSimulator => &[DeviceFamily::AppleTv],
+            Self::WatchOs | Self::WatchSimulator => &[DeviceFamily::AppleWatch],
+        }
+    }
+}
+
+/// A device family, as defined by the `UIDeviceFamily` `Info.plist` key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeviceFamily {
+    /// iPhone and iPod touch.
+    IPhone,
+    /// iPad.
+    IPad,
+    /// Apple TV.
+    AppleTv,
+    /// Apple Watch.
+    AppleWatch,
+}
+
+impl DeviceFamily {
+    /// The integer value of this family in `UIDeviceFamily`.
+    pub fn as_integer(&self) -> u64 {
+        match self {
+            Self::IPhone => 1,
+            Self::IPad => 2,
+            Self::AppleTv => 3,
+            Self::AppleWatch => 4,
+        }
+    }
+}
+
+/// Primitive used to iteratively construct an iOS, tvOS, or watchOS Application Bundle.
+///
+/// This is the shallow bundle equivalent of [crate::MacOsApplicationBundleBuilder].
+/// Paths are relative to the root of the `.app` directory, as there is no
+/// `Contents/` directory in these bundles.
+///
+/// The most important takeaways about the layout are:
+///
+/// 1. The `Info.plist` must contain some required keys defining the bundle.
+///    Call `set_info_plist_required_keys()` and `set_platform()` to ensure
+///    these are defined.
+/// 2. The main executable lives at the root of the bundle. Add it via
+///    `add_executable()`.
+/// 3. App extensions live in `PlugIns/<Name>.appex` and embedded frameworks
+///    in `Frameworks/`.
+///
+/// # Examples
+///
+/// ```
+/// use apple_bundles::{IosApplicationBundleBuilder, IosFamilyPlatform};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut builder = IosApplicationBundleBuilder::new("MyProgram")?;
+///
+/// builder.set_info_plist_required_keys("My Program", "com.example.my_program", "0.1", "MyProgram")?;
+/// builder.set_platform(IosFamilyPlatform::IPhoneOs)?;
+/// builder.set_minimum_os_version("15.0")?;
+///
+/// builder.add_executable("MyProgram", vec![42])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct IosApplicationBundleBuilder {
+    /// Files constituting the application bundle.
+    files: FileManifest,
+}
+
+impl IosApplicationBundleBuilder {
+    /// Create a new iOS-family Application Bundle builder.
+    ///
+    /// The bundle will be populated with a skeleton `Info.plist` file
+    /// defining the bundle name passed.
+    pub fn new(bundle_name: impl ToString) -> Result<Self> {
+        let mut instance = Self {
+            files: FileManifest::default(),
+        };
+
+        instance
+            .set_info_plist_key("CFBundleName", bundle_name.to_string())
+            .context("setting CFBundleName")?;
+
+        // This is an application bundle, so CFBundlePackageType is constant.
+        instance
+            .set_info_plist_key("CFBundlePackageType", BundlePackageType::App.to_string())
+            .context("setting CFBundlePackageType")?;
+
+        Ok(instance)
+    }
+
+    /// Obtain the raw FileManifest backing this builder.
+    pub fn files(&self) -> &FileManifest {
+        &self.files
+    }
+
+    /// Obtain the name of the bundle.
+    ///
+    /// This will parse the stored `Info.plist` and return the value of the
+    /// `CFBundleName` key.
+    pub fn bundle_name(&self) -> Result<String> {
+        Ok(self
+            .get_info_plist_key("CFBundleName")
+            .context("resolving CFBundleName")?
+            .ok_or_else(|| anyhow!("CFBundleName key not defined"))?
+            .as_string()
+            .ok_or_else(|| anyhow!("CFBundleName is not a string"))?
+            .to_string())
+    }
+
+    /// Obtain the parsed content of the `Info.plist` file.
+    ///
+    /// Returns `Some(T)` if an `Info.plist` is defined or `None` if not.
+    ///
+    /// Returns `Err` if the file content could not be resolved or fails to parse
+    /// as a plist dictionary.
+    pub fn info_plist(&self) -> Result<Option<plist::Dictionary>> {
+        if let Some(entry) = self.files.get("Info.plist") {
+            let data = entry.resolve_content().context("resolving file content")?;
+            let cursor = std::io::Cursor::new(data);
+
+            let value = plist::Value::from_reader(cursor).context("parsing plist")?;
+
+            if let Some(dict) = value.into_dictionary() {
+                Ok(Some(dict))
+            } else {
+                Err(anyhow!("parsed plist is not a dictionary"))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Add a file to this application bundle.
+    ///
+    /// The path specified will be added without any checking, replacing
+    /// an existing file at that path, if present.
+    pub fn add_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.files.add_file_entry(path, entry)
+    }
+
+    /// Set the content of `Info.plist` using a `plist::Dictionary`.
+    pub fn set_info_plist_from_dictionary(&mut self, value: plist::Dictionary) -> Result<()> {
+        let mut data: Vec<u8> = vec![];
+
+        let value = plist::Value::from(value);
+
+        value
+            .to_writer_xml(&mut data)
+            .context("serializing plist dictionary to XML")?;
+
+        Ok(self.add_file("Info.plist", data)?)
+    }
+
+    /// Obtain the value of a key in the `Info.plist` file.
+    ///
+    /// Returns `Some(Value)` if the key exists, `None` otherwise.
+    pub fn get_info_plist_key(&self, key: &str) -> Result<Option<plist::Value>> {
+        Ok(
+            if let Some(dict) = self.info_plist().context("parsing Info.plist")? {
+                dict.get(key).cloned()
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Set the value of a key in the `Info.plist` file.
+    ///
+    /// If an existing key is replaced, `Some(Value)` will be returned.
+    pub fn set_info_plist_key(
+        &mut self,
+        key: impl ToString,
+        value: impl Into<plist::Value>,
+    ) -> Result<Option<plist::Value>> {
+        let mut dict = if let Some(dict) = self.info_plist().context("retrieving Info.plist")? {
+            dict
+        } else {
+            plist::Dictionary::new()
+        };
+
+        let old = dict.insert(key.to_string(), value.into());
+
+        self.set_info_plist_from_dictionary(dict)
+            .context("replacing Info.plist dictionary")?;
+
+        Ok(old)
+    }
+
+    /// Defines required keys in the `Info.plist` file.
+    ///
+    /// The following keys are set:
+    ///
+    /// `display_name` sets `CFBundleDisplayName`, the bundle display name.
+    /// `identifier` sets `CFBundleIdentifier`, the bundle identifier.
+    /// `version` sets `CFBundleVersion`, the bundle version string.
+    /// `executable` sets `CFBundleExecutable`, the name of the main executable file.
+    pub fn set_info_plist_required_keys(
+        &mut self,
+        display_name: impl ToString,
+        identifier: impl ToString,
+        version: impl ToString,
+        executable: impl ToString,
+    ) -> Result<()> {
+        self.set_info_plist_key("CFBundleDisplayName", display_name.to_string())
+            .context("setting CFBundleDisplayName")?;
+        self.set_info_plist_key("CFBundleIdentifier", identifier.to_string())
+            .context("setting CFBundleIdentifier")?;
+        self.set_info_plist_key("CFBundleVersion", version.to_string())
+            .context("setting CFBundleVersion")?;
+        self.set_info_plist_key("CFBundleExecutable", executable.to_string())
+            .context("setting CFBundleExecutable")?;
+
+        Ok(())
+    }
+
+    /// Define the platform this bundle targets.
+    ///
+    /// This sets `CFBundleSupportedPlatforms` to the platform and `UIDeviceFamily`
+    /// to the platform's default device families. Call `set_device_families()`
+    /// afterwards to target a different set of devices.
+    pub fn set_platform(&mut self, platform: IosFamilyPlatform) -> Result<()> {
+        self.set_info_plist_key(
+            "CFBundleSupportedPlatforms",
+            plist::Value::Array(vec![platform.as_supported_platform().into()]),
+        )
+        .context("setting CFBundleSupportedPlatforms")?;
+
+        self.set_device_families(platform.default_device_families())
+    }
+
+    /// Set the `UIDeviceFamily` key defining the devices this application runs on.
+    pub fn set_device_families(&mut self, families: &[DeviceFamily]) -> Result<()> {
+        if families.is_empty() {
+            return Err(anyhow!("at least 1 device family must be specified"));
+        }
+
+        self.set_info_plist_key(
+            "UIDeviceFamily",
+            plist::Value::Array(
+                families
+                    .iter()
+                    .map(|x| plist::Value::Integer(x.as_integer().into()))
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .context("setting UIDeviceFamily")?;
+
+        Ok(())
+    }
+
+    /// Set the `MinimumOSVersion` key defining the minimum OS version required to run.
+    pub fn set_minimum_os_version(&mut self, version: impl ToString) -> Result<()> {
+        self.set_info_plist_key("MinimumOSVersion", version.to_string())
+            .context("setting MinimumOSVersion")?;
+
+        Ok(())
+    }
+
+    /// Set the `UIRequiredDeviceCapabilities` key.
+    ///
+    /// Values are capability names, such as `arm64` or `metal`.
+    pub fn set_required_device_capabilities(
+        &mut self,
+        capabilities: impl IntoIterator<Item = impl ToString>,
+    ) -> Result<()> {
+        self.set_info_plist_key(
+            "UIRequiredDeviceCapabilities",
+            plist::Value::Array(
+                capabilities
+                    .into_iter()
+                    .map(|x| plist::Value::from(x.to_string()))
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .context("setting UIRequiredDeviceCapabilities")?;
+
+        Ok(())
+    }
+
+    /// Add the main executable for the bundle.
+    ///
+    /// The file is placed at the root of the bundle and marked as executable.
+    pub fn add_executable(
+        &mut self,
+        path: impl AsRef<Path>,
+        data: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        let mut entry = data.into();
+        entry.set_executable(true);
+
+        self.add_file(path, entry)
+    }
+
+    /// Add the provisioning profile to embed in the bundle.
+    ///
+    /// This materializes as the `embedded.mobileprovision` file.
+    pub fn add_embedded_provisioning_profile(
+        &mut self,
+        data: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file("embedded.mobileprovision", data)
+    }
+
+    /// Add a compiled asset catalog.
+    ///
+    /// This materializes as the `Assets.car` file produced by `actool`. If
+    /// `icon_name` is defined, `CFBundleIconName` is set so the application
+    /// icon is resolved from the catalog.
+    pub fn add_asset_catalog(
+        &mut self,
+        data: impl Into<FileEntry>,
+        icon_name: Option<&str>,
+    ) -> Result<()> {
+        self.add_file("Assets.car", data)?;
+
+        if let Some(name) = icon_name {
+            self.set_info_plist_key("CFBundleIconName", name)
+                .context("setting CFBundleIconName")?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a localized resources file.
+    ///
+    /// The file is placed in the `<locale>.lproj/` directory at the root of
+    /// the bundle.
+    pub fn add_localized_resources_file(
+        &mut self,
+        locale: impl ToString,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(
+            PathBuf::from(format!("{}.lproj", locale.to_string())).join(path),
+            entry,
+        )
+    }
+
+    /// Add a file to the `Frameworks/` directory.
+    ///
+    /// The passed path will be prefixed with `Frameworks/`.
+    pub fn add_file_frameworks(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(PathBuf::from("Frameworks").join(path), entry)
+    }
+
+    /// Add a file to the `PlugIns/` directory.
+    ///
+    /// The passed path will be prefixed with `PlugIns/`.
+    pub fn add_file_plugins(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<(), FileManifestError> {
+        self.add_file(PathBuf::from("PlugIns").join(path), entry)
+    }
+
+    /// Add an app extension bundle.
+    ///
+    /// All files in `files` are placed under `PlugIns/<name>.appex/`.
+    pub fn add_app_extension(&mut self, name: &str, files: &FileManifest) -> Result<()> {
+        let root = PathBuf::from(format!("{name}.appex"));
+
+        for (path, entry) in files.iter_entries() {
+            self.add_file_plugins(root.join(path), entry.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Add an embedded framework bundle.
+    ///
+    /// All files in `files` are placed under `Frameworks/<name>.framework/`.
+    pub fn add_framework(&mut self, name: &str, files: &FileManifest) -> Result<()> {
+        let root = PathBuf::from(format!("{name}.framework"));
+
+        for (path, entry) in files.iter_entries() {
+            self.add_file_frameworks(root.join(path), entry.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Materialize this bundle to the specified directory.
+    ///
+    /// All files comprising this bundle will be written to a directory named
+    /// `<bundle_name>.app` in the directory specified. The path of this directory
+    /// will be returned.
+    ///
+    /// If the destination bundle directory exists, existing files will be
+    /// overwritten. Files already in the destination not defined in this
+    /// builder will not be touched.
+    pub fn materialize_bundle(&self, dest_dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let bundle_name = self.bundle_name().context("resolving bundle name")?;
+        let bundle_dir = dest_dir.as_ref().join(format!("{bundle_name}.app"));
+
+        self.files
+            .materialize_files(&bundle_dir)
+            .context("materializing FileManifest")?;
+
+        Ok(bundle_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::DirectoryBundle};
+
+    #[test]
+    fn new_plist() -> Result<()> {
+        let builder = IosApplicationBundleBuilder::new("MyProgram")?;
+
+        let entries = builder.files().iter_entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, &PathBuf::from("Info.plist"));
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert("CFBundleName".to_string(), "MyProgram".to_string().into());
+        dict.insert("CFBundlePackageType".to_string(), "APPL".to_string().into());
+
+        assert_eq!(builder.info_plist()?, Some(dict));
+
+        Ok(())
+    }
+
+    #[test]
+    fn platform_keys() -> Result<()> {
+        let mut builder = IosApplicationBundleBuilder::new("MyProgram")?;
+
+        builder.set_platform(IosFamilyPlatform::AppleTvOs)?;
+        builder.set_minimum_os_version("16.0")?;
+        builder.set_required_device_capabilities(["arm64"])?;
+
+        let dict = builder.info_plist()?.unwrap();
+        assert_eq!(
+            dict.get("CFBundleSupportedPlatforms"),
+            Some(&plist::Value::Array(vec!["AppleTVOS".into()]))
+        );
+        assert_eq!(
+            dict.get("UIDeviceFamily"),
+            Some(&plist::Value::Array(vec![plist::Value::Integer(3.into())]))
+        );
+        assert_eq!(
+            dict.get("MinimumOSVersion"),
+            Some(&plist::Value::from("16.0"))
+        );
+        assert_eq!(
+            dict.get("UIRequiredDeviceCapabilities"),
+            Some(&plist::Value::Array(vec!["arm64".into()]))
+        );
+
+        builder.set_device_families(&[DeviceFamily::IPhone, DeviceFamily::IPad])?;
+        assert_eq!(
+            builder.get_info_plist_key("UIDeviceFamily")?,
+            Some(plist::Value::Array(vec![
+                plist::Value::Integer(1.into()),
+                plist::Value::Integer(2.into())
+            ]))
+        );
+        assert!(builder.set_device_families(&[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn layout() -> Result<()> {
+        let mut builder = IosApplicationBundleBuilder::new("MyProgram")?;
+        builder.set_info_plist_required_keys(
+            "My Program",
+            "com.example.my_program",
+            "0.1",
+            "MyProgram",
+        )?;
+
+        builder.add_executable("MyProgram", vec![42])?;
+        builder.add_embedded_provisioning_profile(vec![42])?;
+        builder.add_asset_catalog(vec![42], Some("AppIcon"))?;
+
+        let mut extension = FileManifest::default();
+        extension.add_file_entry("Info.plist", vec![42])?;
+        builder.add_app_extension("Widget", &extension)?;
+        builder.add_framework("MyLib", &extension)?;
+
+        assert!(builder.files().get("MyProgram").unwrap().is_executable());
+        assert_eq!(
+            builder
+                .files()
+                .iter_entries()
+                .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+                .collect::<Vec<_>>(),
+            vec![
+                "Assets.car",
+                "Frameworks/MyLib.framework/Info.plist",
+                "Info.plist",
+                "MyProgram",
+                "PlugIns/Widget.appex/Info.plist",
+                "embedded.mobileprovision",
+            ]
+        );
+        assert_eq!(
+            builder.get_info_plist_key("CFBundleIconName")?,
+            Some(plist::Value::from("AppIcon"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn materialize() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let mut builder = IosApplicationBundleBuilder::new("MyProgram")?;
+        builder.set_info_plist_required_keys(
+            "My Program",
+            "com.example.my_program",
+            "0.1",
+            "MyProgram",
+        )?;
+        builder.add_executable("MyProgram", vec![42])?;
+
+        let path = builder.materialize_bundle(td.path())?;
+        assert_eq!(path, td.path().join("MyProgram.app"));
+
+        let bundle = DirectoryBundle::new_from_path(&path)?;
+        assert!(bundle.shallow());
+        assert_eq!(bundle.package_type(), BundlePackageType::App);
+        assert_eq!(
+            bundle.identifier()?,
+            Some("com.example.my_program".to_string())
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! iOS App Store Packages (`.ipa` files)
+
+An IPA is a zip archive holding an application bundle in a `Payload/`
+directory:
+
+```text
+Payload/
+  MyApp.app/
+    Info.plist
+    MyApp
+    ...
+SwiftSupport/
+  iphoneos/
+    libswiftCore.dylib
+Symbols/
+  <UUID>.symbols
+```
+
+`SwiftSupport/` and `Symbols/` are optional. The former holds copies of the
+Swift runtime libraries for apps built against older Swift ABIs. The latter
+holds symbol files uploaded to App Store Connect for symbolication.
+
+Unix permissions and symlinks are stored in the zip's external file
+attributes and are preserved when extracting and writing archives.
+*/
+
+use {
+    crate::DirectoryBundle,
+    anyhow::{anyhow, Context, Result},
+    simple_file_manifest::{create_symlink, FileEntry, FileManifest},
+    std::{
+        io::{Read, Seek, Write},
+        path::{Component, Path, PathBuf},
+    },
+};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Convert a directory tree to a [FileManifest], preserving symlinks.
+fn directory_manifest(root: &Path) -> Result<FileManifest> {
+    let mut m = FileManifest::default();
+
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        let metadata = path.symlink_metadata()?;
+
+        let mut file =
+            FileEntry::new_from_path(path, simple_file_manifest::is_executable(&metadata));
+
+        if metadata.file_type().is_symlink() {
+            file.set_link_target(std::fs::read_link(path)?);
+        }
+
+        m.add_file_entry(path.strip_prefix(root)?, file)?;
+    }
+
+    Ok(m)
+}
+
+/// Resolve the Unix mode to store for a file in the zip archive.
+fn file_mode(entry: &FileEntry) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(metadata) = entry
+            .file_data()
+            .backing_path()
+            .and_then(|p| p.symlink_metadata().ok())
+        {
+            return metadata.permissions().mode() & 0o7777;
+        }
+    }
+
+    if entry.is_executable() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// Resolve the filesystem path to extract an archive member to.
+///
+/// Member names must be relative paths without `..` components. Missing parent
+/// directories are created. Parent directories which are symlinks or resolve
+/// outside `dest_dir` are rejected, as writing through them could modify
+/// arbitrary files.
+fn extraction_path(dest_dir: &Path, name: &str) -> Result<PathBuf> {
+    let components = Path::new(name)
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(c) => Ok(c),
+            _ => Err(anyhow!("illegal path in IPA: {}", name)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (file_name, dirs) = components
+        .split_last()
+        .ok_or_else(|| anyhow!("illegal path in IPA: {}", name))?;
+
+    let mut parent = dest_dir.to_path_buf();
+
+    for dir in dirs {
+        parent.push(dir);
+
+        match parent.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(anyhow!(
+                    "{} in IPA is beneath symlink {}",
+                    name,
+                    parent.display()
+                ));
+            }
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => return Err(anyhow!("{} is not a directory", parent.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => std::fs::create_dir(&parent)?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if !parent.canonicalize()?.starts_with(dest_dir.canonicalize()?) {
+        return Err(anyhow!(
+            "{} in IPA resolves outside {}",
+            name,
+            dest_dir.display()
+        ));
+    }
+
+    Ok(parent.join(file_name))
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// An IPA that has been extracted to the filesystem.
+#[derive(Clone, Debug)]
+pub struct ExtractedIpa {
+    root: PathBuf,
+    app: DirectoryBundle,
+}
+
+impl ExtractedIpa {
+    /// Extract an IPA archive into a directory.
+    ///
+    /// The directory will contain `Payload/`, and possibly `SwiftSupport/` and
+    /// `Symbols/`, directories. It is an error for the archive to not contain
+    /// exactly 1 `Payload/*.app` bundle.
+    ///
+    /// Archive members with absolute paths or `..` components are rejected, as are
+    /// members beneath symlinks. Symlinks are materialized after all other files
+    /// so that archive members cannot be written through them. Member content is
+    /// streamed to disk.
+    pub fn extract(reader: impl Read + Seek, dest_dir: impl AsRef<Path>) -> Result<Self> {
+        let dest_dir = dest_dir.as_ref();
+        let mut za = zip::ZipArchive::new(reader).context("opening zip archive")?;
+
+        std::fs::create_dir_all(dest_dir)?;
+
+        let mut symlinks = vec![];
+
+        for i in 0..za.len() {
+            let mut zf = za.by_index(i)?;
+
+            if zf.is_dir() {
+                let dest_path = extraction_path(dest_dir, zf.name())?;
+
+                if !dest_path.is_dir() {
+                    std::fs::create_dir(&dest_path)?;
+                }
+                continue;
+            }
+
+            let mode = zf.unix_mode();
+
+            if matches!(mode, Some(mode) if mode & S_IFMT == S_IFLNK) {
+                let mut target = String::new();
+                zf.read_to_string(&mut target)
+                    .with_context(|| format!("reading symlink target of {}", zf.name()))?;
+                symlinks.push((zf.name().to_string(), target));
+                continue;
+            }
+
+            let dest_path = extraction_path(dest_dir, zf.name())?;
+
+            let mut fh = std::fs::File::create(&dest_path)
+                .with_context(|| format!("creating {}", dest_path.display()))?;
+            std::io::copy(&mut zf, &mut fh)
+                .with_context(|| format!("writing {}", dest_path.display()))?;
+
+            if let Some(mode) = mode {
+                set_mode(&dest_path, mode)?;
+            }
+        }
+
+        for (name, target) in symlinks {
+            // Resolved after prior symlinks are created, so members beneath them
+            // are rejected.
+            let path = extraction_path(dest_dir, &name)?;
+
+            create_symlink(&path, &target)
+                .with_context(|| format!("creating symlink {}", path.display()))?;
+        }
+
+        Self::from_directory(dest_dir)
+    }
+
+    /// Resolve an instance from a directory holding an already extracted IPA.
+    pub fn from_directory(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let payload = root.join("Payload");
+
+        if !payload.is_dir() {
+            return Err(anyhow!("IPA does not contain a Payload directory"));
+        }
+
+        let mut apps = vec![];
+        for entry in std::fs::read_dir(&payload)? {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() && entry.file_name().to_string_lossy().ends_with(".app")
+            {
+                apps.push(entry.path());
+            }
+        }
+
+        let app = match apps.as_slice() {
+            [app] => DirectoryBundle::new_from_path(app).context("opening application bundle")?,
+            [] => return Err(anyhow!("no .app bundle in IPA Payload directory")),
+            _ => return Err(anyhow!("multiple .app bundles in IPA Payload directory")),
+        };
+
+        Ok(Self { root, app })
+    }
+
+    /// The directory the IPA is extracted to.
+    pub fn root_dir(&self) -> &Path {
+        &self.root
+    }
+
+    /// The application bundle in `Payload/`.
+    pub fn app(&self) -> &DirectoryBundle {
+        &self.app
+    }
+
+    /// Path to the `SwiftSupport/` directory, if present.
+    pub fn swift_support_dir(&self) -> Option<PathBuf> {
+        let path = self.root.join("SwiftSupport");
+        path.is_dir().then_some(path)
+    }
+
+    /// Path to the `Symbols/` directory, if present.
+    pub fn symbols_dir(&self) -> Option<PathBuf> {
+        let path = self.root.join("Symbols");
+        path.is_dir().then_some(path)
+    }
+
+    /// Obtain an [IpaBuilder] that will repackage this IPA.
+    ///
+    /// The application bundle is re-read from disk when the archive is
+    /// written, so modifications (such as re-signing) made to the extracted
+    /// bundle after this call are captured.
+    pub fn to_builder(&self) -> Result<IpaBuilder> {
+        let mut builder = IpaBuilder::new(self.app.clone());
+
+        if let Some(path) = self.swift_support_dir() {
+            builder.swift_support = directory_manifest(&path)?;
+        }
+        if let Some(path) = self.symbols_dir() {
+            builder.symbols = directory_manifest(&path)?;
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Primitive used to construct an IPA archive.
+#[derive(Clone, Debug)]
+pub struct IpaBuilder {
+    app: DirectoryBundle,
+    swift_support: FileManifest,
+    symbols: FileManifest,
+}
+
+impl IpaBuilder {
+    /// Construct a new instance packaging the given application bundle.
+    pub fn new(app: DirectoryBundle) -> Self {
+        Self {
+            app,
+            swift_support: FileManifest::default(),
+            symbols: FileManifest::default(),
+        }
+    }
+
+    /// Add a file to the `SwiftSupport/` directory.
+    ///
+    /// Paths are typically of the form `<platform>/libswiftCore.dylib`.
+    pub fn add_swift_support_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<()> {
+        Ok(self.swift_support.add_file_entry(path, entry)?)
+    }
+
+    /// Add a file to the `Symbols/` directory.
+    pub fn add_symbols_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+    ) -> Result<()> {
+        Ok(self.symbols.add_file_entry(path, entry)?)
+    }
+
+    /// Options for archive members.
+    ///
+    /// Modified times are fixed so archives don't depend on when they are written.
+    fn file_options() -> zip::write::FileOptions {
+        zip::write::FileOptions::default().last_modified_time(zip::DateTime::default())
+    }
+
+    fn write_entry<W: Write + Seek>(
+        zf: &mut zip::ZipWriter<W>,
+        name: String,
+        entry: &FileEntry,
+    ) -> Result<()> {
+        let options = Self::file_options();
+
+        if let Some(target) = entry.link_target() {
+            zf.add_symlink(name, target.to_string_lossy().replace('\\', "/"), options)?;
+        } else {
+            zf.start_file(name, options.unix_permissions(file_mode(entry)))?;
+
+            if let Some(path) = entry.file_data().backing_path() {
+                let mut fh = std::fs::File::open(path)
+                    .with_context(|| format!("opening {}", path.display()))?;
+                std::io::copy(&mut fh, zf)?;
+            } else {
+                zf.write_all(&entry.resolve_content()?)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the IPA archive to a writer.
+    ///
+    /// Archive members are emitted in a deterministic order with fixed modified
+    /// times. So writing the same content produces identical archives.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<W> {
+        let mut zf = zip::ZipWriter::new(writer);
+        let dir_options = Self::file_options();
+
+        zf.add_directory("Payload/", dir_options)?;
+
+        for file in self.app.files(true)? {
+            let name = format!(
+                "Payload/{}/{}",
+                self.app.name(),
+                file.relative_path().display()
+            )
+            .replace('\\', "/");
+
+            Self::write_entry(&mut zf, name, &file.as_file_entry()?)?;
+        }
+
+        for (prefix, manifest) in [
+            ("SwiftSupport", &self.swift_support),
+            ("Symbols", &self.symbols),
+        ] {
+            if manifest.is_empty() {
+                continue;
+            }
+
+            zf.add_directory(format!("{prefix}/"), dir_options)?;
+
+            for (path, entry) in manifest.iter_entries() {
+                let name = format!("{}/{}", prefix, path.display()).replace('\\', "/");
+                Self::write_entry(&mut zf, name, entry)?;
+            }
+        }
+
+        Ok(zf.finish()?)
+    }
+
+    /// Write the IPA archive to a file.
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let fh =
+            std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        self.write(fh)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::IosApplicationBundleBuilder};
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let mut builder = IosApplicationBundleBuilder::new("MyApp")?;
+        builder.set_info_plist_required_keys("My App", "com.example.myapp", "1.0", "MyApp")?;
+        builder.add_executable("MyApp", vec![42])?;
+        builder.add_file("resource.txt", vec![42])?;
+        let bundle_path = builder.materialize_bundle(td.path().join("source"))?;
+        create_symlink(bundle_path.join("link.txt"), "resource.txt")?;
+
+        let mut ipa = IpaBuilder::new(DirectoryBundle::new_from_path(&bundle_path)?);
+        ipa.add_swift_support_file("iphoneos/libswiftCore.dylib", vec![42])?;
+        ipa.add_symbols_file("ABCD.symbols", vec![42])?;
+
+        let data = ipa.write(std::io::Cursor::new(vec![]))?.into_inner();
+
+        let za = zip::ZipArchive::new(std::io::Cursor::new(&data))?;
+        assert_eq!(
+            za.file_names().collect::<std::collections::BTreeSet<_>>(),
+            [
+                "Payload/",
+                "Payload/MyApp.app/Info.plist",
+                "Payload/MyApp.app/MyApp",
+                "Payload/MyApp.app/link.txt",
+                "Payload/MyApp.app/resource.txt",
+                "SwiftSupport/",
+                "SwiftSupport/iphoneos/libswiftCore.dylib",
+                "Symbols/",
+                "Symbols/ABCD.symbols",
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        let dest = td.path().join("extracted");
+        let extracted = ExtractedIpa::extract(std::io::Cursor::new(&data), &dest)?;
+        let app = extracted.app();
+        assert_eq!(app.name(), "MyApp.app");
+        assert_eq!(app.identifier()?, Some("com.example.myapp".to_string()));
+        assert!(extracted.swift_support_dir().is_some());
+        assert!(extracted.symbols_dir().is_some());
+
+        let link = app.root_dir().join("link.txt");
+        assert!(link.symlink_metadata()?.file_type().is_symlink());
+        assert_eq!(std::fs::read_link(link)?, PathBuf::from("resource.txt"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            for name in ["MyApp", "resource.txt"] {
+                let source = bundle_path.join(name).metadata()?.permissions().mode();
+                let mode = app.root_dir().join(name).metadata()?.permissions().mode();
+                assert_eq!(mode & 0o7777, source & 0o7777);
+            }
+        }
+
+        let repacked = extracted
+            .to_builder()?
+            .write(std::io::Cursor::new(vec![]))?
+            .into_inner();
+        let za = zip::ZipArchive::new(std::io::Cursor::new(&repacked))?;
+        assert_eq!(za.len(), 9);
+
+        let rewritten = extracted
+            .to_builder()?
+            .write(std::io::Cursor::new(vec![]))?
+            .into_inner();
+        assert_eq!(rewritten, repacked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_payload() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let mut zf = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zf.start_file("foo.txt", zip::write::FileOptions::default())?;
+        zf.write_all(b"foo")?;
+        let data = zf.finish()?.into_inner();
+
+        assert!(ExtractedIpa::extract(std::io::Cursor::new(data), td.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_paths() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+        let outside = td.path().join("outside");
+        std::fs::create_dir(&outside)?;
+
+        let archive = |entries: &[(&str, Option<&str>)]| -> Result<Vec<u8>> {
+            let mut zf = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+            let options = zip::write::FileOptions::default();
+
+            for (name, target) in entries {
+                if let Some(target) = target {
+                    zf.add_symlink(*name, *target, options)?;
+                } else {
+                    zf.start_file(*name, options)?;
+                    zf.write_all(b"evil")?;
+                }
+            }
+
+            Ok(zf.finish()?.into_inner())
+        };
+
+        let outside_target = outside.to_string_lossy().to_string();
+        let cases = [
+            vec![("Payload/../../evil", None)],
+            vec![("Payload/MyApp.app/../evil", None)],
+            vec![("/evil", None)],
+            // A symlink to outside the destination followed by members beneath it.
+            vec![
+                ("Payload/MyApp.app/link", Some(outside_target.as_str())),
+                ("Payload/MyApp.app/link/evil", Some("target")),
+            ],
+            vec![
+                ("Payload/MyApp.app/link", Some(outside_target.as_str())),
+                ("Payload/MyApp.app/link/evil", None),
+            ],
+            vec![
+                ("Payload/MyApp.app/link", Some("../../../outside")),
+                ("Payload/MyApp.app/link/evil", Some("target")),
+            ],
+        ];
+
+        for (i, entries) in cases.iter().enumerate() {
+            let dest = td.path().join(format!("dest{i}"));
+
+            assert!(
+                ExtractedIpa::extract(std::io::Cursor::new(archive(entries)?), &dest).is_err(),
+                "{entries:?}"
+            );
+            assert!(!td.path().join("evil").exists());
+            assert_eq!(std::fs::read_dir(&outside)?.count(), 0, "{entries:?}");
+        }
+
+        Ok(())
+    }
+}
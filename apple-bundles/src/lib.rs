@@ -4,8 +4,20 @@
 
 mod directory_bundle;
 pub use directory_bundle::*;
+mod framework_bundle;
+pub use framework_bundle::*;
+pub mod icns;
+pub use icns::{IcnsElement, IcnsFile, IcnsIconType};
+mod ios_application_bundle;
+pub use ios_application_bundle::*;
+mod ipa;
+pub use ipa::*;
 mod macos_application_bundle;
 pub use macos_application_bundle::*;
+mod validation;
+pub use validation::*;
+mod xcframework;
+pub use xcframework::*;
 
 /// Denotes the type of a bundle.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -16,6 +28,22 @@ pub enum BundlePackageType {
     Framework,
     /// Generic bundle.
     Bundle,
+    /// App extension (`.appex`).
+    AppExtension,
+    /// XPC service (`.xpc`).
+    XpcService,
+    /// Kernel extension (`.kext`).
+    KernelExtension,
+    /// Loadable plug-in (`.plugin`).
+    Plugin,
+    /// Quick Look generator (`.qlgenerator`).
+    QuickLookGenerator,
+    /// Spotlight metadata importer (`.mdimporter`).
+    SpotlightImporter,
+    /// System extension (`.systemextension`).
+    SystemExtension,
+    /// DriverKit driver extension (`.dext`).
+    DriverExtension,
 }
 
 impl ToString for BundlePackageType {
@@ -23,8 +51,87 @@ impl ToString for BundlePackageType {
         match self {
             Self::App => "APPL",
             Self::Framework => "FMWK",
-            Self::Bundle => "BNDL",
+            Self::Bundle | Self::Plugin | Self::QuickLookGenerator | Self::SpotlightImporter => {
+                "BNDL"
+            }
+            Self::AppExtension | Self::XpcService => "XPC!",
+            Self::KernelExtension => "KEXT",
+            Self::SystemExtension => "SYSX",
+            Self::DriverExtension => "DEXT",
         }
         .to_string()
     }
 }
+
+impl BundlePackageType {
+    /// The conventional file extension of bundles of this type, without the leading `.`.
+    pub fn path_extension(&self) -> &'static str {
+        match self {
+            Self::App => "app",
+            Self::Framework => "framework",
+            Self::Bundle => "bundle",
+            Self::AppExtension => "appex",
+            Self::XpcService => "xpc",
+            Self::KernelExtension => "kext",
+            Self::Plugin => "plugin",
+            Self::QuickLookGenerator => "qlgenerator",
+            Self::SpotlightImporter => "mdimporter",
+            Self::SystemExtension => "systemextension",
+            Self::DriverExtension => "dext",
+        }
+    }
+
+    /// Resolve a bundle type from a bundle directory's file extension.
+    ///
+    /// The extension should not have a leading `.`.
+    pub fn from_path_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "app" => Some(Self::App),
+            "framework" => Some(Self::Framework),
+            "bundle" => Some(Self::Bundle),
+            "appex" => Some(Self::AppExtension),
+            "xpc" => Some(Self::XpcService),
+            "kext" => Some(Self::KernelExtension),
+            "plugin" => Some(Self::Plugin),
+            "qlgenerator" => Some(Self::QuickLookGenerator),
+            "mdimporter" => Some(Self::SpotlightImporter),
+            "systemextension" => Some(Self::SystemExtension),
+            "dext" => Some(Self::DriverExtension),
+            _ => None,
+        }
+    }
+
+    /// Resolve a bundle type from a `CFBundlePackageType` value.
+    ///
+    /// Several bundle types share a `CFBundlePackageType` value. e.g. app
+    /// extensions and XPC services are both `XPC!`. This function returns the
+    /// most generic type for ambiguous values. Use [Self::from_path_extension()]
+    /// to disambiguate.
+    pub fn from_package_type_code(code: &str) -> Option<Self> {
+        match code {
+            "APPL" => Some(Self::App),
+            "FMWK" => Some(Self::Framework),
+            "BNDL" => Some(Self::Bundle),
+            "XPC!" => Some(Self::XpcService),
+            "KEXT" => Some(Self::KernelExtension),
+            "SYSX" => Some(Self::SystemExtension),
+            "DEXT" => Some(Self::DriverExtension),
+            _ => None,
+        }
+    }
+
+    /// Whether this bundle type is a plug-in or extension hosted by another process.
+    pub fn is_extension(&self) -> bool {
+        matches!(
+            self,
+            Self::AppExtension
+                | Self::XpcService
+                | Self::KernelExtension
+                | Self::Plugin
+                | Self::QuickLookGenerator
+                | Self::SpotlightImporter
+                | Self::SystemExtension
+                | Self::DriverExtension
+        )
+    }
+}
@@ -9,7 +9,7 @@ for documentation of the macOS Application Bundle format.
 */
 
 use {
-    crate::BundlePackageType,
+    crate::{BundlePackageType, IcnsFile},
     anyhow::{anyhow, Context, Result},
     simple_file_manifest::{FileEntry, FileManifest, FileManifestError},
     std::path::{Path, PathBuf},
@@ -248,15 +248,35 @@ impl MacOsApplicationBundleBuilder {
     /// Add the icon for the bundle.
     ///
     /// This will materialize the passed raw image data (can be multiple formats)
-    /// into the `Contents/Resources/<BundleName>.icns` file.
+    /// into the `Contents/Resources/<BundleName>.icns` file and point
+    /// `CFBundleIconFile` and `CFBundleIconName` at it.
     pub fn add_icon(&mut self, data: impl Into<FileEntry>) -> Result<()> {
-        Ok(self.add_file_resources(
-            format!(
-                "{}.icns",
-                self.bundle_name().context("resolving bundle name")?
-            ),
-            data,
-        )?)
+        let icon_name = self.bundle_name().context("resolving bundle name")?;
+        let icon_file = format!("{icon_name}.icns");
+
+        self.add_file_resources(&icon_file, data)?;
+        self.set_info_plist_key("CFBundleIconFile", icon_file)
+            .context("setting CFBundleIconFile")?;
+        self.set_info_plist_key("CFBundleIconName", icon_name)
+            .context("setting CFBundleIconName")?;
+
+        Ok(())
+    }
+
+    /// Add the icon for the bundle from PNG images.
+    ///
+    /// An `.icns` file is assembled from the images via
+    /// [crate::IcnsFile::from_png_images()] and added via [Self::add_icon()].
+    /// If `resize` is true, missing icon sizes are produced by resampling
+    /// the passed images.
+    pub fn add_icon_from_png_images(
+        &mut self,
+        images: &[impl AsRef<[u8]>],
+        resize: bool,
+    ) -> Result<()> {
+        let icns = IcnsFile::from_png_images(images, resize).context("building icns file")?;
+
+        self.add_icon(icns.to_vec()?)
     }
 
     /// Add a file to the `Contents/MacOS/` directory.
@@ -423,6 +443,14 @@ mod tests {
             entries[1].0,
             &PathBuf::from("Contents/Resources/MyProgram.icns")
         );
+        assert_eq!(
+            builder.get_info_plist_key("CFBundleIconFile")?,
+            Some(plist::Value::from("MyProgram.icns"))
+        );
+        assert_eq!(
+            builder.get_info_plist_key("CFBundleIconName")?,
+            Some(plist::Value::from("MyProgram"))
+        );
 
         Ok(())
     }
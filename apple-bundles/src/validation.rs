@@ -0,0 +1,703 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Bundle structure validation.
+
+[DirectoryBundle::validate()] looks for structural problems that cause
+`codesign` to fail or Gatekeeper to reject a bundle. Each reported
+[ValidationIssue] has a stable identifier, suitable for filtering in CI:
+
+| Identifier | Severity | Description |
+| --- | --- | --- |
+| `contents-root-file` | Error | Unexpected file directly in `Contents/` |
+| `macos-non-code` | Error | Non-code file in `Contents/MacOS/` |
+| `executable-key-missing` | Error / Warning | `CFBundleExecutable` not defined |
+| `executable-not-found` | Error | `CFBundleExecutable` file does not exist |
+| `executable-not-executable` | Error | Main executable lacks the executable bit |
+| `symlink-absolute` | Error | Symlink has an absolute target |
+| `symlink-broken` | Error | Symlink target does not exist |
+| `symlink-escapes-bundle` | Error | Symlink resolves outside the bundle |
+| `framework-current-missing` | Error | Framework lacks `Versions/Current` |
+| `framework-current-not-symlink` | Error | `Versions/Current` is not a symlink |
+| `framework-current-invalid` | Error | `Versions/Current` does not point to a version |
+| `info-plist-key-missing` | Error / Warning | Required `Info.plist` key not defined |
+| `info-plist-key-type` | Error | `Info.plist` key has the wrong type |
+| `info-plist-package-type-mismatch` | Warning | `CFBundlePackageType` disagrees with the bundle extension |
+| `identifier-invalid` | Error | `CFBundleIdentifier` contains illegal characters |
+| `path-forbidden-character` | Error / Warning | File name contains a problematic character |
+*/
+
+use {
+    crate::{BundlePackageType, DirectoryBundle},
+    anyhow::Result,
+    std::{
+        fmt::{Display, Formatter},
+        io::Read,
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Files allowed directly in the `Contents/` directory.
+const CONTENTS_ROOT_FILES: &[&str] = &[
+    "Info.plist",
+    "PkgInfo",
+    "CodeResources",
+    "embedded.provisionprofile",
+    "version.plist",
+];
+
+/// `Info.plist` keys that must have string values.
+const STRING_KEYS: &[&str] = &[
+    "CFBundleDisplayName",
+    "CFBundleExecutable",
+    "CFBundleIdentifier",
+    "CFBundleName",
+    "CFBundlePackageType",
+    "CFBundleShortVersionString",
+    "CFBundleVersion",
+    "LSMinimumSystemVersion",
+    "MinimumOSVersion",
+];
+
+/// `Info.plist` keys that must have array values.
+const ARRAY_KEYS: &[&str] = &[
+    "CFBundleIconFiles",
+    "CFBundleSupportedPlatforms",
+    "UIDeviceFamily",
+    "UIRequiredDeviceCapabilities",
+];
+
+/// `Info.plist` keys that must have dictionary values.
+const DICTIONARY_KEYS: &[&str] = &["NSExtension", "EXAppExtensionAttributes"];
+
+/// The severity of a [ValidationIssue].
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ValidationSeverity {
+    /// The bundle is suspicious but may still work.
+    Warning,
+    /// The bundle will likely fail to sign or be rejected.
+    Error,
+}
+
+impl Display for ValidationSeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found when validating a bundle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationIssue {
+    id: &'static str,
+    severity: ValidationSeverity,
+    path: Option<PathBuf>,
+    message: String,
+}
+
+impl ValidationIssue {
+    fn new(
+        id: &'static str,
+        severity: ValidationSeverity,
+        path: Option<PathBuf>,
+        message: impl ToString,
+    ) -> Self {
+        Self {
+            id,
+            severity,
+            path,
+            message: message.to_string(),
+        }
+    }
+
+    /// Stable identifier of the kind of problem.
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    /// The severity of this issue.
+    pub fn severity(&self) -> ValidationSeverity {
+        self.severity
+    }
+
+    /// The bundle relative path this issue applies to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Human readable description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}]", self.severity, self.id)?;
+
+        if let Some(path) = &self.path {
+            write!(f, " {}", path.display())?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Whether file content looks like Mach-O or a script.
+fn is_code(path: &Path) -> Result<bool> {
+    let mut header = [0u8; 4];
+
+    let mut fh = std::fs::File::open(path)?;
+    let mut read = 0;
+    while read < header.len() {
+        let count = fh.read(&mut header[read..])?;
+        if count == 0 {
+            break;
+        }
+        read += count;
+    }
+
+    if read >= 2 && &header[0..2] == b"#!" {
+        return Ok(true);
+    }
+
+    Ok(read == 4
+        && matches!(
+            u32::from_be_bytes(header),
+            0xfeedface | 0xfeedfacf | 0xcefaedfe | 0xcffaedfe | 0xcafebabe | 0xbebafeca
+        ))
+}
+
+#[cfg(unix)]
+fn is_executable_file(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable_file(_metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Normalize a path lexically, without consulting the filesystem.
+///
+/// Returns `None` if `..` components escape the root.
+fn normalize_relative(path: &Path) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !res.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(c) => res.push(c),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(res)
+}
+
+impl DirectoryBundle {
+    /// Validate the structure of this bundle.
+    ///
+    /// Returns all problems found. An empty result means no problems were
+    /// detected, not that the bundle is guaranteed to be valid.
+    ///
+    /// Only this bundle is validated: nested bundles are not traversed.
+    /// Validate them individually by iterating [Self::nested_bundles()].
+    pub fn validate(&self) -> Result<Vec<ValidationIssue>> {
+        let mut issues = vec![];
+
+        self.validate_info_plist(&mut issues);
+        self.validate_executable(&mut issues)?;
+        self.validate_files(&mut issues)?;
+
+        if self.package_type() == BundlePackageType::Framework {
+            self.validate_framework_versions(&mut issues)?;
+        }
+
+        Ok(issues)
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(self.root_dir())
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    fn validate_info_plist(&self, issues: &mut Vec<ValidationIssue>) {
+        let plist_path = Some(self.relative(&self.info_plist_path()));
+        let plist = self.info_plist();
+
+        for (key, keys_type, ok) in STRING_KEYS
+            .iter()
+            .map(|k| (*k, "string", plist.get(k).map(|v| v.as_string().is_some())))
+            .chain(
+                ARRAY_KEYS
+                    .iter()
+                    .map(|k| (*k, "array", plist.get(k).map(|v| v.as_array().is_some()))),
+            )
+            .chain(DICTIONARY_KEYS.iter().map(|k| {
+                (
+                    *k,
+                    "dictionary",
+                    plist.get(k).map(|v| v.as_dictionary().is_some()),
+                )
+            }))
+        {
+            if ok == Some(false) {
+                issues.push(ValidationIssue::new(
+                    "info-plist-key-type",
+                    ValidationSeverity::Error,
+                    plist_path.clone(),
+                    format!("{key} must be a {keys_type}"),
+                ));
+            }
+        }
+
+        for (key, severity) in [
+            ("CFBundleIdentifier", ValidationSeverity::Error),
+            ("CFBundlePackageType", ValidationSeverity::Warning),
+            ("CFBundleVersion", ValidationSeverity::Warning),
+        ] {
+            if !plist.contains_key(key) {
+                issues.push(ValidationIssue::new(
+                    "info-plist-key-missing",
+                    severity,
+                    plist_path.clone(),
+                    format!("{key} is not defined"),
+                ));
+            }
+        }
+
+        if let Some(identifier) = plist.get("CFBundleIdentifier").and_then(|v| v.as_string()) {
+            if identifier.is_empty()
+                || !identifier
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-'))
+            {
+                issues.push(ValidationIssue::new(
+                    "identifier-invalid",
+                    ValidationSeverity::Error,
+                    plist_path.clone(),
+                    format!(
+                        "CFBundleIdentifier {identifier:?} may only contain alphanumerics, '.', and '-'"
+                    ),
+                ));
+            }
+        }
+
+        if let Some(code) = plist.get("CFBundlePackageType").and_then(|v| v.as_string()) {
+            let expected = self.package_type().to_string();
+
+            if code != expected {
+                issues.push(ValidationIssue::new(
+                    "info-plist-package-type-mismatch",
+                    ValidationSeverity::Warning,
+                    plist_path,
+                    format!(
+                        "CFBundlePackageType is {code:?}; expected {expected:?} for a .{} bundle",
+                        self.package_type().path_extension()
+                    ),
+                ));
+            }
+        }
+    }
+
+    fn validate_executable(&self, issues: &mut Vec<ValidationIssue>) -> Result<()> {
+        let main = match self.info_plist().get("CFBundleExecutable") {
+            Some(value) => match value.as_string() {
+                Some(main) => main.to_string(),
+                // Type errors are reported by the Info.plist validation.
+                None => return Ok(()),
+            },
+            None => {
+                let severity = match self.package_type() {
+                    BundlePackageType::App
+                    | BundlePackageType::AppExtension
+                    | BundlePackageType::XpcService
+                    | BundlePackageType::SystemExtension
+                    | BundlePackageType::DriverExtension => Some(ValidationSeverity::Error),
+                    BundlePackageType::Framework => Some(ValidationSeverity::Warning),
+                    // Resource only bundles and codeless kexts are legal.
+                    _ => None,
+                };
+
+                if let Some(severity) = severity {
+                    issues.push(ValidationIssue::new(
+                        "executable-key-missing",
+                        severity,
+                        Some(self.relative(&self.info_plist_path())),
+                        "CFBundleExecutable is not defined",
+                    ));
+                }
+
+                return Ok(());
+            }
+        };
+
+        let path = if self.shallow() {
+            self.resolve_path(&main)
+        } else {
+            self.resolve_path(format!("MacOS/{main}"))
+        };
+
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {
+                if !is_executable_file(&metadata) {
+                    issues.push(ValidationIssue::new(
+                        "executable-not-executable",
+                        ValidationSeverity::Error,
+                        Some(self.relative(&path)),
+                        "main executable does not have the executable bit set",
+                    ));
+                }
+            }
+            _ => {
+                issues.push(ValidationIssue::new(
+                    "executable-not-found",
+                    ValidationSeverity::Error,
+                    Some(self.relative(&path)),
+                    format!("CFBundleExecutable {main:?} does not exist"),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_files(&self, issues: &mut Vec<ValidationIssue>) -> Result<()> {
+        let contents = self.root_dir().join("Contents");
+        let macos = contents.join("MacOS");
+
+        for file in self.files(false)? {
+            let relative = file.relative_path().to_path_buf();
+            let absolute = file.absolute_path();
+
+            for component in relative.components() {
+                let name = component.as_os_str();
+
+                match name.to_str() {
+                    None => {
+                        issues.push(ValidationIssue::new(
+                            "path-forbidden-character",
+                            ValidationSeverity::Error,
+                            Some(relative.clone()),
+                            "path is not valid UTF-8",
+                        ));
+                    }
+                    Some(name) if name.chars().any(|c| c.is_control()) => {
+                        issues.push(ValidationIssue::new(
+                            "path-forbidden-character",
+                            ValidationSeverity::Error,
+                            Some(relative.clone()),
+                            "path contains control characters",
+                        ));
+                    }
+                    Some(name) if name.contains(':') => {
+                        issues.push(ValidationIssue::new(
+                            "path-forbidden-character",
+                            ValidationSeverity::Warning,
+                            Some(relative.clone()),
+                            "path contains ':', which is displayed as '/' by Finder",
+                        ));
+                    }
+                    _ => continue,
+                }
+
+                break;
+            }
+
+            if let Some(target) = file.symlink_target()? {
+                self.validate_symlink(issues, &relative, absolute, &target);
+                continue;
+            }
+
+            if !self.shallow() && absolute.parent() == Some(&contents) {
+                let name = relative
+                    .file_name()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                if !CONTENTS_ROOT_FILES.contains(&name.as_str()) {
+                    issues.push(ValidationIssue::new(
+                        "contents-root-file",
+                        ValidationSeverity::Error,
+                        Some(relative.clone()),
+                        "files directly in Contents/ are not sealed by code signatures",
+                    ));
+                }
+            }
+
+            if !self.shallow() && absolute.starts_with(&macos) && !is_code(absolute)? {
+                issues.push(ValidationIssue::new(
+                    "macos-non-code",
+                    ValidationSeverity::Error,
+                    Some(relative.clone()),
+                    "Contents/MacOS/ should only contain Mach-O binaries and scripts; move this to Contents/Resources/",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_symlink(
+        &self,
+        issues: &mut Vec<ValidationIssue>,
+        relative: &Path,
+        absolute: &Path,
+        target: &Path,
+    ) {
+        if target.has_root() {
+            issues.push(ValidationIssue::new(
+                "symlink-absolute",
+                ValidationSeverity::Error,
+                Some(relative.to_path_buf()),
+                format!("symlink target {} is absolute", target.display()),
+            ));
+            return;
+        }
+
+        let resolved = relative
+            .parent()
+            .map(|p| normalize_relative(&p.join(target)))
+            .unwrap_or_default();
+
+        match resolved {
+            None => {
+                issues.push(ValidationIssue::new(
+                    "symlink-escapes-bundle",
+                    ValidationSeverity::Error,
+                    Some(relative.to_path_buf()),
+                    format!("symlink target {} is outside the bundle", target.display()),
+                ));
+            }
+            Some(_) if std::fs::metadata(absolute).is_err() => {
+                issues.push(ValidationIssue::new(
+                    "symlink-broken",
+                    ValidationSeverity::Error,
+                    Some(relative.to_path_buf()),
+                    format!("symlink target {} does not exist", target.display()),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn validate_framework_versions(&self, issues: &mut Vec<ValidationIssue>) -> Result<()> {
+        let versions = self.root_dir().join("Versions");
+
+        // Shallow (iOS) frameworks aren't versioned.
+        if !versions.is_dir() {
+            return Ok(());
+        }
+
+        let current = versions.join("Current");
+        let current_relative = Some(self.relative(&current));
+
+        let metadata = match current.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                issues.push(ValidationIssue::new(
+                    "framework-current-missing",
+                    ValidationSeverity::Error,
+                    current_relative,
+                    "versioned framework does not have a Versions/Current symlink",
+                ));
+                return Ok(());
+            }
+        };
+
+        if !metadata.file_type().is_symlink() {
+            issues.push(ValidationIssue::new(
+                "framework-current-not-symlink",
+                ValidationSeverity::Error,
+                current_relative,
+                "Versions/Current must be a symlink to a version directory",
+            ));
+            return Ok(());
+        }
+
+        let target = std::fs::read_link(&current)?;
+        let versions = self.framework_versions()?;
+
+        let valid = target.components().count() == 1
+            && versions
+                .iter()
+                .any(|v| Path::new(v) == target && v != "Current");
+
+        if !valid {
+            issues.push(ValidationIssue::new(
+                "framework-current-invalid",
+                ValidationSeverity::Error,
+                current_relative,
+                format!(
+                    "Versions/Current points to {}; expected one of {}",
+                    target.display(),
+                    versions.join(", ")
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs::create_dir_all};
+
+    fn write_plist(path: &Path, values: &[(&str, plist::Value)]) -> Result<()> {
+        let mut dict = plist::Dictionary::new();
+        for (k, v) in values {
+            dict.insert(k.to_string(), v.clone());
+        }
+
+        plist::Value::from(dict).to_file_xml(path)?;
+
+        Ok(())
+    }
+
+    fn ids(issues: &[ValidationIssue]) -> Vec<&'static str> {
+        issues.iter().map(|x| x.id()).collect()
+    }
+
+    #[cfg(unix)]
+    fn make_executable(path: &Path) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn valid_app() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let root = td.path().join("MyApp.app");
+        let macos = root.join("Contents/MacOS");
+        create_dir_all(&macos)?;
+        create_dir_all(root.join("Contents/Resources"))?;
+
+        write_plist(
+            &root.join("Contents/Info.plist"),
+            &[
+                ("CFBundleIdentifier", "com.example.myapp".into()),
+                ("CFBundlePackageType", "APPL".into()),
+                ("CFBundleVersion", "1.0".into()),
+                ("CFBundleExecutable", "MyApp".into()),
+            ],
+        )?;
+        std::fs::write(macos.join("MyApp"), b"#!/bin/sh\n")?;
+        make_executable(&macos.join("MyApp"))?;
+        std::fs::write(root.join("Contents/Resources/data.txt"), b"data")?;
+        std::os::unix::fs::symlink("data.txt", root.join("Contents/Resources/link.txt"))?;
+
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert_eq!(ids(&bundle.validate()?), vec![] as Vec<&str>);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn invalid_app() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let root = td.path().join("MyApp.app");
+        let macos = root.join("Contents/MacOS");
+        create_dir_all(&macos)?;
+
+        write_plist(
+            &root.join("Contents/Info.plist"),
+            &[
+                ("CFBundleIdentifier", "com.example/myapp".into()),
+                ("CFBundlePackageType", "BNDL".into()),
+                ("CFBundleVersion", 42.into()),
+                ("CFBundleExecutable", "MyApp".into()),
+            ],
+        )?;
+        std::fs::write(macos.join("MyApp"), b"#!/bin/sh\n")?;
+        std::fs::write(macos.join("config.json"), b"{}")?;
+        std::fs::write(root.join("Contents/stray.txt"), b"")?;
+        std::os::unix::fs::symlink("/etc/passwd", macos.join("absolute"))?;
+        std::os::unix::fs::symlink("../../../outside", macos.join("escape"))?;
+        std::os::unix::fs::symlink("missing", macos.join("broken"))?;
+
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        let issues = bundle.validate()?;
+
+        assert_eq!(
+            ids(&issues),
+            vec![
+                "info-plist-key-type",
+                "identifier-invalid",
+                "info-plist-package-type-mismatch",
+                "executable-not-executable",
+                "symlink-absolute",
+                "symlink-broken",
+                "macos-non-code",
+                "symlink-escapes-bundle",
+                "contents-root-file",
+            ]
+        );
+        assert_eq!(issues[2].severity(), ValidationSeverity::Warning);
+        assert_eq!(
+            issues[0].to_string(),
+            "error [info-plist-key-type] Contents/Info.plist: CFBundleVersion must be a string"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn framework_versions() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let root = td.path().join("MyFramework.framework");
+        let resources = root.join("Versions/A/Resources");
+        create_dir_all(&resources)?;
+        write_plist(
+            &resources.join("Info.plist"),
+            &[
+                ("CFBundleIdentifier", "com.example.framework".into()),
+                ("CFBundlePackageType", "FMWK".into()),
+                ("CFBundleVersion", "1.0".into()),
+            ],
+        )?;
+        std::os::unix::fs::symlink("Versions/A/Resources", root.join("Resources"))?;
+
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert_eq!(
+            ids(&bundle.validate()?),
+            vec!["executable-key-missing", "framework-current-missing"]
+        );
+
+        std::os::unix::fs::symlink("B", root.join("Versions/Current"))?;
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert!(ids(&bundle.validate()?).contains(&"framework-current-invalid"));
+
+        std::fs::remove_file(root.join("Versions/Current"))?;
+        std::os::unix::fs::symlink("A", root.join("Versions/Current"))?;
+        let bundle = DirectoryBundle::new_from_path(&root)?;
+        assert_eq!(ids(&bundle.validate()?), vec!["executable-key-missing"]);
+
+        Ok(())
+    }
+}
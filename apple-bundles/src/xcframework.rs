@@ -0,0 +1,695 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! XCFrameworks
+
+An XCFramework is a directory holding multiple *libraries*, each a framework
+bundle or a static/dynamic library for one platform and platform variant:
+
+```text
+MyFramework.xcframework/
+  Info.plist
+  ios-arm64/
+    MyFramework.framework/
+  ios-arm64_x86_64-simulator/
+    MyFramework.framework/
+  macos-arm64_x86_64/
+    MyFramework.framework/
+```
+
+The root `Info.plist` has a `CFBundlePackageType` of `XFWK` and an
+`AvailableLibraries` array describing each library.
+
+Architectures are detected from the Mach-O binaries using `goblin`, the
+same parser used by `apple-codesign`. (`apple-codesign` depends on this
+crate, so it can't be used directly.)
+*/
+
+use {
+    crate::DirectoryBundle,
+    anyhow::{anyhow, Context, Result},
+    goblin::mach::{constants::cputype::get_arch_name_from_types, Mach},
+    simple_file_manifest::{FileEntry, FileManifest},
+    std::path::{Path, PathBuf},
+};
+
+/// The `CFBundlePackageType` of XCFrameworks.
+pub const XCFRAMEWORK_PACKAGE_TYPE: &str = "XFWK";
+
+/// The `XCFrameworkFormatVersion` we write.
+const FORMAT_VERSION: &str = "1.0";
+
+/// Mask stripping capability bits from a CPU subtype.
+const CPU_SUBTYPE_MASK: u32 = 0x00ff_ffff;
+
+fn arch_name(cputype: u32, cpusubtype: u32) -> Result<String> {
+    get_arch_name_from_types(cputype, cpusubtype & CPU_SUBTYPE_MASK)
+        .map(|x| x.to_string())
+        .ok_or_else(|| {
+            anyhow!(
+                "unknown Mach-O architecture (cputype {}, cpusubtype {})",
+                cputype,
+                cpusubtype
+            )
+        })
+}
+
+/// Resolve the architectures present in a Mach-O binary or static library.
+///
+/// Universal (fat) binaries and `ar` archives of Mach-O objects are supported.
+/// Architecture names are those used by Apple tools, e.g. `arm64` or `x86_64`.
+/// The returned names are sorted and deduplicated.
+pub fn macho_architectures(data: &[u8]) -> Result<Vec<String>> {
+    let mut arches = if data.starts_with(b"!<arch>\n") {
+        let archive = goblin::archive::Archive::parse(data).context("parsing archive")?;
+
+        let mut arches = vec![];
+        for member in archive.members() {
+            let member_data = archive.extract(member, data)?;
+
+            if let Ok(Mach::Binary(macho)) = Mach::parse(member_data) {
+                arches.push(arch_name(macho.header.cputype, macho.header.cpusubtype)?);
+            }
+        }
+
+        arches
+    } else {
+        match Mach::parse(data).context("parsing Mach-O")? {
+            Mach::Binary(macho) => {
+                vec![arch_name(macho.header.cputype, macho.header.cpusubtype)?]
+            }
+            Mach::Fat(multi) => multi
+                .iter_arches()
+                .map(|arch| {
+                    let arch = arch?;
+                    arch_name(arch.cputype, arch.cpusubtype)
+                })
+                .collect::<Result<Vec<_>>>()?,
+        }
+    };
+
+    if arches.is_empty() {
+        return Err(anyhow!("no Mach-O architectures found"));
+    }
+
+    arches.sort();
+    arches.dedup();
+
+    Ok(arches)
+}
+
+/// Describes a library within an XCFramework.
+///
+/// This corresponds to an entry in the `AvailableLibraries` array of the
+/// XCFramework's `Info.plist`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct XcFrameworkLibrary {
+    /// Name of the directory holding the library. e.g. `ios-arm64_x86_64-simulator`.
+    pub identifier: String,
+    /// Path of the library relative to its identifier directory. e.g. `MyFramework.framework`.
+    pub path: String,
+    /// Path of the binary relative to its identifier directory.
+    pub binary_path: Option<String>,
+    /// Path of the headers directory relative to its identifier directory.
+    ///
+    /// Only used by non-framework libraries.
+    pub headers_path: Option<String>,
+    /// Platform the library targets. e.g. `ios`, `macos`.
+    pub platform: String,
+    /// Variant of the platform. e.g. `simulator` or `maccatalyst`.
+    pub platform_variant: Option<String>,
+    /// Architectures present in the library binary.
+    pub architectures: Vec<String>,
+}
+
+impl XcFrameworkLibrary {
+    /// Construct an instance from an `AvailableLibraries` dictionary.
+    pub fn from_dictionary(dict: &plist::Dictionary) -> Result<Self> {
+        let string = |key: &str| -> Result<Option<String>> {
+            match dict.get(key) {
+                Some(value) => Ok(Some(
+                    value
+                        .as_string()
+                        .ok_or_else(|| anyhow!("{} is not a string", key))?
+                        .to_string(),
+                )),
+                None => Ok(None),
+            }
+        };
+        let required = |key: &str| -> Result<String> {
+            string(key)?.ok_or_else(|| anyhow!("{} not defined", key))
+        };
+
+        let architectures = dict
+            .get("SupportedArchitectures")
+            .ok_or_else(|| anyhow!("SupportedArchitectures not defined"))?
+            .as_array()
+            .ok_or_else(|| anyhow!("SupportedArchitectures is not an array"))?
+            .iter()
+            .map(|x| {
+                Ok(x.as_string()
+                    .ok_or_else(|| anyhow!("SupportedArchitectures value not a string"))?
+                    .to_string())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            identifier: required("LibraryIdentifier")?,
+            path: required("LibraryPath")?,
+            binary_path: string("BinaryPath")?,
+            headers_path: string("HeadersPath")?,
+            platform: required("SupportedPlatform")?,
+            platform_variant: string("SupportedPlatformVariant")?,
+            architectures,
+        })
+    }
+
+    /// Convert this instance to an `AvailableLibraries` dictionary.
+    pub fn to_dictionary(&self) -> plist::Dictionary {
+        let mut dict = plist::Dictionary::new();
+
+        if let Some(path) = &self.binary_path {
+            dict.insert("BinaryPath".into(), path.clone().into());
+        }
+        if let Some(path) = &self.headers_path {
+            dict.insert("HeadersPath".into(), path.clone().into());
+        }
+        dict.insert("LibraryIdentifier".into(), self.identifier.clone().into());
+        dict.insert("LibraryPath".into(), self.path.clone().into());
+        dict.insert(
+            "SupportedArchitectures".into(),
+            plist::Value::Array(
+                self.architectures
+                    .iter()
+                    .map(|x| plist::Value::from(x.clone()))
+                    .collect(),
+            ),
+        );
+        dict.insert("SupportedPlatform".into(), self.platform.clone().into());
+        if let Some(variant) = &self.platform_variant {
+            dict.insert("SupportedPlatformVariant".into(), variant.clone().into());
+        }
+
+        dict
+    }
+
+    /// Derive the conventional library identifier for a platform, variant, and architectures.
+    ///
+    /// e.g. `ios-arm64_x86_64-simulator`.
+    pub fn derive_identifier(
+        platform: &str,
+        platform_variant: Option<&str>,
+        architectures: &[String],
+    ) -> String {
+        let mut s = format!("{}-{}", platform, architectures.join("_"));
+
+        if let Some(variant) = platform_variant {
+            s.push('-');
+            s.push_str(variant);
+        }
+
+        s
+    }
+}
+
+/// An XCFramework on the filesystem.
+#[derive(Clone, Debug)]
+pub struct XcFramework {
+    bundle: DirectoryBundle,
+    libraries: Vec<XcFrameworkLibrary>,
+}
+
+impl XcFramework {
+    /// Open an existing XCFramework from its root directory.
+    pub fn new_from_path(directory: &Path) -> Result<Self> {
+        let bundle = DirectoryBundle::new_from_path(directory)?;
+
+        let package_type = bundle.info_plist_key_string("CFBundlePackageType")?;
+        if package_type.as_deref() != Some(XCFRAMEWORK_PACKAGE_TYPE) {
+            return Err(anyhow!(
+                "{} is not an XCFramework: CFBundlePackageType is {:?}",
+                directory.display(),
+                package_type
+            ));
+        }
+
+        let libraries = bundle
+            .info_plist()
+            .get("AvailableLibraries")
+            .ok_or_else(|| anyhow!("AvailableLibraries not defined"))?
+            .as_array()
+            .ok_or_else(|| anyhow!("AvailableLibraries is not an array"))?
+            .iter()
+            .map(|x| {
+                XcFrameworkLibrary::from_dictionary(
+                    x.as_dictionary()
+                        .ok_or_else(|| anyhow!("AvailableLibraries entry not a dictionary"))?,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { bundle, libraries })
+    }
+
+    /// The bundle backing this XCFramework.
+    pub fn bundle(&self) -> &DirectoryBundle {
+        &self.bundle
+    }
+
+    /// Libraries in this XCFramework.
+    pub fn libraries(&self) -> &[XcFrameworkLibrary] {
+        &self.libraries
+    }
+
+    /// Resolve the absolute path to a library.
+    pub fn library_path(&self, library: &XcFrameworkLibrary) -> PathBuf {
+        self.bundle
+            .root_dir()
+            .join(&library.identifier)
+            .join(&library.path)
+    }
+
+    /// Open the framework bundle of a library.
+    ///
+    /// Returns `None` if the library isn't a framework.
+    pub fn library_bundle(&self, library: &XcFrameworkLibrary) -> Result<Option<DirectoryBundle>> {
+        if library.path.ends_with(".framework") {
+            Ok(Some(DirectoryBundle::new_from_path(
+                &self.library_path(library),
+            )?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Find the library for a given platform, variant, and architecture.
+    pub fn find_library(
+        &self,
+        platform: &str,
+        platform_variant: Option<&str>,
+        architecture: &str,
+    ) -> Option<&XcFrameworkLibrary> {
+        self.libraries.iter().find(|lib| {
+            lib.platform == platform
+                && lib.platform_variant.as_deref() == platform_variant
+                && lib.architectures.iter().any(|a| a == architecture)
+        })
+    }
+}
+
+/// Primitive used to construct an XCFramework.
+#[derive(Clone, Debug)]
+pub struct XcFrameworkBuilder {
+    name: String,
+    libraries: Vec<XcFrameworkLibrary>,
+    files: FileManifest,
+}
+
+impl XcFrameworkBuilder {
+    /// Create a new builder for an XCFramework with the given name.
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            libraries: vec![],
+            files: FileManifest::new_with_links(),
+        }
+    }
+
+    /// Libraries added so far.
+    pub fn libraries(&self) -> &[XcFrameworkLibrary] {
+        &self.libraries
+    }
+
+    fn register_library(&mut self, library: XcFrameworkLibrary) -> Result<XcFrameworkLibrary> {
+        if let Some(existing) = self.libraries.iter().find(|lib| {
+            lib.identifier == library.identifier
+                || (lib.platform == library.platform
+                    && lib.platform_variant == library.platform_variant)
+        }) {
+            return Err(anyhow!(
+                "library for platform {} {} already present as {}",
+                library.platform,
+                library.platform_variant.as_deref().unwrap_or(""),
+                existing.identifier
+            ));
+        }
+
+        self.libraries.push(library.clone());
+
+        Ok(library)
+    }
+
+    /// Add a framework bundle for a platform and platform variant.
+    ///
+    /// Architectures are detected from the framework's main executable.
+    pub fn add_framework(
+        &mut self,
+        framework: &DirectoryBundle,
+        platform: &str,
+        platform_variant: Option<&str>,
+    ) -> Result<XcFrameworkLibrary> {
+        let main = framework
+            .main_executable()?
+            .ok_or_else(|| anyhow!("framework does not define CFBundleExecutable"))?;
+
+        // Resolve symlinks so versioned frameworks record the real binary path.
+        let root = framework.root_dir().canonicalize()?;
+        let binary = framework
+            .root_dir()
+            .join(&main)
+            .canonicalize()
+            .with_context(|| format!("resolving framework binary {main}"))?;
+        let binary_relative = binary
+            .strip_prefix(&root)
+            .context("framework binary not inside framework")?;
+
+        let architectures = macho_architectures(&std::fs::read(&binary)?)
+            .with_context(|| format!("resolving architectures of {}", binary.display()))?;
+
+        let identifier =
+            XcFrameworkLibrary::derive_identifier(platform, platform_variant, &architectures);
+        let library_path = framework.name().to_string();
+
+        let library = self.register_library(XcFrameworkLibrary {
+            identifier: identifier.clone(),
+            path: library_path.clone(),
+            binary_path: Some(
+                format!("{}/{}", library_path, binary_relative.display()).replace('\\', "/"),
+            ),
+            headers_path: None,
+            platform: platform.to_string(),
+            platform_variant: platform_variant.map(|x| x.to_string()),
+            architectures,
+        })?;
+
+        let prefix = PathBuf::from(identifier).join(library_path);
+        for file in framework.files(true)? {
+            self.files
+                .add_file_entry(prefix.join(file.relative_path()), file.as_file_entry()?)?;
+        }
+
+        Ok(library)
+    }
+
+    /// Add a static or dynamic library for a platform and platform variant.
+    ///
+    /// `headers` is an optional directory of headers to ship alongside the library.
+    /// Architectures are detected from the library content.
+    pub fn add_library(
+        &mut self,
+        library: &Path,
+        headers: Option<&Path>,
+        platform: &str,
+        platform_variant: Option<&str>,
+    ) -> Result<XcFrameworkLibrary> {
+        let file_name = library
+            .file_name()
+            .ok_or_else(|| anyhow!("unable to resolve library file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        let architectures = macho_architectures(&std::fs::read(library)?)
+            .with_context(|| format!("resolving architectures of {}", library.display()))?;
+
+        let identifier =
+            XcFrameworkLibrary::derive_identifier(platform, platform_variant, &architectures);
+
+        let entry = self.register_library(XcFrameworkLibrary {
+            identifier: identifier.clone(),
+            path: file_name.clone(),
+            binary_path: Some(file_name.clone()),
+            headers_path: headers.map(|_| "Headers".to_string()),
+            platform: platform.to_string(),
+            platform_variant: platform_variant.map(|x| x.to_string()),
+            architectures,
+        })?;
+
+        let prefix = PathBuf::from(identifier);
+        self.files.add_file_entry(
+            prefix.join(&file_name),
+            FileEntry::try_from(library).context("reading library")?,
+        )?;
+
+        if let Some(headers) = headers {
+            for entry in walkdir::WalkDir::new(headers).sort_by_file_name() {
+                let entry = entry?;
+
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+
+                self.files.add_file_entry(
+                    prefix
+                        .join("Headers")
+                        .join(entry.path().strip_prefix(headers)?),
+                    FileEntry::try_from(entry.path())?,
+                )?;
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Obtain the `Info.plist` dictionary for the XCFramework.
+    pub fn info_plist(&self) -> plist::Dictionary {
+        let mut dict = plist::Dictionary::new();
+
+        dict.insert(
+            "AvailableLibraries".into(),
+            plist::Value::Array(
+                self.libraries
+                    .iter()
+                    .map(|lib| plist::Value::Dictionary(lib.to_dictionary()))
+                    .collect(),
+            ),
+        );
+        dict.insert(
+            "CFBundlePackageType".into(),
+            XCFRAMEWORK_PACKAGE_TYPE.into(),
+        );
+        dict.insert("XCFrameworkFormatVersion".into(), FORMAT_VERSION.into());
+
+        dict
+    }
+
+    /// Obtain the [FileManifest] of the materialized XCFramework.
+    ///
+    /// Paths are relative to the `.xcframework` directory.
+    pub fn manifest(&self) -> Result<FileManifest> {
+        if self.libraries.is_empty() {
+            return Err(anyhow!("XCFramework must contain at least 1 library"));
+        }
+
+        let mut m = self.files.clone();
+
+        let mut data = vec![];
+        plist::Value::from(self.info_plist())
+            .to_writer_xml(&mut data)
+            .context("serializing Info.plist")?;
+        m.add_file_entry("Info.plist", data)?;
+
+        Ok(m)
+    }
+
+    /// Materialize this XCFramework to the specified directory.
+    ///
+    /// Files are written to a `<name>.xcframework` directory in the directory
+    /// specified. The path of this directory will be returned. If the directory
+    /// exists, it is removed first.
+    pub fn materialize_bundle(&self, dest_dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let bundle_dir = dest_dir.as_ref().join(format!("{}.xcframework", self.name));
+
+        self.manifest()?
+            .materialize_files_with_replace(&bundle_dir)
+            .context("materializing FileManifest")?;
+
+        Ok(bundle_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{FrameworkBundleBuilder, FrameworkLayout},
+    };
+
+    /// A minimal thin 64-bit Mach-O dylib header.
+    fn thin_macho(cputype: u32, cpusubtype: u32) -> Vec<u8> {
+        let mut data = vec![];
+        for v in [0xfeedfacfu32, cputype, cpusubtype, 6, 0, 0, 0, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
+
+    /// A universal binary with the given slices.
+    fn fat_macho(slices: &[(u32, u32)]) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&0xcafebabeu32.to_be_bytes());
+        header.extend_from_slice(&(slices.len() as u32).to_be_bytes());
+
+        let mut body = vec![];
+        let mut offset = 4096u32;
+        for (cputype, cpusubtype) in slices {
+            let slice = thin_macho(*cputype, *cpusubtype);
+            for v in [*cputype, *cpusubtype, offset, slice.len() as u32, 12] {
+                header.extend_from_slice(&v.to_be_bytes());
+            }
+            body.resize((offset - 4096) as usize, 0);
+            body.extend(slice);
+            offset = 4096 + body.len() as u32;
+        }
+
+        header.resize(4096, 0);
+        header.extend(body);
+        header
+    }
+
+    fn ar_archive(members: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"!<arch>\n".to_vec();
+        for (name, content) in members {
+            data.extend(
+                format!(
+                    "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                    format!("{name}/"),
+                    0,
+                    0,
+                    0,
+                    644,
+                    content.len()
+                )
+                .into_bytes(),
+            );
+            data.extend(content);
+            if content.len() % 2 == 1 {
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+
+    const ARM64: (u32, u32) = (0x0100000c, 0);
+    const ARM64E: (u32, u32) = (0x0100000c, 0x80000002);
+    const X86_64: (u32, u32) = (0x01000007, 3);
+
+    #[test]
+    fn architectures() -> Result<()> {
+        assert_eq!(
+            macho_architectures(&thin_macho(ARM64.0, ARM64.1))?,
+            vec!["arm64"]
+        );
+        assert_eq!(
+            macho_architectures(&thin_macho(ARM64E.0, ARM64E.1))?,
+            vec!["arm64e"]
+        );
+        assert_eq!(
+            macho_architectures(&fat_macho(&[X86_64, ARM64]))?,
+            vec!["arm64", "x86_64"]
+        );
+        assert_eq!(
+            macho_architectures(&ar_archive(&[
+                ("a.o", thin_macho(X86_64.0, X86_64.1)),
+                ("b.o", thin_macho(X86_64.0, X86_64.1)),
+            ]))?,
+            vec!["x86_64"]
+        );
+        assert!(macho_architectures(b"not a binary").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn roundtrip() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-bundles-")
+            .tempdir()?;
+
+        let mut builder = XcFrameworkBuilder::new("MyFramework");
+
+        for (layout, dir, binary, platform, variant) in [
+            (
+                FrameworkLayout::Versioned,
+                "macos",
+                fat_macho(&[X86_64, ARM64]),
+                "macos",
+                None,
+            ),
+            (
+                FrameworkLayout::Shallow,
+                "ios",
+                thin_macho(ARM64.0, ARM64.1),
+                "ios",
+                None,
+            ),
+            (
+                FrameworkLayout::Shallow,
+                "ios-simulator",
+                fat_macho(&[X86_64, ARM64]),
+                "ios",
+                Some("simulator"),
+            ),
+        ] {
+            let mut framework = FrameworkBundleBuilder::new("MyFramework", layout)?;
+            framework.set_info_plist_required_keys("com.example.framework", "1.0");
+            framework.set_binary(binary)?;
+            let path = framework.materialize_bundle(td.path().join("source").join(dir))?;
+
+            builder.add_framework(&DirectoryBundle::new_from_path(&path)?, platform, variant)?;
+        }
+
+        let lib_path = td.path().join("libfoo.a");
+        std::fs::write(
+            &lib_path,
+            ar_archive(&[("foo.o", thin_macho(ARM64.0, ARM64.1))]),
+        )?;
+        let headers = td.path().join("include");
+        std::fs::create_dir_all(&headers)?;
+        std::fs::write(headers.join("foo.h"), b"")?;
+
+        // Same platform and variant as an existing library is rejected.
+        assert!(builder
+            .add_library(&lib_path, Some(&headers), "ios", None)
+            .is_err());
+        builder.add_library(&lib_path, Some(&headers), "tvos", None)?;
+
+        let path = builder.materialize_bundle(td.path().join("dest"))?;
+        let xcf = XcFramework::new_from_path(&path)?;
+
+        assert_eq!(
+            xcf.libraries()
+                .iter()
+                .map(|lib| lib.identifier.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "macos-arm64_x86_64",
+                "ios-arm64",
+                "ios-arm64_x86_64-simulator",
+                "tvos-arm64"
+            ]
+        );
+        assert_eq!(xcf.libraries(), builder.libraries());
+        assert_eq!(
+            xcf.libraries()[0].binary_path.as_deref(),
+            Some("MyFramework.framework/Versions/A/MyFramework")
+        );
+        assert_eq!(xcf.libraries()[3].headers_path.as_deref(), Some("Headers"));
+
+        let lib = xcf
+            .find_library("ios", Some("simulator"), "x86_64")
+            .unwrap();
+        assert_eq!(lib.identifier, "ios-arm64_x86_64-simulator");
+        let bundle = xcf.library_bundle(lib)?.unwrap();
+        assert_eq!(bundle.identifier()?, Some("com.example.framework".into()));
+
+        let macos = xcf.library_bundle(&xcf.libraries()[0])?.unwrap();
+        assert_eq!(macos.framework_versions()?, vec!["A".to_string()]);
+
+        assert!(xcf.library_bundle(&xcf.libraries()[3])?.is_none());
+        assert!(xcf.library_path(&xcf.libraries()[3]).is_file());
+
+        Ok(())
+    }
+}
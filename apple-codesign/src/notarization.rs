@@ -141,6 +141,15 @@ pub struct Notarizer {
 
     /// How long to wait between polling the server for upload status.
     wait_poll_interval: Duration,
+
+    /// Base URL of the App Store Connect API, if not Apple's.
+    api_url: Option<String>,
+
+    /// Base URL of the Notary API, if not Apple's.
+    notary_url: Option<String>,
+
+    /// URL of the S3 endpoint to upload to, if not AWS's.
+    s3_endpoint_url: Option<String>,
 }
 
 impl Notarizer {
@@ -149,6 +158,9 @@ impl Notarizer {
         Self {
             token_encoder,
             wait_poll_interval: Duration::from_secs(3),
+            api_url: None,
+            notary_url: None,
+            s3_endpoint_url: None,
         }
     }
 
@@ -168,6 +180,33 @@ impl Notarizer {
         Ok(Self::new(UnifiedApiKey::from_json_path(path)?.try_into()?))
     }
 
+    /// Set the base URL of the App Store Connect API.
+    ///
+    /// Defaults to [app_store_connect::APP_STORE_CONNECT_API_URL].
+    pub fn set_api_url(&mut self, url: impl ToString) {
+        self.api_url = Some(url.to_string());
+    }
+
+    /// Set the base URL of the Notary API.
+    ///
+    /// Defaults to [app_store_connect::NOTARY_API_URL].
+    pub fn set_notary_url(&mut self, url: impl ToString) {
+        self.notary_url = Some(url.to_string());
+    }
+
+    /// Set the URL of the S3 endpoint that submissions are uploaded to.
+    ///
+    /// By default, AWS's S3 endpoint for the region is used. When set, path-style
+    /// addressing (`<url>/<bucket>/<key>`) is used.
+    pub fn set_s3_endpoint_url(&mut self, url: impl ToString) {
+        self.s3_endpoint_url = Some(url.to_string());
+    }
+
+    /// Set how long to wait between polling the server for submission status.
+    pub fn set_wait_poll_interval(&mut self, interval: Duration) {
+        self.wait_poll_interval = interval;
+    }
+
     /// Attempt to notarize an asset defined by a filesystem path.
     ///
     /// The type of path is sniffed out and the appropriate notarization routine is called.
@@ -255,7 +294,16 @@ impl Notarizer {
 
 impl Notarizer {
     fn client(&self) -> Result<AppStoreConnectClient, AppleCodesignError> {
-        Ok(AppStoreConnectClient::new(self.token_encoder.clone())?)
+        let mut client = AppStoreConnectClient::new(self.token_encoder.clone())?;
+
+        if let Some(url) = &self.api_url {
+            client.set_api_url(url);
+        }
+        if let Some(url) = &self.notary_url {
+            client.set_notary_url(url);
+        }
+
+        Ok(client)
     }
 
     /// Tell the notary service to expect an upload to S3.
@@ -294,23 +342,28 @@ impl Notarizer {
 
         // upload using s3 api
         warn!("resolving AWS S3 configuration from Apple-provided credentials");
-        let config = rt.block_on(
-            aws_config::from_env()
-                .credentials_provider(Credentials::new(
-                    submission.data.attributes.aws_access_key_id.clone(),
-                    submission.data.attributes.aws_secret_access_key.clone(),
-                    Some(submission.data.attributes.aws_session_token.clone()),
-                    None,
-                    "apple-codesign",
-                ))
-                // The region is not given anywhere in the Apple documentation. From
-                // manually testing all available regions, it appears to be
-                // us-west-2.
-                .region(Region::new("us-west-2"))
-                .load(),
-        );
+        let mut loader = aws_config::from_env()
+            .credentials_provider(Credentials::new(
+                submission.data.attributes.aws_access_key_id.clone(),
+                submission.data.attributes.aws_secret_access_key.clone(),
+                Some(submission.data.attributes.aws_session_token.clone()),
+                None,
+                "apple-codesign",
+            ))
+            // The region is not given anywhere in the Apple documentation. From
+            // manually testing all available regions, it appears to be
+            // us-west-2.
+            .region(Region::new("us-west-2"));
+        if let Some(url) = &self.s3_endpoint_url {
+            loader = loader.endpoint_url(url);
+        }
+        let config = rt.block_on(loader.load());
 
-        let s3_client = aws_sdk_s3::Client::new(&config);
+        let s3_client = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::config::Builder::from(&config)
+                .force_path_style(self.s3_endpoint_url.is_some())
+                .build(),
+        );
 
         warn!(
             "uploading asset to s3://{}/{}",
@@ -432,3 +485,56 @@ impl Notarizer {
         Ok(status)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {super::*, app_store_connect::mock_server::MockServer};
+
+    fn mock_notarizer(server: &MockServer) -> Result<Notarizer, AppleCodesignError> {
+        let mut notarizer = Notarizer::new(server.token_encoder()?);
+        notarizer.set_api_url(server.url());
+        notarizer.set_notary_url(server.url());
+        notarizer.set_s3_endpoint_url(server.url());
+        notarizer.set_wait_poll_interval(Duration::from_millis(10));
+
+        Ok(notarizer)
+    }
+
+    #[test]
+    fn notarize_mock_server() -> Result<(), AppleCodesignError> {
+        let server = MockServer::start()?;
+        let notarizer = mock_notarizer(&server)?;
+
+        let td = tempfile::Builder::new()
+            .prefix("apple-codesign-test-")
+            .tempdir()?;
+        let path = td.path().join("app.zip");
+        std::fs::write(&path, b"zip content")?;
+
+        let upload = notarizer.notarize_flat_package(&path, Some(Duration::from_secs(30)))?;
+        let NotarizationUpload::NotaryResponse(response) = upload else {
+            panic!("expected notary response");
+        };
+        assert_eq!(response.data.attributes.name, "app.zip");
+
+        let object = format!("prod/{}", response.data.id);
+        assert_eq!(
+            server.s3_object(app_store_connect::mock_server::MOCK_NOTARY_BUCKET, &object),
+            Some(b"zip content".to_vec())
+        );
+
+        // Rejected submissions surface as errors.
+        server.set_notary_status("Invalid");
+        assert!(notarizer
+            .notarize_flat_package(&path, Some(Duration::from_secs(30)))
+            .is_err());
+
+        // Without waiting we only get the submission ID.
+        assert!(matches!(
+            notarizer.notarize_flat_package(&path, None)?,
+            NotarizationUpload::UploadId(_)
+        ));
+
+        Ok(())
+    }
+}
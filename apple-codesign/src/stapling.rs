@@ -18,7 +18,10 @@ use {
         dmg::{DmgReader, DmgSigner},
         embedded_signature::Blob,
         reader::PathType,
-        ticket_lookup::{default_client, lookup_notarization_ticket},
+        ticket_lookup::{
+            default_client, lookup_notarization_tickets_with_url, TicketLookupResponse,
+            APPLE_TICKET_LOOKUP_URL,
+        },
         AppleCodesignError,
     },
     apple_bundles::DirectoryBundle,
@@ -135,6 +138,7 @@ pub fn xar_notarization_trailer(ticket_data: &[u8]) -> Result<Vec<u8>, AppleCode
 /// Handles stapling operations.
 pub struct Stapler {
     client: Client,
+    lookup_url: String,
 }
 
 impl Stapler {
@@ -142,6 +146,7 @@ impl Stapler {
     pub fn new() -> Result<Self, AppleCodesignError> {
         Ok(Self {
             client: default_client()?,
+            lookup_url: APPLE_TICKET_LOOKUP_URL.to_string(),
         })
     }
 
@@ -150,6 +155,21 @@ impl Stapler {
         self.client = client;
     }
 
+    /// Set the URL of the ticket lookup service.
+    ///
+    /// Defaults to [APPLE_TICKET_LOOKUP_URL].
+    pub fn set_lookup_url(&mut self, url: impl ToString) {
+        self.lookup_url = url.to_string();
+    }
+
+    fn lookup_ticket(&self, record_name: &str) -> Result<TicketLookupResponse, AppleCodesignError> {
+        lookup_notarization_tickets_with_url(
+            &self.client,
+            &self.lookup_url,
+            std::iter::once(record_name),
+        )
+    }
+
     /// Look up a notarization ticket for an app bundle.
     ///
     /// This will resolve the notarization ticket record name from the contents
@@ -165,7 +185,7 @@ impl Stapler {
     ) -> Result<Vec<u8>, AppleCodesignError> {
         let record_name = record_name_from_executable_bundle(bundle)?;
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         let ticket_data = response.signed_ticket(&record_name)?;
 
@@ -203,7 +223,7 @@ impl Stapler {
 
         let record_name = format!("2/{digest_type}/{digest}");
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         response.signed_ticket(&record_name)
     }
@@ -241,7 +261,7 @@ impl Stapler {
 
         let record_name = format!("2/{digest_type}/{digest}");
 
-        let response = lookup_notarization_ticket(&self.client, &record_name)?;
+        let response = self.lookup_ticket(&record_name)?;
 
         response.signed_ticket(&record_name)
     }
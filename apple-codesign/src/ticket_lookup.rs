@@ -177,6 +177,18 @@ pub fn default_client() -> Result<Client, AppleCodesignError> {
 pub fn lookup_notarization_tickets<'a>(
     client: &Client,
     record_names: impl Iterator<Item = &'a str>,
+) -> Result<TicketLookupResponse, AppleCodesignError> {
+    lookup_notarization_tickets_with_url(client, APPLE_TICKET_LOOKUP_URL, record_names)
+}
+
+/// Look up notarization tickets against a ticket lookup service at a given URL.
+///
+/// This is like [lookup_notarization_tickets()] except the URL of the service
+/// is configurable. [APPLE_TICKET_LOOKUP_URL] is Apple's production service.
+pub fn lookup_notarization_tickets_with_url<'a>(
+    client: &Client,
+    url: &str,
+    record_names: impl Iterator<Item = &'a str>,
 ) -> Result<TicketLookupResponse, AppleCodesignError> {
     let body = TicketLookupRequest {
         records: record_names
@@ -190,7 +202,7 @@ pub fn lookup_notarization_tickets<'a>(
     };
 
     let req = client
-        .post(APPLE_TICKET_LOOKUP_URL)
+        .post(url)
         .header("Accept", "application/json")
         .header("Content-Type", "application/json")
         .json(&body);
@@ -247,4 +259,25 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn lookup_ticket_mock_server() -> Result<(), AppleCodesignError> {
+        let server = app_store_connect::mock_server::MockServer::start()?;
+        server.insert_ticket(PYOXIDIZER_APP_RECORD, b"s8chticket");
+
+        let client = default_client()?;
+        let res = lookup_notarization_tickets_with_url(
+            &client,
+            &server.ticket_lookup_url(),
+            [PYOXIDIZER_APP_RECORD, DEADBEEF].into_iter(),
+        )?;
+
+        assert_eq!(res.signed_ticket(PYOXIDIZER_APP_RECORD)?, b"s8chticket");
+        assert!(matches!(
+            res.signed_ticket(DEADBEEF),
+            Err(AppleCodesignError::NotarizationLookupFailure(_, _))
+        ));
+
+        Ok(())
+    }
 }
@@ -5,7 +5,11 @@
 //! Interface to component packages, installable units within flat packages.
 
 use {
-    crate::{package_info::PackageInfo, PkgResult},
+    crate::{
+        package_info::PackageInfo,
+        pbzx::{PbzxReader, PBZX_MAGIC},
+        PkgResult,
+    },
     cpio_archive::ChainedCpioReader,
     std::io::{Cursor, Read},
 };
@@ -19,6 +23,8 @@ const GZIP_HEADER: [u8; 3] = [0x1f, 0x8b, 0x08];
 fn decode_archive(data: Vec<u8>) -> PkgResult<Box<dyn Read>> {
     if data.len() > 3 && data[0..3] == GZIP_HEADER {
         Ok(Box::new(flate2::read::GzDecoder::new(Cursor::new(data))) as Box<dyn Read>)
+    } else if data.starts_with(PBZX_MAGIC) {
+        Ok(Box::new(PbzxReader::new(Cursor::new(data))?) as Box<dyn Read>)
     } else {
         Ok(Box::new(Cursor::new(data)) as Box<dyn Read>)
     }
@@ -80,7 +80,8 @@
 //! * The XAR table of contents is likely compressed with zlib.
 //! * Individual files within XAR archives can be individually compressed
 //!   with a compression format denoted by a MIME type.
-//! * cpio archive files may also be compressed.
+//! * cpio archive files may also be compressed. gzip and pbzx (see [PbzxReader])
+//!   compression is supported.
 //! * Installed files in components may also be compressed (but this file
 //!   content is treated as opaque by the flat package format).
 
@@ -90,6 +91,8 @@ pub mod distribution;
 pub use distribution::Distribution;
 pub mod package_info;
 pub use package_info::PackageInfo;
+pub mod pbzx;
+pub use pbzx::PbzxReader;
 pub mod reader;
 pub use reader::{PkgFlavor, PkgReader};
 
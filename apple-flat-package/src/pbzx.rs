@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! pbzx stream decoding.
+//!
+//! pbzx is a chunked compression format used by Apple for large payloads, such as
+//! the `Payload` of Command Line Tools components and the `Content` of Xcode `.xip`
+//! archives.
+//!
+//! A stream begins with the `pbzx` magic and a big-endian `u64` of flags. Chunks
+//! follow for as long as the previous flags have bit 24 set. Each chunk has a
+//! big-endian `u64` of flags, a `u64` of data length, then the data. Chunk data is
+//! an XZ stream, unless the data length equals the chunk's uncompressed size, in
+//! which case the data is stored as-is.
+
+use std::io::{Cursor, Read};
+
+/// Magic bytes at the beginning of a pbzx stream.
+pub const PBZX_MAGIC: &[u8; 4] = b"pbzx";
+
+/// The flag denoting that another chunk follows.
+const FLAG_MORE_CHUNKS: u64 = 1 << 24;
+
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// A reader that decodes a pbzx stream.
+///
+/// Chunks are decoded one at a time, so memory usage is bounded by the chunk size
+/// (typically 16 MiB) regardless of the stream size.
+pub struct PbzxReader<R: Read> {
+    reader: R,
+    flags: u64,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl<R: Read> PbzxReader<R> {
+    /// Construct an instance from a reader positioned at the beginning of a pbzx stream.
+    ///
+    /// Errors if the stream doesn't begin with the pbzx magic.
+    pub fn new(mut reader: R) -> std::io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != PBZX_MAGIC {
+            return Err(invalid_data("not a pbzx stream"));
+        }
+
+        let flags = read_u64(&mut reader)?;
+
+        Ok(Self {
+            reader,
+            flags,
+            chunk: Cursor::new(vec![]),
+        })
+    }
+
+    /// Return the inner reader, consuming self.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Decode the next chunk into the chunk buffer.
+    ///
+    /// Returns false if there are no more chunks.
+    fn next_chunk(&mut self) -> std::io::Result<bool> {
+        if self.flags & FLAG_MORE_CHUNKS == 0 {
+            return Ok(false);
+        }
+
+        self.flags = read_u64(&mut self.reader)?;
+        let length = read_u64(&mut self.reader)?;
+
+        let mut data = vec![];
+        (&mut self.reader).take(length).read_to_end(&mut data)?;
+
+        if data.len() as u64 != length {
+            return Err(invalid_data("truncated pbzx chunk"));
+        }
+
+        let decoded = if data.starts_with(&XZ_MAGIC) {
+            let mut decoded = vec![];
+            xz2::read::XzDecoder::new(Cursor::new(data)).read_to_end(&mut decoded)?;
+            decoded
+        } else {
+            data
+        };
+
+        self.chunk = Cursor::new(decoded);
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for PbzxReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let count = self.chunk.read(buf)?;
+
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::io::Write};
+
+    fn pbzx_stream(chunks: &[(&[u8], bool)]) -> Vec<u8> {
+        let mut res = PBZX_MAGIC.to_vec();
+        res.extend(FLAG_MORE_CHUNKS.to_be_bytes());
+
+        for (i, (data, compress)) in chunks.iter().enumerate() {
+            let flags = if i == chunks.len() - 1 {
+                0
+            } else {
+                FLAG_MORE_CHUNKS
+            } | data.len() as u64;
+
+            let payload = if *compress {
+                let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+
+            res.extend(flags.to_be_bytes());
+            res.extend((payload.len() as u64).to_be_bytes());
+            res.extend(payload);
+        }
+
+        res
+    }
+
+    #[test]
+    fn decode() -> std::io::Result<()> {
+        let stream = pbzx_stream(&[(b"hello, ", true), (b"raw ", false), (b"world", true)]);
+
+        let mut reader = PbzxReader::new(Cursor::new(stream))?;
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        assert_eq!(data, b"hello, raw world");
+
+        Ok(())
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(PbzxReader::new(Cursor::new(b"not pbzx".to_vec())).is_err());
+
+        let mut stream = pbzx_stream(&[(b"hello", true)]);
+        stream.truncate(stream.len() - 4);
+        let mut reader = PbzxReader::new(Cursor::new(stream)).unwrap();
+        assert!(reader.read_to_end(&mut vec![]).is_err());
+    }
+}
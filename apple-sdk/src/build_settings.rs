@@ -0,0 +1,476 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compiler and linker settings for building against an SDK.
+
+use {
+    crate::{AppleSdk, Error, ParsedSdk, Platform, SdkVersion},
+    std::path::PathBuf,
+};
+
+/// Resolve the Apple architecture name for a target triple.
+///
+/// Rust and LLVM use different names for some architectures. e.g. Rust's `aarch64`
+/// is `arm64` in Apple tooling. This function returns the Apple name, as accepted by
+/// `-arch` arguments.
+pub fn apple_arch_from_target_triple(target: &str) -> Result<&'static str, Error> {
+    let (arch, _) = target
+        .split_once('-')
+        .ok_or_else(|| Error::UnknownTarget(target.to_string()))?;
+
+    Ok(match arch {
+        "aarch64" | "arm64" => "arm64",
+        "arm64e" => "arm64e",
+        "arm64_32" => "arm64_32",
+        "armv7" => "armv7",
+        "armv7k" => "armv7k",
+        "armv7s" => "armv7s",
+        "i386" | "i686" => "i386",
+        "x86_64" => "x86_64",
+        "x86_64h" => "x86_64h",
+        _ => return Err(Error::UnknownTarget(target.to_string())),
+    })
+}
+
+/// Compiler, linker, and environment settings for targeting a platform with an SDK.
+///
+/// Instances are obtained from [ParsedSdk::build_settings()] and capture everything
+/// needed to emit consistent arguments to clang, rustc, and ld64 / `ld64.lld` for a
+/// target triple and deployment target.
+#[derive(Clone, Debug)]
+pub struct SdkBuildSettings {
+    /// Root directory of the SDK.
+    pub sdk_path: PathBuf,
+
+    /// Version of the SDK.
+    pub sdk_version: SdkVersion,
+
+    /// The target triple these settings were derived from. e.g. `aarch64-apple-darwin`.
+    pub target_triple: String,
+
+    /// The platform being targeted.
+    pub platform: Platform,
+
+    /// Whether the target is Mac Catalyst.
+    pub mac_catalyst: bool,
+
+    /// Name of the SDK supported target being used. e.g. `macosx` or `iosmac`.
+    pub supported_target_name: String,
+
+    /// Apple name of the machine architecture. e.g. `arm64`.
+    pub arch: String,
+
+    /// The minimum OS version to target.
+    pub deployment_target: SdkVersion,
+
+    /// Name of the environment variable / setting defining the deployment target.
+    ///
+    /// e.g. `MACOSX_DEPLOYMENT_TARGET`.
+    pub deployment_target_setting_name: String,
+}
+
+impl SdkBuildSettings {
+    /// The OS component of the LLVM target triple.
+    fn llvm_os(&self) -> &'static str {
+        match self.platform {
+            Platform::MacOsX if self.mac_catalyst => "ios",
+            Platform::MacOsX => "macosx",
+            Platform::IPhoneOs | Platform::IPhoneSimulator => "ios",
+            Platform::AppleTvOs | Platform::AppleTvSimulator => "tvos",
+            Platform::WatchOs | Platform::WatchSimulator => "watchos",
+            Platform::XrOs | Platform::XrSimulator => "xros",
+            Platform::DriverKit => "driverkit",
+            Platform::Unknown(_) => "unknown",
+        }
+    }
+
+    fn is_simulator(&self) -> bool {
+        matches!(
+            self.platform,
+            Platform::IPhoneSimulator
+                | Platform::AppleTvSimulator
+                | Platform::WatchSimulator
+                | Platform::XrSimulator
+        )
+    }
+
+    /// The versioned LLVM target triple to pass to clang's `-target`.
+    ///
+    /// e.g. `arm64-apple-macosx11.0`, `arm64-apple-ios14.0-macabi` or
+    /// `arm64-apple-xros1.0-simulator`.
+    pub fn clang_target(&self) -> String {
+        let environment = if self.mac_catalyst {
+            "-macabi"
+        } else if self.is_simulator() {
+            "-simulator"
+        } else {
+            ""
+        };
+
+        format!(
+            "{}-apple-{}{}{}",
+            self.arch,
+            self.llvm_os(),
+            self.deployment_target,
+            environment
+        )
+    }
+
+    /// The `-m*-version-min=<version>` clang argument for this target.
+    ///
+    /// Returns [None] for targets lacking such an argument (Mac Catalyst, visionOS,
+    /// DriverKit). Those targets must use [Self::clang_target()] to convey the
+    /// deployment target.
+    pub fn version_min_arg(&self) -> Option<String> {
+        let flag = match self.platform {
+            Platform::MacOsX if self.mac_catalyst => return None,
+            Platform::MacOsX => "-mmacosx-version-min",
+            Platform::IPhoneOs => "-mios-version-min",
+            Platform::IPhoneSimulator => "-mios-simulator-version-min",
+            Platform::AppleTvOs => "-mtvos-version-min",
+            Platform::AppleTvSimulator => "-mtvos-simulator-version-min",
+            Platform::WatchOs => "-mwatchos-version-min",
+            Platform::WatchSimulator => "-mwatchos-simulator-version-min",
+            Platform::XrOs | Platform::XrSimulator | Platform::DriverKit | Platform::Unknown(_) => {
+                return None
+            }
+        };
+
+        Some(format!("{}={}", flag, self.deployment_target))
+    }
+
+    /// The platform name used by ld64 and `ld64.lld` `-platform_version` arguments.
+    ///
+    /// e.g. `macos`, `mac-catalyst` or `ios-simulator`.
+    pub fn linker_platform_name(&self) -> &'static str {
+        match self.platform {
+            Platform::MacOsX if self.mac_catalyst => "mac-catalyst",
+            Platform::MacOsX => "macos",
+            Platform::IPhoneOs => "ios",
+            Platform::IPhoneSimulator => "ios-simulator",
+            Platform::AppleTvOs => "tvos",
+            Platform::AppleTvSimulator => "tvos-simulator",
+            Platform::WatchOs => "watchos",
+            Platform::WatchSimulator => "watchos-simulator",
+            Platform::XrOs => "xros",
+            Platform::XrSimulator => "xros-simulator",
+            Platform::DriverKit => "driverkit",
+            Platform::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Arguments to pass to clang when compiling or linking.
+    ///
+    /// This is `-isysroot <sdk> -target <triple>`. The versioned target triple conveys
+    /// the deployment target, so [Self::version_min_arg()] is not included: clang
+    /// warns when both are specified.
+    pub fn clang_args(&self) -> Vec<String> {
+        vec![
+            "-isysroot".to_string(),
+            self.sdk_path.display().to_string(),
+            "-target".to_string(),
+            self.clang_target(),
+        ]
+    }
+
+    /// Arguments to pass to rustc.
+    ///
+    /// rustc derives the SDK and deployment target from the `SDKROOT` and
+    /// `*_DEPLOYMENT_TARGET` environment variables. So callers should also set the
+    /// variables from [Self::environment()] when invoking rustc.
+    pub fn rustc_args(&self) -> Vec<String> {
+        vec!["--target".to_string(), self.target_triple.clone()]
+    }
+
+    /// Arguments to pass to ld64 or `ld64.lld` when invoking the linker directly.
+    ///
+    /// This is `-arch <arch> -platform_version <platform> <min> <sdk> -syslibroot <sdk>`.
+    pub fn linker_args(&self) -> Vec<String> {
+        vec![
+            "-arch".to_string(),
+            self.arch.clone(),
+            "-platform_version".to_string(),
+            self.linker_platform_name().to_string(),
+            self.deployment_target.to_string(),
+            self.sdk_version.to_string(),
+            "-syslibroot".to_string(),
+            self.sdk_path.display().to_string(),
+        ]
+    }
+
+    /// Environment variables to define when invoking build tools.
+    ///
+    /// This is `SDKROOT` and the deployment target setting (e.g.
+    /// `MACOSX_DEPLOYMENT_TARGET`).
+    pub fn environment(&self) -> Vec<(String, String)> {
+        vec![
+            ("SDKROOT".to_string(), self.sdk_path.display().to_string()),
+            (
+                self.deployment_target_setting_name.clone(),
+                self.deployment_target.to_string(),
+            ),
+        ]
+    }
+}
+
+/// Default name of the deployment target setting for a platform.
+fn default_deployment_target_setting_name(platform: &Platform, mac_catalyst: bool) -> String {
+    match platform {
+        Platform::MacOsX if mac_catalyst => "IPHONEOS_DEPLOYMENT_TARGET".to_string(),
+        Platform::MacOsX => "MACOSX_DEPLOYMENT_TARGET".to_string(),
+        Platform::IPhoneOs | Platform::IPhoneSimulator => "IPHONEOS_DEPLOYMENT_TARGET".to_string(),
+        Platform::AppleTvOs | Platform::AppleTvSimulator => "TVOS_DEPLOYMENT_TARGET".to_string(),
+        Platform::WatchOs | Platform::WatchSimulator => "WATCHOS_DEPLOYMENT_TARGET".to_string(),
+        Platform::XrOs | Platform::XrSimulator => "XROS_DEPLOYMENT_TARGET".to_string(),
+        Platform::DriverKit => "DRIVERKIT_DEPLOYMENT_TARGET".to_string(),
+        Platform::Unknown(v) => format!("{}_DEPLOYMENT_TARGET", v.to_ascii_uppercase()),
+    }
+}
+
+impl ParsedSdk {
+    /// Resolve compiler and linker settings for building a target triple with this SDK.
+    ///
+    /// `deployment_target` is the minimum OS version to target. If [None], the default
+    /// deployment target of the SDK's supported target is used.
+    ///
+    /// Errors if the target triple is for a different platform than this SDK or if the
+    /// SDK metadata says the deployment target isn't supported.
+    pub fn build_settings(
+        &self,
+        target_triple: &str,
+        deployment_target: Option<SdkVersion>,
+    ) -> Result<SdkBuildSettings, Error> {
+        let platform = Platform::from_target_triple(target_triple)?;
+        let arch = apple_arch_from_target_triple(target_triple)?;
+        let mac_catalyst = Platform::is_mac_catalyst_target_triple(target_triple);
+
+        if &platform != self.platform() {
+            return Err(Error::TargetNotSupported(format!(
+                "{} targets {} but SDK is for {}",
+                target_triple,
+                platform.filesystem_name(),
+                self.platform().filesystem_name()
+            )));
+        }
+
+        let supported_target_name =
+            Platform::supported_target_name_from_target_triple(target_triple)?;
+
+        // Only SDKSettings.json SDKs define supported targets. So we can only validate
+        // against that metadata when it is present.
+        let supported_target = self.supported_targets.get(&supported_target_name);
+
+        if supported_target.is_none() && !self.supported_targets.is_empty() {
+            return Err(Error::TargetNotSupported(format!(
+                "SDK does not define supported target {supported_target_name}"
+            )));
+        }
+
+        let deployment_target = if let Some(version) = deployment_target {
+            version
+        } else if let Some(target) = supported_target {
+            SdkVersion::from(&target.default_deployment_target)
+        } else if mac_catalyst {
+            // The SDK's default deployment target is a macOS version.
+            return Err(Error::TargetNotSupported(format!(
+                "{target_triple} requires an explicit deployment target with this SDK"
+            )));
+        } else {
+            SdkVersion::from(&self.default_deployment_target)
+        };
+
+        // Reject malformed versions.
+        deployment_target.semantic_version()?;
+
+        if let Some(target) = supported_target {
+            let minimum = SdkVersion::from(&target.minimum_deployment_target);
+
+            let too_new = if let Some(maximum) = &target.maximum_deployment_target {
+                deployment_target > SdkVersion::from(maximum)
+            } else {
+                false
+            };
+
+            if deployment_target < minimum || too_new {
+                return Err(Error::TargetNotSupported(format!(
+                    "deployment target {deployment_target} not supported by {supported_target_name} target of SDK {}",
+                    self.name
+                )));
+            }
+        }
+
+        let deployment_target_setting_name = supported_target
+            .and_then(|target| target.deployment_target_setting_name.clone())
+            .unwrap_or_else(|| default_deployment_target_setting_name(&platform, mac_catalyst));
+
+        Ok(SdkBuildSettings {
+            sdk_path: self.path().to_path_buf(),
+            sdk_version: self.version.clone(),
+            target_triple: target_triple.to_string(),
+            platform,
+            mac_catalyst,
+            supported_target_name,
+            arch: arch.to_string(),
+            deployment_target,
+            deployment_target_setting_name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::SdkSettingsJson};
+
+    const MACOSX_11_3_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx11.3-settings.json");
+    const XROS_1_0_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/xros1.0-settings.json");
+
+    fn parse_sdk(data: &[u8], path: &str, platform: Platform) -> Result<ParsedSdk, Error> {
+        let value = serde_json::from_slice::<SdkSettingsJson>(data)?;
+
+        ParsedSdk::from_json(PathBuf::from(path), false, platform, value)
+    }
+
+    #[test]
+    fn macos() -> Result<(), Error> {
+        let sdk = parse_sdk(
+            MACOSX_11_3_SETTINGS_JSON,
+            "/sdks/MacOSX11.3.sdk",
+            Platform::MacOsX,
+        )?;
+
+        let settings = sdk.build_settings("aarch64-apple-darwin", Some("11.0".into()))?;
+        assert_eq!(settings.arch, "arm64");
+        assert_eq!(settings.supported_target_name, "macosx");
+        assert_eq!(settings.clang_target(), "arm64-apple-macosx11.0");
+        assert_eq!(
+            settings.version_min_arg(),
+            Some("-mmacosx-version-min=11.0".to_string())
+        );
+        assert_eq!(
+            settings.clang_args(),
+            vec![
+                "-isysroot",
+                "/sdks/MacOSX11.3.sdk",
+                "-target",
+                "arm64-apple-macosx11.0"
+            ]
+        );
+        assert_eq!(
+            settings.rustc_args(),
+            vec!["--target", "aarch64-apple-darwin"]
+        );
+        assert_eq!(
+            settings.linker_args(),
+            vec![
+                "-arch",
+                "arm64",
+                "-platform_version",
+                "macos",
+                "11.0",
+                "11.3",
+                "-syslibroot",
+                "/sdks/MacOSX11.3.sdk"
+            ]
+        );
+        assert_eq!(
+            settings.environment(),
+            vec![
+                ("SDKROOT".to_string(), "/sdks/MacOSX11.3.sdk".to_string()),
+                ("MACOSX_DEPLOYMENT_TARGET".to_string(), "11.0".to_string())
+            ]
+        );
+
+        // Default deployment target comes from the SDK.
+        let settings = sdk.build_settings("x86_64h-apple-darwin", None)?;
+        assert_eq!(settings.clang_target(), "x86_64h-apple-macosx11.3");
+
+        // Deployment targets outside the supported range are rejected.
+        assert!(matches!(
+            sdk.build_settings("x86_64-apple-darwin", Some("10.5".into())),
+            Err(Error::TargetNotSupported(_))
+        ));
+        assert!(matches!(
+            sdk.build_settings("x86_64-apple-darwin", Some("12.0".into())),
+            Err(Error::TargetNotSupported(_))
+        ));
+
+        // As are other platforms.
+        assert!(matches!(
+            sdk.build_settings("aarch64-apple-ios", None),
+            Err(Error::TargetNotSupported(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mac_catalyst() -> Result<(), Error> {
+        let sdk = parse_sdk(
+            MACOSX_11_3_SETTINGS_JSON,
+            "/sdks/MacOSX11.3.sdk",
+            Platform::MacOsX,
+        )?;
+
+        let settings = sdk.build_settings("aarch64-apple-ios-macabi", Some("14.0".into()))?;
+        assert_eq!(settings.supported_target_name, "iosmac");
+        assert_eq!(settings.clang_target(), "arm64-apple-ios14.0-macabi");
+        assert_eq!(settings.version_min_arg(), None);
+        assert_eq!(settings.linker_platform_name(), "mac-catalyst");
+        assert_eq!(
+            settings.environment()[1],
+            ("IPHONEOS_DEPLOYMENT_TARGET".to_string(), "14.0".to_string())
+        );
+
+        let settings = sdk.build_settings("x86_64-apple-ios-macabi", None)?;
+        assert_eq!(settings.deployment_target, SdkVersion::from("14.5"));
+
+        // macOS versions aren't valid Mac Catalyst deployment targets.
+        assert!(sdk
+            .build_settings("aarch64-apple-ios-macabi", Some("11.0".into()))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn visionos() -> Result<(), Error> {
+        let sdk = parse_sdk(XROS_1_0_SETTINGS_JSON, "/sdks/XROS1.0.sdk", Platform::XrOs)?;
+
+        let settings = sdk.build_settings("aarch64-apple-visionos", None)?;
+        assert_eq!(settings.clang_target(), "arm64-apple-xros1.0");
+        assert_eq!(settings.version_min_arg(), None);
+        assert_eq!(settings.linker_platform_name(), "xros");
+        assert_eq!(
+            settings.deployment_target_setting_name,
+            "XROS_DEPLOYMENT_TARGET"
+        );
+
+        assert!(sdk
+            .build_settings("aarch64-apple-visionos-sim", None)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn arch_names() -> Result<(), Error> {
+        assert_eq!(apple_arch_from_target_triple("aarch64-apple-ios")?, "arm64");
+        assert_eq!(
+            apple_arch_from_target_triple("arm64e-apple-darwin")?,
+            "arm64e"
+        );
+        assert_eq!(
+            apple_arch_from_target_triple("arm64_32-apple-watchos")?,
+            "arm64_32"
+        );
+        assert_eq!(apple_arch_from_target_triple("i686-apple-darwin")?, "i386");
+        assert!(apple_arch_from_target_triple("riscv64-apple-darwin").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,558 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting SDKs from Apple distribution archives.
+//!
+//! Xcode is distributed as a `.xip` file. This is a XAR archive whose `Content`
+//! file is a pbzx compressed cpio archive of `Xcode.app`. SDKs live in
+//! `Xcode.app/Contents/Developer/Platforms/*.platform/Developer/SDKs/`.
+//!
+//! The Command Line Tools are distributed as a product flat package (`.pkg`).
+//! Its component packages have pbzx compressed `Payload` cpio archives installing
+//! files into `Library/Developer/CommandLineTools/SDKs/`.
+//!
+//! In both cases, SDKs are identified as `*.sdk` entries in a `SDKs` directory.
+
+use {
+    crate::{Error, Platform, SdkPath},
+    apple_flat_package::{PbzxReader, PkgReader},
+    apple_xar::reader::XarReader,
+    cpio_archive::CpioHeader,
+    std::{
+        collections::BTreeSet,
+        io::{Read, Seek, SeekFrom},
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// cpio file type mask.
+const S_IFMT: u32 = 0o170000;
+/// cpio directory file type.
+const S_IFDIR: u32 = 0o040000;
+/// cpio symlink file type.
+const S_IFLNK: u32 = 0o120000;
+
+/// Resolve the SDK directory name and path within the SDK for an archive path.
+///
+/// Returns [None] if the path is not under a `SDKs/*.sdk` directory.
+fn sdk_relative_path(archive_path: &str) -> Option<(&str, PathBuf)> {
+    let parts = archive_path
+        .split('/')
+        .filter(|x| !x.is_empty() && *x != ".")
+        .collect::<Vec<_>>();
+
+    let sdk_index = parts
+        .windows(2)
+        .position(|w| w[0] == "SDKs" && w[1].ends_with(".sdk"))?
+        + 1;
+
+    let rest = parts[sdk_index + 1..].iter().collect::<PathBuf>();
+
+    Some((parts[sdk_index], rest))
+}
+
+/// Resolve the filesystem path to write a relative archive path to.
+///
+/// Missing parent directories are created. Errors if the path has components
+/// that could escape `dest_dir` or if a parent directory is a symlink, as it
+/// could redirect the write elsewhere.
+fn extraction_path(dest_dir: &Path, rel_path: &Path, name: &str) -> Result<PathBuf, Error> {
+    let components = rel_path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(c) => Ok(c),
+            _ => Err(Error::ArchiveFormat(format!(
+                "illegal path in archive: {}",
+                name
+            ))),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let (file_name, dirs) = components
+        .split_last()
+        .ok_or_else(|| Error::ArchiveFormat(format!("illegal path in archive: {}", name)))?;
+
+    let mut parent = dest_dir.to_path_buf();
+
+    for dir in dirs {
+        parent.push(dir);
+
+        match parent.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(Error::ArchiveFormat(format!(
+                    "{} in archive is beneath symlink {}",
+                    name,
+                    parent.display()
+                )));
+            }
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                return Err(Error::ArchiveFormat(format!(
+                    "{} is not a directory",
+                    parent.display()
+                )));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => std::fs::create_dir(&parent)?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if !parent.canonicalize()?.starts_with(dest_dir.canonicalize()?) {
+        return Err(Error::ArchiveFormat(format!(
+            "{} in archive resolves outside {}",
+            name,
+            dest_dir.display()
+        )));
+    }
+
+    Ok(parent.join(file_name))
+}
+
+/// Extracts SDKs from Xcode `.xip` archives and Command Line Tools `.pkg` installers.
+///
+/// Each SDK found is written to a directory in the destination directory having the
+/// SDK's name. e.g. `MacOSX14.2.sdk`. Symlinks to SDKs (e.g. `MacOSX.sdk`) and
+/// within SDKs are preserved. The extracted directories can be loaded with
+/// [crate::SimpleSdk] and [crate::ParsedSdk].
+#[derive(Clone, Debug, Default)]
+pub struct SdkExtractor {
+    platforms: Vec<Platform>,
+}
+
+impl SdkExtractor {
+    /// Construct an instance that extracts SDKs for all platforms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only extract SDKs for the given platform.
+    ///
+    /// Can be called multiple times to extract SDKs for multiple platforms.
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platforms.push(platform);
+        self
+    }
+
+    fn want_sdk(&self, sdk_name: &str) -> bool {
+        if self.platforms.is_empty() {
+            return true;
+        }
+
+        match SdkPath::from_path(sdk_name) {
+            Ok(sdk) => self.platforms.contains(&sdk.platform),
+            Err(_) => false,
+        }
+    }
+
+    /// Extract SDKs from an Xcode `.xip` file.
+    ///
+    /// The `Content` of the archive is streamed, so the Xcode application is never
+    /// fully materialized in memory or on disk.
+    ///
+    /// Returns the paths of SDK directories and symlinks written.
+    pub fn extract_xip(
+        &self,
+        path: impl AsRef<Path>,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let xar = XarReader::new(std::fs::File::open(path.as_ref())?)?;
+
+        let content = xar
+            .find_file("Content")?
+            .ok_or_else(|| Error::ArchiveFormat("xip lacks Content file".to_string()))?;
+        let data = content
+            .data
+            .as_ref()
+            .ok_or_else(|| Error::ArchiveFormat("xip Content has no data".to_string()))?;
+
+        // We read the heap directly so we can stream the content.
+        if data.encoding.style != "application/octet-stream" {
+            return Err(Error::ArchiveFormat(format!(
+                "unsupported xip Content encoding: {}",
+                data.encoding.style
+            )));
+        }
+
+        let offset = xar.heap_start_offset() + data.offset;
+        let length = data.length;
+
+        let mut fh = xar.into_inner();
+        fh.seek(SeekFrom::Start(offset))?;
+
+        self.extract_pbzx_cpio(fh.take(length), dest_dir)
+    }
+
+    /// Extract SDKs from a Command Line Tools `.pkg` installer.
+    ///
+    /// All component packages are examined.
+    ///
+    /// Returns the paths of SDK directories and symlinks written.
+    pub fn extract_pkg(
+        &self,
+        path: impl AsRef<Path>,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut reader = PkgReader::new(std::fs::File::open(path.as_ref())?)?;
+
+        let mut components = reader.component_packages()?;
+        components.extend(reader.root_component()?);
+
+        let mut res = BTreeSet::new();
+
+        for component in components {
+            if let Some(cpio) = component.payload_reader()? {
+                res.extend(self.extract_cpio_reader(cpio, dest_dir.as_ref())?);
+            }
+        }
+
+        Ok(res.into_iter().collect())
+    }
+
+    /// Extract SDKs from a pbzx compressed cpio archive.
+    ///
+    /// This is the format of `.xip` `Content` and Command Line Tools `Payload` files.
+    pub fn extract_pbzx_cpio(
+        &self,
+        reader: impl Read + 'static,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        self.extract_cpio(PbzxReader::new(reader)?, dest_dir)
+    }
+
+    /// Extract SDKs from an uncompressed cpio archive.
+    pub fn extract_cpio(
+        &self,
+        reader: impl Read + 'static,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let cpio = cpio_archive::reader(reader)?;
+
+        self.extract_cpio_reader(cpio, dest_dir.as_ref())
+    }
+
+    fn extract_cpio_reader<T: Read>(
+        &self,
+        mut cpio: Box<cpio_archive::ChainedCpioReader<T>>,
+        dest_dir: &Path,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut sdks = BTreeSet::new();
+        // Symlinks are created after all other entries. Entries beneath a symlink are
+        // rejected so a symlink can't redirect where other entries are written.
+        let mut symlinks = vec![];
+
+        std::fs::create_dir_all(dest_dir)?;
+
+        while let Some(header) = cpio.read_next()? {
+            let Some((sdk_name, rel_path)) = sdk_relative_path(header.name()) else {
+                continue;
+            };
+
+            if !self.want_sdk(sdk_name) {
+                continue;
+            }
+
+            // Avoid a trailing separator for the SDK directory entry itself.
+            let rel_path = if rel_path.as_os_str().is_empty() {
+                PathBuf::from(sdk_name)
+            } else {
+                PathBuf::from(sdk_name).join(rel_path)
+            };
+
+            sdks.insert(dest_dir.join(sdk_name));
+
+            match header.mode() & S_IFMT {
+                S_IFDIR => {
+                    let dest_path = extraction_path(dest_dir, &rel_path, header.name())?;
+
+                    if !dest_path.is_dir() {
+                        std::fs::create_dir(&dest_path)?;
+                    }
+                }
+                S_IFLNK => {
+                    let mut target = String::new();
+                    cpio.read_to_string(&mut target)?;
+
+                    symlinks.push((rel_path, header.name().to_string(), target));
+                }
+                _ => {
+                    let dest_path = extraction_path(dest_dir, &rel_path, header.name())?;
+                    write_file(&mut cpio, header.as_ref(), &dest_path)?;
+                }
+            }
+        }
+
+        for (rel_path, name, target) in symlinks {
+            // Resolved now since previously created symlinks may be parents.
+            let path = extraction_path(dest_dir, &rel_path, &name)?;
+
+            if path.symlink_metadata().is_ok() {
+                std::fs::remove_file(&path)?;
+            }
+
+            create_symlink(&target, &path)?;
+        }
+
+        Ok(sdks.into_iter().collect())
+    }
+}
+
+fn write_file(reader: &mut impl Read, header: &dyn CpioHeader, path: &Path) -> Result<(), Error> {
+    // Replace rather than write through an existing symlink.
+    if path.is_symlink() {
+        std::fs::remove_file(path)?;
+    }
+
+    let mut fh = std::fs::File::create(path)?;
+    std::io::copy(reader, &mut fh)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fh.set_permissions(std::fs::Permissions::from_mode(header.mode() & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = header;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, path: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, path)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &str, _path: &Path) -> Result<(), Error> {
+    Err(Error::FunctionalityNotSupported(
+        "extracting symlinks on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{AppleSdk, ParsedSdk, SimpleSdk},
+        cpio_archive::OdcBuilder,
+        std::io::Cursor,
+    };
+
+    const MACOSX_11_3_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx11.3-settings.json");
+
+    const MACOS_SDKS: &str =
+        "./Xcode.app/Contents/Developer/Platforms/MacOSX.platform/Developer/SDKs";
+    const IOS_SDKS: &str =
+        "./Xcode.app/Contents/Developer/Platforms/iPhoneOS.platform/Developer/SDKs";
+
+    fn xcode_cpio() -> Result<Vec<u8>, Error> {
+        let mut builder = OdcBuilder::new(Cursor::new(vec![]));
+
+        let symlink = |builder: &mut OdcBuilder<_>, path: String, target: &str| {
+            let mut header = builder.next_header();
+            header.name = path;
+            header.mode = S_IFLNK | 0o755;
+            header.file_size = target.len() as _;
+            builder.append_header_with_data(header, target)
+        };
+
+        builder.append_file_from_data("./Xcode.app/Contents/Info.plist", b"ignored", 0o100644)?;
+        builder.append_file_from_data(
+            format!("{MACOS_SDKS}/MacOSX11.3.sdk/SDKSettings.json"),
+            MACOSX_11_3_SETTINGS_JSON,
+            0o100644,
+        )?;
+        builder.append_file_from_data(
+            format!("{MACOS_SDKS}/MacOSX11.3.sdk/usr/bin/tool"),
+            b"#!/bin/sh\n",
+            0o100755,
+        )?;
+        builder.append_file_from_data(
+            format!("{MACOS_SDKS}/MacOSX11.3.sdk/System/Library/Frameworks/Foo.framework/Versions/A/Foo.tbd"),
+            b"stub",
+            0o100644,
+        )?;
+        symlink(
+            &mut builder,
+            format!("{MACOS_SDKS}/MacOSX11.3.sdk/System/Library/Frameworks/Foo.framework/Foo.tbd"),
+            "Versions/A/Foo.tbd",
+        )?;
+        symlink(
+            &mut builder,
+            format!("{MACOS_SDKS}/MacOSX.sdk"),
+            "MacOSX11.3.sdk",
+        )?;
+        builder.append_file_from_data(
+            format!("{IOS_SDKS}/iPhoneOS14.5.sdk/SDKSettings.json"),
+            b"{}",
+            0o100644,
+        )?;
+
+        Ok(builder.into_inner()?.into_inner())
+    }
+
+    /// Wrap data in a pbzx stream having a single stored chunk.
+    fn pbzx(data: &[u8]) -> Vec<u8> {
+        let mut res = b"pbzx".to_vec();
+        res.extend((1u64 << 24).to_be_bytes());
+        res.extend((data.len() as u64).to_be_bytes());
+        res.extend((data.len() as u64).to_be_bytes());
+        res.extend(data);
+
+        res
+    }
+
+    #[test]
+    fn extract_xcode_content() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let dest = td.path();
+
+        let sdks =
+            SdkExtractor::new().extract_pbzx_cpio(Cursor::new(pbzx(&xcode_cpio()?)), dest)?;
+
+        assert_eq!(
+            sdks,
+            vec![
+                dest.join("MacOSX.sdk"),
+                dest.join("MacOSX11.3.sdk"),
+                dest.join("iPhoneOS14.5.sdk")
+            ]
+        );
+        assert!(!dest.join("Xcode.app").exists());
+
+        let sdk = ParsedSdk::from_directory(&dest.join("MacOSX11.3.sdk"))?;
+        assert_eq!(sdk.name, "macosx11.3");
+        assert!(!sdk.is_symlink());
+
+        let sdk = SimpleSdk::from_directory(&dest.join("MacOSX.sdk"))?;
+        assert!(sdk.is_symlink());
+
+        let framework = dest.join("MacOSX11.3.sdk/System/Library/Frameworks/Foo.framework");
+        assert_eq!(
+            std::fs::read_link(framework.join("Foo.tbd"))?,
+            PathBuf::from("Versions/A/Foo.tbd")
+        );
+        assert_eq!(std::fs::read(framework.join("Foo.tbd"))?, b"stub");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dest.join("MacOSX11.3.sdk/usr/bin/tool"))?
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_platform_filter() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let dest = td.path();
+
+        let sdks = SdkExtractor::new()
+            .platform(Platform::IPhoneOs)
+            .extract_cpio(Cursor::new(xcode_cpio()?), dest)?;
+
+        assert_eq!(sdks, vec![dest.join("iPhoneOS14.5.sdk")]);
+        assert!(!dest.join("MacOSX11.3.sdk").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_escaping_paths() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+
+        let mut builder = OdcBuilder::new(Cursor::new(vec![]));
+        builder.append_file_from_data("SDKs/MacOSX.sdk/../../escape", b"bad", 0o100644)?;
+        let data = builder.into_inner()?.into_inner();
+
+        assert!(matches!(
+            SdkExtractor::new().extract_cpio(Cursor::new(data), td.path()),
+            Err(Error::ArchiveFormat(_))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reject_writes_through_symlinks() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let dest = td.path().join("dest");
+        let outside = td.path().join("outside");
+        std::fs::create_dir(&outside)?;
+
+        let symlink = |builder: &mut OdcBuilder<_>, path: &str, target: &str| {
+            let mut header = builder.next_header();
+            header.name = path.to_string();
+            header.mode = S_IFLNK | 0o755;
+            header.file_size = target.len() as _;
+            builder.append_header_with_data(header, target)
+        };
+
+        // A symlink pointing outside followed by a symlink beneath it.
+        let mut builder = OdcBuilder::new(Cursor::new(vec![]));
+        symlink(
+            &mut builder,
+            "SDKs/MacOSX.sdk/usr",
+            outside.to_str().unwrap(),
+        )?;
+        symlink(&mut builder, "SDKs/MacOSX.sdk/usr/lib", "/etc")?;
+        let data = builder.into_inner()?.into_inner();
+
+        assert!(matches!(
+            SdkExtractor::new().extract_cpio(Cursor::new(data), &dest),
+            Err(Error::ArchiveFormat(_))
+        ));
+        assert_eq!(std::fs::read_dir(&outside)?.count(), 0);
+
+        // A file beneath a symlink left over from a previous extraction.
+        let mut builder = OdcBuilder::new(Cursor::new(vec![]));
+        builder.append_file_from_data("SDKs/MacOSX.sdk/usr/lib/libz.tbd", b"bad", 0o100644)?;
+        let data = builder.into_inner()?.into_inner();
+
+        assert!(matches!(
+            SdkExtractor::new().extract_cpio(Cursor::new(data), &dest),
+            Err(Error::ArchiveFormat(_))
+        ));
+        assert_eq!(std::fs::read_dir(&outside)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sdk_paths() {
+        assert_eq!(
+            sdk_relative_path(
+                "./Library/Developer/CommandLineTools/SDKs/MacOSX14.2.sdk/usr/lib/libz.tbd"
+            ),
+            Some(("MacOSX14.2.sdk", PathBuf::from("usr/lib/libz.tbd")))
+        );
+        assert_eq!(
+            sdk_relative_path("Library/Developer/CommandLineTools/SDKs/MacOSX.sdk"),
+            Some(("MacOSX.sdk", PathBuf::new()))
+        );
+        assert_eq!(
+            sdk_relative_path("./Library/Developer/CommandLineTools/usr/bin/clang"),
+            None
+        );
+    }
+}
@@ -62,6 +62,26 @@
 //! This functionality is exposed through the [SdkSearch] struct. See its
 //! documentation for more.
 //!
+//! # Text-Based Stubs
+//!
+//! SDKs describe their libraries using `.tbd` text-based stub files. With the
+//! `parse` crate feature, these can be parsed via [TbdFile]. [ParsedSdk] can also
+//! find which libraries in the SDK export a symbol via
+//! [ParsedSdk::find_symbol_exports()].
+//!
+//! # SDK Extraction
+//!
+//! With the `extract` crate feature, [SdkExtractor] can extract SDKs from Xcode
+//! `.xip` archives and Command Line Tools `.pkg` installers. This allows obtaining
+//! SDKs on machines not running macOS.
+//!
+//! # SDK Minimization
+//!
+//! With the `minimize` crate feature, [SdkMinimizer] computes the subset of an SDK
+//! needed to use specific frameworks, libraries, and headers. The resulting
+//! [SdkManifest] can be materialized to a directory or written as a reproducible
+//! tar archive along with a content digest suitable for caching.
+//!
 //! # Common Functionality
 //!
 //! To locate the default SDK to use, do something like this:
@@ -84,10 +104,18 @@
 //! }
 //! ```
 
+#[cfg(feature = "parse")]
+mod build_settings;
+#[cfg(feature = "extract")]
+mod extract;
+#[cfg(feature = "minimize")]
+mod minimize;
 #[cfg(feature = "parse")]
 mod parsed_sdk;
 mod search;
 mod simple_sdk;
+#[cfg(feature = "parse")]
+mod tbd;
 
 use std::{
     cmp::Ordering,
@@ -100,11 +128,25 @@ use std::{
 
 pub use crate::{search::*, simple_sdk::SimpleSdk};
 
+#[cfg(feature = "extract")]
+pub use crate::extract::SdkExtractor;
+
+#[cfg(feature = "parse")]
+pub use crate::build_settings::{apple_arch_from_target_triple, SdkBuildSettings};
+
+#[cfg(feature = "minimize")]
+pub use crate::minimize::{SdkManifest, SdkManifestEntryKind, SdkMinimizer, DETERMINISTIC_MTIME};
+
 #[cfg(feature = "parse")]
 pub use crate::parsed_sdk::{
     ParsedSdk, SdkSettingsJson, SdkSettingsJsonDefaultProperties, SupportedTarget,
 };
 
+#[cfg(feature = "parse")]
+pub use crate::tbd::{
+    SdkSymbolExport, TbdFile, TbdLibrary, TbdReexportedLibrary, TbdSymbol, TbdSymbolKind, TbdTarget,
+};
+
 /// Default install path for the Xcode command line tools.
 pub const COMMAND_LINE_TOOLS_DEFAULT_PATH: &str = "/Library/Developer/CommandLineTools";
 
@@ -116,6 +158,11 @@ pub const XCODE_APP_DEFAULT_PATH: &str = "/Applications/Xcode.app";
 /// This directory contains platforms, toolchains, etc.
 pub const XCODE_APP_RELATIVE_PATH_DEVELOPER: &str = "Contents/Developer";
 
+/// Name of the macOS SDK supported target / variant for Mac Catalyst.
+///
+/// Mac Catalyst (iOS apps running on macOS) is targeted using the macOS SDK.
+pub const MAC_CATALYST_TARGET_NAME: &str = "iosmac";
+
 /// Error type for this crate.
 #[derive(Debug)]
 pub enum Error {
@@ -155,8 +202,22 @@ pub enum Error {
     SerdeJson(serde_json::Error),
     #[cfg(feature = "plist")]
     Plist(plist::Error),
+    #[cfg(feature = "parse")]
+    Yaml(serde_yaml::Error),
+    /// Error parsing a `.tbd` file.
+    TbdParse(String),
     /// Maybe a new target is added to rust toolchain.
     UnknownTarget(String),
+    /// A target or deployment target is not supported by an SDK.
+    TargetNotSupported(String),
+    #[cfg(feature = "extract")]
+    Xar(apple_xar::Error),
+    #[cfg(feature = "extract")]
+    FlatPackage(apple_flat_package::Error),
+    #[cfg(feature = "extract")]
+    Cpio(cpio_archive::Error),
+    /// An archive being extracted is malformed or not supported.
+    ArchiveFormat(String),
 }
 
 impl Display for Error {
@@ -195,7 +256,18 @@ impl Display for Error {
             Self::SerdeJson(err) => f.write_fmt(format_args!("JSON parsing error: {err}")),
             #[cfg(feature = "plist")]
             Self::Plist(err) => f.write_fmt(format_args!("plist error: {err}")),
+            #[cfg(feature = "parse")]
+            Self::Yaml(err) => f.write_fmt(format_args!("YAML error: {err}")),
+            Self::TbdParse(s) => f.write_fmt(format_args!("TBD parse error: {s}")),
+            #[cfg(feature = "extract")]
+            Self::Xar(err) => f.write_fmt(format_args!("XAR error: {err}")),
+            #[cfg(feature = "extract")]
+            Self::FlatPackage(err) => f.write_fmt(format_args!("flat package error: {err}")),
+            #[cfg(feature = "extract")]
+            Self::Cpio(err) => f.write_fmt(format_args!("cpio error: {err}")),
+            Self::ArchiveFormat(s) => f.write_fmt(format_args!("archive format error: {s}")),
             Self::UnknownTarget(target) => f.write_fmt(format_args!("unknown target: {target}")),
+            Self::TargetNotSupported(s) => f.write_fmt(format_args!("target not supported: {s}")),
         }
     }
 }
@@ -222,6 +294,34 @@ impl From<plist::Error> for Error {
     }
 }
 
+#[cfg(feature = "parse")]
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::Yaml(e)
+    }
+}
+
+#[cfg(feature = "extract")]
+impl From<apple_xar::Error> for Error {
+    fn from(e: apple_xar::Error) -> Self {
+        Self::Xar(e)
+    }
+}
+
+#[cfg(feature = "extract")]
+impl From<apple_flat_package::Error> for Error {
+    fn from(e: apple_flat_package::Error) -> Self {
+        Self::FlatPackage(e)
+    }
+}
+
+#[cfg(feature = "extract")]
+impl From<cpio_archive::Error> for Error {
+    fn from(e: cpio_archive::Error) -> Self {
+        Self::Cpio(e)
+    }
+}
+
 /// A known Apple platform type.
 ///
 /// Instances are equivalent to each other if their filesystem representation
@@ -237,6 +337,8 @@ pub enum Platform {
     MacOsX,
     WatchOs,
     WatchSimulator,
+    XrOs,
+    XrSimulator,
     Unknown(String),
 }
 
@@ -254,6 +356,8 @@ impl FromStr for Platform {
             "macosx" => Ok(Self::MacOsX),
             "watchos" => Ok(Self::WatchOs),
             "watchsimulator" => Ok(Self::WatchSimulator),
+            "xros" | "visionos" => Ok(Self::XrOs),
+            "xrsimulator" | "visionossimulator" => Ok(Self::XrSimulator),
             v => Ok(Self::Unknown(v.to_string())),
         }
     }
@@ -302,27 +406,67 @@ impl Platform {
     /// Attempt to construct an instance from a target triple.
     ///
     /// The argument should be a target triple of a Rust toolchain. e.g.
-    /// `x86_64-apple-darwin`.
+    /// `x86_64-apple-darwin`. Clang style `*-apple-macosx`, `*-apple-xros` and
+    /// `*-apple-driverkit` triples are also recognized.
+    ///
+    /// Mac Catalyst triples (`*-apple-ios-macabi`) resolve to [Self::MacOsX], as
+    /// Mac Catalyst binaries are built against the macOS SDK using its `iosmac`
+    /// variant. See [Self::supported_target_name_from_target_triple()].
     ///
     /// Will return [Error::UnknownTarget] if this does not appear to be a known
     /// target triple.
     pub fn from_target_triple(target: &str) -> Result<Self, Error> {
         let platform = match target {
             target if target.ends_with("-apple-darwin") => Self::MacOsX,
+            target if target.ends_with("-apple-macosx") => Self::MacOsX,
+            target if target.ends_with("-apple-ios-macabi") => Self::MacOsX,
             "i386-apple-ios" | "x86_64-apple-ios" => Self::IPhoneSimulator,
             target if target.ends_with("-apple-ios-sim") => Platform::IPhoneSimulator,
             target if target.ends_with("-apple-ios") => Platform::IPhoneOs,
-            target if target.ends_with("-apple-ios-macabi") => Platform::IPhoneOs,
             "i386-apple-watchos" => Self::WatchSimulator,
             target if target.ends_with("-apple-watchos-sim") => Self::WatchSimulator,
             target if target.ends_with("-apple-watchos") => Platform::WatchOs,
             "x86_64-apple-tvos" => Self::AppleTvSimulator,
+            target if target.ends_with("-apple-tvos-sim") => Self::AppleTvSimulator,
             target if target.ends_with("-apple-tvos") => Platform::AppleTvOs,
+            target if target.ends_with("-apple-visionos-sim") => Self::XrSimulator,
+            target if target.ends_with("-apple-xros-simulator") => Self::XrSimulator,
+            target if target.ends_with("-apple-visionos") => Self::XrOs,
+            target if target.ends_with("-apple-xros") => Self::XrOs,
+            target if target.ends_with("-apple-driverkit") => Self::DriverKit,
             _ => return Err(Error::UnknownTarget(target.to_string())),
         };
         Ok(platform)
     }
 
+    /// Whether a target triple targets Mac Catalyst.
+    ///
+    /// Mac Catalyst (iOS apps running on macOS) triples have an `-ios-macabi` suffix.
+    /// e.g. `aarch64-apple-ios-macabi`.
+    pub fn is_mac_catalyst_target_triple(target: &str) -> bool {
+        target.ends_with("-apple-ios-macabi")
+    }
+
+    /// Obtain the SDK supported target name for a target triple.
+    ///
+    /// The returned value is the key in the `SupportedTargets` dictionary of
+    /// `SDKSettings.json` files describing the target triple. It is usually the same as
+    /// [Self::supported_target_name()] for the platform of the triple. However, Mac
+    /// Catalyst triples resolve to [MAC_CATALYST_TARGET_NAME] (`iosmac`), the name of
+    /// the macOS SDK variant used to build them.
+    ///
+    /// Will return [Error::UnknownTarget] if this does not appear to be a known
+    /// target triple.
+    pub fn supported_target_name_from_target_triple(target: &str) -> Result<String, Error> {
+        let platform = Self::from_target_triple(target)?;
+
+        if Self::is_mac_catalyst_target_triple(target) {
+            Ok(MAC_CATALYST_TARGET_NAME.to_string())
+        } else {
+            Ok(platform.supported_target_name())
+        }
+    }
+
     /// Obtain the name of this platform as used in filesystem paths.
     ///
     /// This is just the platform part of the name without the trailing
@@ -339,10 +483,21 @@ impl Platform {
             Self::MacOsX => "MacOSX",
             Self::WatchOs => "WatchOS",
             Self::WatchSimulator => "WatchSimulator",
+            Self::XrOs => "XROS",
+            Self::XrSimulator => "XRSimulator",
             Self::Unknown(v) => v,
         }
     }
 
+    /// Obtain the name of the default SDK supported target for this platform.
+    ///
+    /// This is the key in the `SupportedTargets` dictionary of `SDKSettings.json`
+    /// files used to target this platform. e.g. `macosx` or `xros`. It is also the
+    /// value of the `PLATFORM_NAME` SDK property.
+    pub fn supported_target_name(&self) -> String {
+        self.filesystem_name().to_ascii_lowercase()
+    }
+
     /// Obtain the directory name of this platform.
     ///
     /// This simply appends `.platform` to [Self::filesystem_name()].
@@ -1054,6 +1209,49 @@ mod test {
     fn apple_platform() -> Result<(), Error> {
         assert_eq!(Platform::from_str("macosx")?, Platform::MacOsX);
         assert_eq!(Platform::from_str("MacOSX")?, Platform::MacOsX);
+        assert_eq!(Platform::from_str("XROS")?, Platform::XrOs);
+        assert_eq!(Platform::from_str("visionOS")?, Platform::XrOs);
+        assert_eq!(Platform::from_str("XRSimulator")?, Platform::XrSimulator);
+        assert_eq!(
+            Platform::from_platform_path(Path::new("XRSimulator.platform"))?,
+            Platform::XrSimulator
+        );
+        assert_eq!(Platform::XrOs.supported_target_name(), "xros");
+        assert_eq!(
+            Platform::IPhoneSimulator.supported_target_name(),
+            "iphonesimulator"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_supported_target_name() -> Result<(), Error> {
+        fn test(target: &str, name: &str) {
+            assert_eq!(
+                Platform::supported_target_name_from_target_triple(target).unwrap(),
+                name
+            );
+        }
+        test("aarch64-apple-darwin", "macosx");
+        test("arm64e-apple-darwin", "macosx");
+        test("aarch64-apple-ios-macabi", "iosmac");
+        test("x86_64-apple-ios-macabi", "iosmac");
+        test("aarch64-apple-ios", "iphoneos");
+        test("aarch64-apple-ios-sim", "iphonesimulator");
+        test("aarch64-apple-visionos", "xros");
+        test("aarch64-apple-visionos-sim", "xrsimulator");
+        test("arm64-apple-driverkit", "driverkit");
+
+        assert!(Platform::is_mac_catalyst_target_triple(
+            "aarch64-apple-ios-macabi"
+        ));
+        assert!(!Platform::is_mac_catalyst_target_triple(
+            "aarch64-apple-ios"
+        ));
+        assert!(
+            Platform::supported_target_name_from_target_triple("x86_64-pc-windows-msvc").is_err()
+        );
 
         Ok(())
     }
@@ -1066,7 +1264,7 @@ mod test {
         }
         test("aarch64-apple-darwin", MacOsX);
         test("aarch64-apple-ios", IPhoneOs);
-        test("aarch64-apple-ios-macabi", IPhoneOs);
+        test("aarch64-apple-ios-macabi", MacOsX);
         test("aarch64-apple-ios-sim", IPhoneSimulator);
         test("aarch64-apple-tvos", AppleTvOs); // this can also can be simulator
         test("aarch64-apple-watchos-sim", WatchSimulator);
@@ -1078,9 +1276,19 @@ mod test {
         test("i686-apple-darwin", MacOsX);
         test("x86_64-apple-darwin", MacOsX);
         test("x86_64-apple-ios", IPhoneSimulator);
-        test("x86_64-apple-ios-macabi", IPhoneOs);
+        test("x86_64-apple-ios-macabi", MacOsX);
         test("x86_64-apple-tvos", AppleTvSimulator);
         test("x86_64-apple-watchos-sim", WatchSimulator);
+        test("aarch64-apple-tvos-sim", AppleTvSimulator);
+        test("aarch64-apple-visionos", XrOs);
+        test("aarch64-apple-visionos-sim", XrSimulator);
+        test("arm64-apple-xros", XrOs);
+        test("arm64-apple-xros-simulator", XrSimulator);
+        test("arm64e-apple-darwin", MacOsX);
+        test("arm64e-apple-ios", IPhoneOs);
+        test("arm64e-apple-tvos", AppleTvOs);
+        test("arm64-apple-macosx", MacOsX);
+        test("arm64-apple-driverkit", DriverKit);
 
         assert!(Platform::from_target_triple("x86_64-unknown-linux-gnu").is_err());
 
@@ -0,0 +1,1364 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! SDK minimization and reproducible SDK archives.
+//!
+//! Full SDKs are large. Hermetic builds often only need a handful of frameworks
+//! and libraries. [SdkMinimizer] computes the closure of SDK files needed to use a
+//! set of frameworks, libraries, and headers:
+//!
+//! * Frameworks are included in their entirety (headers, modules, `.tbd` stubs).
+//! * Libraries re-exported by an included `.tbd` file are included.
+//! * Headers referenced by `#include`, `#import`, and `@import` directives in
+//!   included headers are included. `<Foo/Bar.h>` references a framework if
+//!   `Foo.framework` exists. Otherwise it is resolved against `usr/include`.
+//!   `@import Foo` references a framework if `Foo.framework` exists. Otherwise the
+//!   module map in `usr/include` declaring the module is included.
+//! * Headers and umbrella directories declared by included module maps are
+//!   included.
+//! * `.tbd` files which can't be parsed are included without their re-exports.
+//! * Symlink targets are included. Paths reached through symlinked directories
+//!   are recorded at their real location.
+//! * `SDKSettings.json` and `SDKSettings.plist` are always included.
+//!
+//! The result is an [SdkManifest], which can be materialized to a directory or
+//! written as a deterministic tar archive.
+
+use {
+    crate::{AppleSdk, Error, ParsedSdk, TbdFile},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::{BTreeMap, BTreeSet, VecDeque},
+        ffi::OsString,
+        io::{BufRead, Write},
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// Directory in SDKs holding frameworks.
+const FRAMEWORKS_DIRECTORY: &str = "System/Library/Frameworks";
+
+/// Directory in SDKs holding headers.
+const INCLUDE_DIRECTORY: &str = "usr/include";
+
+/// Files always included in minimized SDKs.
+const SDK_SETTINGS_FILES: &[&str] = &["SDKSettings.json", "SDKSettings.plist"];
+
+/// Modification time of all entries in tar archives written by [SdkManifest::write_tar()].
+///
+/// This is 2000-01-01T00:00:00Z. A fixed value ensures the archive content only
+/// depends on SDK content.
+pub const DETERMINISTIC_MTIME: u64 = 946684800;
+
+/// Maximum number of symlinks followed when resolving a path.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// The type of an entry in an [SdkManifest].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SdkManifestEntryKind {
+    /// A directory.
+    Directory,
+    /// A regular file.
+    File {
+        /// Size of the file in bytes.
+        size: u64,
+        /// Whether the file is executable.
+        executable: bool,
+        /// Hex encoded SHA-256 digest of the file content.
+        sha256: String,
+    },
+    /// A symlink.
+    Symlink {
+        /// The symlink target, as stored in the link.
+        target: PathBuf,
+    },
+}
+
+/// A set of files in an SDK.
+///
+/// Paths are relative to the SDK root and iterated in sorted order.
+#[derive(Clone, Debug)]
+pub struct SdkManifest {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, SdkManifestEntryKind>,
+}
+
+impl SdkManifest {
+    /// The root directory of the SDK this manifest was derived from.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Entries in this manifest, sorted by path.
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &SdkManifestEntryKind)> {
+        self.entries.iter().map(|(k, v)| (k.as_path(), v))
+    }
+
+    /// Whether the manifest has an entry for a relative path.
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        self.entries.contains_key(path.as_ref())
+    }
+
+    /// Obtain a textual representation of the manifest.
+    ///
+    /// There is one line per entry, sorted by path:
+    ///
+    /// * `dir <path>`
+    /// * `file <sha256> <mode> <size> <path>`
+    /// * `symlink <path> <target>`
+    ///
+    /// Paths always use `/` as the separator.
+    pub fn to_text(&self) -> String {
+        let mut res = String::new();
+
+        for (path, entry) in &self.entries {
+            let path = archive_path(path);
+
+            let line = match entry {
+                SdkManifestEntryKind::Directory => format!("dir {path}\n"),
+                SdkManifestEntryKind::File {
+                    size,
+                    executable,
+                    sha256,
+                } => format!(
+                    "file {} {:o} {} {}\n",
+                    sha256,
+                    file_mode(*executable),
+                    size,
+                    path
+                ),
+                SdkManifestEntryKind::Symlink { target } => {
+                    format!("symlink {} {}\n", path, archive_path(target))
+                }
+            };
+
+            res.push_str(&line);
+        }
+
+        res
+    }
+
+    /// Hex encoded SHA-256 digest of [Self::to_text()].
+    ///
+    /// This uniquely identifies the content of the manifest and is suitable as a
+    /// cache key.
+    pub fn digest(&self) -> String {
+        hex::encode(Sha256::digest(self.to_text().as_bytes()))
+    }
+
+    /// Write the manifest's files to a new SDK directory.
+    ///
+    /// `dest_dir` is the SDK directory to create. e.g. `/tmp/MacOSX11.3.sdk`.
+    pub fn materialize(&self, dest_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dest_dir = dest_dir.as_ref();
+        std::fs::create_dir_all(dest_dir)?;
+
+        // Symlinks are created last so they can't influence where files are written.
+        let mut symlinks = vec![];
+
+        for (path, entry) in &self.entries {
+            let dest_path = dest_dir.join(path);
+
+            match entry {
+                SdkManifestEntryKind::Directory => {
+                    std::fs::create_dir_all(&dest_path)?;
+                }
+                SdkManifestEntryKind::File { executable, .. } => {
+                    if let Some(parent) = dest_path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+
+                    std::fs::copy(self.root.join(path), &dest_path)?;
+
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        std::fs::set_permissions(
+                            &dest_path,
+                            std::fs::Permissions::from_mode(file_mode(*executable)),
+                        )?;
+                    }
+                }
+                SdkManifestEntryKind::Symlink { target } => {
+                    symlinks.push((dest_path, target));
+                }
+            }
+        }
+
+        for (path, target) in symlinks {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            create_symlink(target, &path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a deterministic tar archive of the manifest's files.
+    ///
+    /// Entries are prefixed by `prefix` (typically the SDK directory name, e.g.
+    /// `MacOSX11.3.sdk`) and written in sorted order. Parent directories are
+    /// always emitted. All entries have mtime [DETERMINISTIC_MTIME], uid and gid 0,
+    /// and a mode of `0755` (directories and executables) or `0644`. So the archive
+    /// is byte identical for identical manifests.
+    ///
+    /// The archive is not compressed. Wrap the writer in a compressor if desired.
+    pub fn write_tar<W: Write>(&self, prefix: &str, writer: W) -> Result<W, Error> {
+        let mut builder = tar::Builder::new(writer);
+
+        // Collect all directories, including implicit parents.
+        let mut directories = BTreeSet::new();
+        directories.insert(PathBuf::new());
+        for (path, entry) in &self.entries {
+            if matches!(entry, SdkManifestEntryKind::Directory) {
+                directories.insert(path.clone());
+            }
+
+            let mut parent = path.parent();
+            while let Some(p) = parent {
+                directories.insert(p.to_path_buf());
+                parent = p.parent();
+            }
+        }
+
+        let mut paths = directories
+            .iter()
+            .map(|p| (p.clone(), None))
+            .chain(
+                self.entries
+                    .iter()
+                    .filter(|(_, e)| !matches!(e, SdkManifestEntryKind::Directory))
+                    .map(|(p, e)| (p.clone(), Some(e))),
+            )
+            .collect::<Vec<_>>();
+        // Sort on the archive path so ordering is platform independent.
+        paths.sort_by_cached_key(|(p, _)| archive_path(p));
+
+        for (path, entry) in paths {
+            let name = if path.as_os_str().is_empty() {
+                format!("{prefix}/")
+            } else {
+                format!("{}/{}", prefix, archive_path(&path))
+            };
+
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(DETERMINISTIC_MTIME);
+            header.set_uid(0);
+            header.set_gid(0);
+
+            match entry {
+                None => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_data(&mut header, &name, std::io::empty())?;
+                }
+                Some(SdkManifestEntryKind::File {
+                    size, executable, ..
+                }) => {
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_mode(file_mode(*executable));
+                    header.set_size(*size);
+
+                    let fh = std::fs::File::open(self.root.join(&path))?;
+                    builder.append_data(&mut header, &name, fh)?;
+                }
+                Some(SdkManifestEntryKind::Symlink { target }) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_link(&mut header, &name, archive_path(target))?;
+                }
+                Some(SdkManifestEntryKind::Directory) => unreachable!("directories filtered above"),
+            }
+        }
+
+        Ok(builder.into_inner()?)
+    }
+}
+
+/// Normalize a relative path to a `/` delimited string.
+fn archive_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn file_mode(executable: bool) -> u32 {
+    if executable {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, path)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _path: &Path) -> Result<(), Error> {
+    Err(Error::FunctionalityNotSupported(
+        "materializing symlinks on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Lexically resolve a path relative to the SDK root.
+///
+/// Returns [None] if the path escapes the root.
+fn normalize_relative(path: &Path) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::Normal(v) => res.push(v),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !res.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(res)
+}
+
+/// A header reference found in a source file.
+#[derive(Debug, Eq, PartialEq)]
+enum HeaderReference {
+    /// `#include <...>`.
+    Angle(String),
+    /// `#include "..."`.
+    Quote(String),
+    /// `@import Module;`.
+    Module(String),
+}
+
+/// Find header references in C / Objective-C source.
+fn header_references(data: &[u8]) -> Vec<HeaderReference> {
+    let mut res = vec![];
+
+    for line in data.lines().map_while(Result::ok) {
+        let line = line.trim_start();
+
+        if let Some(rest) = line.strip_prefix('#') {
+            let rest = rest.trim_start();
+
+            let Some(rest) = ["include_next", "include", "import"]
+                .iter()
+                .find_map(|d| rest.strip_prefix(d))
+            else {
+                continue;
+            };
+            let rest = rest.trim_start();
+
+            if let Some(v) = rest.strip_prefix('<') {
+                if let Some((name, _)) = v.split_once('>') {
+                    res.push(HeaderReference::Angle(name.to_string()));
+                }
+            } else if let Some(v) = rest.strip_prefix('"') {
+                if let Some((name, _)) = v.split_once('"') {
+                    res.push(HeaderReference::Quote(name.to_string()));
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("@import") {
+            let module = rest
+                .trim()
+                .trim_end_matches(';')
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .trim();
+
+            if !module.is_empty() {
+                res.push(HeaderReference::Module(module.to_string()));
+            }
+        }
+    }
+
+    res
+}
+
+/// A file referenced by a module map.
+#[derive(Debug, Eq, PartialEq)]
+enum ModuleMapReference {
+    /// `header "..."`, possibly `private`, `textual`, or `umbrella`.
+    Header { name: String, private: bool },
+    /// `umbrella "..."`.
+    Directory(String),
+    /// `extern module Name "..."`.
+    ModuleMap(String),
+}
+
+/// A token in a module map.
+#[derive(Debug, Eq, PartialEq)]
+enum ModuleMapToken {
+    /// An identifier or keyword.
+    Word(String),
+    /// A string literal.
+    String(String),
+    /// Any other character.
+    Punct(char),
+}
+
+/// Split module map source into tokens, ignoring comments.
+fn module_map_tokens(data: &[u8]) -> Vec<ModuleMapToken> {
+    let data = String::from_utf8_lossy(data);
+    let mut chars = data.chars().peekable();
+    let mut res = vec![];
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            '"' => {
+                let mut value = String::new();
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                }
+                res.push(ModuleMapToken::String(value));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut value = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    value.push(c);
+                }
+                res.push(ModuleMapToken::Word(value));
+            }
+            c => res.push(ModuleMapToken::Punct(c)),
+        }
+    }
+
+    res
+}
+
+/// Find headers and directories referenced by a module map.
+///
+/// `exclude header` declarations are ignored.
+fn module_map_references(data: &[u8]) -> Vec<ModuleMapReference> {
+    let mut res = vec![];
+    let mut keywords = vec![];
+
+    for token in module_map_tokens(data) {
+        match token {
+            ModuleMapToken::Word(word) => keywords.push(word),
+            ModuleMapToken::String(name) => {
+                let valid = keywords
+                    .iter()
+                    .all(|k| ["private", "textual", "umbrella", "header"].contains(&k.as_str()));
+
+                match keywords.last().map(|k| k.as_str()) {
+                    Some("header") if valid => res.push(ModuleMapReference::Header {
+                        name,
+                        private: keywords.iter().any(|k| k == "private"),
+                    }),
+                    Some("umbrella") if valid => res.push(ModuleMapReference::Directory(name)),
+                    _ if keywords.len() == 3
+                        && keywords[0] == "extern"
+                        && keywords[1] == "module" =>
+                    {
+                        res.push(ModuleMapReference::ModuleMap(name))
+                    }
+                    _ => {}
+                }
+
+                keywords.clear();
+            }
+            ModuleMapToken::Punct(_) => keywords.clear(),
+        }
+    }
+
+    res
+}
+
+/// Find the names of top-level modules declared by a module map.
+fn module_map_modules(data: &[u8]) -> Vec<String> {
+    let mut res = vec![];
+    let mut depth = 0usize;
+    let mut previous = None;
+
+    for token in module_map_tokens(data) {
+        match &token {
+            ModuleMapToken::Punct('{') => depth += 1,
+            ModuleMapToken::Punct('}') => depth = depth.saturating_sub(1),
+            ModuleMapToken::Word(name) if depth == 0 => {
+                if let Some(ModuleMapToken::Word(keyword)) = &previous {
+                    if keyword == "module" {
+                        res.push(name.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        previous = Some(token);
+    }
+
+    res
+}
+
+/// Computes the minimal set of SDK files needed to use frameworks, libraries, and headers.
+///
+/// See the [module documentation](self) for the dependency rules.
+#[derive(Clone, Debug)]
+pub struct SdkMinimizer<'a> {
+    sdk: &'a ParsedSdk,
+    roots: Vec<PathBuf>,
+}
+
+impl<'a> SdkMinimizer<'a> {
+    /// Construct an instance for an SDK.
+    pub fn new(sdk: &'a ParsedSdk) -> Self {
+        Self { sdk, roots: vec![] }
+    }
+
+    /// Include a framework and its dependencies. e.g. `Foundation`.
+    pub fn framework(mut self, name: impl AsRef<str>) -> Self {
+        self.roots
+            .push(PathBuf::from(FRAMEWORKS_DIRECTORY).join(format!("{}.framework", name.as_ref())));
+        self
+    }
+
+    /// Include a library in `usr/lib` and its dependencies.
+    ///
+    /// The name is the library file name without extension. e.g. `libz` or `libSystem`.
+    pub fn library(mut self, name: impl AsRef<str>) -> Self {
+        self.roots
+            .push(PathBuf::from("usr/lib").join(format!("{}.tbd", name.as_ref())));
+        self
+    }
+
+    /// Include a header in `usr/include` and the headers it references. e.g. `stdio.h`.
+    pub fn header(mut self, path: impl AsRef<Path>) -> Self {
+        self.roots
+            .push(PathBuf::from(INCLUDE_DIRECTORY).join(path.as_ref()));
+        self
+    }
+
+    /// Include an arbitrary path relative to the SDK root and its dependencies.
+    ///
+    /// Directories are included in their entirety.
+    pub fn path(mut self, path: impl AsRef<Path>) -> Self {
+        self.roots.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Compute the dependency closure.
+    ///
+    /// Errors if a root doesn't exist in the SDK. Missing dependencies (e.g.
+    /// re-exported libraries that aren't shipped in SDKs) are ignored.
+    pub fn resolve(&self) -> Result<SdkManifest, Error> {
+        let root = self.sdk.path();
+
+        let mut walker = Walker {
+            root,
+            entries: BTreeMap::new(),
+            queue: VecDeque::new(),
+            unfollowed_links: BTreeSet::new(),
+            include_modules: None,
+        };
+
+        for path in &self.roots {
+            let normalized =
+                normalize_relative(path).ok_or_else(|| Error::PathNotSdk(root.join(path)))?;
+
+            if root.join(&normalized).symlink_metadata().is_err() {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} not found in SDK", path.display()),
+                )));
+            }
+
+            walker.queue.push_back(normalized);
+        }
+
+        for name in SDK_SETTINGS_FILES {
+            if root.join(name).exists() {
+                walker.queue.push_back(PathBuf::from(name));
+            }
+        }
+
+        walker.run()?;
+
+        Ok(SdkManifest {
+            root: root.to_path_buf(),
+            entries: walker.entries,
+        })
+    }
+}
+
+struct Walker<'a> {
+    root: &'a Path,
+    entries: BTreeMap<PathBuf, SdkManifestEntryKind>,
+    queue: VecDeque<PathBuf>,
+    /// Symlinks recorded by [Self::resolve_parents()] whose targets weren't followed.
+    unfollowed_links: BTreeSet<PathBuf>,
+    /// Module names declared by module maps in `usr/include` and their module map.
+    ///
+    /// Populated on first use.
+    include_modules: Option<BTreeMap<String, PathBuf>>,
+}
+
+impl<'a> Walker<'a> {
+    fn run(&mut self) -> Result<(), Error> {
+        while let Some(path) = self.queue.pop_front() {
+            let Some(path) = self.resolve_parents(&path)? else {
+                continue;
+            };
+
+            if self.entries.contains_key(&path) && !self.unfollowed_links.remove(&path) {
+                continue;
+            }
+
+            let full_path = self.root.join(&path);
+            let metadata = match std::fs::symlink_metadata(&full_path) {
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(&full_path)?;
+
+                // Absolute symlinks and symlinks leaving the SDK are recorded but not
+                // followed.
+                if target.is_relative() {
+                    if let Some(resolved) =
+                        normalize_relative(&path.parent().unwrap_or(Path::new("")).join(&target))
+                    {
+                        self.queue.push_back(resolved);
+                    }
+                }
+
+                self.entries
+                    .insert(path, SdkManifestEntryKind::Symlink { target });
+            } else if metadata.is_dir() {
+                let mut children = std::fs::read_dir(&full_path)?
+                    .map(|entry| Ok(path.join(entry?.file_name())))
+                    .collect::<Result<Vec<_>, std::io::Error>>()?;
+                children.sort();
+
+                self.queue.extend(children);
+                self.entries.insert(path, SdkManifestEntryKind::Directory);
+            } else {
+                let data = std::fs::read(&full_path)?;
+
+                self.scan_file(&path, &data)?;
+
+                self.entries.insert(
+                    path,
+                    SdkManifestEntryKind::File {
+                        size: data.len() as u64,
+                        executable: is_executable(&metadata),
+                        sha256: hex::encode(Sha256::digest(&data)),
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolve symlinks in the parent directories of a path.
+    ///
+    /// [std::fs::symlink_metadata()] follows symlinked parents. So a path reached
+    /// through e.g. `Versions/Current` would be recorded as a real directory next
+    /// to the `Versions/Current` symlink. Instead, the path is rewritten to its
+    /// real location and the traversed symlinks are recorded.
+    ///
+    /// Returns [None] if resolution leaves the SDK or doesn't terminate.
+    fn resolve_parents(&mut self, path: &Path) -> Result<Option<PathBuf>, Error> {
+        let mut pending = path
+            .components()
+            .map(|c| c.as_os_str().to_os_string())
+            .collect::<VecDeque<OsString>>();
+        let mut resolved = PathBuf::new();
+        let mut hops = 0;
+
+        while let Some(name) = pending.pop_front() {
+            let candidate = resolved.join(name);
+
+            // The final component is handled by the caller.
+            if pending.is_empty() {
+                return Ok(Some(candidate));
+            }
+
+            let full_path = self.root.join(&candidate);
+            let metadata = match std::fs::symlink_metadata(&full_path) {
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            if !metadata.file_type().is_symlink() {
+                resolved = candidate;
+                continue;
+            }
+
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Ok(None);
+            }
+
+            let target = std::fs::read_link(&full_path)?;
+            if target.is_absolute() {
+                return Ok(None);
+            }
+            let Some(resolved_target) = normalize_relative(&resolved.join(&target)) else {
+                return Ok(None);
+            };
+
+            // Only the link is needed, not everything it points to.
+            if !self.entries.contains_key(&candidate) {
+                self.unfollowed_links.insert(candidate.clone());
+                self.entries
+                    .insert(candidate, SdkManifestEntryKind::Symlink { target });
+            }
+
+            // The target may traverse symlinks itself. So resolve it from the root.
+            for c in resolved_target.components().rev() {
+                pending.push_front(c.as_os_str().to_os_string());
+            }
+            resolved = PathBuf::new();
+        }
+
+        Ok(Some(resolved))
+    }
+
+    /// Queue dependencies of a file.
+    fn scan_file(&mut self, path: &Path, data: &[u8]) -> Result<(), Error> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("tbd") => {
+                let tbd = match TbdFile::parse(data) {
+                    Ok(tbd) => tbd,
+                    Err(e) => {
+                        log::warn!("not following re-exports of {}: {}", path.display(), e);
+                        return Ok(());
+                    }
+                };
+
+                for library in &tbd.libraries {
+                    for reexport in &library.reexported_libraries {
+                        if let Some(path) = self.install_name_path(&reexport.install_name) {
+                            self.queue.push_back(path);
+                        }
+                    }
+                }
+            }
+            Some("h") | Some("hpp") => {
+                for reference in header_references(data) {
+                    if let Some(path) = self.resolve_header_reference(path, &reference)? {
+                        self.queue.push_back(path);
+                    }
+                }
+            }
+            Some(extension) if extension == "modulemap" || path.ends_with("module.map") => {
+                for reference in module_map_references(data) {
+                    if let Some(path) = self.resolve_module_map_reference(path, &reference) {
+                        self.queue.push_back(path);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the SDK path to include for a library install name.
+    fn install_name_path(&self, install_name: &str) -> Option<PathBuf> {
+        let path = normalize_relative(Path::new(install_name.trim_start_matches('/')))?;
+
+        if let Some(framework) = self.framework_for_path(&path) {
+            return Some(framework);
+        }
+
+        let path = if path.extension().map(|x| x == "dylib").unwrap_or(false) {
+            path.with_extension("tbd")
+        } else {
+            let mut s = path.into_os_string();
+            s.push(".tbd");
+            PathBuf::from(s)
+        };
+
+        Some(path)
+    }
+
+    /// If a path is within a top-level framework, resolve that framework's directory.
+    fn framework_for_path(&self, path: &Path) -> Option<PathBuf> {
+        let rest = path.strip_prefix(FRAMEWORKS_DIRECTORY).ok()?;
+        let name = rest.components().next()?.as_os_str().to_str()?;
+
+        if name.ends_with(".framework") {
+            Some(PathBuf::from(FRAMEWORKS_DIRECTORY).join(name))
+        } else {
+            None
+        }
+    }
+
+    fn resolve_header_reference(
+        &mut self,
+        source: &Path,
+        reference: &HeaderReference,
+    ) -> Result<Option<PathBuf>, Error> {
+        let framework_path =
+            |name: &str| PathBuf::from(FRAMEWORKS_DIRECTORY).join(format!("{name}.framework"));
+
+        Ok(match reference {
+            HeaderReference::Module(name) => {
+                let path = framework_path(name);
+
+                if self.root.join(&path).exists() {
+                    Some(path)
+                } else {
+                    self.include_modules()?.get(name).cloned()
+                }
+            }
+            HeaderReference::Angle(name) => {
+                if let Some((framework, _)) = name.split_once('/') {
+                    let path = framework_path(framework);
+                    if self.root.join(&path).exists() {
+                        return Ok(Some(path));
+                    }
+                }
+
+                normalize_relative(&Path::new(INCLUDE_DIRECTORY).join(name))
+            }
+            HeaderReference::Quote(name) => {
+                let Some(candidate) =
+                    normalize_relative(&source.parent().unwrap_or(Path::new("")).join(name))
+                else {
+                    return Ok(None);
+                };
+
+                if self.root.join(&candidate).exists() {
+                    Some(candidate)
+                } else {
+                    normalize_relative(&Path::new(INCLUDE_DIRECTORY).join(name))
+                }
+            }
+        })
+    }
+
+    /// Resolve a path referenced by a module map.
+    ///
+    /// As with clang, paths in module maps in a framework's `Modules` directory are
+    /// relative to the framework. Headers are in its `Headers` or `PrivateHeaders`
+    /// directory. Other paths are relative to the module map's directory.
+    fn resolve_module_map_reference(
+        &self,
+        source: &Path,
+        reference: &ModuleMapReference,
+    ) -> Option<PathBuf> {
+        let directory = source.parent().unwrap_or(Path::new(""));
+
+        let framework = match directory.parent() {
+            Some(parent)
+                if directory.ends_with("Modules")
+                    && parent
+                        .extension()
+                        .map(|x| x == "framework")
+                        .unwrap_or(false) =>
+            {
+                Some(parent)
+            }
+            _ => None,
+        };
+
+        let path = match (framework, reference) {
+            (Some(framework), ModuleMapReference::Header { name, private }) => framework
+                .join(if *private {
+                    "PrivateHeaders"
+                } else {
+                    "Headers"
+                })
+                .join(name),
+            (Some(framework), ModuleMapReference::Directory(name))
+            | (Some(framework), ModuleMapReference::ModuleMap(name)) => framework.join(name),
+            (None, ModuleMapReference::Header { name, .. })
+            | (None, ModuleMapReference::Directory(name))
+            | (None, ModuleMapReference::ModuleMap(name)) => directory.join(name),
+        };
+
+        normalize_relative(&path)
+    }
+
+    /// Modules declared by module maps in `usr/include`.
+    ///
+    /// Like clang, `module.modulemap` and `module.map` in `usr/include` and its
+    /// immediate subdirectories are examined, as are `*.modulemap` files in
+    /// `usr/include`. The first module map declaring a module wins.
+    fn include_modules(&mut self) -> Result<&BTreeMap<String, PathBuf>, Error> {
+        if self.include_modules.is_none() {
+            let include = Path::new(INCLUDE_DIRECTORY);
+            let mut candidates = vec![];
+
+            let entries = match std::fs::read_dir(self.root.join(include)) {
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(self.include_modules.insert(BTreeMap::new()));
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let mut names = entries
+                .map(|entry| Ok(entry?.file_name()))
+                .collect::<Result<Vec<_>, std::io::Error>>()?;
+            names.sort();
+
+            for name in names {
+                let path = include.join(&name);
+
+                if path.extension().map(|x| x == "modulemap").unwrap_or(false)
+                    || name == "module.map"
+                {
+                    candidates.push(path);
+                } else if self.root.join(&path).is_dir() {
+                    candidates.push(path.join("module.modulemap"));
+                    candidates.push(path.join("module.map"));
+                }
+            }
+
+            // Prefer the top-level module map.
+            candidates.sort_by_key(|p| *p != include.join("module.modulemap"));
+
+            let mut modules = BTreeMap::new();
+
+            for path in candidates {
+                let data = match std::fs::read(self.root.join(&path)) {
+                    Ok(v) => v,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+
+                for module in module_map_modules(&data) {
+                    modules.entry(module).or_insert_with(|| path.clone());
+                }
+            }
+
+            self.include_modules = Some(modules);
+        }
+
+        Ok(self.include_modules.as_ref().expect("populated above"))
+    }
+}
+
+impl ParsedSdk {
+    /// Obtain an [SdkMinimizer] for producing a trimmed copy of this SDK.
+    pub fn minimizer(&self) -> SdkMinimizer<'_> {
+        SdkMinimizer::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::io::Read};
+
+    const MACOSX_11_3_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx11.3-settings.json");
+    const FOUNDATION_TBD: &[u8] = include_bytes!("testfiles/foundation-v4.tbd");
+
+    const LIBOBJC_TBD: &str = "--- !tapi-tbd
+tbd-version:     4
+targets:         [ x86_64-macos, arm64-macos ]
+install-name:    '/usr/lib/libobjc.A.dylib'
+exports:
+  - targets:         [ x86_64-macos, arm64-macos ]
+    symbols:         [ _objc_msgSend ]
+...
+";
+
+    /// Create a fake SDK with a handful of frameworks, libraries, and headers.
+    fn fake_sdk(root: &Path) -> Result<ParsedSdk, Error> {
+        let sdk = root.join("MacOSX11.3.sdk");
+
+        let write = |path: &str, data: &[u8]| -> Result<(), Error> {
+            let path = sdk.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, data)?;
+            Ok(())
+        };
+
+        write("SDKSettings.json", MACOSX_11_3_SETTINGS_JSON)?;
+
+        let foundation = "System/Library/Frameworks/Foundation.framework";
+        write(
+            &format!("{foundation}/Versions/C/Headers/Foundation.h"),
+            b"#import <CoreFoundation/CoreFoundation.h>\n#include <objc/objc.h>\n#import \"NSString.h\"\n",
+        )?;
+        write(
+            &format!("{foundation}/Versions/C/Headers/NSString.h"),
+            b"@import Darwin.C;\n",
+        )?;
+        write(
+            &format!("{foundation}/Versions/C/Foundation.tbd"),
+            FOUNDATION_TBD,
+        )?;
+        write(
+            "System/Library/Frameworks/CoreFoundation.framework/Headers/CoreFoundation.h",
+            b"#include <stdint.h>\n",
+        )?;
+        write(
+            "System/Library/Frameworks/UIKit.framework/Headers/UIKit.h",
+            b"#import <Foundation/Foundation.h>\n",
+        )?;
+        write(
+            "System/Library/Frameworks/UIKit.framework/Headers/UIView.h",
+            b"",
+        )?;
+        write(
+            "System/Library/Frameworks/UIKit.framework/PrivateHeaders/UIKitPrivate.h",
+            b"",
+        )?;
+        write(
+            "System/Library/Frameworks/UIKit.framework/Modules/module.modulemap",
+            b"framework module UIKit {\n  umbrella header \"UIKit.h\"\n  header \"UIView.h\"\n  exclude header \"Excluded.h\"\n  private header \"UIKitPrivate.h\"\n}\n",
+        )?;
+        write(
+            "usr/include/module.modulemap",
+            b"module Darwin [system] {\n  module C { header \"darwin_c.h\" export * }\n  umbrella \"sys\"\n}\n",
+        )?;
+        write("usr/include/darwin_c.h", b"")?;
+        write("usr/include/sys/types.h", b"")?;
+        write("usr/include/objc/objc.h", b"  #  include \"objc-api.h\"\n")?;
+        write("usr/include/objc/objc-api.h", b"")?;
+        write("usr/include/stdint.h", b"#include_next <stdint.h>\n")?;
+        write("usr/include/unused.h", b"")?;
+        write("usr/lib/libobjc.A.tbd", LIBOBJC_TBD.as_bytes())?;
+        write("usr/lib/libunused.tbd", b"")?;
+        write("usr/lib/libbroken.tbd", b"--- !tapi-tbd-v9\n")?;
+
+        #[cfg(unix)]
+        {
+            let fw = sdk.join(foundation);
+            std::os::unix::fs::symlink("C", fw.join("Versions/Current"))?;
+            std::os::unix::fs::symlink("Versions/Current/Headers", fw.join("Headers"))?;
+            std::os::unix::fs::symlink("libobjc.A.tbd", sdk.join("usr/lib/libobjc.tbd"))?;
+            std::os::unix::fs::symlink("/nonexistent", sdk.join("usr/lib/absolute.tbd"))?;
+        }
+
+        ParsedSdk::from_directory(&sdk)
+    }
+
+    #[test]
+    fn header_references_parsing() {
+        assert_eq!(
+            header_references(
+                b"#import <A/B.h>\n # include \"c.h\" // comment\n#include_next <d.h>\n@import E.F;\n#define X\nint x;\n"
+            ),
+            vec![
+                HeaderReference::Angle("A/B.h".into()),
+                HeaderReference::Quote("c.h".into()),
+                HeaderReference::Angle("d.h".into()),
+                HeaderReference::Module("E".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn module_map_parsing() {
+        let data = b"framework module Foo [system] {
+  umbrella header \"Foo.h\" // comment \"ignored.h\"
+  /* header \"commented.h\" */
+  module Bar { private textual header \"Bar.h\" export * }
+  exclude header \"Excluded.h\"
+  umbrella \"Baz\"
+  link framework \"Foo\"
+}
+extern module Qux \"qux/module.modulemap\"
+module Other {}
+";
+
+        assert_eq!(
+            module_map_references(data),
+            vec![
+                ModuleMapReference::Header {
+                    name: "Foo.h".into(),
+                    private: false
+                },
+                ModuleMapReference::Header {
+                    name: "Bar.h".into(),
+                    private: true
+                },
+                ModuleMapReference::Directory("Baz".into()),
+                ModuleMapReference::ModuleMap("qux/module.modulemap".into()),
+            ]
+        );
+        assert_eq!(
+            module_map_modules(data),
+            vec!["Foo".to_string(), "Qux".to_string(), "Other".to_string()]
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_relative(Path::new("a/./b/../c")),
+            Some(PathBuf::from("a/c"))
+        );
+        assert_eq!(normalize_relative(Path::new("a/../../b")), None);
+        assert_eq!(normalize_relative(Path::new("/a")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_framework() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let sdk = fake_sdk(td.path())?;
+
+        let manifest = sdk.minimizer().framework("Foundation").resolve()?;
+
+        let fw = "System/Library/Frameworks";
+        for path in [
+            "SDKSettings.json".to_string(),
+            format!("{fw}/Foundation.framework"),
+            format!("{fw}/Foundation.framework/Headers"),
+            format!("{fw}/Foundation.framework/Versions/C/Foundation.tbd"),
+            format!("{fw}/Foundation.framework/Versions/C/Headers/NSString.h"),
+            format!("{fw}/CoreFoundation.framework/Headers/CoreFoundation.h"),
+            "usr/include/module.modulemap".to_string(),
+            "usr/include/darwin_c.h".to_string(),
+            "usr/include/sys/types.h".to_string(),
+            "usr/include/objc/objc.h".to_string(),
+            "usr/include/objc/objc-api.h".to_string(),
+            "usr/include/stdint.h".to_string(),
+            "usr/lib/libobjc.A.tbd".to_string(),
+        ] {
+            assert!(manifest.contains(&path), "{path} in manifest");
+        }
+
+        for path in [
+            format!("{fw}/UIKit.framework"),
+            "usr/include/unused.h".to_string(),
+            "usr/lib/libunused.tbd".to_string(),
+        ] {
+            assert!(!manifest.contains(&path), "{path} not in manifest");
+        }
+
+        assert_eq!(
+            manifest
+                .entries()
+                .find(|(p, _)| p.ends_with("Foundation.framework/Headers"))
+                .map(|(_, e)| e.clone()),
+            Some(SdkManifestEntryKind::Symlink {
+                target: "Versions/Current/Headers".into()
+            })
+        );
+
+        // Symlinks are followed and absolute symlinks are retained but not followed.
+        let manifest = sdk
+            .minimizer()
+            .path("usr/lib/libobjc.tbd")
+            .path("usr/lib/absolute.tbd")
+            .resolve()?;
+        assert!(manifest.contains("usr/lib/libobjc.A.tbd"));
+        assert!(manifest.contains("usr/lib/absolute.tbd"));
+
+        // Module maps reference headers relative to the framework.
+        let manifest = sdk
+            .minimizer()
+            .path(format!("{fw}/UIKit.framework/Modules/module.modulemap"))
+            .resolve()?;
+        for path in [
+            format!("{fw}/UIKit.framework/Headers/UIKit.h"),
+            format!("{fw}/UIKit.framework/Headers/UIView.h"),
+            format!("{fw}/UIKit.framework/PrivateHeaders/UIKitPrivate.h"),
+            format!("{fw}/Foundation.framework/Versions/C/Foundation.tbd"),
+        ] {
+            assert!(manifest.contains(&path), "{path} in manifest");
+        }
+
+        // Unparseable .tbd files are included but don't fail resolution.
+        let manifest = sdk.minimizer().library("libbroken").resolve()?;
+        assert!(manifest.contains("usr/lib/libbroken.tbd"));
+
+        assert!(sdk.minimizer().framework("Missing").resolve().is_err());
+        assert!(sdk.minimizer().path("../escape").resolve().is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn materialize_framework() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let sdk = fake_sdk(td.path())?;
+        let fw = PathBuf::from("System/Library/Frameworks/Foundation.framework");
+
+        let manifest = sdk.minimizer().framework("Foundation").resolve()?;
+
+        // Paths reached through symlinked directories are recorded at their real location.
+        let current = fw.join("Versions/Current");
+        assert!(!manifest
+            .entries()
+            .any(|(p, _)| p.starts_with(&current) && p != current));
+
+        let dest = td.path().join("minimized").join("MacOSX11.3.sdk");
+        manifest.materialize(&dest)?;
+        assert_eq!(std::fs::read_link(dest.join(&current))?, PathBuf::from("C"));
+        assert_eq!(
+            std::fs::read(dest.join(&fw).join("Headers/NSString.h"))?,
+            b"@import Darwin.C;\n"
+        );
+
+        let minimized = ParsedSdk::from_directory(&dest)?;
+        let manifest2 = minimized.minimizer().framework("Foundation").resolve()?;
+        assert_eq!(manifest.digest(), manifest2.digest());
+
+        // Tar archives don't have conflicting entries.
+        let data = manifest.write_tar("MacOSX11.3.sdk", vec![])?;
+        let mut archive = tar::Archive::new(std::io::Cursor::new(data));
+        let mut names = BTreeSet::new();
+        for entry in archive.entries()? {
+            let name = entry?.path()?.to_path_buf();
+            assert!(names.insert(name.clone()), "{} is unique", name.display());
+            assert!(!name.starts_with(Path::new("MacOSX11.3.sdk").join(&current).join("Headers")));
+        }
+
+        // Individual files are resolved through symlinked parents. The links are
+        // retained but their targets aren't included in their entirety.
+        let manifest = sdk
+            .minimizer()
+            .path(fw.join("Headers/NSString.h"))
+            .resolve()?;
+        assert!(manifest.contains(fw.join("Headers")));
+        assert!(manifest.contains(&current));
+        assert!(manifest.contains(fw.join("Versions/C/Headers/NSString.h")));
+        assert!(!manifest.contains(fw.join("Versions/C/Foundation.tbd")));
+
+        let dest = td.path().join("header").join("MacOSX11.3.sdk");
+        manifest.materialize(&dest)?;
+        assert_eq!(
+            std::fs::read(dest.join(&fw).join("Headers/NSString.h"))?,
+            b"@import Darwin.C;\n"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reproducible_tar() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let sdk = fake_sdk(td.path())?;
+
+        let manifest = sdk
+            .minimizer()
+            .library("libobjc")
+            .header("objc/objc.h")
+            .resolve()?;
+
+        assert_eq!(
+            manifest.to_text(),
+            format!(
+                "file {} 644 {} SDKSettings.json\n\
+                 file {} 644 0 usr/include/objc/objc-api.h\n\
+                 file {} 644 26 usr/include/objc/objc.h\n\
+                 file {} 644 {} usr/lib/libobjc.A.tbd\n\
+                 symlink usr/lib/libobjc.tbd libobjc.A.tbd\n",
+                hex::encode(Sha256::digest(MACOSX_11_3_SETTINGS_JSON)),
+                MACOSX_11_3_SETTINGS_JSON.len(),
+                hex::encode(Sha256::digest(b"")),
+                hex::encode(Sha256::digest(b"  #  include \"objc-api.h\"\n")),
+                hex::encode(Sha256::digest(LIBOBJC_TBD.as_bytes())),
+                LIBOBJC_TBD.len(),
+            )
+        );
+
+        let a = manifest.write_tar("MacOSX11.3.sdk", vec![])?;
+        let b = manifest.write_tar("MacOSX11.3.sdk", vec![])?;
+        assert_eq!(a, b);
+
+        let mut archive = tar::Archive::new(std::io::Cursor::new(a));
+        let mut names = vec![];
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            assert_eq!(entry.header().mtime()?, DETERMINISTIC_MTIME);
+            assert_eq!(entry.header().uid()?, 0);
+            names.push(entry.path()?.to_string_lossy().to_string());
+
+            if entry.path()?.ends_with("objc.h") {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                assert_eq!(data, b"  #  include \"objc-api.h\"\n");
+            }
+        }
+        assert_eq!(
+            names,
+            vec![
+                "MacOSX11.3.sdk/",
+                "MacOSX11.3.sdk/SDKSettings.json",
+                "MacOSX11.3.sdk/usr",
+                "MacOSX11.3.sdk/usr/include",
+                "MacOSX11.3.sdk/usr/include/objc",
+                "MacOSX11.3.sdk/usr/include/objc/objc-api.h",
+                "MacOSX11.3.sdk/usr/include/objc/objc.h",
+                "MacOSX11.3.sdk/usr/lib",
+                "MacOSX11.3.sdk/usr/lib/libobjc.A.tbd",
+                "MacOSX11.3.sdk/usr/lib/libobjc.tbd",
+            ]
+        );
+
+        // Materializing and re-resolving yields the same manifest digest.
+        let dest = td.path().join("minimized").join("MacOSX11.3.sdk");
+        manifest.materialize(&dest)?;
+        let minimized = ParsedSdk::from_directory(&dest)?;
+        let manifest2 = minimized
+            .minimizer()
+            .library("libobjc")
+            .header("objc/objc.h")
+            .resolve()?;
+        assert_eq!(manifest.digest(), manifest2.digest());
+
+        Ok(())
+    }
+}
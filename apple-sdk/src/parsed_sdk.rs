@@ -49,6 +49,11 @@ pub struct SupportedTarget {
     /// version to use.
     pub deployment_target_setting_name: Option<String>,
 
+    /// The highest version of a platform that this SDK can target.
+    ///
+    /// This is typically the SDK version with a `.99` patch version. e.g. `11.3.99`.
+    pub maximum_deployment_target: Option<String>,
+
     /// The lowest version of a platform that this SDK can target.
     ///
     /// Using this SDK, it is possible to emit code that will support running
@@ -108,7 +113,7 @@ pub struct ParsedSdk {
     /// The platform this SDK belongs to.
     platform: Platform,
 
-    version: SdkVersion,
+    pub(crate) version: SdkVersion,
 
     /// The name of the platform.
     ///
@@ -205,6 +210,11 @@ impl AppleSdk for ParsedSdk {
 
     /// Whether this SDK supports the given deployment target.
     ///
+    /// `target_name` is either the name of a supported target (e.g. `macosx` or
+    /// `iosmac`) or a target triple (e.g. `aarch64-apple-ios-macabi`). Target triples
+    /// are resolved via [Platform::supported_target_name_from_target_triple()] and
+    /// never match SDKs for a different platform.
+    ///
     /// This API does not work reliably on SDKs loaded from plists because the plist metadata
     /// lacks the required version constraint annotations.
     fn supports_deployment_target(
@@ -212,8 +222,18 @@ impl AppleSdk for ParsedSdk {
         target_name: &str,
         target_version: &SdkVersion,
     ) -> Result<bool, Error> {
+        let target_name = if target_name.contains("-apple-") {
+            if Platform::from_target_triple(target_name)? != self.platform {
+                return Ok(false);
+            }
+
+            Platform::supported_target_name_from_target_triple(target_name)?
+        } else {
+            target_name.to_string()
+        };
+
         Ok(
-            if let Some(target) = self.supported_targets.get(target_name) {
+            if let Some(target) = self.supported_targets.get(&target_name) {
                 target
                     .deployment_targets_versions()
                     .contains(target_version)
@@ -367,6 +387,7 @@ mod test {
         include_bytes!("testfiles/macosx10.10-settings.plist");
     const MACOSX_10_15_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx10.15-settings.json");
     const MACOSX_11_3_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx11.3-settings.json");
+    const XROS_1_0_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/xros1.0-settings.json");
 
     fn macosx_10_9() -> Result<ParsedSdk, Error> {
         let value = plist::Value::from_reader(std::io::Cursor::new(MACOSX_10_9_SETTINGS_PLIST))?;
@@ -412,12 +433,19 @@ mod test {
         )
     }
 
+    fn xros_1_0() -> Result<ParsedSdk, Error> {
+        let value = serde_json::from_slice::<SdkSettingsJson>(XROS_1_0_SETTINGS_JSON)?;
+
+        ParsedSdk::from_json(PathBuf::from("XROS1.0.sdk"), false, Platform::XrOs, value)
+    }
+
     fn all_test_sdks() -> Result<Vec<ParsedSdk>, Error> {
         Ok(vec![
             macosx_10_9()?,
             macosx_10_10()?,
             macosx_10_15()?,
             macosx_11_3()?,
+            xros_1_0()?,
         ])
     }
 
@@ -497,4 +525,62 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn supports_deployment_target_triple() -> Result<(), Error> {
+        let sdk = macosx_11_3()?;
+
+        assert!(sdk.supports_deployment_target("x86_64-apple-darwin", &SdkVersion::from("10.9"))?);
+        assert!(sdk.supports_deployment_target("arm64e-apple-darwin", &SdkVersion::from("11.0"))?);
+        assert!(!sdk.supports_deployment_target("aarch64-apple-darwin", &SdkVersion::from("14.0"))?);
+
+        // Mac Catalyst uses the iosmac variant with iOS versions.
+        assert!(sdk.supports_deployment_target("iosmac", &SdkVersion::from("14.0"))?);
+        assert!(
+            sdk.supports_deployment_target("aarch64-apple-ios-macabi", &SdkVersion::from("14.0"))?
+        );
+        assert!(
+            !sdk.supports_deployment_target("aarch64-apple-ios-macabi", &SdkVersion::from("11.0"))?
+        );
+
+        // Triples for other platforms never match.
+        assert!(!sdk.supports_deployment_target("aarch64-apple-ios", &SdkVersion::from("14.0"))?);
+        assert!(
+            !sdk.supports_deployment_target("aarch64-apple-visionos", &SdkVersion::from("1.0"))?
+        );
+
+        let sdk = xros_1_0()?;
+        assert_eq!(sdk.platform(), &Platform::XrOs);
+        assert!(sdk.supports_deployment_target("xros", &SdkVersion::from("1.0"))?);
+        assert!(sdk.supports_deployment_target("aarch64-apple-visionos", &SdkVersion::from("1.0"))?);
+        assert!(!sdk
+            .supports_deployment_target("aarch64-apple-visionos-sim", &SdkVersion::from("1.0"))?);
+
+        assert!(sdk
+            .supports_deployment_target("aarch64-apple-unknown", &SdkVersion::from("1.0"))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_target_triple() -> Result<(), Error> {
+        let search = SdkSearch::empty()
+            .target_triple("aarch64-apple-ios-macabi")?
+            .deployment_target("aarch64-apple-ios-macabi", "14.0");
+
+        assert!(search.filter_sdk(&macosx_11_3()?)?);
+        assert!(!search.filter_sdk(&macosx_10_15()?)?);
+        assert!(!search.filter_sdk(&xros_1_0()?)?);
+
+        let search = SdkSearch::empty().target_triple("aarch64-apple-visionos")?;
+        assert!(search.filter_sdk(&xros_1_0()?)?);
+        assert!(!search.filter_sdk(&macosx_11_3()?)?);
+
+        assert!(SdkSearch::empty()
+            .target_triple("x86_64-pc-windows-msvc")
+            .is_err());
+
+        Ok(())
+    }
 }
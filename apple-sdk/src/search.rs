@@ -431,6 +431,18 @@ impl SdkSearch {
         self
     }
 
+    /// Set the SDK platform to search for from a target triple.
+    ///
+    /// This is like [Self::platform()] except the platform is derived from a target
+    /// triple via [Platform::from_target_triple()]. e.g. `aarch64-apple-visionos`
+    /// searches for [Platform::XrOs] SDKs and the Mac Catalyst triple
+    /// `aarch64-apple-ios-macabi` searches for [Platform::MacOsX] SDKs.
+    ///
+    /// Errors if the target triple is not known.
+    pub fn target_triple(self, target: &str) -> Result<Self, Error> {
+        Ok(self.platform(Platform::from_target_triple(target)?))
+    }
+
     /// Minimum SDK version to require.
     ///
     /// Effectively imposes a `>=` filter on found SDKs.
@@ -454,7 +466,9 @@ impl SdkSearch {
     /// Deployment target that the SDK must support.
     ///
     /// When set, only SDKs that support targeting the given target-version pair will
-    /// be returned. Example values are (`macosx`, `10.15`).
+    /// be returned. Example values are (`macosx`, `10.15`) and (`iosmac`, `14.0`).
+    /// The target can also be a target triple, e.g. (`aarch64-apple-ios-macabi`,
+    /// `14.0`). See [AppleSdk::supports_deployment_target()].
     ///
     /// Only modern SDKs with `SDKSettings.json` files advertise their targeting settings
     /// in a way that allows this filter to work.
@@ -0,0 +1,1058 @@
+// Copyright 2022 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Text-based stub (`.tbd`) files.
+//!
+//! SDKs don't ship Mach-O dylibs. Instead, each library is described by a
+//! *text-based stub* file defining the library's install name, versions, the
+//! targets it supports, and the symbols it exports. Linkers consume these files
+//! in place of the real library.
+//!
+//! Versions 3 and 4 of the format are YAML documents (`--- !tapi-tbd-v3` and
+//! `--- !tapi-tbd`, respectively). Version 5 is JSON. A file can contain multiple
+//! libraries: the first is the main library and subsequent ones are libraries
+//! inlined into it (typically re-exported sub-libraries of umbrella frameworks).
+
+use {
+    crate::{apple_arch_from_target_triple, AppleSdk, Error, ParsedSdk, Platform, SdkVersion},
+    serde::Deserialize,
+    std::{
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Relative paths in SDKs holding `.tbd` files.
+const SDK_TBD_DIRECTORIES: &[&str] = &[
+    "System/Library/Frameworks",
+    "System/iOSSupport/System/Library/Frameworks",
+    "System/iOSSupport/usr/lib",
+    "usr/lib",
+];
+
+/// A target in a `.tbd` file.
+///
+/// Targets are an architecture plus a platform. e.g. `x86_64-macos` or
+/// `arm64-ios-simulator`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TbdTarget {
+    /// Architecture name. e.g. `arm64`.
+    pub arch: String,
+
+    /// Platform name. e.g. `macos`, `maccatalyst`, `ios-simulator`.
+    pub platform: String,
+
+    /// Minimum deployment target.
+    ///
+    /// Only defined in TBD v5 files.
+    pub min_deployment: Option<SdkVersion>,
+}
+
+impl Display for TbdTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}-{}", self.arch, self.platform))
+    }
+}
+
+impl TbdTarget {
+    /// Parse a `<arch>-<platform>` target string.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let (arch, platform) = s
+            .split_once('-')
+            .ok_or_else(|| Error::TbdParse(format!("malformed target: {s}")))?;
+
+        Ok(Self {
+            arch: arch.to_string(),
+            platform: platform.to_string(),
+            min_deployment: None,
+        })
+    }
+
+    /// Resolve the `.tbd` target for a target triple.
+    ///
+    /// e.g. `aarch64-apple-darwin` is `arm64-macos` and `aarch64-apple-ios-macabi`
+    /// is `arm64-maccatalyst`.
+    pub fn from_target_triple(target: &str) -> Result<Self, Error> {
+        let arch = apple_arch_from_target_triple(target)?;
+
+        let platform = if Platform::is_mac_catalyst_target_triple(target) {
+            "maccatalyst"
+        } else {
+            match Platform::from_target_triple(target)? {
+                Platform::MacOsX => "macos",
+                Platform::IPhoneOs => "ios",
+                Platform::IPhoneSimulator => "ios-simulator",
+                Platform::AppleTvOs => "tvos",
+                Platform::AppleTvSimulator => "tvos-simulator",
+                Platform::WatchOs => "watchos",
+                Platform::WatchSimulator => "watchos-simulator",
+                Platform::XrOs => "xros",
+                Platform::XrSimulator => "xros-simulator",
+                Platform::DriverKit => "driverkit",
+                Platform::Unknown(_) => return Err(Error::UnknownTarget(target.to_string())),
+            }
+        };
+
+        Ok(Self {
+            arch: arch.to_string(),
+            platform: platform.to_string(),
+            min_deployment: None,
+        })
+    }
+
+    /// Whether this target has the same architecture and platform as another.
+    ///
+    /// [Self::min_deployment] is ignored.
+    pub fn matches(&self, other: &TbdTarget) -> bool {
+        self.arch == other.arch && self.platform == other.platform
+    }
+}
+
+/// The type of an exported symbol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TbdSymbolKind {
+    /// A regular global symbol.
+    Global,
+    /// A weak defined symbol.
+    Weak,
+    /// A thread local symbol.
+    ThreadLocal,
+    /// An Objective-C class.
+    ///
+    /// Names do not have the `_OBJC_CLASS_$_` prefix.
+    ObjcClass,
+    /// An Objective-C exception type.
+    ///
+    /// Names do not have the `_OBJC_EHTYPE_$_` prefix.
+    ObjcEhType,
+    /// An Objective-C instance variable.
+    ///
+    /// Names do not have the `_OBJC_IVAR_$_` prefix.
+    ObjcIvar,
+}
+
+/// A symbol exported by a library in a `.tbd` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TbdSymbol {
+    /// Symbol name, as it appears in the file.
+    pub name: String,
+
+    /// The type of symbol.
+    pub kind: TbdSymbolKind,
+
+    /// Targets this symbol is exported for.
+    pub targets: Vec<TbdTarget>,
+
+    /// Whether the symbol is re-exported from another library.
+    pub reexported: bool,
+}
+
+impl TbdSymbol {
+    /// The symbol names as they appear in Mach-O symbol tables.
+    ///
+    /// Objective-C classes have both class and metaclass symbols.
+    pub fn linker_names(&self) -> Vec<String> {
+        match self.kind {
+            TbdSymbolKind::Global | TbdSymbolKind::Weak | TbdSymbolKind::ThreadLocal => {
+                vec![self.name.clone()]
+            }
+            TbdSymbolKind::ObjcClass => vec![
+                format!("_OBJC_CLASS_$_{}", self.name),
+                format!("_OBJC_METACLASS_$_{}", self.name),
+            ],
+            TbdSymbolKind::ObjcEhType => vec![format!("_OBJC_EHTYPE_$_{}", self.name)],
+            TbdSymbolKind::ObjcIvar => vec![format!("_OBJC_IVAR_$_{}", self.name)],
+        }
+    }
+
+    /// Whether this symbol defines the given Mach-O symbol name.
+    pub fn matches(&self, name: &str) -> bool {
+        self.linker_names().iter().any(|x| x == name)
+    }
+
+    /// Whether this symbol is exported for a target.
+    pub fn has_target(&self, target: &TbdTarget) -> bool {
+        self.targets.iter().any(|t| t.matches(target))
+    }
+}
+
+/// A library re-exported by another library.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TbdReexportedLibrary {
+    /// Install name of the re-exported library.
+    pub install_name: String,
+
+    /// Targets the re-export applies to.
+    pub targets: Vec<TbdTarget>,
+}
+
+/// A library defined in a `.tbd` file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TbdLibrary {
+    /// The install name of the library.
+    ///
+    /// e.g. `/System/Library/Frameworks/Foundation.framework/Versions/C/Foundation`.
+    pub install_name: String,
+
+    /// The current version of the library.
+    pub current_version: Option<String>,
+
+    /// The compatibility version of the library.
+    pub compatibility_version: Option<String>,
+
+    /// Targets this library is available for.
+    pub targets: Vec<TbdTarget>,
+
+    /// Name of the umbrella framework this library is part of.
+    pub parent_umbrella: Option<String>,
+
+    /// Libraries re-exported by this library.
+    pub reexported_libraries: Vec<TbdReexportedLibrary>,
+
+    /// Symbols exported and re-exported by this library.
+    pub symbols: Vec<TbdSymbol>,
+}
+
+impl TbdLibrary {
+    /// Symbols that aren't Objective-C constructs.
+    pub fn exported_symbols(&self) -> impl Iterator<Item = &TbdSymbol> {
+        self.symbols.iter().filter(|s| {
+            matches!(
+                s.kind,
+                TbdSymbolKind::Global | TbdSymbolKind::Weak | TbdSymbolKind::ThreadLocal
+            )
+        })
+    }
+
+    /// Names of Objective-C classes exported by this library.
+    pub fn objc_classes(&self) -> impl Iterator<Item = &str> {
+        self.symbols
+            .iter()
+            .filter(|s| s.kind == TbdSymbolKind::ObjcClass)
+            .map(|s| s.name.as_str())
+    }
+
+    /// Find symbols defining the given Mach-O symbol name.
+    pub fn find_symbol(&self, name: &str) -> Vec<&TbdSymbol> {
+        self.symbols.iter().filter(|s| s.matches(name)).collect()
+    }
+
+    /// OS versions for which a symbol is hidden.
+    ///
+    /// Libraries can export `$ld$hide$os<version>$<symbol>` symbols to instruct the linker
+    /// that `<symbol>` isn't available when targeting OS `<version>`. This returns
+    /// these versions for a symbol.
+    pub fn hidden_symbol_versions(&self, name: &str) -> Vec<SdkVersion> {
+        let suffix = format!("${name}");
+
+        self.symbols
+            .iter()
+            .filter_map(|s| {
+                s.name
+                    .strip_prefix("$ld$hide$os")
+                    .and_then(|v| v.strip_suffix(&suffix))
+                    .map(SdkVersion::from)
+            })
+            .collect()
+    }
+
+    /// Name of the framework holding this library, if it is in a framework.
+    pub fn framework_name(&self) -> Option<&str> {
+        let (prefix, _) = self.install_name.rsplit_once(".framework/")?;
+
+        prefix.rsplit('/').next()
+    }
+}
+
+/// A parsed `.tbd` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TbdFile {
+    /// The TBD format version.
+    pub version: u8,
+
+    /// Libraries defined in this file.
+    ///
+    /// The first entry is the main library. Subsequent entries are libraries inlined
+    /// into it.
+    pub libraries: Vec<TbdLibrary>,
+}
+
+impl TbdFile {
+    /// Parse `.tbd` file content.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let s = std::str::from_utf8(data)
+            .map_err(|_| Error::TbdParse("file is not UTF-8".to_string()))?;
+
+        let res = if s.trim_start().starts_with('{') {
+            parse_v5(serde_json::from_str(s)?)?
+        } else {
+            parse_yaml(s)?
+        };
+
+        if res.libraries.is_empty() {
+            Err(Error::TbdParse("no libraries defined".to_string()))
+        } else {
+            Ok(res)
+        }
+    }
+
+    /// Parse a `.tbd` file from a filesystem path.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&std::fs::read(path.as_ref())?)
+    }
+
+    /// The main library in this file.
+    ///
+    /// Parsed files always have one. [None] is only returned if [Self::libraries]
+    /// was emptied.
+    pub fn main_library(&self) -> Option<&TbdLibrary> {
+        self.libraries.first()
+    }
+}
+
+fn yaml_string(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn yaml_strings(value: Option<&serde_yaml::Value>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_sequence())
+        .map(|seq| seq.iter().filter_map(yaml_string).collect())
+        .unwrap_or_default()
+}
+
+fn yaml_targets(value: Option<&serde_yaml::Value>) -> Result<Vec<TbdTarget>, Error> {
+    yaml_strings(value)
+        .iter()
+        .map(|s| TbdTarget::parse(s))
+        .collect()
+}
+
+/// Normalize a v3 `platform` value to its v4 target platform name.
+fn v3_platform_name(platform: &str) -> &str {
+    match platform {
+        "macosx" => "macos",
+        "iosmac" => "maccatalyst",
+        v => v,
+    }
+}
+
+/// Symbol lists in v3 and v4 export sections.
+const YAML_SYMBOL_KEYS: &[(&str, TbdSymbolKind)] = &[
+    ("symbols", TbdSymbolKind::Global),
+    ("weak-def-symbols", TbdSymbolKind::Weak),
+    ("weak-symbols", TbdSymbolKind::Weak),
+    ("thread-local-symbols", TbdSymbolKind::ThreadLocal),
+    ("objc-classes", TbdSymbolKind::ObjcClass),
+    ("objc-eh-types", TbdSymbolKind::ObjcEhType),
+    ("objc-ivars", TbdSymbolKind::ObjcIvar),
+];
+
+fn yaml_symbols(
+    section: &serde_yaml::Value,
+    targets: &[TbdTarget],
+    reexported: bool,
+) -> Vec<TbdSymbol> {
+    YAML_SYMBOL_KEYS
+        .iter()
+        .flat_map(|(key, kind)| {
+            yaml_strings(section.get(key))
+                .into_iter()
+                .map(|name| TbdSymbol {
+                    name,
+                    kind: *kind,
+                    targets: targets.to_vec(),
+                    reexported,
+                })
+        })
+        .collect()
+}
+
+fn parse_yaml(s: &str) -> Result<TbdFile, Error> {
+    let mut version = None;
+    let mut libraries = vec![];
+
+    for document in serde_yaml::Deserializer::from_str(s) {
+        let value = serde_yaml::Value::deserialize(document)?;
+
+        let (tag, value) = match value {
+            serde_yaml::Value::Tagged(tagged) => (tagged.tag.to_string(), tagged.value),
+            _ => {
+                return Err(Error::TbdParse(
+                    "YAML document lacks a !tapi-tbd tag".to_string(),
+                ))
+            }
+        };
+
+        let doc_version = match tag.as_str() {
+            "!tapi-tbd-v3" => 3,
+            "!tapi-tbd" => value
+                .get("tbd-version")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| Error::TbdParse("tbd-version missing".to_string()))?
+                as u8,
+            tag => return Err(Error::TbdParse(format!("unsupported document tag: {tag}"))),
+        };
+
+        let library = match doc_version {
+            3 => parse_v3_library(&value)?,
+            4 => parse_v4_library(&value)?,
+            v => return Err(Error::TbdParse(format!("unsupported TBD version: {v}"))),
+        };
+
+        version.get_or_insert(doc_version);
+        libraries.push(library);
+    }
+
+    Ok(TbdFile {
+        version: version.unwrap_or_default(),
+        libraries,
+    })
+}
+
+fn yaml_install_name(value: &serde_yaml::Value) -> Result<String, Error> {
+    value
+        .get("install-name")
+        .and_then(yaml_string)
+        .ok_or_else(|| Error::TbdParse("install-name missing".to_string()))
+}
+
+fn parse_v3_library(value: &serde_yaml::Value) -> Result<TbdLibrary, Error> {
+    let platform = value
+        .get("platform")
+        .and_then(yaml_string)
+        .ok_or_else(|| Error::TbdParse("platform missing".to_string()))?;
+    let platform = v3_platform_name(&platform);
+
+    let to_targets = |archs: Vec<String>| -> Vec<TbdTarget> {
+        archs
+            .into_iter()
+            .map(|arch| TbdTarget {
+                arch,
+                platform: platform.to_string(),
+                min_deployment: None,
+            })
+            .collect()
+    };
+
+    let mut library = TbdLibrary {
+        install_name: yaml_install_name(value)?,
+        current_version: value.get("current-version").and_then(yaml_string),
+        compatibility_version: value.get("compatibility-version").and_then(yaml_string),
+        targets: to_targets(yaml_strings(value.get("archs"))),
+        parent_umbrella: value.get("parent-umbrella").and_then(yaml_string),
+        ..Default::default()
+    };
+
+    for section in value
+        .get("exports")
+        .and_then(|v| v.as_sequence())
+        .into_iter()
+        .flatten()
+    {
+        let targets = to_targets(yaml_strings(section.get("archs")));
+
+        for install_name in yaml_strings(section.get("re-exports")) {
+            library.reexported_libraries.push(TbdReexportedLibrary {
+                install_name,
+                targets: targets.clone(),
+            });
+        }
+
+        library
+            .symbols
+            .extend(yaml_symbols(section, &targets, false));
+    }
+
+    Ok(library)
+}
+
+fn parse_v4_library(value: &serde_yaml::Value) -> Result<TbdLibrary, Error> {
+    let mut library = TbdLibrary {
+        install_name: yaml_install_name(value)?,
+        current_version: value.get("current-version").and_then(yaml_string),
+        compatibility_version: value.get("compatibility-version").and_then(yaml_string),
+        targets: yaml_targets(value.get("targets"))?,
+        parent_umbrella: value
+            .get("parent-umbrella")
+            .and_then(|v| v.as_sequence())
+            .and_then(|seq| seq.first())
+            .and_then(|v| v.get("umbrella"))
+            .and_then(yaml_string),
+        ..Default::default()
+    };
+
+    for section in value
+        .get("reexported-libraries")
+        .and_then(|v| v.as_sequence())
+        .into_iter()
+        .flatten()
+    {
+        let targets = yaml_targets(section.get("targets"))?;
+
+        for install_name in yaml_strings(section.get("libraries")) {
+            library.reexported_libraries.push(TbdReexportedLibrary {
+                install_name,
+                targets: targets.clone(),
+            });
+        }
+    }
+
+    for (key, reexported) in [("exports", false), ("reexports", true)] {
+        for section in value
+            .get(key)
+            .and_then(|v| v.as_sequence())
+            .into_iter()
+            .flatten()
+        {
+            let targets = yaml_targets(section.get("targets"))?;
+
+            library
+                .symbols
+                .extend(yaml_symbols(section, &targets, reexported));
+        }
+    }
+
+    Ok(library)
+}
+
+#[derive(Deserialize)]
+struct V5File {
+    tapi_tbd_version: u8,
+    main_library: V5Library,
+    #[serde(default)]
+    libraries: Vec<V5Library>,
+}
+
+#[derive(Deserialize)]
+struct V5TargetInfo {
+    target: String,
+    min_deployment: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct V5Name {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct V5Version {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct V5Umbrella {
+    umbrella: String,
+}
+
+#[derive(Deserialize)]
+struct V5ReexportedLibraries {
+    targets: Option<Vec<String>>,
+    names: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct V5SymbolSection {
+    #[serde(default)]
+    global: Vec<String>,
+    #[serde(default)]
+    weak: Vec<String>,
+    #[serde(default)]
+    thread_local: Vec<String>,
+    #[serde(default)]
+    objc_class: Vec<String>,
+    #[serde(default)]
+    objc_eh_type: Vec<String>,
+    #[serde(default)]
+    objc_ivar: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct V5Symbols {
+    targets: Option<Vec<String>>,
+    #[serde(default)]
+    data: V5SymbolSection,
+    #[serde(default)]
+    text: V5SymbolSection,
+}
+
+#[derive(Deserialize)]
+struct V5Library {
+    target_info: Vec<V5TargetInfo>,
+    install_names: Vec<V5Name>,
+    #[serde(default)]
+    current_versions: Vec<V5Version>,
+    #[serde(default)]
+    compatibility_versions: Vec<V5Version>,
+    #[serde(default)]
+    parent_umbrellas: Vec<V5Umbrella>,
+    #[serde(default)]
+    reexported_libraries: Vec<V5ReexportedLibraries>,
+    #[serde(default)]
+    exported_symbols: Vec<V5Symbols>,
+    #[serde(default)]
+    reexported_symbols: Vec<V5Symbols>,
+}
+
+fn parse_v5(file: V5File) -> Result<TbdFile, Error> {
+    if file.tapi_tbd_version != 5 {
+        return Err(Error::TbdParse(format!(
+            "unsupported TBD version: {}",
+            file.tapi_tbd_version
+        )));
+    }
+
+    let libraries = std::iter::once(file.main_library)
+        .chain(file.libraries)
+        .map(parse_v5_library)
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(TbdFile {
+        version: 5,
+        libraries,
+    })
+}
+
+fn parse_v5_library(library: V5Library) -> Result<TbdLibrary, Error> {
+    let targets = library
+        .target_info
+        .iter()
+        .map(|info| {
+            let mut target = TbdTarget::parse(&info.target)?;
+            target.min_deployment = info.min_deployment.as_ref().map(SdkVersion::from);
+
+            Ok(target)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // Entries without an explicit target list apply to all targets. Otherwise
+    // resolve target names to the library's targets so deployment info is retained.
+    let resolve_targets = |names: &Option<Vec<String>>| -> Result<Vec<TbdTarget>, Error> {
+        if let Some(names) = names {
+            names
+                .iter()
+                .map(|name| {
+                    let target = TbdTarget::parse(name)?;
+
+                    Ok(targets
+                        .iter()
+                        .find(|t| t.matches(&target))
+                        .cloned()
+                        .unwrap_or(target))
+                })
+                .collect()
+        } else {
+            Ok(targets.clone())
+        }
+    };
+
+    let mut res = TbdLibrary {
+        install_name: library
+            .install_names
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::TbdParse("install_names missing".to_string()))?
+            .name,
+        current_version: library
+            .current_versions
+            .into_iter()
+            .next()
+            .map(|v| v.version),
+        compatibility_version: library
+            .compatibility_versions
+            .into_iter()
+            .next()
+            .map(|v| v.version),
+        parent_umbrella: library
+            .parent_umbrellas
+            .into_iter()
+            .next()
+            .map(|v| v.umbrella),
+        ..Default::default()
+    };
+
+    for entry in &library.reexported_libraries {
+        let entry_targets = resolve_targets(&entry.targets)?;
+
+        for install_name in &entry.names {
+            res.reexported_libraries.push(TbdReexportedLibrary {
+                install_name: install_name.clone(),
+                targets: entry_targets.clone(),
+            });
+        }
+    }
+
+    for (entries, reexported) in [
+        (&library.exported_symbols, false),
+        (&library.reexported_symbols, true),
+    ] {
+        for entry in entries {
+            let entry_targets = resolve_targets(&entry.targets)?;
+
+            for section in [&entry.data, &entry.text] {
+                for (names, kind) in [
+                    (&section.global, TbdSymbolKind::Global),
+                    (&section.weak, TbdSymbolKind::Weak),
+                    (&section.thread_local, TbdSymbolKind::ThreadLocal),
+                    (&section.objc_class, TbdSymbolKind::ObjcClass),
+                    (&section.objc_eh_type, TbdSymbolKind::ObjcEhType),
+                    (&section.objc_ivar, TbdSymbolKind::ObjcIvar),
+                ] {
+                    res.symbols.extend(names.iter().map(|name| TbdSymbol {
+                        name: name.clone(),
+                        kind,
+                        targets: entry_targets.clone(),
+                        reexported,
+                    }));
+                }
+            }
+        }
+    }
+
+    res.targets = targets;
+
+    Ok(res)
+}
+
+/// Describes a library in an SDK exporting a symbol.
+#[derive(Clone, Debug)]
+pub struct SdkSymbolExport {
+    /// Path of the `.tbd` file defining the library, relative to the SDK root.
+    pub tbd_path: PathBuf,
+
+    /// Install name of the library exporting the symbol.
+    pub install_name: String,
+
+    /// Name of the framework holding the library, if any.
+    pub framework: Option<String>,
+
+    /// The exported symbol.
+    pub symbol: TbdSymbol,
+
+    /// OS versions for which the symbol is hidden via `$ld$hide$os` directives.
+    pub hidden_os_versions: Vec<SdkVersion>,
+}
+
+impl SdkSymbolExport {
+    /// Whether the symbol is available when targeting a target at a deployment target.
+    ///
+    /// The symbol must be exported for the target, the deployment target must not be
+    /// lower than the target's minimum deployment target (if known), and the symbol
+    /// must not be hidden for the deployment target version.
+    pub fn is_available(&self, target: &TbdTarget, deployment_target: &SdkVersion) -> bool {
+        let target_ok = self.symbol.targets.iter().any(|t| {
+            t.matches(target)
+                && t.min_deployment
+                    .as_ref()
+                    .map(|min| deployment_target >= min)
+                    .unwrap_or(true)
+        });
+
+        target_ok
+            && !self
+                .hidden_os_versions
+                .iter()
+                .any(|v| v == deployment_target)
+    }
+}
+
+fn find_tbd_files(root: &Path, dir: &Path, res: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        // Don't follow symlinks: framework Versions/Current links would yield duplicates.
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            find_tbd_files(root, &path, res)?;
+        } else if file_type.is_file() && path.extension().map(|x| x == "tbd").unwrap_or(false) {
+            res.push(
+                path.strip_prefix(root)
+                    .expect("path should be under root")
+                    .to_path_buf(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+impl ParsedSdk {
+    /// Find `.tbd` files in this SDK.
+    ///
+    /// Frameworks and `usr/lib` directories (including the Mac Catalyst
+    /// `System/iOSSupport` variants) are searched. Symlinks aren't followed.
+    /// Returned paths are relative to the SDK root and sorted.
+    pub fn tbd_paths(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut res = vec![];
+
+        for dir in SDK_TBD_DIRECTORIES {
+            find_tbd_files(self.path(), &self.path().join(dir), &mut res)?;
+        }
+
+        res.sort();
+
+        Ok(res)
+    }
+
+    /// Find libraries in this SDK exporting a symbol.
+    ///
+    /// `name` is the symbol name as it appears in Mach-O symbol tables. e.g.
+    /// `_malloc` or `_OBJC_CLASS_$_NSObject`.
+    ///
+    /// Every `.tbd` file in the SDK is parsed, so this can be slow. Files which
+    /// fail to parse are logged and skipped. Use [SdkSymbolExport::is_available()]
+    /// to check whether a result is usable for a given target and deployment
+    /// target.
+    pub fn find_symbol_exports(&self, name: &str) -> Result<Vec<SdkSymbolExport>, Error> {
+        let mut res = vec![];
+
+        for tbd_path in self.tbd_paths()? {
+            let tbd = match TbdFile::from_path(self.path().join(&tbd_path)) {
+                Ok(tbd) => tbd,
+                Err(e) => {
+                    log::warn!("skipping {}: {}", tbd_path.display(), e);
+                    continue;
+                }
+            };
+
+            for library in &tbd.libraries {
+                for symbol in library.find_symbol(name) {
+                    res.push(SdkSymbolExport {
+                        tbd_path: tbd_path.clone(),
+                        install_name: library.install_name.clone(),
+                        framework: library.framework_name().map(|x| x.to_string()),
+                        symbol: symbol.clone(),
+                        hidden_os_versions: library.hidden_symbol_versions(name),
+                    });
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::SdkSettingsJson};
+
+    const LIBZ_V3: &[u8] = include_bytes!("testfiles/libz-v3.tbd");
+    const FOUNDATION_V4: &[u8] = include_bytes!("testfiles/foundation-v4.tbd");
+    const UIKIT_V5: &[u8] = include_bytes!("testfiles/uikit-v5.tbd");
+    const MACOSX_11_3_SETTINGS_JSON: &[u8] = include_bytes!("testfiles/macosx11.3-settings.json");
+
+    fn target(s: &str) -> TbdTarget {
+        TbdTarget::parse(s).unwrap()
+    }
+
+    #[test]
+    fn parse_v3() -> Result<(), Error> {
+        let tbd = TbdFile::parse(LIBZ_V3)?;
+        assert_eq!(tbd.version, 3);
+        assert_eq!(tbd.libraries.len(), 1);
+
+        let lib = tbd.main_library().unwrap();
+        assert_eq!(lib.install_name, "/usr/lib/libz.1.dylib");
+        assert_eq!(lib.current_version.as_deref(), Some("1.2.11"));
+        assert_eq!(lib.compatibility_version.as_deref(), Some("1"));
+        assert_eq!(
+            lib.targets,
+            vec![target("i386-macos"), target("x86_64-macos")]
+        );
+        assert_eq!(lib.framework_name(), None);
+
+        let symbols = lib.find_symbol("_inflateBack");
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].targets, vec![target("x86_64-macos")]);
+        assert_eq!(
+            lib.hidden_symbol_versions("_inflateBack"),
+            vec![SdkVersion::from("10.4")]
+        );
+
+        let weak = lib.find_symbol("_zlibWeak");
+        assert_eq!(weak[0].kind, TbdSymbolKind::Weak);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_v4() -> Result<(), Error> {
+        let tbd = TbdFile::parse(FOUNDATION_V4)?;
+        assert_eq!(tbd.version, 4);
+        assert_eq!(tbd.libraries.len(), 2);
+
+        let lib = tbd.main_library().unwrap();
+        assert_eq!(lib.framework_name(), Some("Foundation"));
+        assert_eq!(lib.current_version.as_deref(), Some("1770.106"));
+        assert_eq!(lib.compatibility_version.as_deref(), Some("300"));
+        assert_eq!(lib.targets.len(), 3);
+        assert_eq!(
+            lib.reexported_libraries[0].install_name,
+            "/usr/lib/libobjc.A.dylib"
+        );
+        assert_eq!(
+            lib.objc_classes().collect::<Vec<_>>(),
+            vec!["NSString", "NSArray"]
+        );
+
+        assert_eq!(lib.find_symbol("_OBJC_CLASS_$_NSString").len(), 1);
+        assert_eq!(lib.find_symbol("_OBJC_METACLASS_$_NSArray").len(), 1);
+        assert_eq!(lib.find_symbol("_OBJC_EHTYPE_$_NSException").len(), 1);
+        assert_eq!(lib.find_symbol("_OBJC_IVAR_$_NSString._length").len(), 1);
+        assert!(lib.find_symbol("NSString").is_empty());
+
+        let intel = lib.find_symbol("_NSLegacyIntelOnly");
+        assert!(intel[0].has_target(&target("x86_64-macos")));
+        assert!(!intel[0].has_target(&target("arm64-macos")));
+
+        assert_eq!(
+            lib.find_symbol("_NSThreadThing")[0].kind,
+            TbdSymbolKind::ThreadLocal
+        );
+
+        let reexport = lib.find_symbol("_objc_msgSend");
+        assert!(reexport[0].reexported);
+        assert_eq!(lib.exported_symbols().count(), 6);
+
+        let inlined = &tbd.libraries[1];
+        assert_eq!(inlined.parent_umbrella.as_deref(), Some("Foundation"));
+        assert_eq!(inlined.framework_name(), Some("FoundationPrivate"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_v5() -> Result<(), Error> {
+        let tbd = TbdFile::parse(UIKIT_V5)?;
+        assert_eq!(tbd.version, 5);
+
+        let lib = tbd.main_library().unwrap();
+        assert_eq!(lib.framework_name(), Some("UIKit"));
+        assert_eq!(lib.current_version.as_deref(), Some("7082.1.2"));
+        assert_eq!(
+            lib.targets[0].min_deployment,
+            Some(SdkVersion::from("13.1"))
+        );
+        assert_eq!(lib.reexported_libraries.len(), 1);
+        assert_eq!(lib.objc_classes().collect::<Vec<_>>(), vec!["UIView"]);
+
+        // Symbols without targets apply to all targets.
+        let main = lib.find_symbol("_UIApplicationMain");
+        assert_eq!(main[0].targets.len(), 3);
+
+        let arm64 = lib.find_symbol("_UIArm64Only");
+        assert_eq!(arm64[0].kind, TbdSymbolKind::Weak);
+        assert_eq!(arm64[0].targets.len(), 1);
+        // Deployment info is carried over from the library targets.
+        assert_eq!(
+            arm64[0].targets[0].min_deployment,
+            Some(SdkVersion::from("13.1"))
+        );
+
+        assert!(lib.find_symbol("_UIKitCoreSymbol")[0].reexported);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(TbdFile::parse(b"--- !tapi-tbd-v2\narchs: [ x86_64 ]\n").is_err());
+        assert!(TbdFile::parse(b"archs: [ x86_64 ]\n").is_err());
+        assert!(TbdFile::parse(b"{\"tapi_tbd_version\": 6}").is_err());
+
+        let mut tbd = TbdFile::parse(LIBZ_V3).unwrap();
+        tbd.libraries.clear();
+        assert!(tbd.main_library().is_none());
+    }
+
+    #[test]
+    fn target_from_triple() -> Result<(), Error> {
+        assert_eq!(
+            TbdTarget::from_target_triple("aarch64-apple-darwin")?,
+            target("arm64-macos")
+        );
+        assert_eq!(
+            TbdTarget::from_target_triple("x86_64-apple-ios-macabi")?,
+            target("x86_64-maccatalyst")
+        );
+        assert_eq!(
+            TbdTarget::from_target_triple("aarch64-apple-ios-sim")?,
+            target("arm64-ios-simulator")
+        );
+        assert_eq!(
+            TbdTarget::from_target_triple("aarch64-apple-visionos")?.to_string(),
+            "arm64-xros"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn sdk_symbol_lookup() -> Result<(), Error> {
+        let td = tempfile::Builder::new()
+            .prefix("apple-sdk-test-")
+            .tempdir()?;
+        let root = td.path().join("MacOSX11.3.sdk");
+
+        for (path, data) in [
+            ("usr/lib/libz.tbd", LIBZ_V3),
+            (
+                "System/Library/Frameworks/Foundation.framework/Versions/C/Foundation.tbd",
+                FOUNDATION_V4,
+            ),
+            (
+                "System/iOSSupport/System/Library/Frameworks/UIKit.framework/Versions/A/UIKit.tbd",
+                UIKIT_V5,
+            ),
+            // Unparseable files don't prevent finding symbols in others.
+            ("usr/lib/libbroken.tbd", b"--- !tapi-tbd-v9\n"),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, data)?;
+        }
+
+        let value = serde_json::from_slice::<SdkSettingsJson>(MACOSX_11_3_SETTINGS_JSON)?;
+        let sdk = ParsedSdk::from_json(root, false, Platform::MacOsX, value)?;
+
+        assert_eq!(sdk.tbd_paths()?.len(), 4);
+
+        let exports = sdk.find_symbol_exports("_OBJC_CLASS_$_NSString")?;
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].framework.as_deref(), Some("Foundation"));
+        assert!(exports[0].is_available(&target("arm64-macos"), &"11.0".into()));
+        assert!(!exports[0].is_available(&target("arm64-ios"), &"14.0".into()));
+
+        let exports = sdk.find_symbol_exports("_inflateBack")?;
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].install_name, "/usr/lib/libz.1.dylib");
+        assert!(exports[0].is_available(&target("x86_64-macos"), &"10.9".into()));
+        assert!(!exports[0].is_available(&target("x86_64-macos"), &"10.4".into()));
+
+        let exports = sdk.find_symbol_exports("_UIApplicationMain")?;
+        assert_eq!(exports.len(), 1);
+        let catalyst = TbdTarget::from_target_triple("aarch64-apple-ios-macabi")?;
+        assert!(exports[0].is_available(&catalyst, &"14.0".into()));
+        assert!(!exports[0].is_available(&catalyst, &"13.0".into()));
+
+        assert!(sdk.find_symbol_exports("_does_not_exist")?.is_empty());
+
+        Ok(())
+    }
+}